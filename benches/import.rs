@@ -0,0 +1,77 @@
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use finance_analyzer::db::connection::DbConnection;
+use finance_analyzer::import::{ImportPipeline, ImportSummary, IMPORT_BATCH};
+use finance_analyzer::models::category::Category;
+use finance_analyzer::models::merchant::MerchantAlias;
+use finance_analyzer::utils::csv::CsvTransactions;
+use common::statement;
+
+const MERCHANTS: [&str; 8] = [
+    "Albert Heijn 1403 AMSTERDAM",
+    "AH to go 5812",
+    "NS GROEP IZ NS",
+    "Picnic",
+    "Uber BV",
+    "Spotify AB",
+    "Bakkerij de Korenaar",
+    "CCV*Corner Shop",
+];
+
+fn pipeline() -> ImportPipeline {
+    ImportPipeline::new(MerchantAlias::default_aliases(), Category::default_categories())
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for rows in [10_000, 100_000] {
+        let csv = statement(&MERCHANTS, rows);
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &csv, |b, csv| {
+            b.iter(|| CsvTransactions::new(csv.as_bytes()).filter(Result::is_ok).count());
+        });
+    }
+    group.finish();
+}
+
+// Counting the rows no rule matches, the only per-row work besides parsing
+fn uncategorized(c: &mut Criterion) {
+    let pipeline = pipeline();
+    let rules = pipeline.rules();
+    let batch: Vec<_> = CsvTransactions::new(statement(&MERCHANTS, IMPORT_BATCH).as_bytes())
+        .map(Result::unwrap)
+        .collect();
+
+    let mut group = c.benchmark_group("uncategorized");
+    group.throughput(Throughput::Elements(IMPORT_BATCH as u64));
+    group.bench_function("batch", |b| b.iter(|| pipeline.uncategorized(&rules, &batch)));
+    group.finish();
+}
+
+// The whole pipeline into a fresh database, as `import` runs it
+fn import(c: &mut Criterion) {
+    let pipeline = pipeline();
+    let mut group = c.benchmark_group("import");
+    group.sample_size(10);
+    for rows in [10_000, 100_000] {
+        let csv = statement(&MERCHANTS, rows);
+        group.throughput(Throughput::Elements(rows as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(rows), &csv, |b, csv| {
+            b.iter_batched_ref(
+                || DbConnection::new(":memory:").unwrap(),
+                |db| {
+                    let mut summary = ImportSummary::default();
+                    pipeline.import(db.get_connection(), CsvTransactions::new(csv.as_bytes()), &mut summary).unwrap();
+                    assert_eq!(summary.inserted, rows);
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, uncategorized, import);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use rust_decimal::Decimal;
+use crate::models::{category::SourceKind, transaction::Transaction};
+
+pub fn category_totals<'a, I>(transactions: I) -> HashMap<String, Decimal>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    let mut totals = HashMap::new();
+
+    for transaction in transactions.into_iter().filter(|t| !t.excluded) {
+        for (category, amount) in transaction.allocations() {
+            *totals.entry(category.to_string()).or_insert(Decimal::ZERO) += amount;
+        }
+    }
+
+    totals
+}
+
+// Categories ordered by absolute total, largest first
+pub fn sorted_totals(totals: &HashMap<String, Decimal>) -> Vec<(&str, Decimal)> {
+    let mut categories: Vec<(&str, Decimal)> = totals
+        .iter()
+        .map(|(category, total)| (category.as_str(), *total))
+        .collect();
+    categories.sort_by(|a, b| b.1.abs().cmp(&a.1.abs()).then(a.0.cmp(b.0)));
+    categories
+}
+
+// How many transactions got their category from each source; None counts
+// the uncategorized ones
+pub fn source_counts<'a, I>(transactions: I) -> Vec<(Option<SourceKind>, usize)>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    let mut counts: HashMap<Option<SourceKind>, usize> = HashMap::new();
+    for transaction in transactions {
+        *counts.entry(transaction.category_source.as_ref().map(|s| s.kind())).or_default() += 1;
+    }
+
+    SourceKind::all()
+        .into_iter()
+        .map(Some)
+        .chain([None])
+        .map(|kind| (kind, counts.get(&kind).copied().unwrap_or_default()))
+        .collect()
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{Duration, NaiveDate};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use crate::models::{
+    flag::{FlagKind, TransactionFlag},
+    transaction::Transaction,
+};
+
+const FOREIGN_CURRENCY_MARKERS: [&str; 5] = [
+    "exchange rate",
+    "original amount",
+    "koers",
+    "valuta",
+    "oorspr. bedrag",
+];
+
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    pub outlier_z_score: f64,
+    pub min_history: usize,
+    pub first_time_threshold: Decimal,
+    pub warmup_days: i64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        AnomalyConfig {
+            outlier_z_score: 3.0,
+            min_history: 4,
+            first_time_threshold: Decimal::from(100),
+            warmup_days: 30,
+        }
+    }
+}
+
+pub fn detect_anomalies(transactions: &[Transaction], config: &AnomalyConfig) -> Vec<(u64, TransactionFlag)> {
+    let mut flags = Vec::new();
+    flags.extend(amount_outliers(transactions, config));
+    flags.extend(first_time_merchants(transactions, config));
+    flags.extend(duplicate_charges(transactions));
+    flags.extend(foreign_currency_surprises(transactions));
+    flags
+}
+
+fn amount_outliers(transactions: &[Transaction], config: &AnomalyConfig) -> Vec<(u64, TransactionFlag)> {
+    let mut by_merchant: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut by_category: HashMap<String, Vec<f64>> = HashMap::new();
+    for transaction in transactions {
+        let amount = transaction.amount.to_f64().unwrap_or(0.0);
+        by_merchant.entry(transaction.merchant.to_lowercase()).or_default().push(amount);
+        if let Some(category) = &transaction.category {
+            by_category.entry(category.clone()).or_default().push(amount);
+        }
+    }
+
+    transactions
+        .iter()
+        .filter_map(|transaction| {
+            let amount = transaction.amount.to_f64().unwrap_or(0.0);
+            let merchant_history = &by_merchant[&transaction.merchant.to_lowercase()];
+            if let Some(mean) = outlier_mean(merchant_history, amount, config) {
+                return Some((transaction.id, TransactionFlag::new(
+                    FlagKind::AmountOutlier,
+                    format!("{:.2} vs. usual {:.2} at {}", amount, mean, transaction.merchant),
+                )));
+            }
+
+            let category = transaction.category.as_ref()?;
+            let mean = outlier_mean(&by_category[category], amount, config)?;
+            Some((transaction.id, TransactionFlag::new(
+                FlagKind::AmountOutlier,
+                format!("{:.2} vs. usual {:.2} in {}", amount, mean, category),
+            )))
+        })
+        .collect()
+}
+
+// Returns the mean of the other amounts when `amount` is far outside of them
+fn outlier_mean(history: &[f64], amount: f64, config: &AnomalyConfig) -> Option<f64> {
+    // `history` contains the transaction itself
+    let others = history.len() - 1;
+    if others < config.min_history {
+        return None;
+    }
+
+    let mean = (history.iter().sum::<f64>() - amount) / others as f64;
+    let variance = history
+        .iter()
+        .map(|x| (x - mean).powi(2))
+        .sum::<f64>()
+        - (amount - mean).powi(2);
+    let std_dev = (variance / others as f64).sqrt();
+    let deviation = (amount - mean).abs();
+
+    if deviation > config.outlier_z_score * std_dev && deviation > mean.abs() * 0.5 {
+        Some(mean)
+    } else {
+        None
+    }
+}
+
+fn first_time_merchants(transactions: &[Transaction], config: &AnomalyConfig) -> Vec<(u64, TransactionFlag)> {
+    let Some(history_start) = transactions.iter().map(|t| t.date.date()).min() else {
+        return Vec::new();
+    };
+    let warmup_end = history_start + Duration::days(config.warmup_days);
+
+    let mut first_seen: HashMap<String, &Transaction> = HashMap::new();
+    for transaction in transactions {
+        let entry = first_seen.entry(transaction.merchant.to_lowercase()).or_insert(transaction);
+        if (transaction.date, transaction.id) < (entry.date, entry.id) {
+            *entry = transaction;
+        }
+    }
+
+    first_seen
+        .into_values()
+        .filter(|t| t.date.date() > warmup_end && t.amount.abs() >= config.first_time_threshold)
+        .map(|t| (t.id, TransactionFlag::new(
+            FlagKind::FirstTimeMerchant,
+            format!("First payment to {} is {:.2}", t.merchant, t.amount),
+        )))
+        .collect()
+}
+
+fn duplicate_charges(transactions: &[Transaction]) -> Vec<(u64, TransactionFlag)> {
+    let mut groups: HashMap<(&str, String, NaiveDate, Decimal), Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions.iter().filter(|t| t.amount < Decimal::ZERO) {
+        let key = (
+            transaction.account.as_str(),
+            transaction.merchant.to_lowercase(),
+            transaction.date.date(),
+            transaction.amount,
+        );
+        groups.entry(key).or_default().push(transaction);
+    }
+
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .flat_map(|mut group| {
+            group.sort_by_key(|t| t.id);
+            let count = group.len();
+            // The first charge is expected, every repeat is suspicious
+            group.into_iter().skip(1).map(move |t| (t.id, TransactionFlag::new(
+                FlagKind::DuplicateCharge,
+                format!("{} charged {:.2} {} times on {}", t.merchant, t.amount, count, t.date.format("%Y-%m-%d")),
+            )))
+        })
+        .collect()
+}
+
+fn foreign_currency_surprises(transactions: &[Transaction]) -> Vec<(u64, TransactionFlag)> {
+    let mut foreign: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|t| is_foreign_currency(&t.description))
+        .collect();
+    foreign.sort_by_key(|t| (t.date, t.id));
+
+    // Only the first foreign-currency payment to a merchant is a surprise
+    let mut seen = HashSet::new();
+    foreign
+        .into_iter()
+        .filter(|t| seen.insert(t.merchant.to_lowercase()))
+        .map(|t| (t.id, TransactionFlag::new(
+            FlagKind::ForeignCurrency,
+            format!("Paid in a foreign currency: {}", t.description.trim()),
+        )))
+        .collect()
+}
+
+fn is_foreign_currency(description: &str) -> bool {
+    let description = description.to_lowercase();
+    FOREIGN_CURRENCY_MARKERS.iter().any(|marker| description.contains(marker))
+}
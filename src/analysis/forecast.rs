@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use anyhow::Result;
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
+use crate::models::transaction::Transaction;
+use super::recurring::{detect_recurring, RecurringItem};
+
+pub const FORECAST_HORIZONS: [u32; 3] = [30, 60, 90];
+
+#[derive(Debug, Clone)]
+pub struct ForecastConfig {
+    pub horizon_days: u32,
+    pub history_days: i64,
+    pub low_balance_threshold: Decimal,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        ForecastConfig {
+            horizon_days: FORECAST_HORIZONS[0],
+            history_days: 90,
+            low_balance_threshold: Decimal::ZERO,
+        }
+    }
+}
+
+impl ForecastConfig {
+    pub fn next_horizon(&mut self) {
+        let position = FORECAST_HORIZONS.iter().position(|&h| h == self.horizon_days);
+        self.horizon_days = match position {
+            Some(i) => FORECAST_HORIZONS[(i + 1) % FORECAST_HORIZONS.len()],
+            None => FORECAST_HORIZONS[0],
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ForecastPoint {
+    pub date: NaiveDate,
+    pub balance: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct LowBalanceWarning {
+    pub account: String,
+    pub date: NaiveDate,
+    pub balance: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountForecast {
+    pub account: String,
+    pub starting_balance: Decimal,
+    pub points: Vec<ForecastPoint>,
+    pub recurring: Vec<RecurringItem>,
+    pub daily_variable_spending: HashMap<String, Decimal>,
+}
+
+impl AccountForecast {
+    pub fn ending_balance(&self) -> Decimal {
+        self.points.last().map(|p| p.balance).unwrap_or(self.starting_balance)
+    }
+
+    pub fn lowest_point(&self) -> Option<&ForecastPoint> {
+        self.points.iter().min_by_key(|p| p.balance)
+    }
+
+    pub fn low_balance_warning(&self, threshold: Decimal) -> Option<LowBalanceWarning> {
+        self.points
+            .iter()
+            .find(|p| p.balance < threshold)
+            .map(|p| LowBalanceWarning {
+                account: self.account.clone(),
+                date: p.date,
+                balance: p.balance,
+            })
+    }
+}
+
+pub fn forecast_balances(transactions: &[Transaction], config: &ForecastConfig) -> Vec<AccountForecast> {
+    let Some(as_of) = transactions.iter().map(|t| t.date.date()).max() else {
+        return Vec::new();
+    };
+    let until = as_of + Duration::days(config.horizon_days as i64);
+
+    let recurring = detect_recurring(transactions);
+    let recurring_ids: HashSet<u64> = recurring
+        .iter()
+        .flat_map(|item| item.transaction_ids.iter().copied())
+        .collect();
+
+    let mut by_account: HashMap<&str, Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions {
+        by_account.entry(transaction.account.as_str()).or_default().push(transaction);
+    }
+
+    let mut forecasts: Vec<AccountForecast> = by_account
+        .into_iter()
+        .map(|(account, account_transactions)| {
+            let account_recurring: Vec<RecurringItem> = recurring
+                .iter()
+                .filter(|item| item.account == account)
+                .cloned()
+                .collect();
+
+            let daily_variable_spending = variable_spending(
+                &account_transactions,
+                &recurring_ids,
+                as_of,
+                config.history_days,
+            );
+            let daily_spend: Decimal = daily_variable_spending.values().sum();
+
+            let mut scheduled: HashMap<NaiveDate, Decimal> = HashMap::new();
+            for item in &account_recurring {
+                for date in item.occurrences_between(as_of, until) {
+                    *scheduled.entry(date).or_insert(Decimal::ZERO) += item.amount;
+                }
+            }
+
+            let starting_balance = starting_balance(&account_transactions);
+            let mut balance = starting_balance;
+            let points = (1..=config.horizon_days as i64)
+                .map(|offset| {
+                    let date = as_of + Duration::days(offset);
+                    balance += scheduled.get(&date).copied().unwrap_or(Decimal::ZERO) + daily_spend;
+                    ForecastPoint { date, balance: balance.round_dp(2) }
+                })
+                .collect();
+
+            AccountForecast {
+                account: account.to_string(),
+                starting_balance,
+                points,
+                recurring: account_recurring,
+                daily_variable_spending,
+            }
+        })
+        .collect();
+
+    forecasts.sort_by(|a, b| a.account.cmp(&b.account));
+    forecasts
+}
+
+// Average daily outflow per category over the history window, excluding
+// recurring items. An account with less history than the window is averaged
+// over the days it has.
+fn variable_spending(
+    transactions: &[&Transaction],
+    recurring_ids: &HashSet<u64>,
+    as_of: NaiveDate,
+    history_days: i64,
+) -> HashMap<String, Decimal> {
+    let history_start = as_of - Duration::days(history_days);
+    let mut totals: HashMap<String, Decimal> = HashMap::new();
+    for transaction in transactions {
+        if transaction.amount >= Decimal::ZERO
+            || transaction.date.date() <= history_start
+            || recurring_ids.contains(&transaction.id)
+        {
+            continue;
+        }
+        let category = transaction.category.as_deref().unwrap_or("Uncategorized").to_string();
+        *totals.entry(category).or_insert(Decimal::ZERO) += transaction.amount;
+    }
+
+    let first = transactions.iter().map(|t| t.date.date()).min().unwrap_or(as_of);
+    let days = history_days.min((as_of - first).num_days() + 1);
+    let days = Decimal::from(days.max(1));
+    totals.into_iter().map(|(category, total)| (category, total / days)).collect()
+}
+
+// ING exports list the newest transaction first, so on the latest date the
+// lowest id carries the resulting balance
+fn starting_balance(transactions: &[&Transaction]) -> Decimal {
+    transactions
+        .iter()
+        .filter(|t| t.balance.is_some())
+        .max_by(|a, b| a.date.cmp(&b.date).then(b.id.cmp(&a.id)))
+        .and_then(|t| t.balance)
+        .unwrap_or_else(|| transactions.iter().map(|t| t.amount).sum())
+}
+
+pub fn write_forecast_csv<W: Write>(forecasts: &[AccountForecast], threshold: Decimal, writer: W) -> Result<()> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(["account", "date", "balance", "below_threshold"])?;
+    for forecast in forecasts {
+        for point in &forecast.points {
+            csv_writer.write_record([
+                forecast.account.as_str(),
+                &point.date.format("%Y-%m-%d").to_string(),
+                &format!("{:.2}", point.balance),
+                if point.balance < threshold { "yes" } else { "no" },
+            ])?;
+        }
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
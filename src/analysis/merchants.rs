@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use chrono::{Duration, NaiveDate};
+use rust_decimal::Decimal;
+use crate::models::{category::CategoryType, transaction::Transaction};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportPeriod {
+    Last30Days,
+    Last90Days,
+    LastYear,
+    AllTime,
+}
+
+impl ReportPeriod {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReportPeriod::Last30Days => "last 30 days",
+            ReportPeriod::Last90Days => "last 90 days",
+            ReportPeriod::LastYear => "last 12 months",
+            ReportPeriod::AllTime => "all time",
+        }
+    }
+
+    pub fn next(&self) -> ReportPeriod {
+        match self {
+            ReportPeriod::Last30Days => ReportPeriod::Last90Days,
+            ReportPeriod::Last90Days => ReportPeriod::LastYear,
+            ReportPeriod::LastYear => ReportPeriod::AllTime,
+            ReportPeriod::AllTime => ReportPeriod::Last30Days,
+        }
+    }
+
+    // First day included in the period ending at `as_of`
+    pub fn start(&self, as_of: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            ReportPeriod::Last30Days => Some(as_of - Duration::days(29)),
+            ReportPeriod::Last90Days => Some(as_of - Duration::days(89)),
+            ReportPeriod::LastYear => Some(as_of - Duration::days(364)),
+            ReportPeriod::AllTime => None,
+        }
+    }
+
+    pub fn contains(&self, as_of: NaiveDate, date: NaiveDate) -> bool {
+        self.start(as_of).is_none_or(|start| date >= start) && date <= as_of
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MerchantStats {
+    pub merchant: String,
+    pub total_spend: Decimal,
+    pub visits: usize,
+    pub average_ticket: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct Concentration {
+    pub category: String,
+    pub top_n: usize,
+    pub merchants: usize,
+    pub share: Decimal,
+}
+
+impl Concentration {
+    pub fn describe(&self) -> String {
+        let shown = self.top_n.min(self.merchants);
+        format!(
+            "top {} merchant{} = {:.0}% of {}",
+            shown,
+            if shown == 1 { "" } else { "s" },
+            self.share,
+            self.category
+        )
+    }
+}
+
+// Spending excludes income, transfers between own accounts and anything
+// excluded from reports
+fn is_spending(transaction: &Transaction) -> bool {
+    !transaction.excluded
+        && transaction.amount < Decimal::ZERO
+        && transaction.category.as_deref() != Some(CategoryType::InternalTransfer.as_str())
+}
+
+pub fn transactions_in_period(transactions: &[Transaction], period: ReportPeriod) -> Vec<&Transaction> {
+    let Some(as_of) = transactions.iter().map(|t| t.date.date()).max() else {
+        return Vec::new();
+    };
+    transactions
+        .iter()
+        .filter(|t| period.contains(as_of, t.date.date()))
+        .collect()
+}
+
+pub fn top_merchants<'a, I>(transactions: I) -> Vec<MerchantStats>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    merchant_totals(transactions.into_iter().filter(|t| is_spending(t)))
+}
+
+// Per-merchant totals of whatever is passed in, largest first. Income and
+// transfers count by size, so this also works for a single category that
+// isn't spending.
+pub fn merchant_totals<'a, I>(transactions: I) -> Vec<MerchantStats>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    let mut totals: HashMap<&str, (Decimal, usize)> = HashMap::new();
+    for transaction in transactions.into_iter().filter(|t| !t.excluded) {
+        let entry = totals.entry(transaction.merchant.as_str()).or_insert((Decimal::ZERO, 0));
+        entry.0 += transaction.amount;
+        entry.1 += 1;
+    }
+
+    let mut stats: Vec<MerchantStats> = totals
+        .into_iter()
+        .map(|(merchant, (total, visits))| MerchantStats {
+            merchant: merchant.to_string(),
+            total_spend: total.abs(),
+            visits,
+            average_ticket: (total.abs() / Decimal::from(visits)).round_dp(2),
+        })
+        .collect();
+
+    stats.sort_by(|a, b| b.total_spend.cmp(&a.total_spend).then(a.merchant.cmp(&b.merchant)));
+    stats
+}
+
+pub fn category_concentration<'a, I>(transactions: I, top_n: usize) -> Vec<Concentration>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    let mut by_category: HashMap<&str, Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions.into_iter().filter(|t| is_spending(t)) {
+        let category = transaction.category.as_deref().unwrap_or("Uncategorized");
+        by_category.entry(category).or_default().push(transaction);
+    }
+
+    let mut concentration: Vec<(Decimal, Concentration)> = by_category
+        .into_iter()
+        .filter_map(|(category, transactions)| {
+            let stats = top_merchants(transactions);
+            let total: Decimal = stats.iter().map(|s| s.total_spend).sum();
+            if total.is_zero() {
+                return None;
+            }
+            let top: Decimal = stats.iter().take(top_n).map(|s| s.total_spend).sum();
+
+            Some((total, Concentration {
+                category: category.to_string(),
+                top_n,
+                merchants: stats.len(),
+                share: (top / total * Decimal::from(100)).round_dp(1),
+            }))
+        })
+        .collect();
+
+    concentration.sort_by_key(|(total, _)| std::cmp::Reverse(*total));
+    concentration.into_iter().map(|(_, c)| c).collect()
+}
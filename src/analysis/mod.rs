@@ -0,0 +1,7 @@
+pub mod aggregate;
+pub mod anomaly;
+pub mod forecast;
+pub mod merchants;
+pub mod monthly;
+pub mod recurring;
+pub mod trend;
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::Decimal;
+use crate::analysis::{
+    aggregate::{category_totals, sorted_totals},
+    merchants::{top_merchants, MerchantStats},
+};
+use crate::models::{category::CategoryType, flag::FlagStatus, transaction::Transaction};
+
+const TOP_MERCHANTS: usize = 10;
+const LARGEST_EXPENSES: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct BudgetVariance {
+    pub category: String,
+    pub budget: Decimal,
+    pub spent: Decimal,
+}
+
+impl BudgetVariance {
+    // Positive when under budget, negative when overspent
+    pub fn remaining(&self) -> Decimal {
+        self.budget - self.spent
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NotableTransaction {
+    pub date: NaiveDate,
+    pub merchant: String,
+    pub category: String,
+    pub amount: Decimal,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MonthlyReport {
+    pub month: NaiveDate,
+    pub transactions: usize,
+    pub income: Decimal,
+    pub expenses: Decimal,
+    pub categories: Vec<(String, Decimal)>,
+    pub top_merchants: Vec<MerchantStats>,
+    pub budgets: Vec<BudgetVariance>,
+    pub notable: Vec<NotableTransaction>,
+}
+
+impl MonthlyReport {
+    pub fn title(&self) -> String {
+        format!("Monthly report: {}", self.month.format("%B %Y"))
+    }
+
+    pub fn net(&self) -> Decimal {
+        self.income - self.expenses
+    }
+
+    // Categories with net spending, as positive amounts, largest first
+    pub fn spending_by_category(&self) -> Vec<(&str, Decimal)> {
+        self.categories
+            .iter()
+            .filter(|(_, total)| *total < Decimal::ZERO)
+            .map(|(category, total)| (category.as_str(), -*total))
+            .collect()
+    }
+}
+
+// Parses "YYYY-MM" into the first day of that month
+pub fn parse_month(month: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d")
+        .with_context(|| format!("Invalid month '{}', expected YYYY-MM", month))
+}
+
+pub fn latest_month(transactions: &[Transaction]) -> Option<NaiveDate> {
+    transactions
+        .iter()
+        .map(|t| t.date.date())
+        .max()
+        .and_then(|date| date.with_day(1))
+}
+
+fn is_transfer(transaction: &Transaction) -> bool {
+    transaction.category.as_deref() == Some(CategoryType::InternalTransfer.as_str())
+}
+
+pub fn monthly_report(
+    transactions: &[Transaction],
+    month: NaiveDate,
+    budgets: &HashMap<String, Decimal>,
+) -> MonthlyReport {
+    let in_month: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|t| !t.excluded && t.date.year() == month.year() && t.date.month() == month.month())
+        .collect();
+
+    let income = in_month
+        .iter()
+        .filter(|t| t.amount > Decimal::ZERO && !is_transfer(t))
+        .map(|t| t.amount)
+        .sum();
+    let expenses = in_month
+        .iter()
+        .filter(|t| t.amount < Decimal::ZERO && !is_transfer(t))
+        .map(|t| -t.amount)
+        .sum();
+
+    let totals = category_totals(in_month.iter().copied());
+    let categories: Vec<(String, Decimal)> = sorted_totals(&totals)
+        .into_iter()
+        .map(|(category, total)| (category.to_string(), total))
+        .collect();
+
+    let mut budget_variance: Vec<BudgetVariance> = budgets
+        .iter()
+        .map(|(category, budget)| BudgetVariance {
+            category: category.clone(),
+            budget: *budget,
+            spent: totals.get(category).map(|total| (-*total).max(Decimal::ZERO)).unwrap_or_default(),
+        })
+        .collect();
+    budget_variance.sort_by(|a, b| a.remaining().cmp(&b.remaining()).then(a.category.cmp(&b.category)));
+
+    MonthlyReport {
+        month,
+        transactions: in_month.len(),
+        income,
+        expenses,
+        categories,
+        top_merchants: top_merchants(in_month.iter().copied()).into_iter().take(TOP_MERCHANTS).collect(),
+        budgets: budget_variance,
+        notable: notable_transactions(&in_month),
+    }
+}
+
+// Flagged transactions the user has not dismissed, then the largest expenses
+fn notable_transactions(transactions: &[&Transaction]) -> Vec<NotableTransaction> {
+    let notable = |transaction: &Transaction, reason: String| NotableTransaction {
+        date: transaction.date.date(),
+        merchant: transaction.merchant.clone(),
+        category: transaction.category.clone().unwrap_or_else(|| "Uncategorized".to_string()),
+        amount: transaction.amount,
+        reason,
+    };
+
+    let mut result = Vec::new();
+    let mut flagged = Vec::new();
+    for transaction in transactions {
+        let reasons: Vec<&str> = transaction.flags
+            .iter()
+            .filter(|f| f.status != FlagStatus::Dismissed)
+            .map(|f| f.reason.as_str())
+            .collect();
+        if !reasons.is_empty() {
+            flagged.push(transaction.id);
+            result.push(notable(transaction, reasons.join("; ")));
+        }
+    }
+
+    let mut expenses: Vec<&&Transaction> = transactions
+        .iter()
+        .filter(|t| t.amount < Decimal::ZERO && !is_transfer(t) && !flagged.contains(&t.id))
+        .collect();
+    expenses.sort_by_key(|t| t.amount);
+    for transaction in expenses.into_iter().take(LARGEST_EXPENSES) {
+        result.push(notable(transaction, "Largest expense".to_string()));
+    }
+
+    result
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use chrono::{Duration, Months, NaiveDate};
+use rust_decimal::Decimal;
+use crate::models::transaction::Transaction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cadence {
+    Weekly,
+    Monthly,
+}
+
+impl Cadence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cadence::Weekly => "weekly",
+            Cadence::Monthly => "monthly",
+        }
+    }
+
+    fn from_interval(days: i64) -> Option<Cadence> {
+        match days {
+            6..=8 => Some(Cadence::Weekly),
+            26..=35 => Some(Cadence::Monthly),
+            _ => None,
+        }
+    }
+
+    fn tolerance(&self) -> i64 {
+        match self {
+            Cadence::Weekly => 2,
+            Cadence::Monthly => 5,
+        }
+    }
+
+    fn nominal_days(&self) -> i64 {
+        match self {
+            Cadence::Weekly => 7,
+            Cadence::Monthly => 30,
+        }
+    }
+
+    pub fn advance(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Cadence::Weekly => date + Duration::days(7),
+            Cadence::Monthly => date.checked_add_months(Months::new(1)).unwrap_or(date + Duration::days(30)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecurringItem {
+    pub account: String,
+    pub merchant: String,
+    pub category: Option<String>,
+    pub amount: Decimal,
+    pub cadence: Cadence,
+    pub last_date: NaiveDate,
+    pub transaction_ids: Vec<u64>,
+}
+
+impl RecurringItem {
+    // Expected occurrences strictly after `from` up to and including `until`
+    pub fn occurrences_between(&self, from: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+        let mut next = self.cadence.advance(self.last_date);
+        while next <= until {
+            if next > from {
+                dates.push(next);
+            }
+            next = self.cadence.advance(next);
+        }
+        dates
+    }
+}
+
+const MIN_OCCURRENCES: usize = 3;
+
+pub fn detect_recurring(transactions: &[Transaction]) -> Vec<RecurringItem> {
+    let Some(as_of) = transactions.iter().map(|t| t.date.date()).max() else {
+        return Vec::new();
+    };
+
+    let mut groups: HashMap<(String, String, bool), Vec<&Transaction>> = HashMap::new();
+    for transaction in transactions {
+        let key = (
+            transaction.account.clone(),
+            transaction.merchant.to_lowercase(),
+            transaction.amount < Decimal::ZERO,
+        );
+        groups.entry(key).or_default().push(transaction);
+    }
+
+    let mut items: Vec<RecurringItem> = groups
+        .into_values()
+        .filter_map(|mut group| {
+            if group.len() < MIN_OCCURRENCES {
+                return None;
+            }
+            group.sort_by_key(|t| t.date);
+            recurring_item(&group, as_of)
+        })
+        .collect();
+
+    items.sort_by(|a, b| a.account.cmp(&b.account).then(a.merchant.cmp(&b.merchant)));
+    items
+}
+
+fn recurring_item(group: &[&Transaction], as_of: NaiveDate) -> Option<RecurringItem> {
+    let mut intervals: Vec<i64> = group
+        .windows(2)
+        .map(|w| (w[1].date.date() - w[0].date.date()).num_days())
+        .collect();
+    intervals.sort_unstable();
+    let cadence = Cadence::from_interval(intervals[intervals.len() / 2])?;
+
+    // Allow a single irregular gap (e.g. a skipped or doubled payment)
+    let irregular = intervals
+        .iter()
+        .filter(|&&days| (days - cadence.nominal_days()).abs() > cadence.tolerance())
+        .count();
+    if irregular > 1 {
+        return None;
+    }
+
+    let total: Decimal = group.iter().map(|t| t.amount).sum();
+    let average = total / Decimal::from(group.len());
+    let max_deviation = average.abs() * Decimal::new(25, 2);
+    if group.iter().any(|t| (t.amount - average).abs() > max_deviation) {
+        return None;
+    }
+
+    // Items that stopped recurring are not part of the forecast
+    let last = group.last()?;
+    if (as_of - last.date.date()).num_days() > cadence.nominal_days() * 2 {
+        return None;
+    }
+
+    Some(RecurringItem {
+        account: last.account.clone(),
+        merchant: last.merchant.clone(),
+        category: last.category.clone(),
+        amount: average.round_dp(2),
+        cadence,
+        last_date: last.date.date(),
+        transaction_ids: group.iter().map(|t| t.id).collect(),
+    })
+}
@@ -0,0 +1,17 @@
+use std::collections::BTreeMap;
+use rust_decimal::Decimal;
+use crate::models::transaction::Transaction;
+
+// Net amount per calendar month, oldest month first
+pub fn monthly_totals<'a, I>(transactions: I) -> Vec<(String, Decimal)>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    let mut totals: BTreeMap<String, Decimal> = BTreeMap::new();
+    for transaction in transactions.into_iter().filter(|t| !t.excluded) {
+        *totals
+            .entry(transaction.date.format("%Y-%m").to_string())
+            .or_insert(Decimal::ZERO) += transaction.amount;
+    }
+    totals.into_iter().collect()
+}
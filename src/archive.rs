@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use crate::models::category::SourceKind;
+
+pub const FORMAT: &str = "finance-analyzer-archive";
+pub const FORMAT_VERSION: u32 = 1;
+
+// A whole book in one JSON file:
+//
+//   { "manifest": { "format": ..., "sha256": ..., ... }, "data": { ... } }
+//
+// The checksum covers the bytes of `data` exactly as written. Rows refer to
+// each other by name rather than by id, so an archive can be merged into a
+// database whose ids differ.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub format_version: u32,
+    pub app_version: String,
+    pub schema_version: u32,
+    pub created_at: String,
+    pub profile: String,
+    pub counts: BTreeMap<String, usize>,
+    pub sha256: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ArchiveData {
+    pub categories: Vec<String>,
+    pub rules: Vec<ArchivedRule>,
+    pub budgets: Vec<ArchivedBudget>,
+    pub transactions: Vec<ArchivedTransaction>,
+    pub merchant_aliases: Vec<ArchivedAlias>,
+    pub ledger_accounts: Vec<ArchivedLedgerAccount>,
+    pub saved_filters: Vec<ArchivedFilter>,
+    pub settings: Vec<ArchivedSetting>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedRule {
+    pub category: String,
+    pub pattern: String,
+    pub priority: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedBudget {
+    pub category: String,
+    pub amount: String,
+}
+
+// Values are kept exactly as stored, so the six columns that identify a
+// transaction match byte for byte when merging
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedTransaction {
+    pub date: String,
+    pub amount: String,
+    pub merchant: String,
+    pub description: String,
+    pub account: String,
+    pub balance: String,
+    pub imported_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub excluded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<ArchivedAssignment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<ArchivedFlag>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<ArchivedSplit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedAssignment {
+    pub category: String,
+    pub source: SourceKind,
+    pub confidence: Option<f64>,
+    pub assigned_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedFlag {
+    pub kind: String,
+    pub reason: String,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedSplit {
+    pub category: String,
+    pub amount: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedAlias {
+    pub pattern: String,
+    pub canonical: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedLedgerAccount {
+    pub name: String,
+    pub account: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedFilter {
+    pub name: String,
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedSetting {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug)]
+pub struct Archive {
+    pub manifest: Manifest,
+    pub data: ArchiveData,
+}
+
+#[derive(Serialize)]
+struct RawArchiveOut<'a> {
+    manifest: &'a Manifest,
+    data: &'a RawValue,
+}
+
+#[derive(Deserialize)]
+struct RawArchiveIn {
+    manifest: Manifest,
+    data: Box<RawValue>,
+}
+
+impl ArchiveData {
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        BTreeMap::from([
+            ("categories".to_string(), self.categories.len()),
+            ("rules".to_string(), self.rules.len()),
+            ("budgets".to_string(), self.budgets.len()),
+            ("transactions".to_string(), self.transactions.len()),
+            ("tags".to_string(), self.transactions.iter().map(|t| t.tags.len()).sum()),
+            ("exclusions".to_string(), self.transactions.iter().filter(|t| t.excluded).count()),
+            ("category_assignments".to_string(), self.transactions.iter().filter(|t| t.category.is_some()).count()),
+            ("flags".to_string(), self.transactions.iter().map(|t| t.flags.len()).sum()),
+            ("splits".to_string(), self.transactions.iter().filter(|t| !t.splits.is_empty()).count()),
+            ("merchant_aliases".to_string(), self.merchant_aliases.len()),
+            ("ledger_accounts".to_string(), self.ledger_accounts.len()),
+            ("saved_filters".to_string(), self.saved_filters.len()),
+            ("settings".to_string(), self.settings.len()),
+        ])
+    }
+}
+
+pub fn write_archive<W: Write>(data: &ArchiveData, profile: &str, schema_version: u32, mut writer: W) -> Result<Manifest> {
+    let raw = serde_json::value::to_raw_value(data)?;
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        format_version: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version,
+        created_at: Local::now().to_rfc3339(),
+        profile: profile.to_string(),
+        counts: data.counts(),
+        sha256: checksum(raw.get()),
+    };
+
+    serde_json::to_writer(&mut writer, &RawArchiveOut { manifest: &manifest, data: &raw })?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(manifest)
+}
+
+// Refuses archives that were cut short or edited by hand, and ones written
+// in a newer format than this build understands
+pub fn read_archive(path: &Path) -> Result<Archive> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let raw: RawArchiveIn = serde_json::from_str(&text)
+        .with_context(|| format!("{} is not a finance-analyzer archive", path.display()))?;
+
+    let manifest = raw.manifest;
+    if manifest.format != FORMAT {
+        bail!("{} is not a finance-analyzer archive (format '{}')", path.display(), manifest.format);
+    }
+    if manifest.format_version > FORMAT_VERSION {
+        bail!(
+            "{} uses archive format {}, newer than this build supports ({}); upgrade finance-analyzer",
+            path.display(),
+            manifest.format_version,
+            FORMAT_VERSION
+        );
+    }
+    let actual = checksum(raw.data.get());
+    if actual != manifest.sha256 {
+        bail!(
+            "Checksum mismatch in {}: the manifest says {}, the data hashes to {}; the archive is damaged",
+            path.display(),
+            manifest.sha256,
+            actual
+        );
+    }
+
+    let data: ArchiveData = serde_json::from_str(raw.data.get())
+        .with_context(|| format!("Invalid data in {}", path.display()))?;
+    Ok(Archive { manifest, data })
+}
+
+fn checksum(bytes: &str) -> String {
+    Sha256::digest(bytes.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
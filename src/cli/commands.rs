@@ -0,0 +1,811 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use crate::analysis::{
+    aggregate::{category_totals, sorted_totals, source_counts},
+    merchants::{category_concentration, top_merchants, transactions_in_period, ReportPeriod},
+    monthly::{self, latest_month, parse_month},
+};
+use crate::archive::{read_archive, write_archive};
+use crate::db::{
+    archive::{ArchiveDb, Prefer},
+    batch::{BatchAction, BatchDb},
+    budget::BudgetDb,
+    category::CategoryDb,
+    connection::{backups, is_encrypted},
+    filter::FilterDb,
+    integrity::IntegrityDb,
+    journal::JournalDb,
+    ledger::LedgerDb,
+    merchant::MerchantDb,
+    split::SplitDb,
+    transaction::TransactionDb,
+};
+use crate::config::{Config, Profile, DEFAULT_PROFILE};
+use crate::passphrase;
+use crate::export::{summarize, write_summary, write_transactions, ExportFormat};
+use crate::import::import_file;
+use crate::ledger::{validate_account, write_journal, AccountMap, JournalFormat};
+use crate::report::{write_report, ReportFormat};
+use crate::models::{category::{Category, Rule}, merchant::MerchantAlias, transaction::Transaction};
+use crate::query::Query;
+use crate::ui::app::App;
+use super::{
+    AliasesCommand, BackupArgs, BudgetCommand, CategoriesCommand, CategorizeArgs, Command, DbCommand, ExportArgs, FiltersCommand, HistoryCommand, LedgerCommand, MonthlyArgs,
+    ReportArgs, ReportCommand, RestoreArgs, RulesCommand, EXIT_FAILURE, EXIT_NOT_FOUND, EXIT_SUCCESS, EXIT_UNCATEGORIZED,
+};
+
+const CONCENTRATION_TOP_N: usize = 5;
+
+// Runs a non-interactive command and returns the process exit status
+pub fn run(command: Command, config: &Config, profile: &Profile) -> Result<u8> {
+    match command {
+        Command::Tui { .. } => Err(anyhow!("The TUI cannot be started from a non-interactive command")),
+        Command::Import { files } => import(&files, profile),
+        Command::Report(args) => report(&args, profile),
+        Command::Categorize(args) => categorize(&args, profile),
+        Command::Categories { command } => categories(command, profile),
+        Command::Rules { command } => rules(command, profile),
+        Command::Budget { command } => budget(command, profile),
+        Command::Export(args) => export(&args, profile),
+        Command::Ledger { command } => ledger(command, profile),
+        Command::Filters { command } => filters(command, profile),
+        Command::Aliases { command } => aliases(command, profile),
+        Command::History { command } => history(command, profile),
+        Command::Db { command } => db(command, profile),
+        Command::Backup(args) => backup(&args, profile),
+        Command::Restore(args) => restore(&args, profile),
+        Command::Profiles => profiles(config, profile),
+    }
+}
+
+fn import(files: &[String], profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let mut status = EXIT_SUCCESS;
+
+    for file in files {
+        match import_file(db_connection.get_connection(), file) {
+            Ok(summary) => {
+                print!(
+                    "{}: {} read, {} imported, {} already stored",
+                    file,
+                    summary.read,
+                    summary.inserted,
+                    summary.duplicates()
+                );
+                if !summary.skipped_reasons.is_empty() {
+                    print!(", {} skipped", summary.skipped_reasons.len());
+                }
+                if summary.uncategorized > 0 {
+                    print!(", {} matched no rule", summary.uncategorized);
+                }
+                println!();
+                for reason in &summary.skipped_reasons {
+                    eprintln!("{}: skipped {}", file, reason);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {:#}", file, e);
+                status = EXIT_FAILURE;
+            }
+        }
+    }
+
+    Ok(status)
+}
+
+fn report(args: &ReportArgs, profile: &Profile) -> Result<u8> {
+    if let Some(ReportCommand::Monthly(monthly)) = &args.command {
+        return monthly_report(monthly, profile);
+    }
+
+    let app = App::new(profile, profile.open()?)?;
+    let period = ReportPeriod::from(args.period);
+    let in_period = transactions_in_period(&app.transactions, period);
+
+    let totals = category_totals(in_period.iter().copied());
+    println!("Category totals ({}, {} transactions)", period.label(), in_period.len());
+    for (category, total) in sorted_totals(&totals) {
+        println!("  {:<30} {:>12.2}", category, total);
+    }
+    println!("  {:<30} {:>12.2}", "Total", totals.values().sum::<rust_decimal::Decimal>());
+
+    println!();
+    println!("Category sources ({})", period.label());
+    for (kind, count) in source_counts(in_period.iter().copied()) {
+        println!("  {:<30} {:>12}", kind.map(|k| k.label()).unwrap_or("Uncategorized"), count);
+    }
+
+    println!();
+    println!("Top merchants ({})", period.label());
+    for (rank, stats) in top_merchants(in_period.iter().copied()).iter().take(args.merchants).enumerate() {
+        println!(
+            "  {:>3}. {:<30} {:>12.2} {:>5} visits  avg {:>8.2}",
+            rank + 1,
+            stats.merchant,
+            stats.total_spend,
+            stats.visits,
+            stats.average_ticket
+        );
+    }
+
+    println!();
+    println!("Concentration");
+    for concentration in category_concentration(in_period, CONCENTRATION_TOP_N) {
+        println!("  {}", concentration.describe());
+    }
+
+    Ok(EXIT_SUCCESS)
+}
+
+fn monthly_report(args: &MonthlyArgs, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let budgets = BudgetDb::new(db_connection.get_connection()).get_budgets()?;
+    let app = App::new(profile, db_connection)?;
+    let month = match &args.month {
+        Some(month) => parse_month(month)?,
+        None => match latest_month(&app.transactions) {
+            Some(month) => month,
+            None => {
+                eprintln!("No transactions to report on");
+                return Ok(EXIT_NOT_FOUND);
+            }
+        },
+    };
+
+    let report = monthly::monthly_report(&app.transactions, month, &budgets);
+
+    let format = match (args.format, &args.output) {
+        (Some(format), _) => ReportFormat::from(format),
+        (None, Some(path)) => ReportFormat::from_path(path).unwrap_or(ReportFormat::Html),
+        (None, None) => ReportFormat::Html,
+    };
+    let path = args.output.clone()
+        .unwrap_or_else(|| format!("report-{}.{}", month.format("%Y-%m"), format.extension()));
+
+    write_report(&report, format, File::create(&path)?)?;
+    println!("Wrote {} ({} transactions) to {}", report.title(), report.transactions, path);
+    Ok(EXIT_SUCCESS)
+}
+
+fn categorize(args: &CategorizeArgs, profile: &Profile) -> Result<u8> {
+    if let (Some(transaction_id), Some(category)) = (args.transaction, &args.category) {
+        let mut db_connection = profile.open()?;
+        if !TransactionDb::new(db_connection.get_connection()).exists(transaction_id)? {
+            eprintln!("No transaction with id {}", transaction_id);
+            return Ok(EXIT_NOT_FOUND);
+        }
+
+        if CategoryDb::new(db_connection.get_connection()).get_category_id(category)?.is_none() {
+            eprintln!("Unknown category '{}'", category);
+            return Ok(EXIT_NOT_FOUND);
+        }
+        BatchDb::new(db_connection.get_connection())
+            .apply(&[transaction_id], &BatchAction::SetCategory(category.clone()))?;
+        println!("Transaction {} assigned to {}", transaction_id, category);
+        return Ok(EXIT_SUCCESS);
+    }
+
+    if let Some(transaction_id) = args.transaction {
+        let mut db_connection = profile.open()?;
+        if !TransactionDb::new(db_connection.get_connection()).exists(transaction_id)? {
+            eprintln!("No transaction with id {}", transaction_id);
+            return Ok(EXIT_NOT_FOUND);
+        }
+
+        SplitDb::new(db_connection.get_connection()).set_splits(transaction_id, &args.split)?;
+        if args.split.is_empty() {
+            println!("Transaction {} is no longer split", transaction_id);
+        } else {
+            let parts: Vec<String> = args.split.iter().map(|split| format!("{} {:.2}", split.category, split.amount)).collect();
+            println!("Transaction {} split across {}", transaction_id, parts.join(", "));
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+
+    let app = App::new(profile, profile.open()?)?;
+    let uncategorized: Vec<&Transaction> = app.transactions
+        .iter()
+        .filter(|t| t.category.is_none() && t.splits.is_empty())
+        .collect();
+
+    println!(
+        "{} transactions, {} categorized, {} uncategorized",
+        app.transactions.len(),
+        app.transactions.len() - uncategorized.len(),
+        uncategorized.len()
+    );
+
+    if args.list_uncategorized {
+        for transaction in &uncategorized {
+            println!(
+                "  {:>6} {} {:>10.2} {}",
+                transaction.id,
+                transaction.date.format("%Y-%m-%d"),
+                transaction.amount,
+                transaction.merchant
+            );
+        }
+    }
+
+    if args.fail_on_uncategorized && !uncategorized.is_empty() {
+        return Ok(EXIT_UNCATEGORIZED);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn rules(command: RulesCommand, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let mut category_db = CategoryDb::new(db_connection.get_connection());
+
+    match command {
+        RulesCommand::List { category } => {
+            for (id, rule) in category_db.get_rules()? {
+                if category.as_ref().is_some_and(|c| *c != rule.category) {
+                    continue;
+                }
+                println!("{:>5}  {:<20} {:>3}  {}", id, rule.category, rule.priority, rule.pattern);
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        RulesCommand::Add { category, pattern, priority } => {
+            let id = category_db.add_rule(&Rule { id: 0, pattern, category, priority })?;
+            println!("Added rule {}", id);
+            Ok(EXIT_SUCCESS)
+        }
+        RulesCommand::Remove { id } => {
+            if category_db.remove_rule(id)? {
+                println!("Removed rule {}", id);
+                Ok(EXIT_SUCCESS)
+            } else {
+                eprintln!("No rule with id {}", id);
+                Ok(EXIT_NOT_FOUND)
+            }
+        }
+        RulesCommand::Update { id, pattern, category, priority } => {
+            let Some((_, mut rule)) = category_db.get_rules()?.into_iter().find(|(rule_id, _)| *rule_id == id) else {
+                eprintln!("No rule with id {}", id);
+                return Ok(EXIT_NOT_FOUND);
+            };
+            if let Some(pattern) = pattern {
+                rule.pattern = pattern;
+            }
+            if let Some(category) = category {
+                rule.category = category;
+            }
+            if let Some(priority) = priority {
+                rule.priority = priority;
+            }
+            category_db.update_rule(&rule)?;
+            println!("Rule {}: '{}' -> {} (priority {})", id, rule.pattern, rule.category, rule.priority);
+            Ok(EXIT_SUCCESS)
+        }
+    }
+}
+
+fn categories(command: CategoriesCommand, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let mut category_db = CategoryDb::new(db_connection.get_connection());
+
+    match command {
+        CategoriesCommand::List => {
+            for category in category_db.get_all_categories()? {
+                println!("  {:<30} {:>3} rules", category.name, category.rules.len());
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        CategoriesCommand::Add { name } => {
+            if category_db.get_category_id(&name)?.is_some() {
+                eprintln!("Category '{}' already exists", name);
+                return Ok(EXIT_FAILURE);
+            }
+            category_db.save_category(&Category { name: name.clone(), rules: Vec::new() })?;
+            println!("Added category {}", name);
+            Ok(EXIT_SUCCESS)
+        }
+        CategoriesCommand::Rename { name, new_name } => {
+            if category_db.rename_category(&name, &new_name)? {
+                println!("Renamed {} to {}", name, new_name);
+                Ok(EXIT_SUCCESS)
+            } else {
+                eprintln!("Unknown category '{}'", name);
+                Ok(EXIT_NOT_FOUND)
+            }
+        }
+        CategoriesCommand::Delete { name, reassign_to } => {
+            let Some(moved) = category_db.delete_category(&name, reassign_to.as_deref())? else {
+                eprintln!("Unknown category '{}'", name);
+                return Ok(EXIT_NOT_FOUND);
+            };
+            match reassign_to {
+                Some(target) => println!("Deleted {}; moved {} transactions to {}", name, moved.assignments, target),
+                None => println!("Deleted {}", name),
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        CategoriesCommand::Merge { source, target } => {
+            let moved = category_db.merge_categories(&source, &target)?;
+            println!(
+                "Merged {} into {}: {} rules and {} transactions moved{}{}",
+                source,
+                target,
+                moved.rules,
+                moved.assignments,
+                if moved.budget { ", budget moved" } else { "" },
+                if moved.ledger_account { ", ledger account moved" } else { "" },
+            );
+            Ok(EXIT_SUCCESS)
+        }
+    }
+}
+
+fn budget(command: BudgetCommand, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+
+    match command {
+        BudgetCommand::List => {
+            let mut budgets: Vec<_> = BudgetDb::new(db_connection.get_connection()).get_budgets()?.into_iter().collect();
+            budgets.sort();
+            for (category, amount) in budgets {
+                println!("  {:<30} {:>12.2}", category, amount);
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        BudgetCommand::Set { category, amount } => {
+            if CategoryDb::new(db_connection.get_connection()).get_category_id(&category)?.is_none() {
+                eprintln!("Unknown category '{}'", category);
+                return Ok(EXIT_NOT_FOUND);
+            }
+            BudgetDb::new(db_connection.get_connection()).set_budget(&category, amount)?;
+            println!("Budget for {} set to {:.2} per month", category, amount.abs());
+            Ok(EXIT_SUCCESS)
+        }
+        BudgetCommand::Remove { category } => {
+            if BudgetDb::new(db_connection.get_connection()).remove_budget(&category)? {
+                println!("Removed budget for {}", category);
+                Ok(EXIT_SUCCESS)
+            } else {
+                eprintln!("No budget for '{}'", category);
+                Ok(EXIT_NOT_FOUND)
+            }
+        }
+    }
+}
+
+fn export(args: &ExportArgs, profile: &Profile) -> Result<u8> {
+    let mut app = App::new(profile, profile.open()?)?;
+    if let Some(filter) = &args.filter {
+        app.apply_filter(filter.clone())?;
+    }
+    let transactions = app.visible_transactions();
+
+    let format = match (args.format, &args.output) {
+        (Some(format), _) => ExportFormat::from(format),
+        (None, Some(path)) => ExportFormat::from_path(path).unwrap_or(ExportFormat::Csv),
+        (None, None) => ExportFormat::Csv,
+    };
+
+    let writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+
+    if args.summary {
+        write_summary(format, &summarize(&transactions), writer)?;
+    } else {
+        write_transactions(format, &transactions, writer)?;
+    }
+
+    if let Some(path) = &args.output {
+        println!("Exported {} transactions to {} ({})", transactions.len(), path, format.label());
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn ledger(command: LedgerCommand, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+
+    match command {
+        LedgerCommand::Export { format, output, filter } => {
+            let map = AccountMap::load(db_connection.get_connection())?;
+            let mut app = App::new(profile, db_connection)?;
+            if let Some(filter) = filter {
+                app.apply_filter(filter)?;
+            }
+            let transactions = app.visible_transactions();
+
+            let format = match (format, &output) {
+                (Some(format), _) => JournalFormat::from(format),
+                (None, Some(path)) => JournalFormat::from_path(path).unwrap_or(JournalFormat::Beancount),
+                (None, None) => JournalFormat::Beancount,
+            };
+            let writer: Box<dyn Write> = match &output {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout()),
+            };
+            write_journal(format, &transactions, &map, writer)?;
+
+            if let Some(path) = &output {
+                println!("Exported {} transactions to {} ({})", transactions.len(), path, format.label());
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        LedgerCommand::Accounts => {
+            let map = AccountMap::load(db_connection.get_connection())?;
+            let mut categories = CategoryDb::new(db_connection.get_connection()).get_all_categories()?;
+            categories.sort_by(|a, b| a.name.cmp(&b.name));
+            for category in categories {
+                let marker = if map.mappings().contains_key(&category.name) { "" } else { "  (default)" };
+                println!("  {:<20} {}{}", category.name, map.category_account(Some(&category.name), -rust_decimal::Decimal::ONE), marker);
+            }
+
+            let app = App::new(profile, db_connection)?;
+            let mut accounts: Vec<&str> = app.transactions.iter().map(|t| t.account.as_str()).collect();
+            accounts.sort();
+            accounts.dedup();
+            for account in accounts {
+                let marker = if map.mappings().contains_key(account) { "" } else { "  (default)" };
+                println!("  {:<20} {}{}", account, map.bank_account(account), marker);
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        LedgerCommand::Map { name, account } => {
+            validate_account(&account)?;
+            LedgerDb::new(db_connection.get_connection()).set_account(&name, &account)?;
+            println!("{} maps to {}", name, account);
+            Ok(EXIT_SUCCESS)
+        }
+        LedgerCommand::Unmap { name } => {
+            if LedgerDb::new(db_connection.get_connection()).remove_account(&name)? {
+                println!("Removed mapping for {}", name);
+                Ok(EXIT_SUCCESS)
+            } else {
+                eprintln!("No mapping for '{}'", name);
+                Ok(EXIT_NOT_FOUND)
+            }
+        }
+    }
+}
+
+fn filters(command: FiltersCommand, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let mut filter_db = FilterDb::new(db_connection.get_connection());
+
+    match command {
+        FiltersCommand::List => {
+            let mut filters: Vec<_> = filter_db.get_all_filters()?.into_iter().collect();
+            filters.sort();
+            for (name, query) in filters {
+                println!("  @{:<20} {}", name, query);
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        FiltersCommand::Save { name, query } => {
+            if !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+                eprintln!("Filter names may only contain letters, digits, '-' and '_'");
+                return Ok(EXIT_FAILURE);
+            }
+            let mut saved = filter_db.get_all_filters()?;
+            saved.insert(name.clone(), query.clone());
+            if let Err(e) = Query::parse(&format!("@{}", name), &saved) {
+                eprintln!("Invalid filter: {}", e);
+                return Ok(EXIT_FAILURE);
+            }
+            filter_db.save_filter(&name, &query)?;
+            println!("Saved filter @{}", name);
+            Ok(EXIT_SUCCESS)
+        }
+        FiltersCommand::Remove { name } => {
+            if filter_db.remove_filter(&name)? {
+                println!("Removed filter @{}", name);
+                Ok(EXIT_SUCCESS)
+            } else {
+                eprintln!("No saved filter named '{}'", name);
+                Ok(EXIT_NOT_FOUND)
+            }
+        }
+    }
+}
+
+fn aliases(command: AliasesCommand, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let mut merchant_db = MerchantDb::new(db_connection.get_connection());
+
+    match command {
+        AliasesCommand::List => {
+            for alias in merchant_db.get_all_aliases()? {
+                println!("  {:<30} {}", alias.pattern, alias.canonical);
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        AliasesCommand::Add { pattern, name } => {
+            if pattern.trim().is_empty() {
+                eprintln!("An alias needs a pattern to match");
+                return Ok(EXIT_FAILURE);
+            }
+            merchant_db.save_alias(&MerchantAlias::new(pattern.trim(), name.trim()))?;
+            println!("'{}' is now shown as {}", pattern.trim(), name.trim());
+            Ok(EXIT_SUCCESS)
+        }
+        AliasesCommand::Remove { pattern } => {
+            if merchant_db.remove_alias(&pattern)? {
+                println!("Removed the alias for '{}'", pattern);
+                Ok(EXIT_SUCCESS)
+            } else {
+                eprintln!("No alias for '{}'", pattern);
+                Ok(EXIT_NOT_FOUND)
+            }
+        }
+    }
+}
+
+fn history(command: HistoryCommand, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let mut journal_db = JournalDb::new(db_connection.get_connection());
+
+    match command {
+        HistoryCommand::List { limit, verbose } => {
+            for entry in journal_db.get_entries(limit)? {
+                println!(
+                    "{:>5}  {}  {}{}",
+                    entry.id,
+                    entry.created_at,
+                    entry.description,
+                    if entry.undone { " (undone)" } else { "" }
+                );
+                if verbose {
+                    for change in &entry.changes {
+                        println!("         {}", change.describe());
+                    }
+                }
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        HistoryCommand::Undo => match journal_db.undo()? {
+            Some(description) => {
+                println!("Undone: {}", description);
+                Ok(EXIT_SUCCESS)
+            }
+            None => {
+                eprintln!("Nothing to undo");
+                Ok(EXIT_NOT_FOUND)
+            }
+        },
+        HistoryCommand::Redo => match journal_db.redo()? {
+            Some(description) => {
+                println!("Redone: {}", description);
+                Ok(EXIT_SUCCESS)
+            }
+            None => {
+                eprintln!("Nothing to redo");
+                Ok(EXIT_NOT_FOUND)
+            }
+        },
+    }
+}
+
+fn db(command: DbCommand, profile: &Profile) -> Result<u8> {
+    match command {
+        DbCommand::Migrate { dry_run } => {
+            let mut db_connection = profile.open_unmigrated()?;
+            let pending = db_connection.pending_migrations()?;
+            let version = db_connection.schema_version()?;
+
+            if dry_run {
+                if pending.is_empty() {
+                    println!("Database schema at {} is up to date (version {})", profile.db_path.display(), version);
+                    return Ok(EXIT_SUCCESS);
+                }
+                println!("{} is at schema version {}; {} migration(s) pending", profile.db_path.display(), version, pending.len());
+                if let Some(backup) = db_connection.backup_path()? {
+                    println!("Would back up to {}", backup.display());
+                }
+                for migration in pending {
+                    println!("  {:>3}  {}", migration.version, migration.description);
+                }
+                return Ok(EXIT_SUCCESS);
+            }
+
+            let report = db_connection.migrate()?;
+            if let Some(backup) = &report.backup {
+                println!("Backed up {} to {}", profile.db_path.display(), backup.display());
+            }
+            for migration in &report.applied {
+                println!("Applied {:>3}  {}", migration.version, migration.description);
+            }
+            let count = TransactionDb::new(db_connection.get_connection()).count()?;
+            println!("Database schema at {} is up to date (version {}, {} transactions)", profile.db_path.display(), report.to, count);
+            Ok(EXIT_SUCCESS)
+        }
+        DbCommand::Fsck { repair } => {
+            let mut db_connection = profile.open_unmigrated()?;
+            println!("Checking {} (schema version {})", profile.db_path.display(), db_connection.schema_version()?);
+            let pending = db_connection.pending_migrations()?.len();
+            if pending > 0 {
+                println!("{} migration(s) pending; run `db migrate`", pending);
+            }
+
+            let problems = IntegrityDb::new(db_connection.get_connection()).check()?;
+            for problem in &problems {
+                match problem.row_id {
+                    Some(id) => println!("  {} #{}: {}", problem.table, id, problem.description),
+                    None => println!("  {}: {}", problem.table, problem.description),
+                }
+            }
+            if problems.is_empty() {
+                println!("No problems found");
+                return Ok(EXIT_SUCCESS);
+            }
+
+            let repairable = problems.iter().filter(|p| p.repairable).count();
+            let unrepairable = problems.len() - repairable;
+            if repair && repairable > 0 {
+                let deleted = IntegrityDb::new(db_connection.get_connection()).repair(&problems)?;
+                println!("Deleted {} row(s)", deleted);
+            } else if repairable > 0 {
+                println!("{} problem(s) can be fixed with --repair", repairable);
+            }
+            if unrepairable > 0 {
+                println!("{} problem(s) can't be repaired here; restore from a backup", unrepairable);
+            }
+            let remaining = if repair { unrepairable } else { problems.len() };
+            Ok(if remaining > 0 { EXIT_FAILURE } else { EXIT_SUCCESS })
+        }
+        DbCommand::Encrypt => {
+            if profile.is_encrypted()? {
+                return Err(anyhow!("{} is already encrypted; use `db passphrase` to change the passphrase", profile.db_path.display()));
+            }
+            let db_connection = profile.open()?;
+            let passphrase = passphrase::prompt_new()?;
+            let path = db_connection.rewrite(Some(&passphrase))?;
+            println!("Encrypted {}", path.display());
+
+            let plaintext: Vec<_> = backups(&path)?
+                .into_iter()
+                .filter(|backup| !is_encrypted(backup).unwrap_or(true))
+                .collect();
+            if !plaintext.is_empty() {
+                println!("Backups from earlier migrations are still in plaintext; delete them once they are no longer needed:");
+                for backup in plaintext {
+                    println!("  {}", backup.display());
+                }
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        DbCommand::Decrypt => {
+            if !profile.is_encrypted()? {
+                return Err(anyhow!("{} is not encrypted", profile.db_path.display()));
+            }
+            let path = profile.open()?.rewrite(None)?;
+            println!("Decrypted {}; it is stored in plaintext now", path.display());
+            Ok(EXIT_SUCCESS)
+        }
+        DbCommand::Passphrase => {
+            if !profile.is_encrypted()? {
+                return Err(anyhow!("{} is not encrypted; use `db encrypt` first", profile.db_path.display()));
+            }
+            let mut db_connection = profile.open()?;
+            let passphrase = passphrase::prompt_new()?;
+            db_connection.change_passphrase(&passphrase)?;
+            println!("Changed the passphrase for {}", profile.db_path.display());
+            if backups(&profile.db_path)?.iter().any(|backup| is_encrypted(backup).unwrap_or(false)) {
+                println!("Backups from earlier migrations still open with their old passphrase");
+            }
+            Ok(EXIT_SUCCESS)
+        }
+    }
+}
+
+fn backup(args: &BackupArgs, profile: &Profile) -> Result<u8> {
+    let mut db_connection = profile.open()?;
+    let schema_version = db_connection.schema_version()?;
+    let data = ArchiveDb::new(db_connection.get_connection()).export()?;
+
+    let path = args.output.clone().unwrap_or_else(|| {
+        PathBuf::from(format!("finance-analyzer-{}-{}.json", profile.name, Local::now().format("%Y%m%d-%H%M%S")))
+    });
+    let file = File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let manifest = write_archive(&data, &profile.name, schema_version, BufWriter::new(file))?;
+
+    println!(
+        "Backed up {} transactions, {} categories and {} rules to {}",
+        data.transactions.len(),
+        data.categories.len(),
+        data.rules.len(),
+        path.display()
+    );
+    println!("sha256 {}", manifest.sha256);
+    if db_connection.is_encrypted() {
+        eprintln!("Note: the archive itself is not encrypted; keep it somewhere safe");
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn restore(args: &RestoreArgs, profile: &Profile) -> Result<u8> {
+    let archive = read_archive(&args.archive)?;
+    let mut db_connection = profile.open()?;
+
+    let existing = TransactionDb::new(db_connection.get_connection()).count()?;
+    if existing > 0 && !args.merge {
+        return Err(anyhow!(
+            "{} already has {} transactions; pass --merge to merge the archive into it",
+            profile.db_path.display(),
+            existing
+        ));
+    }
+    // Into an empty database the archive is the only source of truth
+    let prefer = if args.merge { Prefer::from(args.prefer) } else { Prefer::Archive };
+
+    let report = ArchiveDb::new(db_connection.get_connection()).restore(&archive.data, prefer, args.dry_run)?;
+
+    println!(
+        "Archive {} from profile '{}', written {}",
+        args.archive.display(),
+        archive.manifest.profile,
+        archive.manifest.created_at
+    );
+    let verb = if args.dry_run { "Would add" } else { "Added" };
+    let added: Vec<String> = report
+        .added
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .map(|(kind, count)| format!("{} {}", count, kind.replace('_', " ")))
+        .collect();
+    if added.is_empty() {
+        println!("{} nothing; {} already has everything in the archive", verb, profile.db_path.display());
+    } else {
+        println!("{} {}", verb, added.join(", "));
+    }
+
+    if !report.conflicts.is_empty() {
+        let kept = match prefer {
+            Prefer::Local => "keeping the local value",
+            Prefer::Archive => "taking the archive's value",
+        };
+        println!("{} conflict(s), {}:", report.conflicts.len(), kept);
+        for conflict in &report.conflicts {
+            println!("  {:<16} {}: local {}, archive {}", conflict.kind, conflict.key, conflict.local, conflict.archived);
+        }
+    }
+    if report.journal_cleared > 0 {
+        let verb = if args.dry_run { "Would clear" } else { "Cleared" };
+        println!("{} the undo history ({} entries); a restore can't be undone", verb, report.journal_cleared);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn profiles(config: &Config, active: &Profile) -> Result<u8> {
+    match Config::path() {
+        Some(path) if path.exists() => println!("Config: {}", path.display()),
+        Some(path) => println!("Config: {} (not created yet)", path.display()),
+        None => println!("Config: no config directory on this system"),
+    }
+
+    let mut names: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+    if !names.contains(&DEFAULT_PROFILE) {
+        names.insert(0, DEFAULT_PROFILE);
+    }
+    if !names.contains(&active.name.as_str()) {
+        names.push(&active.name);
+    }
+
+    for name in names {
+        let profile = if name == active.name {
+            active.clone()
+        } else {
+            config.resolve(None, Some(name))?
+        };
+        let marker = if name == active.name { "*" } else { " " };
+        let state = if !profile.db_path.exists() {
+            " (not created yet)"
+        } else if profile.is_encrypted()? {
+            " (encrypted)"
+        } else {
+            ""
+        };
+        println!("{} {:<16} {}{}", marker, profile.name, profile.db_path.display(), state);
+    }
+    Ok(EXIT_SUCCESS)
+}
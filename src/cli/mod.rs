@@ -0,0 +1,479 @@
+pub mod commands;
+
+use std::path::PathBuf;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use crate::analysis::merchants::ReportPeriod;
+use crate::db::archive::Prefer;
+use rust_decimal::Decimal;
+use crate::export::ExportFormat;
+use crate::ledger::JournalFormat;
+use crate::models::transaction::Split;
+use crate::report::ReportFormat;
+
+pub const EXIT_SUCCESS: u8 = 0;
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_UNCATEGORIZED: u8 = 3;
+pub const EXIT_NOT_FOUND: u8 = 4;
+
+#[derive(Debug, Parser)]
+#[command(name = "finance-analyzer", version, about = "Analyze and categorize ING bank statements")]
+#[command(override_usage = "finance-analyzer [OPTIONS] [CSV]\n       finance-analyzer [OPTIONS] <COMMAND>")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// CSV file to import before opening the TUI (same as `tui <CSV>`)
+    pub csv: Option<String>,
+
+    /// Database file to use instead of the profile's
+    #[arg(long, global = true, value_name = "PATH")]
+    pub db: Option<PathBuf>,
+
+    /// Profile from the config file, e.g. household or business
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Open the interactive terminal UI
+    Tui {
+        /// CSV file to import before opening
+        csv: Option<String>,
+    },
+    /// Import ING CSV exports or Beancount journals into the database
+    Import {
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+    /// Print category totals and top merchants, or write a monthly report
+    Report(ReportArgs),
+    /// Apply category rules, or assign a category manually
+    Categorize(CategorizeArgs),
+    /// Add, rename, delete or merge categories
+    Categories {
+        #[command(subcommand)]
+        command: CategoriesCommand,
+    },
+    /// Manage categorization rules
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+    /// Manage monthly category budgets
+    Budget {
+        #[command(subcommand)]
+        command: BudgetCommand,
+    },
+    /// Write transactions or category totals as CSV, JSON, JSON Lines or Markdown
+    Export(ExportArgs),
+    /// Export to Ledger, hledger or Beancount and map categories to accounts
+    Ledger {
+        #[command(subcommand)]
+        command: LedgerCommand,
+    },
+    /// Manage saved filters, used as @name in filter queries
+    Filters {
+        #[command(subcommand)]
+        command: FiltersCommand,
+    },
+    /// Manage merchant aliases, which show matching bank descriptions under one name
+    Aliases {
+        #[command(subcommand)]
+        command: AliasesCommand,
+    },
+    /// Show, undo or redo recorded edits
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Write the whole database to a portable archive
+    Backup(BackupArgs),
+    /// Restore an archive, or merge it into a database that has data
+    Restore(RestoreArgs),
+    /// List the profiles from the config file and their databases
+    Profiles,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PeriodArg {
+    #[value(name = "30d")]
+    Days30,
+    #[value(name = "90d")]
+    Days90,
+    Year,
+    All,
+}
+
+impl From<PeriodArg> for ReportPeriod {
+    fn from(period: PeriodArg) -> Self {
+        match period {
+            PeriodArg::Days30 => ReportPeriod::Last30Days,
+            PeriodArg::Days90 => ReportPeriod::Last90Days,
+            PeriodArg::Year => ReportPeriod::LastYear,
+            PeriodArg::All => ReportPeriod::AllTime,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ReportArgs {
+    #[command(subcommand)]
+    pub command: Option<ReportCommand>,
+
+    /// Period ending at the most recent transaction
+    #[arg(long, value_enum, default_value = "all")]
+    pub period: PeriodArg,
+
+    /// Number of top merchants to list
+    #[arg(long, default_value_t = 10)]
+    pub merchants: usize,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReportCommand {
+    /// Write a printable HTML or PDF report for one month
+    Monthly(MonthlyArgs),
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReportFormatArg {
+    Html,
+    Pdf,
+}
+
+impl From<ReportFormatArg> for ReportFormat {
+    fn from(format: ReportFormatArg) -> Self {
+        match format {
+            ReportFormatArg::Html => ReportFormat::Html,
+            ReportFormatArg::Pdf => ReportFormat::Pdf,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct MonthlyArgs {
+    /// Month to report on as YYYY-MM; defaults to the most recent month
+    #[arg(long)]
+    pub month: Option<String>,
+
+    /// Output format; inferred from the output extension, otherwise html
+    #[arg(long, value_enum)]
+    pub format: Option<ReportFormatArg>,
+
+    /// Output file; defaults to report-YYYY-MM.html or .pdf
+    #[arg(long, short)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("assignment").args(["category", "split", "unsplit"])))]
+pub struct CategorizeArgs {
+    /// Transaction id to assign a category to or split
+    #[arg(long, requires = "assignment")]
+    pub transaction: Option<u64>,
+
+    /// Category to assign to --transaction
+    #[arg(long, requires = "transaction")]
+    pub category: Option<String>,
+
+    /// Split --transaction across categories, e.g. --split Groceries=30 --split Household=12.10
+    #[arg(long, value_name = "CATEGORY=AMOUNT", value_parser = parse_split, requires = "transaction")]
+    pub split: Vec<Split>,
+
+    /// Remove the split from --transaction
+    #[arg(long, requires = "transaction")]
+    pub unsplit: bool,
+
+    /// Print transactions no rule matched
+    #[arg(long)]
+    pub list_uncategorized: bool,
+
+    /// Exit with status 3 when transactions remain uncategorized
+    #[arg(long)]
+    pub fail_on_uncategorized: bool,
+}
+
+fn parse_split(value: &str) -> Result<Split, String> {
+    let (category, amount) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected CATEGORY=AMOUNT, got '{}'", value))?;
+    let amount = amount
+        .trim()
+        .replace(',', ".")
+        .parse::<Decimal>()
+        .map_err(|_| format!("'{}' is not an amount", amount))?;
+    Ok(Split { category: category.trim().to_string(), amount })
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RulesCommand {
+    /// List all rules, highest priority first
+    List {
+        #[arg(long)]
+        category: Option<String>,
+    },
+    /// Add a pattern to an existing category
+    Add {
+        category: String,
+        pattern: String,
+        #[arg(long, default_value_t = 1)]
+        priority: u8,
+    },
+    /// Remove a rule by id
+    Remove {
+        id: i64,
+    },
+    /// Change a rule's pattern, category or priority
+    Update {
+        id: i64,
+        #[arg(long)]
+        pattern: Option<String>,
+        #[arg(long)]
+        category: Option<String>,
+        #[arg(long)]
+        priority: Option<u8>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CategoriesCommand {
+    /// List categories with their number of rules
+    List,
+    /// Add an empty category
+    Add {
+        name: String,
+    },
+    /// Rename a category; its rules, budget and transactions follow
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Delete a category with its rules and budget
+    Delete {
+        name: String,
+        /// Move its transactions to this category instead of uncategorizing them
+        #[arg(long)]
+        reassign_to: Option<String>,
+    },
+    /// Move everything from one category into another and delete it
+    Merge {
+        source: String,
+        target: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BudgetCommand {
+    /// List budgets
+    List,
+    /// Set the monthly spending limit for a category
+    Set {
+        category: String,
+        amount: Decimal,
+    },
+    /// Remove a category's budget
+    Remove {
+        category: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FormatArg {
+    Csv,
+    Json,
+    Jsonl,
+    Markdown,
+}
+
+impl From<FormatArg> for ExportFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Csv => ExportFormat::Csv,
+            FormatArg::Json => ExportFormat::Json,
+            FormatArg::Jsonl => ExportFormat::JsonLines,
+            FormatArg::Markdown => ExportFormat::Markdown,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Output file; defaults to stdout
+    #[arg(long, short)]
+    pub output: Option<String>,
+
+    /// Output format; inferred from the output extension, otherwise csv
+    #[arg(long, value_enum)]
+    pub format: Option<FormatArg>,
+
+    /// Only export transactions matching this filter query, e.g. 'category:Groceries amount<-50'
+    #[arg(long, allow_hyphen_values = true)]
+    pub filter: Option<String>,
+
+    /// Export category totals instead of individual transactions
+    #[arg(long)]
+    pub summary: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum JournalFormatArg {
+    Ledger,
+    Hledger,
+    Beancount,
+}
+
+impl From<JournalFormatArg> for JournalFormat {
+    fn from(format: JournalFormatArg) -> Self {
+        match format {
+            JournalFormatArg::Ledger => JournalFormat::Ledger,
+            JournalFormatArg::Hledger => JournalFormat::Hledger,
+            JournalFormatArg::Beancount => JournalFormat::Beancount,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LedgerCommand {
+    /// Write transactions as journal entries
+    Export {
+        /// Journal format; inferred from the output extension, otherwise beancount
+        #[arg(long, value_enum)]
+        format: Option<JournalFormatArg>,
+
+        /// Output file; defaults to stdout
+        #[arg(long, short)]
+        output: Option<String>,
+
+        /// Only export transactions matching this filter query, e.g. 'category:Groceries amount<-50'
+        #[arg(long, allow_hyphen_values = true)]
+        filter: Option<String>,
+    },
+    /// Show the journal account used for each category and bank account
+    Accounts,
+    /// Map a category or bank account number to a journal account
+    Map {
+        name: String,
+        /// e.g. Expenses:Food:Groceries
+        account: String,
+    },
+    /// Remove a mapping and go back to the default account name
+    Unmap {
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum HistoryCommand {
+    /// List recent edits, newest first
+    List {
+        /// Number of entries to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Also print each row-level change
+        #[arg(long)]
+        verbose: bool,
+    },
+    /// Undo the most recent edit
+    Undo,
+    /// Redo the most recently undone edit
+    Redo,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AliasesCommand {
+    /// List aliases by merchant name
+    List,
+    /// Show merchants whose description contains PATTERN as NAME
+    Add {
+        pattern: String,
+        name: String,
+    },
+    /// Remove the alias for a pattern
+    Remove {
+        pattern: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FiltersCommand {
+    /// List saved filters
+    List,
+    /// Save a filter query under a name
+    Save {
+        name: String,
+        #[arg(allow_hyphen_values = true)]
+        query: String,
+    },
+    /// Remove a saved filter
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// Archive to write; defaults to finance-analyzer-<profile>-<timestamp>.json
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// Archive written by `backup`
+    pub archive: PathBuf,
+
+    /// Merge into a database that already has transactions
+    #[arg(long)]
+    pub merge: bool,
+
+    /// Which value to keep when the archive and the database disagree
+    #[arg(long, value_enum, default_value = "local", requires = "merge")]
+    pub prefer: PreferArg,
+
+    /// Report what would be added and every conflict without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PreferArg {
+    Local,
+    Archive,
+}
+
+impl From<PreferArg> for Prefer {
+    fn from(prefer: PreferArg) -> Self {
+        match prefer {
+            PreferArg::Local => Prefer::Local,
+            PreferArg::Archive => Prefer::Archive,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Create or upgrade the database schema, backing up the old file first
+    Migrate {
+        /// Show the pending migrations without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the file and the references between tables, like fsck
+    Fsck {
+        /// Delete rows that refer to missing rows, and unreadable history entries
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Encrypt the database with a passphrase (SQLCipher)
+    Encrypt,
+    /// Store the database in plaintext again
+    Decrypt,
+    /// Change the passphrase of an encrypted database
+    Passphrase,
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use crate::db::connection::{is_encrypted, DbConnection, DEFAULT_DB_PATH};
+use crate::passphrase;
+
+const APP_DIR: &str = "finance-analyzer";
+pub const DEFAULT_PROFILE: &str = "default";
+const DEFAULT_LOCK_AFTER_MINUTES: u64 = 15;
+// Passphrase attempts before giving up, at the prompt and the lock screen
+pub const UNLOCK_ATTEMPTS: usize = 3;
+// Each open of an encrypted profile asks for the passphrase, so commands
+// open theirs once; counted so the tests can check that
+static OPENS: AtomicUsize = AtomicUsize::new(0);
+
+// ~/.config/finance-analyzer/config.toml, e.g.
+//
+//   default_profile = "household"
+//   lock_after_minutes = 15   # encrypted databases only; 0 never locks
+//
+//   [profiles.household]
+//   db = "~/books/household.db"
+//
+//   [profiles.business]
+//   db = "business.db"    # relative to the data directory
+//   lock_after_minutes = 5
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub default_profile: Option<String>,
+    pub lock_after_minutes: Option<u64>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub db: Option<PathBuf>,
+    pub lock_after_minutes: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub db_path: PathBuf,
+    // How long the TUI may sit idle before an encrypted database locks again
+    pub lock_after: Option<Duration>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_DIR).join("config.toml"))
+    }
+
+    // A missing file is the same as an empty one
+    pub fn load() -> Result<Config> {
+        match Config::path() {
+            Some(path) if path.exists() => Config::load_from(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    // --db wins over everything; otherwise the profile from --profile, the
+    // config's default_profile or "default", in that order
+    pub fn resolve(&self, db: Option<&Path>, profile: Option<&str>) -> Result<Profile> {
+        if let Some(db) = db {
+            let name = profile
+                .map(str::to_string)
+                .or_else(|| db.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+            let lock_after = self.lock_after(self.profiles.get(&name));
+            return Ok(Profile { name, db_path: db.to_path_buf(), lock_after });
+        }
+
+        let name = profile
+            .or(self.default_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE);
+        let lock_after = self.lock_after(self.profiles.get(name));
+
+        match self.profiles.get(name) {
+            Some(ProfileConfig { db: Some(db), .. }) => Ok(Profile { name: name.to_string(), db_path: expand(db)?, lock_after }),
+            Some(ProfileConfig { db: None, .. }) => Ok(Profile {
+                name: name.to_string(),
+                db_path: data_path(&format!("{}.db", name))?,
+                lock_after,
+            }),
+            // Refuse typos instead of quietly starting an empty book
+            None if name != DEFAULT_PROFILE => Err(anyhow!(
+                "Unknown profile '{}'. Add a [profiles.{}] section to {}",
+                name,
+                name,
+                Config::path().map(|p| p.display().to_string()).unwrap_or_else(|| "the config file".to_string())
+            )),
+            None => Ok(Profile { name: name.to_string(), db_path: default_db_path()?, lock_after }),
+        }
+    }
+
+    fn lock_after(&self, profile: Option<&ProfileConfig>) -> Option<Duration> {
+        let minutes = profile
+            .and_then(|p| p.lock_after_minutes)
+            .or(self.lock_after_minutes)
+            .unwrap_or(DEFAULT_LOCK_AFTER_MINUTES);
+        (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+    }
+}
+
+pub fn opens() -> usize {
+    OPENS.load(Ordering::Relaxed)
+}
+
+impl Profile {
+    pub fn open(&self) -> Result<DbConnection> {
+        let mut db = self.open_unmigrated()?;
+        db.migrate()?;
+        Ok(db)
+    }
+
+    // For `db migrate`, which upgrades the schema itself. Encrypted
+    // databases ask for the passphrase, unless it is in the environment.
+    pub fn open_unmigrated(&self) -> Result<DbConnection> {
+        OPENS.fetch_add(1, Ordering::Relaxed);
+        self.create_dir()?;
+        if !self.is_encrypted()? {
+            return DbConnection::open(&self.db_path);
+        }
+
+        if let Some(passphrase) = passphrase::from_env() {
+            return DbConnection::unlock(&self.db_path, &passphrase)?
+                .ok_or_else(|| anyhow!("Wrong passphrase in {} for {}", passphrase::PASSPHRASE_ENV, self.db_path.display()));
+        }
+        for attempt in 1..=UNLOCK_ATTEMPTS {
+            let passphrase = passphrase::prompt(&format!("Passphrase for {} ({}): ", self.name, self.db_path.display()))?;
+            if let Some(db) = DbConnection::unlock(&self.db_path, &passphrase)? {
+                return Ok(db);
+            }
+            if attempt < UNLOCK_ATTEMPTS {
+                eprintln!("Wrong passphrase, try again");
+            }
+        }
+        Err(anyhow!("Wrong passphrase for {}", self.db_path.display()))
+    }
+
+    // For the TUI's lock screen, which reads the passphrase itself
+    pub fn unlock(&self, passphrase: &str) -> Result<Option<DbConnection>> {
+        let Some(mut db) = DbConnection::unlock(&self.db_path, passphrase)? else {
+            return Ok(None);
+        };
+        db.migrate()?;
+        Ok(Some(db))
+    }
+
+    pub fn is_encrypted(&self) -> Result<bool> {
+        is_encrypted(&self.db_path)
+    }
+
+    fn create_dir(&self) -> Result<()> {
+        if let Some(parent) = self.db_path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        Ok(())
+    }
+
+    // Databases used to live in whatever directory the binary was started from
+    pub fn is_legacy(&self) -> bool {
+        self.db_path == Path::new(DEFAULT_DB_PATH)
+    }
+}
+
+pub fn default_db_location() -> Result<PathBuf> {
+    data_path(DEFAULT_DB_PATH)
+}
+
+fn data_path(file: &str) -> Result<PathBuf> {
+    dirs::data_dir()
+        .map(|dir| dir.join(APP_DIR).join(file))
+        .ok_or_else(|| anyhow!("Could not determine the data directory; pass --db"))
+}
+
+// Keeps using ./finance.db for setups from before profiles existed. Once the
+// data directory has a database too it is unclear which one holds the books
+fn default_db_path() -> Result<PathBuf> {
+    let path = default_db_location()?;
+    if !Path::new(DEFAULT_DB_PATH).exists() {
+        return Ok(path);
+    }
+    if path.exists() {
+        return Err(anyhow!(
+            "Found {} in the current directory and {}. Move or delete one of them, or pick one with --db",
+            DEFAULT_DB_PATH,
+            path.display()
+        ));
+    }
+    Ok(PathBuf::from(DEFAULT_DB_PATH))
+}
+
+// Profile paths: ~ is the home directory, relative paths are in the data directory
+pub fn expand(path: &Path) -> Result<PathBuf> {
+    if let Ok(rest) = path.strip_prefix("~") {
+        let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine the home directory"))?;
+        return Ok(home.join(rest));
+    }
+    if path.is_relative() {
+        return data_path("").map(|dir| dir.join(path));
+    }
+    Ok(path.to_path_buf())
+}
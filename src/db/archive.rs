@@ -0,0 +1,487 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Transaction as SqlTransaction};
+use rust_decimal::Decimal;
+use crate::archive::{
+    ArchiveData, ArchivedAlias, ArchivedAssignment, ArchivedBudget, ArchivedFilter, ArchivedFlag,
+    ArchivedLedgerAccount, ArchivedRule, ArchivedSetting, ArchivedSplit, ArchivedTransaction,
+};
+use crate::models::category::SourceKind;
+
+pub struct ArchiveDb<'a> {
+    conn: &'a mut Connection,
+}
+
+// Which side wins when the archive and the database disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prefer {
+    Local,
+    Archive,
+}
+
+// Tables that map a unique name to a single value
+struct KeyedTable {
+    kind: &'static str,
+    name: &'static str,
+    key: &'static str,
+    value: &'static str,
+}
+
+const MERCHANT_ALIASES: KeyedTable = KeyedTable { kind: "merchant alias", name: "merchant_aliases", key: "pattern", value: "canonical" };
+const LEDGER_ACCOUNTS: KeyedTable = KeyedTable { kind: "ledger account", name: "ledger_accounts", key: "name", value: "account" };
+const SAVED_FILTERS: KeyedTable = KeyedTable { kind: "saved filter", name: "saved_filters", key: "name", value: "query" };
+const SETTINGS: KeyedTable = KeyedTable { kind: "setting", name: "settings", key: "key", value: "value" };
+
+#[derive(Debug)]
+pub struct Conflict {
+    pub kind: &'static str,
+    pub key: String,
+    pub local: String,
+    pub archived: String,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    pub added: BTreeMap<&'static str, usize>,
+    pub conflicts: Vec<Conflict>,
+    pub journal_cleared: usize,
+}
+
+impl RestoreReport {
+    fn add(&mut self, kind: &'static str, count: usize) {
+        *self.added.entry(kind).or_default() += count;
+    }
+
+    fn conflict(&mut self, kind: &'static str, key: String, local: &str, archived: &str) {
+        self.conflicts.push(Conflict { kind, key, local: local.to_string(), archived: archived.to_string() });
+    }
+}
+
+impl<'a> ArchiveDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn export(&mut self) -> Result<ArchiveData> {
+        let conn = &*self.conn;
+
+        let categories = collect(conn, "SELECT name FROM categories ORDER BY id", |row| row.get(0))?;
+        let rules = collect(
+            conn,
+            "SELECT c.name, cr.pattern, cr.priority
+             FROM category_rules cr
+             JOIN categories c ON c.id = cr.category_id
+             ORDER BY cr.id",
+            |row| Ok(ArchivedRule { category: row.get(0)?, pattern: row.get(1)?, priority: row.get(2)? }),
+        )?;
+        let budgets = collect(
+            conn,
+            "SELECT c.name, b.amount FROM budgets b JOIN categories c ON c.id = b.category_id ORDER BY c.name",
+            |row| Ok(ArchivedBudget { category: row.get(0)?, amount: row.get(1)? }),
+        )?;
+
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        for (id, tag) in collect(conn, "SELECT transaction_id, tag FROM transaction_tags ORDER BY id", |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })? {
+            tags.entry(id).or_default().push(tag);
+        }
+        let excluded: HashSet<i64> =
+            collect(conn, "SELECT transaction_id FROM excluded_transactions", |row| row.get(0))?.into_iter().collect();
+
+        // Later rows win, as they do in CategoryDb::get_assigned_categories
+        let mut assignments: HashMap<i64, ArchivedAssignment> = HashMap::new();
+        for (id, assignment) in collect(
+            conn,
+            "SELECT tc.transaction_id, c.name, tc.source, tc.confidence, tc.assigned_at
+             FROM transaction_categories tc
+             JOIN categories c ON c.id = tc.category_id
+             ORDER BY tc.id",
+            |row| {
+                let source: String = row.get(2)?;
+                Ok((row.get::<_, i64>(0)?, ArchivedAssignment {
+                    category: row.get(1)?,
+                    source: SourceKind::parse(&source).unwrap_or_default(),
+                    confidence: row.get(3)?,
+                    assigned_at: row.get(4)?,
+                }))
+            },
+        )? {
+            assignments.insert(id, assignment);
+        }
+
+        let mut flags: HashMap<i64, Vec<ArchivedFlag>> = HashMap::new();
+        for (id, flag) in collect(
+            conn,
+            "SELECT transaction_id, kind, reason, status FROM transaction_flags ORDER BY id",
+            |row| Ok((row.get::<_, i64>(0)?, ArchivedFlag { kind: row.get(1)?, reason: row.get(2)?, status: row.get(3)? })),
+        )? {
+            flags.entry(id).or_default().push(flag);
+        }
+
+        let mut splits: HashMap<i64, Vec<ArchivedSplit>> = HashMap::new();
+        for (id, split) in collect(
+            conn,
+            "SELECT s.transaction_id, c.name, s.amount
+             FROM transaction_splits s
+             JOIN categories c ON c.id = s.category_id
+             ORDER BY s.id",
+            |row| Ok((row.get::<_, i64>(0)?, ArchivedSplit { category: row.get(1)?, amount: row.get(2)? })),
+        )? {
+            splits.entry(id).or_default().push(split);
+        }
+
+        let transactions = collect(
+            conn,
+            "SELECT id, date, amount, merchant, description, account, balance, imported_at
+             FROM transactions
+             ORDER BY id",
+            |row| {
+                let id: i64 = row.get(0)?;
+                Ok(ArchivedTransaction {
+                    date: row.get(1)?,
+                    amount: row.get(2)?,
+                    merchant: row.get(3)?,
+                    description: row.get(4)?,
+                    account: row.get(5)?,
+                    balance: row.get(6)?,
+                    imported_at: row.get(7)?,
+                    tags: tags.remove(&id).unwrap_or_default(),
+                    excluded: excluded.contains(&id),
+                    category: assignments.remove(&id),
+                    flags: flags.remove(&id).unwrap_or_default(),
+                    splits: splits.remove(&id).unwrap_or_default(),
+                })
+            },
+        )?;
+
+        Ok(ArchiveData {
+            categories,
+            rules,
+            budgets,
+            transactions,
+            merchant_aliases: collect(conn, "SELECT pattern, canonical FROM merchant_aliases ORDER BY pattern", |row| {
+                Ok(ArchivedAlias { pattern: row.get(0)?, canonical: row.get(1)? })
+            })?,
+            ledger_accounts: collect(conn, "SELECT name, account FROM ledger_accounts ORDER BY name", |row| {
+                Ok(ArchivedLedgerAccount { name: row.get(0)?, account: row.get(1)? })
+            })?,
+            saved_filters: collect(conn, "SELECT name, query FROM saved_filters ORDER BY name", |row| {
+                Ok(ArchivedFilter { name: row.get(0)?, query: row.get(1)? })
+            })?,
+            settings: collect(conn, "SELECT key, value FROM settings ORDER BY key", |row| {
+                Ok(ArchivedSetting { key: row.get(0)?, value: row.get(1)? })
+            })?,
+        })
+    }
+
+    // Adds everything the database doesn't have yet and reports every
+    // value that differs, keeping the side given by `prefer`. Rows only in
+    // the database are left alone. A dry run does the same work and rolls
+    // it back.
+    //
+    // A restore isn't journaled. Once it has changed anything the entries
+    // before it may no longer apply, so the journal is cleared.
+    pub fn restore(&mut self, data: &ArchiveData, prefer: Prefer, dry_run: bool) -> Result<RestoreReport> {
+        let tx = self.conn.transaction()?;
+        let mut report = RestoreReport::default();
+        let take_archive = prefer == Prefer::Archive;
+
+        for name in &data.categories {
+            category_id(&tx, name, &mut report)?;
+        }
+
+        for rule in &data.rules {
+            let category = category_id(&tx, &rule.category, &mut report)?;
+            let existing: Option<(i64, u8)> = tx
+                .query_row(
+                    "SELECT id, priority FROM category_rules WHERE category_id = ? AND pattern = ? ORDER BY id LIMIT 1",
+                    params![category, rule.pattern],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            match existing {
+                None => {
+                    tx.execute(
+                        "INSERT INTO category_rules (category_id, pattern, priority) VALUES (?, ?, ?)",
+                        params![category, rule.pattern, rule.priority],
+                    )?;
+                    report.add("rules", 1);
+                }
+                Some((id, priority)) if priority != rule.priority => {
+                    report.conflict("rule priority", format!("{} → {}", rule.pattern, rule.category), &priority.to_string(), &rule.priority.to_string());
+                    if take_archive {
+                        tx.execute("UPDATE category_rules SET priority = ? WHERE id = ?", params![rule.priority, id])?;
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        for budget in &data.budgets {
+            let category = category_id(&tx, &budget.category, &mut report)?;
+            let existing: Option<String> = tx
+                .query_row("SELECT amount FROM budgets WHERE category_id = ?", params![category], |row| row.get(0))
+                .optional()?;
+            match existing {
+                None => {
+                    tx.execute("INSERT INTO budgets (category_id, amount) VALUES (?, ?)", params![category, budget.amount])?;
+                    report.add("budgets", 1);
+                }
+                Some(amount) if !same_amount(&amount, &budget.amount) => {
+                    report.conflict("budget", budget.category.clone(), &amount, &budget.amount);
+                    if take_archive {
+                        tx.execute("UPDATE budgets SET amount = ? WHERE category_id = ?", params![budget.amount, category])?;
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        for transaction in &data.transactions {
+            restore_transaction(&tx, transaction, take_archive, &mut report)?;
+        }
+
+        let keyed = [
+            (&MERCHANT_ALIASES, data.merchant_aliases.iter().map(|a| (&a.pattern, &a.canonical)).collect::<Vec<_>>()),
+            (&LEDGER_ACCOUNTS, data.ledger_accounts.iter().map(|a| (&a.name, &a.account)).collect()),
+            (&SAVED_FILTERS, data.saved_filters.iter().map(|f| (&f.name, &f.query)).collect()),
+            (&SETTINGS, data.settings.iter().map(|s| (&s.key, &s.value)).collect()),
+        ];
+        for (table, rows) in keyed {
+            for (key, value) in rows {
+                restore_keyed(&tx, table, key, value, take_archive, &mut report)?;
+            }
+        }
+
+        let changed = report.added.values().any(|count| *count > 0) || (take_archive && !report.conflicts.is_empty());
+        if changed {
+            report.journal_cleared = tx.execute("DELETE FROM journal", [])?;
+        }
+
+        if !dry_run {
+            tx.commit()?;
+        }
+        Ok(report)
+    }
+}
+
+fn collect<T>(conn: &Connection, sql: &str, f: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>) -> Result<Vec<T>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], f)?.collect::<rusqlite::Result<Vec<T>>>()?;
+    Ok(rows)
+}
+
+fn category_id(tx: &SqlTransaction, name: &str, report: &mut RestoreReport) -> Result<i64> {
+    let existing: Option<i64> = tx
+        .query_row("SELECT id FROM categories WHERE name = ?", params![name], |row| row.get(0))
+        .optional()?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    tx.execute("INSERT INTO categories (name) VALUES (?)", params![name])?;
+    report.add("categories", 1);
+    Ok(tx.last_insert_rowid())
+}
+
+// Budgets are typed in by hand, so 300 and 300.00 are the same budget
+fn same_amount(a: &str, b: &str) -> bool {
+    match (Decimal::from_str(a), Decimal::from_str(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn restore_transaction(tx: &SqlTransaction, transaction: &ArchivedTransaction, take_archive: bool, report: &mut RestoreReport) -> Result<()> {
+    let inserted = tx.execute(
+        "INSERT OR IGNORE INTO transactions (date, amount, merchant, description, account, balance, imported_at)
+         VALUES (?, ?, ?, ?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP))",
+        params![
+            transaction.date,
+            transaction.amount,
+            transaction.merchant,
+            transaction.description,
+            transaction.account,
+            transaction.balance,
+            transaction.imported_at,
+        ],
+    )? > 0;
+    let id: i64 = if inserted {
+        report.add("transactions", 1);
+        tx.last_insert_rowid()
+    } else {
+        tx.query_row(
+            "SELECT id FROM transactions
+             WHERE date = ? AND amount = ? AND merchant = ? AND description = ? AND account = ? AND balance = ?",
+            params![
+                transaction.date,
+                transaction.amount,
+                transaction.merchant,
+                transaction.description,
+                transaction.account,
+                transaction.balance,
+            ],
+            |row| row.get(0),
+        )?
+    };
+    let key = format!(
+        "{} {} {}",
+        transaction.date.get(..10).unwrap_or(&transaction.date),
+        transaction.merchant,
+        transaction.amount
+    );
+
+    for tag in &transaction.tags {
+        let added = tx.execute(
+            "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag) VALUES (?, ?)",
+            params![id, tag],
+        )?;
+        report.add("tags", added);
+    }
+
+    let excluded: bool = tx.query_row(
+        "SELECT COUNT(*) > 0 FROM excluded_transactions WHERE transaction_id = ?",
+        params![id],
+        |row| row.get(0),
+    )?;
+    if excluded != transaction.excluded {
+        if !inserted {
+            let state = |excluded: bool| if excluded { "excluded" } else { "included" };
+            report.conflict("exclusion", key.clone(), state(excluded), state(transaction.excluded));
+        }
+        if inserted || take_archive {
+            if transaction.excluded {
+                tx.execute("INSERT OR IGNORE INTO excluded_transactions (transaction_id) VALUES (?)", params![id])?;
+            } else {
+                tx.execute("DELETE FROM excluded_transactions WHERE transaction_id = ?", params![id])?;
+            }
+        }
+    }
+
+    if let Some(assignment) = &transaction.category {
+        let current: Option<String> = tx
+            .query_row(
+                "SELECT c.name FROM transaction_categories tc
+                 JOIN categories c ON c.id = tc.category_id
+                 WHERE tc.transaction_id = ?
+                 ORDER BY tc.id DESC LIMIT 1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let apply = match &current {
+            None => true,
+            Some(current) if *current != assignment.category => {
+                report.conflict("category", key.clone(), current, &assignment.category);
+                take_archive
+            }
+            Some(_) => false,
+        };
+        if apply {
+            let category = category_id(tx, &assignment.category, report)?;
+            tx.execute(
+                "INSERT OR REPLACE INTO transaction_categories (transaction_id, category_id, source, confidence, assigned_at)
+                 VALUES (?, ?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP))",
+                params![id, category, assignment.source.as_str(), assignment.confidence, assignment.assigned_at],
+            )?;
+            report.add("category assignments", 1);
+        }
+    }
+
+    for flag in &transaction.flags {
+        let status: Option<String> = tx
+            .query_row(
+                "SELECT status FROM transaction_flags WHERE transaction_id = ? AND kind = ?",
+                params![id, flag.kind],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match status {
+            None => {
+                tx.execute(
+                    "INSERT INTO transaction_flags (transaction_id, kind, reason, status) VALUES (?, ?, ?, ?)",
+                    params![id, flag.kind, flag.reason, flag.status],
+                )?;
+                report.add("flags", 1);
+            }
+            Some(status) if status != flag.status => {
+                report.conflict("flag", format!("{} ({})", key, flag.kind), &status, &flag.status);
+                if take_archive {
+                    tx.execute(
+                        "UPDATE transaction_flags SET status = ? WHERE transaction_id = ? AND kind = ?",
+                        params![flag.status, id, flag.kind],
+                    )?;
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    if !transaction.splits.is_empty() {
+        let current = collect(
+            tx,
+            &format!(
+                "SELECT c.name, s.amount FROM transaction_splits s
+                 JOIN categories c ON c.id = s.category_id
+                 WHERE s.transaction_id = {} ORDER BY s.id",
+                id
+            ),
+            |row| Ok(ArchivedSplit { category: row.get(0)?, amount: row.get(1)? }),
+        )?;
+        let apply = if current.is_empty() {
+            true
+        } else if current != transaction.splits {
+            report.conflict("split", key.clone(), &describe_splits(&current), &describe_splits(&transaction.splits));
+            take_archive
+        } else {
+            false
+        };
+        if apply {
+            tx.execute("DELETE FROM transaction_splits WHERE transaction_id = ?", params![id])?;
+            for split in &transaction.splits {
+                let category = category_id(tx, &split.category, report)?;
+                tx.execute(
+                    "INSERT INTO transaction_splits (transaction_id, category_id, amount) VALUES (?, ?, ?)",
+                    params![id, category, split.amount],
+                )?;
+            }
+            report.add("splits", 1);
+        }
+    }
+    Ok(())
+}
+
+fn describe_splits(splits: &[ArchivedSplit]) -> String {
+    splits.iter().map(|split| format!("{} {}", split.category, split.amount)).collect::<Vec<_>>().join(", ")
+}
+
+fn restore_keyed(tx: &SqlTransaction, table: &KeyedTable, key: &str, value: &str, take_archive: bool, report: &mut RestoreReport) -> Result<()> {
+    let existing: Option<String> = tx
+        .query_row(
+            &format!("SELECT {} FROM {} WHERE {} = ?", table.value, table.name, table.key),
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match existing {
+        None => {
+            tx.execute(
+                &format!("INSERT INTO {} ({}, {}) VALUES (?, ?)", table.name, table.key, table.value),
+                params![key, value],
+            )?;
+            report.add(table.name, 1);
+        }
+        Some(existing) if existing != value => {
+            report.conflict(table.kind, key.to_string(), &existing, value);
+            if take_archive {
+                tx.execute(
+                    &format!("UPDATE {} SET {} = ? WHERE {} = ?", table.name, table.value, table.key),
+                    params![value, key],
+                )?;
+            }
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::models::category::SourceKind;
+use super::journal::{delete_stored, load_assignment, load_stored, record, set_excluded, Change, StoredAssignment};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchAction {
+    SetCategory(String),
+    AddTags(Vec<String>),
+    RemoveTags(Vec<String>),
+    SetExcluded(bool),
+    Delete,
+}
+
+impl BatchAction {
+    pub fn describe(&self, count: usize) -> String {
+        let noun = if count == 1 { "transaction" } else { "transactions" };
+        match self {
+            BatchAction::SetCategory(category) => format!("Moved {} {} to {}", count, noun, category),
+            BatchAction::AddTags(tags) => format!("Tagged {} {} with #{}", count, noun, tags.join(" #")),
+            BatchAction::RemoveTags(tags) => format!("Removed #{} from {} {}", tags.join(" #"), count, noun),
+            BatchAction::SetExcluded(true) => format!("Excluded {} {} from reports", count, noun),
+            BatchAction::SetExcluded(false) => format!("Included {} {} in reports", count, noun),
+            BatchAction::Delete => format!("Deleted {} {}", count, noun),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub description: String,
+    pub changes: Vec<Change>,
+}
+
+pub struct BatchDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> BatchDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    // Applies the action to every transaction in one database transaction
+    pub fn apply(&mut self, transaction_ids: &[u64], action: &BatchAction) -> Result<Batch> {
+        let tx = self.conn.transaction()?;
+        let mut changes = Vec::new();
+
+        let category_id: Option<i64> = match action {
+            BatchAction::SetCategory(category) => Some(
+                tx.query_row("SELECT id FROM categories WHERE name = ?", params![category], |row| row.get(0))
+                    .optional()?
+                    .ok_or_else(|| anyhow!("Unknown category '{}'", category))?,
+            ),
+            _ => None,
+        };
+
+        for &id in transaction_ids {
+            let id = id as i64;
+            match action {
+                BatchAction::SetCategory(category) => {
+                    let category_id = category_id.unwrap_or_default();
+                    let previous = tx.query_row(
+                        "SELECT c.name FROM transaction_categories tc
+                         JOIN categories c ON c.id = tc.category_id
+                         WHERE tc.transaction_id = ?
+                         ORDER BY tc.id DESC LIMIT 1",
+                        params![id],
+                        |row| row.get(0),
+                    ).optional()?;
+                    // Assigning a category the transaction had before moves that row to the top
+                    let replaced = load_assignment(&tx, id, category_id)?;
+                    if let Some(replaced) = &replaced {
+                        tx.execute("DELETE FROM transaction_categories WHERE id = ?", params![replaced.id])?;
+                    }
+                    tx.execute(
+                        "INSERT INTO transaction_categories (transaction_id, category_id, source) VALUES (?, ?, ?)",
+                        params![id, category_id, SourceKind::Manual.as_str()],
+                    )?;
+                    let assignment_id = tx.last_insert_rowid();
+                    let assigned_at = tx.query_row(
+                        "SELECT assigned_at FROM transaction_categories WHERE id = ?",
+                        params![assignment_id],
+                        |row| row.get(0),
+                    )?;
+                    changes.push(Change::CategoryAssigned {
+                        transaction_id: id,
+                        assignment: StoredAssignment {
+                            id: assignment_id,
+                            category_id,
+                            assigned_at,
+                            source: SourceKind::Manual,
+                            confidence: None,
+                        },
+                        category: category.clone(),
+                        previous,
+                        replaced,
+                    });
+                }
+                BatchAction::AddTags(tags) => {
+                    for tag in tags {
+                        let added = tx.execute(
+                            "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag) VALUES (?, ?)",
+                            params![id, tag],
+                        )?;
+                        if added > 0 {
+                            changes.push(Change::TagAdded { transaction_id: id, tag: tag.clone() });
+                        }
+                    }
+                }
+                BatchAction::RemoveTags(tags) => {
+                    for tag in tags {
+                        let removed = tx.execute(
+                            "DELETE FROM transaction_tags WHERE transaction_id = ? AND tag = ?",
+                            params![id, tag],
+                        )?;
+                        if removed > 0 {
+                            changes.push(Change::TagRemoved { transaction_id: id, tag: tag.clone() });
+                        }
+                    }
+                }
+                BatchAction::SetExcluded(excluded) => {
+                    if set_excluded(&tx, id, *excluded)? {
+                        changes.push(Change::ExclusionChanged { transaction_id: id, excluded: *excluded });
+                    }
+                }
+                BatchAction::Delete => {
+                    if let Some(stored) = load_stored(&tx, id)? {
+                        delete_stored(&tx, id)?;
+                        changes.push(Change::Deleted(Box::new(stored)));
+                    }
+                }
+            }
+        }
+
+        let description = action.describe(transaction_ids.len());
+        record(&tx, &description, &changes)?;
+        tx.commit()?;
+        Ok(Batch { description, changes })
+    }
+}
+
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use rust_decimal::Decimal;
+use super::journal::{load_budget, record, write_budget, Change};
+
+pub struct BudgetDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> BudgetDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    // Monthly spending limit for a category, as a positive amount
+    pub fn set_budget(&mut self, category: &str, amount: Decimal) -> Result<()> {
+        let amount = amount.abs().to_string();
+        self.write(category, Some(&amount), &format!("Set the {} budget to {}", category, amount))?;
+        Ok(())
+    }
+
+    pub fn remove_budget(&mut self, category: &str) -> Result<bool> {
+        self.write(category, None, &format!("Removed the {} budget", category))
+    }
+
+    // Journaled like other edits; returns whether anything changed
+    fn write(&mut self, category: &str, amount: Option<&str>, description: &str) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let category_id: i64 = tx
+            .query_row("SELECT id FROM categories WHERE name = ?", params![category], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| anyhow!("Unknown category '{}'", category))?;
+        let before = load_budget(&tx, category_id)?;
+        if before.as_deref() == amount {
+            return Ok(false);
+        }
+        write_budget(&tx, category_id, amount)?;
+        let change = Change::BudgetChanged {
+            category_id,
+            category: category.to_string(),
+            before,
+            after: amount.map(str::to_string),
+        };
+        record(&tx, description, &[change])?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn get_budgets(&mut self) -> Result<HashMap<String, Decimal>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.name, b.amount
+             FROM budgets b
+             JOIN categories c ON c.id = b.category_id"
+        )?;
+
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut budgets = HashMap::new();
+        for row in rows {
+            let (category, amount) = row?;
+            if let Ok(amount) = Decimal::from_str(&amount) {
+                budgets.insert(category, amount);
+            }
+        }
+
+        Ok(budgets)
+    }
+}
@@ -1,11 +1,25 @@
-use anyhow::Result;
-use rusqlite::{params, Connection};
-use crate::models::category::{Category, Rule};
+use std::collections::HashMap;
+use anyhow::{anyhow, bail, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction as SqlTransaction};
+use crate::models::category::{Category, CategorySource, Rule, SourceKind};
+use super::journal::{
+    delete_rule, load_budget, load_category_assignments, load_ledger_account, load_rule, record, write_budget,
+    write_ledger_account, write_rule, Change, StoredAssignment, StoredRule,
+};
 
 pub struct CategoryDb<'a> {
     conn: &'a mut Connection,
 }
 
+// What a merge or a reassigning delete carried over to the other category
+#[derive(Debug, Default, PartialEq)]
+pub struct MovedRows {
+    pub rules: usize,
+    pub assignments: usize,
+    pub budget: bool,
+    pub ledger_account: bool,
+}
+
 impl<'a> CategoryDb<'a> {
     pub fn new(conn: &'a mut Connection) -> Self {
         Self { conn }
@@ -34,7 +48,7 @@ impl<'a> CategoryDb<'a> {
 
     pub fn get_all_categories(&mut self) -> Result<Vec<Category>> {
         let mut stmt = self.conn.prepare(
-            "SELECT c.id, c.name, cr.pattern, cr.priority 
+            "SELECT c.id, c.name, cr.pattern, cr.priority, cr.id
              FROM categories c 
              LEFT JOIN category_rules cr ON c.id = cr.category_id"
         )?;
@@ -44,22 +58,22 @@ impl<'a> CategoryDb<'a> {
             let name: String = row.get(1)?;
             let pattern: Option<String> = row.get(2).ok();
             let priority: Option<u8> = row.get(3).ok();
+            let rule_id: Option<i64> = row.get(4)?;
 
-            Ok((id, name, pattern, priority))
+            Ok((id, name, pattern, priority, rule_id))
         })?;
 
         let mut categories = Vec::new();
         let mut current_category: Option<(i64, Category)> = None;
 
         for row in rows {
-            let (id, name, pattern, priority) = row?;
+            let (id, name, pattern, priority, rule_id) = row?;
 
-            if let Some((current_id, _)) = current_category.as_ref() {
-                if *current_id != id {
-                    if let Some((_, category)) = current_category.take() {
-                        categories.push(category);
-                    }
-                }
+            if let Some((current_id, _)) = current_category.as_ref()
+                && *current_id != id
+                && let Some((_, category)) = current_category.take()
+            {
+                categories.push(category);
             }
 
             if current_category.is_none() {
@@ -69,14 +83,15 @@ impl<'a> CategoryDb<'a> {
                 }));
             }
 
-            if let (Some(pattern), Some(priority)) = (pattern, priority) {
-                if let Some((_, category)) = current_category.as_mut() {
-                    category.rules.push(Rule {
-                        pattern,
-                        category: category.name.clone(),
-                        priority,
-                    });
-                }
+            if let (Some(pattern), Some(priority)) = (pattern, priority)
+                && let Some((_, category)) = current_category.as_mut()
+            {
+                category.rules.push(Rule {
+                    id: rule_id.unwrap_or_default(),
+                    pattern,
+                    category: category.name.clone(),
+                    priority,
+                });
             }
         }
 
@@ -87,13 +102,8 @@ impl<'a> CategoryDb<'a> {
         Ok(categories)
     }
 
-    pub fn assign_category(&mut self, transaction_id: i64, category_id: i64) -> Result<()> {
-        self.conn.execute(
-            "INSERT OR REPLACE INTO transaction_categories (transaction_id, category_id)
-             VALUES (?, ?)",
-            params![transaction_id, category_id],
-        )?;
-        Ok(())
+    pub fn assign_category(&mut self, transaction_id: i64, category_id: i64, source: SourceKind, confidence: Option<f64>) -> Result<()> {
+        assign(self.conn, transaction_id, category_id, source, confidence)
     }
 
     pub fn initialize_default_categories(&mut self) -> Result<()> {
@@ -106,7 +116,7 @@ impl<'a> CategoryDb<'a> {
 
     pub fn get_category_by_name(&mut self, name: &str) -> Result<Option<Category>> {
         let mut stmt = self.conn.prepare(
-            "SELECT c.id, c.name, cr.pattern, cr.priority 
+            "SELECT c.id, c.name, cr.pattern, cr.priority, cr.id
              FROM categories c 
              LEFT JOIN category_rules cr ON c.id = cr.category_id
              WHERE c.name = ?"
@@ -117,14 +127,15 @@ impl<'a> CategoryDb<'a> {
             let name: String = row.get(1)?;
             let pattern: Option<String> = row.get(2).ok();
             let priority: Option<u8> = row.get(3).ok();
+            let rule_id: Option<i64> = row.get(4)?;
 
-            Ok((id, name, pattern, priority))
+            Ok((id, name, pattern, priority, rule_id))
         })?;
 
         let mut category: Option<Category> = None;
 
         for row in rows {
-            let (_, name, pattern, priority) = row?;
+            let (_, name, pattern, priority, rule_id) = row?;
 
             if category.is_none() {
                 category = Some(Category {
@@ -133,17 +144,396 @@ impl<'a> CategoryDb<'a> {
                 });
             }
 
-            if let (Some(pattern), Some(priority)) = (pattern, priority) {
-                if let Some(category) = category.as_mut() {
-                    category.rules.push(Rule {
-                        pattern,
-                        category: category.name.clone(),
-                        priority,
-                    });
-                }
+            if let (Some(pattern), Some(priority)) = (pattern, priority)
+                && let Some(category) = category.as_mut()
+            {
+                category.rules.push(Rule {
+                    id: rule_id.unwrap_or_default(),
+                    pattern,
+                    category: category.name.clone(),
+                    priority,
+                });
             }
         }
 
         Ok(category)
     }
-}
\ No newline at end of file
+
+    pub fn get_category_id(&mut self, name: &str) -> Result<Option<i64>> {
+        let id = self.conn
+            .query_row(
+                "SELECT id FROM categories WHERE name = ?",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(id)
+    }
+
+    pub fn get_rules(&mut self) -> Result<Vec<(i64, Rule)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT cr.id, c.name, cr.pattern, cr.priority
+             FROM category_rules cr
+             JOIN categories c ON c.id = cr.category_id
+             ORDER BY cr.priority DESC, c.name, cr.id"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get(0)?, Rule {
+                id: row.get(0)?,
+                category: row.get(1)?,
+                pattern: row.get(2)?,
+                priority: row.get(3)?,
+            }))
+        })?;
+
+        let mut rules = Vec::new();
+        for row in rows {
+            rules.push(row?);
+        }
+        Ok(rules)
+    }
+
+    // Rule changes are journaled so they can be undone like other edits
+    pub fn add_rule(&mut self, rule: &Rule) -> Result<i64> {
+        let category_id = self.get_category_id(&rule.category)?
+            .ok_or_else(|| anyhow!("Unknown category '{}'", rule.category))?;
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO category_rules (category_id, pattern, priority) VALUES (?, ?, ?)",
+            params![category_id, rule.pattern, rule.priority],
+        )?;
+        let id = tx.last_insert_rowid();
+        if let Some(stored) = load_rule(&tx, id)? {
+            record(&tx, &format!("Added rule '{}' for {}", rule.pattern, rule.category), &[Change::RuleAdded(stored)])?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn remove_rule(&mut self, rule_id: i64) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let Some(stored) = load_rule(&tx, rule_id)? else {
+            return Ok(false);
+        };
+        delete_rule(&tx, rule_id)?;
+        record(&tx, &format!("Removed rule '{}' for {}", stored.pattern, stored.category), &[Change::RuleRemoved(stored)])?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn update_rule(&mut self, rule: &Rule) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let Some(before) = load_rule(&tx, rule.id)? else {
+            return Ok(false);
+        };
+        let category_id = category_id(&tx, &rule.category)?
+            .ok_or_else(|| anyhow!("Unknown category '{}'", rule.category))?;
+        let after = StoredRule {
+            id: rule.id,
+            category_id,
+            category: rule.category.clone(),
+            pattern: rule.pattern.clone(),
+            priority: rule.priority,
+        };
+        if after != before {
+            write_rule(&tx, &after)?;
+            record(&tx, &format!("Updated rule '{}' for {}", rule.pattern, rule.category), &[Change::RuleUpdated { before, after }])?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    // Makes the stored category match `category`: renames it and brings its
+    // rules in line, keeping the ids of rules whose pattern is unchanged
+    pub fn update_category(&mut self, name: &str, category: &Category) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let Some(id) = category_id(&tx, name)? else {
+            return Ok(false);
+        };
+        let mut changes = rename(&tx, id, name, &category.name)?;
+        changes.extend(sync_rules(&tx, id, &category.rules)?);
+        record(&tx, &format!("Updated category {}", category.name), &changes)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn upsert_category(&mut self, category: &Category) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+        let mut changes = Vec::new();
+        let id = match category_id(&tx, &category.name)? {
+            Some(id) => id,
+            None => {
+                tx.execute("INSERT INTO categories (name) VALUES (?)", params![category.name])?;
+                let id = tx.last_insert_rowid();
+                changes.push(Change::CategoryAdded { id, name: category.name.clone() });
+                id
+            }
+        };
+        changes.extend(sync_rules(&tx, id, &category.rules)?);
+        record(&tx, &format!("Saved category {}", category.name), &changes)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    // Rules, assignments and budgets refer to the category by id and follow
+    // it; the ledger account mapping is keyed by name
+    pub fn rename_category(&mut self, name: &str, new_name: &str) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let Some(id) = category_id(&tx, name)? else {
+            return Ok(false);
+        };
+        let changes = rename(&tx, id, name, new_name)?;
+        record(&tx, &format!("Renamed category {} to {}", name, new_name), &changes)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    // Rules and the budget go with the category. Its transactions become
+    // uncategorized unless they are handed to `reassign_to`
+    pub fn delete_category(&mut self, name: &str, reassign_to: Option<&str>) -> Result<Option<MovedRows>> {
+        let tx = self.conn.transaction()?;
+        let Some(id) = category_id(&tx, name)? else {
+            return Ok(None);
+        };
+        let mut moved = MovedRows::default();
+        let mut changes = Vec::new();
+        if let Some(target) = reassign_to {
+            let target_id = category_id(&tx, target)?.ok_or_else(|| anyhow!("Unknown category '{}'", target))?;
+            if target_id == id {
+                bail!("Cannot reassign '{}' to itself", name);
+            }
+            moved.assignments = move_assignments(&tx, (id, name), (target_id, target), &mut changes)?;
+        }
+        remove_category(&tx, id, name, &mut changes)?;
+        let description = match reassign_to {
+            Some(target) => format!("Deleted category {} (moved to {})", name, target),
+            None => format!("Deleted category {}", name),
+        };
+        record(&tx, &description, &changes)?;
+        tx.commit()?;
+        Ok(Some(moved))
+    }
+
+    // Folds `source` into `target`. Where both have something the target's
+    // wins: rules with the same pattern, the budget and the ledger account
+    pub fn merge_categories(&mut self, source: &str, target: &str) -> Result<MovedRows> {
+        let tx = self.conn.transaction()?;
+        let source_id = category_id(&tx, source)?.ok_or_else(|| anyhow!("Unknown category '{}'", source))?;
+        let target_id = category_id(&tx, target)?.ok_or_else(|| anyhow!("Unknown category '{}'", target))?;
+        if source_id == target_id {
+            bail!("Cannot merge '{}' into itself", source);
+        }
+
+        let mut changes = Vec::new();
+        let target_patterns: Vec<String> = category_rules(&tx, target_id)?.into_iter().map(|rule| rule.pattern).collect();
+        let mut rules = 0;
+        for rule in category_rules(&tx, source_id)? {
+            if target_patterns.contains(&rule.pattern) {
+                delete_rule(&tx, rule.id)?;
+                changes.push(Change::RuleRemoved(rule));
+            } else {
+                let after = StoredRule { category_id: target_id, category: target.to_string(), ..rule.clone() };
+                write_rule(&tx, &after)?;
+                changes.push(Change::RuleUpdated { before: rule, after });
+                rules += 1;
+            }
+        }
+        let assignments = move_assignments(&tx, (source_id, source), (target_id, target), &mut changes)?;
+
+        let budget = match (load_budget(&tx, source_id)?, load_budget(&tx, target_id)?) {
+            (Some(amount), None) => {
+                write_budget(&tx, target_id, Some(&amount))?;
+                changes.push(Change::BudgetChanged {
+                    category_id: target_id,
+                    category: target.to_string(),
+                    before: None,
+                    after: Some(amount),
+                });
+                true
+            }
+            _ => false,
+        };
+        let ledger_account = match (load_ledger_account(&tx, source)?, load_ledger_account(&tx, target)?) {
+            (Some(account), None) => {
+                write_ledger_account(&tx, target, Some(&account))?;
+                changes.push(Change::LedgerAccountChanged { name: target.to_string(), before: None, after: Some(account) });
+                true
+            }
+            _ => false,
+        };
+
+        remove_category(&tx, source_id, source, &mut changes)?;
+        record(&tx, &format!("Merged category {} into {}", source, target), &changes)?;
+        tx.commit()?;
+        Ok(MovedRows { rules, assignments, budget, ledger_account })
+    }
+
+    // Stored assignments with where they came from; the most recent one wins
+    pub fn get_assigned_categories(&mut self) -> Result<HashMap<u64, (String, CategorySource)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tc.transaction_id, c.name, tc.source, tc.confidence, tc.assigned_at
+             FROM transaction_categories tc
+             JOIN categories c ON c.id = tc.category_id
+             ORDER BY tc.id"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let transaction_id: i64 = row.get(0)?;
+            let name: String = row.get(1)?;
+            let source: String = row.get(2)?;
+            let source = CategorySource::stored(SourceKind::parse(&source).unwrap_or_default(), row.get(3)?, row.get(4)?);
+            Ok((transaction_id as u64, name, source))
+        })?;
+
+        let mut assignments = HashMap::new();
+        for row in rows {
+            let (transaction_id, name, source) = row?;
+            assignments.insert(transaction_id, (name, source));
+        }
+        Ok(assignments)
+    }
+}
+
+pub(crate) fn assign(conn: &Connection, transaction_id: i64, category_id: i64, source: SourceKind, confidence: Option<f64>) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO transaction_categories (transaction_id, category_id, source, confidence)
+         VALUES (?, ?, ?, ?)",
+        params![transaction_id, category_id, source.as_str(), confidence],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn category_id(tx: &SqlTransaction, name: &str) -> Result<Option<i64>> {
+    let id = tx
+        .query_row("SELECT id FROM categories WHERE name = ?", params![name], |row| row.get(0))
+        .optional()?;
+    Ok(id)
+}
+
+fn category_rules(tx: &SqlTransaction, category_id: i64) -> Result<Vec<StoredRule>> {
+    let mut stmt = tx.prepare("SELECT id FROM category_rules WHERE category_id = ? ORDER BY id")?;
+    let ids = stmt
+        .query_map(params![category_id], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut rules = Vec::new();
+    for id in ids {
+        rules.extend(load_rule(tx, id)?);
+    }
+    Ok(rules)
+}
+
+fn rename(tx: &SqlTransaction, id: i64, name: &str, new_name: &str) -> Result<Vec<Change>> {
+    if new_name == name {
+        return Ok(Vec::new());
+    }
+    if category_id(tx, new_name)?.is_some() {
+        bail!("Category '{}' already exists; merge into it instead", new_name);
+    }
+    tx.execute("UPDATE categories SET name = ? WHERE id = ?", params![new_name, id])?;
+    let mut changes = vec![Change::CategoryRenamed { id, before: name.to_string(), after: new_name.to_string() }];
+    if let (Some(account), None) = (load_ledger_account(tx, name)?, load_ledger_account(tx, new_name)?) {
+        write_ledger_account(tx, name, None)?;
+        write_ledger_account(tx, new_name, Some(&account))?;
+        changes.push(Change::LedgerAccountChanged { name: name.to_string(), before: Some(account.clone()), after: None });
+        changes.push(Change::LedgerAccountChanged { name: new_name.to_string(), before: None, after: Some(account) });
+    }
+    Ok(changes)
+}
+
+fn sync_rules(tx: &SqlTransaction, category_id: i64, rules: &[Rule]) -> Result<Vec<Change>> {
+    let existing = category_rules(tx, category_id)?;
+    let mut changes = Vec::new();
+
+    for stored in &existing {
+        if !rules.iter().any(|rule| rule.pattern == stored.pattern) {
+            delete_rule(tx, stored.id)?;
+            changes.push(Change::RuleRemoved(stored.clone()));
+        }
+    }
+    for rule in rules {
+        match existing.iter().find(|stored| stored.pattern == rule.pattern) {
+            Some(stored) if stored.priority != rule.priority => {
+                let after = StoredRule { priority: rule.priority, ..stored.clone() };
+                write_rule(tx, &after)?;
+                changes.push(Change::RuleUpdated { before: stored.clone(), after });
+            }
+            Some(_) => {}
+            None => {
+                tx.execute(
+                    "INSERT INTO category_rules (category_id, pattern, priority) VALUES (?, ?, ?)",
+                    params![category_id, rule.pattern, rule.priority],
+                )?;
+                if let Some(stored) = load_rule(tx, tx.last_insert_rowid())? {
+                    changes.push(Change::RuleAdded(stored));
+                }
+            }
+        }
+    }
+    Ok(changes)
+}
+
+// A transaction assigned to both categories keeps whichever assignment is
+// newer, so the category it shows doesn't change
+fn move_assignments(tx: &SqlTransaction, (from, from_name): (i64, &str), (to, to_name): (i64, &str), changes: &mut Vec<Change>) -> Result<usize> {
+    let moving = load_category_assignments(tx, from)?;
+    let staying = load_category_assignments(tx, to)?;
+    for (transaction_id, assignment) in &moving {
+        if let Some((_, other)) = staying.iter().find(|(id, _)| id == transaction_id) {
+            let (older, category) = if other.id < assignment.id { (other, to_name) } else { (assignment, from_name) };
+            tx.execute("DELETE FROM transaction_categories WHERE id = ?", params![older.id])?;
+            changes.push(Change::AssignmentRemoved {
+                transaction_id: *transaction_id,
+                assignment: older.clone(),
+                category: category.to_string(),
+            });
+            if older.id == assignment.id {
+                continue;
+            }
+        }
+        // The assignment keeps its id, so undoing puts it back in place
+        tx.execute("UPDATE transaction_categories SET category_id = ? WHERE id = ?", params![to, assignment.id])?;
+        changes.push(Change::CategoryAssigned {
+            transaction_id: *transaction_id,
+            assignment: StoredAssignment { category_id: to, ..assignment.clone() },
+            category: to_name.to_string(),
+            previous: Some(from_name.to_string()),
+            replaced: Some(assignment.clone()),
+        });
+    }
+    Ok(moving.len())
+}
+
+// Records and removes everything that still refers to the category, then
+// the category itself. Split parts have to add up, so a category that is
+// part of a split is never removed here.
+fn remove_category(tx: &SqlTransaction, id: i64, name: &str, changes: &mut Vec<Change>) -> Result<()> {
+    let mut stmt = tx.prepare("SELECT transaction_id FROM transaction_splits WHERE category_id = ? ORDER BY transaction_id")?;
+    let split: Vec<String> = stmt
+        .query_map(params![id], |row| row.get::<_, i64>(0))?
+        .map(|id| id.map(|id| format!("#{}", id)))
+        .collect::<rusqlite::Result<_>>()?;
+    if !split.is_empty() {
+        bail!("{} is part of the split on {}; change those splits first", name, split.join(", "));
+    }
+    for (transaction_id, assignment) in load_category_assignments(tx, id)? {
+        tx.execute("DELETE FROM transaction_categories WHERE id = ?", params![assignment.id])?;
+        changes.push(Change::AssignmentRemoved { transaction_id, assignment, category: name.to_string() });
+    }
+    for rule in category_rules(tx, id)? {
+        delete_rule(tx, rule.id)?;
+        changes.push(Change::RuleRemoved(rule));
+    }
+    if let Some(amount) = load_budget(tx, id)? {
+        write_budget(tx, id, None)?;
+        changes.push(Change::BudgetChanged { category_id: id, category: name.to_string(), before: Some(amount), after: None });
+    }
+    if let Some(account) = load_ledger_account(tx, name)? {
+        write_ledger_account(tx, name, None)?;
+        changes.push(Change::LedgerAccountChanged { name: name.to_string(), before: Some(account), after: None });
+    }
+    tx.execute("DELETE FROM categories WHERE id = ?", params![id])?;
+    changes.push(Change::CategoryRemoved { id, name: name.to_string() });
+    Ok(())
+}
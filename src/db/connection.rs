@@ -1,56 +1,200 @@
-use anyhow::Result;
-use rusqlite::Connection;
-use std::path::Path;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Local;
+use rusqlite::{params, Connection, ErrorCode};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use super::migrations::{apply_pending, current_version, pending, Migration};
+
+pub const DEFAULT_DB_PATH: &str = "finance.db";
+
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
 
 #[derive(Debug)]
 pub struct DbConnection {
     conn: Connection,
+    // None for in-memory databases, which are never backed up
+    path: Option<PathBuf>,
+    encrypted: bool,
+}
+
+#[derive(Debug)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    pub applied: Vec<&'static Migration>,
+    pub backup: Option<PathBuf>,
 }
 
 impl DbConnection {
+    // Opens the database and brings its schema up to date
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let conn = Connection::open(path)?;
-        let db = DbConnection { conn };
-        db.initialize()?;
+        let mut db = DbConnection::open(path)?;
+        db.migrate()?;
         Ok(db)
     }
 
+    // Opens the database without touching its schema
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if is_encrypted(path)? {
+            bail!("{} is encrypted; a passphrase is needed to open it", path.display());
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        enforce_foreign_keys(&conn)?;
+        let path = (path != Path::new(":memory:")).then(|| path.to_path_buf());
+        Ok(DbConnection { conn, path, encrypted: false })
+    }
+
+    // Opens an encrypted database without touching its schema; None when
+    // the passphrase is wrong
+    pub fn unlock<P: AsRef<Path>>(path: P, passphrase: &str) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        conn.pragma_update(None, "key", passphrase)?;
+        // SQLCipher only checks the key once the first page is read
+        match conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+            Ok(_) => {
+                enforce_foreign_keys(&conn)?;
+                Ok(Some(DbConnection { conn, path: Some(path.to_path_buf()), encrypted: true }))
+            }
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::NotADatabase => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to open {}", path.display())),
+        }
+    }
+
     pub fn get_connection(&mut self) -> &mut Connection {
         &mut self.conn
     }
 
-    fn initialize(&self) -> Result<()> {
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS categories (
-                id INTEGER PRIMARY KEY,
-                name TEXT NOT NULL UNIQUE,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
 
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS category_rules (
-                id INTEGER PRIMARY KEY,
-                category_id INTEGER NOT NULL,
-                pattern TEXT NOT NULL,
-                priority INTEGER NOT NULL DEFAULT 1,
-                FOREIGN KEY(category_id) REFERENCES categories(id)
-            )",
+    pub fn schema_version(&self) -> Result<u32> {
+        current_version(&self.conn)
+    }
+
+    pub fn pending_migrations(&self) -> Result<Vec<&'static Migration>> {
+        pending(&self.conn)
+    }
+
+    // Where the copy taken before migrating goes; None when there is
+    // nothing worth keeping yet
+    pub fn backup_path(&self) -> Result<Option<PathBuf>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let has_tables: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table'",
             [],
+            |row| row.get(0),
         )?;
+        if !has_tables {
+            return Ok(None);
+        }
+
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".v{}-{}.bak", self.schema_version()?, Local::now().format("%Y%m%d-%H%M%S")));
+        Ok(Some(path.with_file_name(name)))
+    }
+
+    pub fn migrate(&mut self) -> Result<MigrationReport> {
+        let from = self.schema_version()?;
+        if self.pending_migrations()?.is_empty() {
+            return Ok(MigrationReport { from, to: from, applied: Vec::new(), backup: None });
+        }
+
+        let backup = self.backup_path()?;
+        if let Some(backup) = &backup {
+            self.conn
+                .execute("VACUUM INTO ?", params![backup.to_string_lossy()])
+                .with_context(|| format!("Failed to back up the database to {}", backup.display()))?;
+        }
+
+        let applied = apply_pending(&mut self.conn)?;
+        Ok(MigrationReport { from, to: self.schema_version()?, applied, backup })
+    }
+
+    pub fn change_passphrase(&mut self, passphrase: &str) -> Result<()> {
+        if !self.encrypted {
+            bail!("The database is not encrypted");
+        }
+        self.conn.pragma_update(None, "rekey", passphrase)?;
+        Ok(())
+    }
+
+    // Rewrites the file encrypted with `passphrase`, or in plaintext for
+    // None. The copy is written next to the original and renamed over it,
+    // so an interrupted run leaves the old file intact.
+    pub fn rewrite(self, passphrase: Option<&str>) -> Result<PathBuf> {
+        let path = self.path.clone().ok_or_else(|| anyhow!("An in-memory database can't be encrypted"))?;
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".rewrite");
+        let temp = path.with_file_name(name);
+        let _ = std::fs::remove_file(&temp);
 
         self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS transaction_categories (
-                id INTEGER PRIMARY KEY,
-                transaction_id INTEGER NOT NULL,
-                category_id INTEGER NOT NULL,
-                assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY(category_id) REFERENCES categories(id)
-            )",
-            [],
+            "ATTACH DATABASE ? AS rewritten KEY ?",
+            params![temp.to_string_lossy(), passphrase.unwrap_or("")],
         )?;
+        let exported = self
+            .conn
+            .query_row("SELECT sqlcipher_export('rewritten')", [], |_| Ok(()))
+            .and_then(|_| self.conn.execute("DETACH DATABASE rewritten", []));
+        if let Err(e) = exported {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e).with_context(|| format!("Failed to write {}", temp.display()));
+        }
+        drop(self);
 
-        Ok(())
+        std::fs::rename(&temp, &path)
+            .with_context(|| format!("Failed to replace {} with {}", path.display(), temp.display()))?;
+        Ok(path)
     }
-}
\ No newline at end of file
+}
+
+// SQLite leaves foreign keys unchecked unless each connection asks for it
+fn enforce_foreign_keys(conn: &Connection) -> Result<()> {
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(())
+}
+
+// Plain SQLite files start with a fixed header; SQLCipher files look like
+// random bytes from the first one on. Missing and empty files are new
+// databases, which start out plain.
+pub fn is_encrypted(path: &Path) -> Result<bool> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    file.by_ref().take(SQLITE_HEADER.len() as u64).read_to_end(&mut header)?;
+    Ok(!header.is_empty() && header != SQLITE_HEADER)
+}
+
+// Copies taken before earlier migrations, oldest first
+pub fn backups(path: &Path) -> Result<Vec<PathBuf>> {
+    let Some(file_name) = path.file_name() else {
+        return Ok(Vec::new());
+    };
+    let prefix = format!("{}.v", file_name.to_string_lossy());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry_path = entry?.path();
+        let name = entry_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if name.starts_with(&prefix) && name.ends_with(".bak") {
+            backups.push(entry_path);
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
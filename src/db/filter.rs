@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use anyhow::Result;
+use rusqlite::Connection;
+use super::journal::{load_filter, record, write_filter, Change};
+
+pub struct FilterDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> FilterDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn save_filter(&mut self, name: &str, query: &str) -> Result<()> {
+        self.write(name, Some(query), &format!("Saved filter @{}", name))?;
+        Ok(())
+    }
+
+    pub fn remove_filter(&mut self, name: &str) -> Result<bool> {
+        self.write(name, None, &format!("Removed filter @{}", name))
+    }
+
+    // Journaled like other edits; returns whether anything changed
+    fn write(&mut self, name: &str, query: Option<&str>, description: &str) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let before = load_filter(&tx, name)?;
+        if before.as_deref() == query {
+            return Ok(false);
+        }
+        write_filter(&tx, name, query)?;
+        let change = Change::FilterChanged { name: name.to_string(), before, after: query.map(str::to_string) };
+        record(&tx, description, &[change])?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn get_all_filters(&mut self) -> Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare("SELECT name, query FROM saved_filters")?;
+        let filters = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<HashMap<String, String>>>()?;
+        Ok(filters)
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use crate::models::flag::{FlagKind, FlagStatus, TransactionFlag};
+use super::journal::{load_flag, record, Change};
+
+pub struct FlagDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> FlagDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    // `flags` is everything detection found. Existing flags keep their
+    // review status; pending ones it no longer finds are dropped, reviewed
+    // ones stay. Transactions deleted since they were loaded are skipped.
+    pub fn save_flags(&mut self, flags: &[(u64, TransactionFlag)]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+
+        let detected: HashSet<(i64, &str)> = flags
+            .iter()
+            .map(|(transaction_id, flag)| (*transaction_id as i64, flag.kind.as_str()))
+            .collect();
+        let pending: Vec<(i64, String)> = {
+            let mut stmt = tx.prepare("SELECT transaction_id, kind FROM transaction_flags WHERE status = ?")?;
+            let rows = stmt.query_map(params![FlagStatus::Pending.as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        for (transaction_id, kind) in &pending {
+            if !detected.contains(&(*transaction_id, kind.as_str())) {
+                tx.execute(
+                    "DELETE FROM transaction_flags WHERE transaction_id = ? AND kind = ?",
+                    params![transaction_id, kind],
+                )?;
+            }
+        }
+
+        for (transaction_id, flag) in flags {
+            tx.execute(
+                "INSERT OR IGNORE INTO transaction_flags (transaction_id, kind, reason, status)
+                 SELECT id, ?, ?, ? FROM transactions WHERE id = ?",
+                params![flag.kind.as_str(), flag.reason, flag.status.as_str(), *transaction_id as i64],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_all_flags(&mut self) -> Result<HashMap<u64, Vec<TransactionFlag>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT transaction_id, kind, reason, status
+             FROM transaction_flags
+             ORDER BY transaction_id, id"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let transaction_id: i64 = row.get(0)?;
+            let kind: String = row.get(1)?;
+            let reason: String = row.get(2)?;
+            let status: String = row.get(3)?;
+
+            Ok((transaction_id, kind, reason, status))
+        })?;
+
+        let mut flags: HashMap<u64, Vec<TransactionFlag>> = HashMap::new();
+        for row in rows {
+            let (transaction_id, kind, reason, status) = row?;
+            if let (Some(kind), Some(status)) = (FlagKind::parse(&kind), FlagStatus::parse(&status)) {
+                flags.entry(transaction_id as u64).or_default().push(TransactionFlag {
+                    kind,
+                    reason,
+                    status,
+                });
+            }
+        }
+
+        Ok(flags)
+    }
+
+    // Reviews are journaled, so marking a flag by mistake can be undone
+    pub fn set_status(&mut self, transaction_id: u64, kind: FlagKind, status: FlagStatus) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let id = transaction_id as i64;
+        let Some(before) = load_flag(&tx, id, kind.as_str())? else {
+            return Ok(());
+        };
+        tx.execute(
+            "UPDATE transaction_flags SET status = ?, reviewed_at = CURRENT_TIMESTAMP
+             WHERE transaction_id = ? AND kind = ?",
+            params![status.as_str(), id, kind.as_str()],
+        )?;
+        if let Some(after) = load_flag(&tx, id, kind.as_str())?
+            && after.status != before.status
+        {
+            let description = format!("Marked the {} flag on #{} {}", kind.as_str(), id, status.as_str());
+            record(&tx, &description, &[Change::FlagReviewed { transaction_id: id, before, after }])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
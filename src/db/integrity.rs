@@ -0,0 +1,74 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use super::journal::Change;
+
+pub struct IntegrityDb<'a> {
+    conn: &'a mut Connection,
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    pub table: String,
+    pub row_id: Option<i64>,
+    pub description: String,
+    // Orphaned rows and unreadable journal entries can be deleted; damage
+    // to the file itself can't be fixed from here
+    pub repairable: bool,
+}
+
+impl<'a> IntegrityDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn check(&mut self) -> Result<Vec<Problem>> {
+        let mut problems = Vec::new();
+
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        for message in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            let message = message?;
+            if message != "ok" {
+                problems.push(Problem { table: "database".to_string(), row_id: None, description: message, repairable: false });
+            }
+        }
+
+        let mut stmt = self.conn.prepare("SELECT \"table\", rowid, parent FROM pragma_foreign_key_check")?;
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, String>(2)?)))? {
+            let (table, row_id, parent) = row?;
+            problems.push(Problem {
+                table,
+                row_id,
+                description: format!("refers to a missing row in {}", parent),
+                repairable: true,
+            });
+        }
+
+        let mut stmt = self.conn.prepare("SELECT id, changes FROM journal ORDER BY id")?;
+        for row in stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))? {
+            let (id, changes) = row?;
+            if let Err(e) = serde_json::from_str::<Vec<Change>>(&changes) {
+                problems.push(Problem {
+                    table: "journal".to_string(),
+                    row_id: Some(id),
+                    description: format!("can't be read, so it can't be undone: {}", e),
+                    repairable: true,
+                });
+            }
+        }
+
+        Ok(problems)
+    }
+
+    // Deletes the rows `check` reported as repairable; returns how many
+    pub fn repair(&mut self, problems: &[Problem]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut deleted = 0;
+        for problem in problems.iter().filter(|p| p.repairable) {
+            if let Some(row_id) = problem.row_id {
+                deleted += tx.execute(&format!("DELETE FROM \"{}\" WHERE rowid = ?", problem.table), params![row_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,784 @@
+use anyhow::{anyhow, bail, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction as SqlTransaction};
+use serde::{Deserialize, Serialize};
+use crate::models::category::SourceKind;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredAssignment {
+    pub id: i64,
+    pub category_id: i64,
+    pub assigned_at: Option<String>,
+    #[serde(default)]
+    pub source: SourceKind,
+    #[serde(default)]
+    pub confidence: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredFlag {
+    pub kind: String,
+    pub reason: String,
+    pub status: String,
+    pub flagged_at: Option<String>,
+    pub reviewed_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredRule {
+    pub id: i64,
+    pub category_id: i64,
+    pub category: String,
+    pub pattern: String,
+    pub priority: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredSplit {
+    pub category_id: i64,
+    pub category: String,
+    pub amount: String,
+}
+
+// Everything stored for a transaction, so a delete can be put back as it was
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredTransaction {
+    pub id: i64,
+    pub date: String,
+    pub amount: String,
+    pub merchant: String,
+    pub description: String,
+    pub account: String,
+    pub balance: String,
+    pub imported_at: Option<String>,
+    pub tags: Vec<String>,
+    pub assignments: Vec<StoredAssignment>,
+    pub flags: Vec<StoredFlag>,
+    pub excluded: bool,
+    #[serde(default)]
+    pub splits: Vec<StoredSplit>,
+}
+
+// A single row-level change. Only changes that actually happened are
+// recorded, so undoing them restores exactly the previous state.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    // `previous` is the manual assignment it overrides; None means the
+    // category came from the rules. `replaced` is an earlier assignment to
+    // the same category, which only one row may hold.
+    CategoryAssigned {
+        transaction_id: i64,
+        assignment: StoredAssignment,
+        category: String,
+        previous: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        replaced: Option<StoredAssignment>,
+    },
+    TagAdded { transaction_id: i64, tag: String },
+    TagRemoved { transaction_id: i64, tag: String },
+    ExclusionChanged { transaction_id: i64, excluded: bool },
+    Deleted(Box<StoredTransaction>),
+    RuleAdded(StoredRule),
+    RuleRemoved(StoredRule),
+    RuleUpdated { before: StoredRule, after: StoredRule },
+    // Category edits cascade, so each one is recorded with the rules,
+    // assignments, budget and ledger account it took along
+    AssignmentRemoved { transaction_id: i64, assignment: StoredAssignment, category: String },
+    CategoryAdded { id: i64, name: String },
+    CategoryRemoved { id: i64, name: String },
+    CategoryRenamed { id: i64, before: String, after: String },
+    BudgetChanged { category_id: i64, category: String, before: Option<String>, after: Option<String> },
+    LedgerAccountChanged { name: String, before: Option<String>, after: Option<String> },
+    AliasChanged { pattern: String, before: Option<String>, after: Option<String> },
+    FilterChanged { name: String, before: Option<String>, after: Option<String> },
+    FlagReviewed { transaction_id: i64, before: StoredFlag, after: StoredFlag },
+    SplitsChanged { transaction_id: i64, before: Vec<StoredSplit>, after: Vec<StoredSplit> },
+}
+
+impl Change {
+    pub fn transaction_id(&self) -> Option<i64> {
+        match self {
+            Change::CategoryAssigned { transaction_id, .. }
+            | Change::TagAdded { transaction_id, .. }
+            | Change::TagRemoved { transaction_id, .. }
+            | Change::ExclusionChanged { transaction_id, .. }
+            | Change::AssignmentRemoved { transaction_id, .. }
+            | Change::FlagReviewed { transaction_id, .. }
+            | Change::SplitsChanged { transaction_id, .. } => Some(*transaction_id),
+            Change::Deleted(stored) => Some(stored.id),
+            Change::RuleAdded(_)
+            | Change::RuleRemoved(_)
+            | Change::RuleUpdated { .. }
+            | Change::CategoryAdded { .. }
+            | Change::CategoryRemoved { .. }
+            | Change::CategoryRenamed { .. }
+            | Change::BudgetChanged { .. }
+            | Change::LedgerAccountChanged { .. }
+            | Change::AliasChanged { .. }
+            | Change::FilterChanged { .. } => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Change::CategoryAssigned { transaction_id, category, previous, .. } => format!(
+                "#{}: {} -> {}",
+                transaction_id,
+                previous.as_deref().unwrap_or("(rules)"),
+                category
+            ),
+            Change::TagAdded { transaction_id, tag } => format!("#{}: +#{}", transaction_id, tag),
+            Change::TagRemoved { transaction_id, tag } => format!("#{}: -#{}", transaction_id, tag),
+            Change::ExclusionChanged { transaction_id, excluded: true } => format!("#{}: excluded from reports", transaction_id),
+            Change::ExclusionChanged { transaction_id, excluded: false } => format!("#{}: included in reports", transaction_id),
+            Change::Deleted(stored) => format!("#{}: deleted {} {} {}", stored.id, stored.date, stored.amount, stored.merchant),
+            Change::RuleAdded(rule) => format!("rule {}: added '{}' -> {}", rule.id, rule.pattern, rule.category),
+            Change::RuleRemoved(rule) => format!("rule {}: removed '{}' -> {}", rule.id, rule.pattern, rule.category),
+            Change::RuleUpdated { before, after } => format!(
+                "rule {}: '{}' -> {} (priority {}) became '{}' -> {} (priority {})",
+                after.id, before.pattern, before.category, before.priority, after.pattern, after.category, after.priority
+            ),
+            Change::AssignmentRemoved { transaction_id, category, .. } => format!("#{}: {} -> (rules)", transaction_id, category),
+            Change::CategoryAdded { name, .. } => format!("category {}: added", name),
+            Change::CategoryRemoved { name, .. } => format!("category {}: removed", name),
+            Change::CategoryRenamed { before, after, .. } => format!("category {}: renamed to {}", before, after),
+            Change::BudgetChanged { category, before, after, .. } => format!(
+                "budget {}: {} -> {}",
+                category,
+                before.as_deref().unwrap_or("(none)"),
+                after.as_deref().unwrap_or("(none)")
+            ),
+            Change::LedgerAccountChanged { name, before, after } => format!(
+                "ledger account {}: {} -> {}",
+                name,
+                before.as_deref().unwrap_or("(none)"),
+                after.as_deref().unwrap_or("(none)")
+            ),
+            Change::AliasChanged { pattern, before, after } => format!(
+                "alias '{}': {} -> {}",
+                pattern,
+                before.as_deref().unwrap_or("(none)"),
+                after.as_deref().unwrap_or("(none)")
+            ),
+            Change::FilterChanged { name, before, after } => format!(
+                "filter @{}: {} -> {}",
+                name,
+                before.as_deref().unwrap_or("(none)"),
+                after.as_deref().unwrap_or("(none)")
+            ),
+            Change::FlagReviewed { transaction_id, before, after } => format!(
+                "#{}: {} flag {} -> {}",
+                transaction_id, after.kind, before.status, after.status
+            ),
+            Change::SplitsChanged { transaction_id, before, after } => format!(
+                "#{}: split {} -> {}",
+                transaction_id,
+                describe_splits(before),
+                describe_splits(after)
+            ),
+        }
+    }
+}
+
+fn describe_splits(splits: &[StoredSplit]) -> String {
+    if splits.is_empty() {
+        return "(none)".to_string();
+    }
+    splits.iter().map(|split| format!("{} {}", split.category, split.amount)).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub id: i64,
+    pub description: String,
+    pub changes: Vec<Change>,
+    pub undone: bool,
+    pub created_at: String,
+}
+
+// Every edit is stored with the row-level changes it made, so it can be
+// undone and redone across restarts. Undone entries stay available for
+// redo until the next edit replaces them.
+pub struct JournalDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> JournalDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    // Reverts the most recent edit, newest change first. Returns its
+    // description, or None if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<Option<String>> {
+        let tx = self.conn.transaction()?;
+        let Some(entry) = load_entry(&tx, "SELECT id, description, changes, undone, created_at FROM journal WHERE undone = 0 ORDER BY id DESC LIMIT 1")? else {
+            return Ok(None);
+        };
+        let mut notes = Vec::new();
+        for change in entry.changes.iter().rev() {
+            notes.extend(revert(&tx, change)?);
+        }
+        tx.execute("UPDATE journal SET undone = 1 WHERE id = ?", params![entry.id])?;
+        tx.commit()?;
+        Ok(Some(with_notes(entry.description, &notes)))
+    }
+
+    // Applies the oldest undone edit again
+    pub fn redo(&mut self) -> Result<Option<String>> {
+        let tx = self.conn.transaction()?;
+        let Some(entry) = load_entry(&tx, "SELECT id, description, changes, undone, created_at FROM journal WHERE undone = 1 ORDER BY id LIMIT 1")? else {
+            return Ok(None);
+        };
+        let mut notes = Vec::new();
+        for change in &entry.changes {
+            notes.extend(reapply(&tx, change)?);
+        }
+        tx.execute("UPDATE journal SET undone = 0 WHERE id = ?", params![entry.id])?;
+        tx.commit()?;
+        Ok(Some(with_notes(entry.description, &notes)))
+    }
+
+    // Newest first
+    pub fn get_entries(&mut self, limit: usize) -> Result<Vec<JournalEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, description, changes, undone, created_at FROM journal ORDER BY id DESC LIMIT ?"
+        )?;
+        let rows = stmt.query_map(params![limit as i64], read_entry)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(parse_entry(row?)?);
+        }
+        Ok(entries)
+    }
+}
+
+// Called inside the transaction that made the changes, so an edit and its
+// journal entry are stored together or not at all
+pub(crate) fn record(tx: &SqlTransaction, description: &str, changes: &[Change]) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    tx.execute("DELETE FROM journal WHERE undone = 1", [])?;
+    tx.execute(
+        "INSERT INTO journal (description, changes) VALUES (?, ?)",
+        params![description, serde_json::to_string(changes)?],
+    )?;
+    Ok(())
+}
+
+// Parts of an entry that could not be applied as recorded, such as a
+// deleted transaction that has been imported again since
+fn with_notes(description: String, notes: &[String]) -> String {
+    if notes.is_empty() {
+        description
+    } else {
+        format!("{} ({})", description, notes.join("; "))
+    }
+}
+
+type EntryRow = (i64, String, String, bool, String);
+
+fn read_entry(row: &rusqlite::Row) -> rusqlite::Result<EntryRow> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+}
+
+fn parse_entry((id, description, changes, undone, created_at): EntryRow) -> Result<JournalEntry> {
+    let changes = serde_json::from_str(&changes)
+        .map_err(|e| anyhow!("Journal entry {} is unreadable: {}", id, e))?;
+    Ok(JournalEntry { id, description, changes, undone, created_at })
+}
+
+fn load_entry(tx: &SqlTransaction, sql: &str) -> Result<Option<JournalEntry>> {
+    match tx.query_row(sql, [], read_entry).optional()? {
+        Some(row) => Ok(Some(parse_entry(row)?)),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn set_excluded(tx: &SqlTransaction, id: i64, excluded: bool) -> Result<bool> {
+    let changed = if excluded {
+        tx.execute("INSERT OR IGNORE INTO excluded_transactions (transaction_id) VALUES (?)", params![id])?
+    } else {
+        tx.execute("DELETE FROM excluded_transactions WHERE transaction_id = ?", params![id])?
+    };
+    Ok(changed > 0)
+}
+
+pub(crate) fn insert_assignment(tx: &SqlTransaction, transaction_id: i64, assignment: &StoredAssignment) -> Result<()> {
+    tx.execute(
+        "INSERT INTO transaction_categories (id, transaction_id, category_id, assigned_at, source, confidence)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            assignment.id,
+            transaction_id,
+            assignment.category_id,
+            assignment.assigned_at,
+            assignment.source.as_str(),
+            assignment.confidence,
+        ],
+    )?;
+    Ok(())
+}
+
+fn revert(tx: &SqlTransaction, change: &Change) -> Result<Option<String>> {
+    match change {
+        Change::CategoryAssigned { transaction_id, assignment, replaced, .. } => {
+            tx.execute("DELETE FROM transaction_categories WHERE id = ?", params![assignment.id])?;
+            if let Some(replaced) = replaced {
+                insert_assignment(tx, *transaction_id, replaced)?;
+            }
+        }
+        Change::TagAdded { transaction_id, tag } => {
+            tx.execute(
+                "DELETE FROM transaction_tags WHERE transaction_id = ? AND tag = ?",
+                params![transaction_id, tag],
+            )?;
+        }
+        Change::TagRemoved { transaction_id, tag } => {
+            tx.execute(
+                "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag) VALUES (?, ?)",
+                params![transaction_id, tag],
+            )?;
+        }
+        Change::ExclusionChanged { transaction_id, excluded } => {
+            set_excluded(tx, *transaction_id, !excluded)?;
+        }
+        Change::Deleted(stored) => return restore_stored(tx, stored),
+        Change::RuleAdded(rule) => delete_rule(tx, rule.id)?,
+        Change::RuleRemoved(rule) => insert_rule(tx, rule)?,
+        Change::RuleUpdated { before, .. } => write_rule(tx, before)?,
+        Change::AssignmentRemoved { transaction_id, assignment, .. } => insert_assignment(tx, *transaction_id, assignment)?,
+        Change::CategoryAdded { id, .. } => delete_category_row(tx, *id)?,
+        Change::CategoryRemoved { id, name } => insert_category(tx, *id, name)?,
+        Change::CategoryRenamed { id, before, .. } => rename_category_row(tx, *id, before)?,
+        Change::BudgetChanged { category_id, before, .. } => write_budget(tx, *category_id, before.as_deref())?,
+        Change::LedgerAccountChanged { name, before, .. } => write_ledger_account(tx, name, before.as_deref())?,
+        Change::AliasChanged { pattern, before, .. } => write_alias(tx, pattern, before.as_deref())?,
+        Change::FilterChanged { name, before, .. } => write_filter(tx, name, before.as_deref())?,
+        Change::FlagReviewed { transaction_id, before, .. } => write_flag_review(tx, *transaction_id, before)?,
+        Change::SplitsChanged { transaction_id, before, .. } => write_splits(tx, *transaction_id, before)?,
+    }
+    Ok(None)
+}
+
+fn reapply(tx: &SqlTransaction, change: &Change) -> Result<Option<String>> {
+    match change {
+        Change::CategoryAssigned { transaction_id, assignment, replaced, .. } => {
+            if let Some(replaced) = replaced {
+                tx.execute("DELETE FROM transaction_categories WHERE id = ?", params![replaced.id])?;
+            }
+            insert_assignment(tx, *transaction_id, assignment)?;
+        }
+        Change::TagAdded { transaction_id, tag } => {
+            tx.execute(
+                "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag) VALUES (?, ?)",
+                params![transaction_id, tag],
+            )?;
+        }
+        Change::TagRemoved { transaction_id, tag } => {
+            tx.execute(
+                "DELETE FROM transaction_tags WHERE transaction_id = ? AND tag = ?",
+                params![transaction_id, tag],
+            )?;
+        }
+        Change::ExclusionChanged { transaction_id, excluded } => {
+            set_excluded(tx, *transaction_id, *excluded)?;
+        }
+        Change::Deleted(stored) => {
+            // The id may have gone to another transaction if the restore was skipped
+            if stored_key(tx, stored)? != Some(stored.id) {
+                return Ok(Some(format!("#{} was not restored, so there was nothing to delete", stored.id)));
+            }
+            delete_stored(tx, stored.id)?;
+        }
+        Change::RuleAdded(rule) => insert_rule(tx, rule)?,
+        Change::RuleRemoved(rule) => delete_rule(tx, rule.id)?,
+        Change::RuleUpdated { after, .. } => write_rule(tx, after)?,
+        Change::AssignmentRemoved { assignment, .. } => {
+            tx.execute("DELETE FROM transaction_categories WHERE id = ?", params![assignment.id])?;
+        }
+        Change::CategoryAdded { id, name } => insert_category(tx, *id, name)?,
+        Change::CategoryRemoved { id, .. } => delete_category_row(tx, *id)?,
+        Change::CategoryRenamed { id, after, .. } => rename_category_row(tx, *id, after)?,
+        Change::BudgetChanged { category_id, after, .. } => write_budget(tx, *category_id, after.as_deref())?,
+        Change::LedgerAccountChanged { name, after, .. } => write_ledger_account(tx, name, after.as_deref())?,
+        Change::AliasChanged { pattern, after, .. } => write_alias(tx, pattern, after.as_deref())?,
+        Change::FilterChanged { name, after, .. } => write_filter(tx, name, after.as_deref())?,
+        Change::FlagReviewed { transaction_id, after, .. } => write_flag_review(tx, *transaction_id, after)?,
+        Change::SplitsChanged { transaction_id, after, .. } => write_splits(tx, *transaction_id, after)?,
+    }
+    Ok(None)
+}
+
+pub(crate) fn insert_rule(tx: &SqlTransaction, rule: &StoredRule) -> Result<()> {
+    tx.execute(
+        "INSERT INTO category_rules (id, category_id, pattern, priority) VALUES (?, ?, ?, ?)",
+        params![rule.id, rule.category_id, rule.pattern, rule.priority],
+    ).map_err(|e| anyhow!("Cannot restore rule {}: {}", rule.id, e))?;
+    Ok(())
+}
+
+pub(crate) fn write_rule(tx: &SqlTransaction, rule: &StoredRule) -> Result<()> {
+    let updated = tx.execute(
+        "UPDATE category_rules SET category_id = ?, pattern = ?, priority = ? WHERE id = ?",
+        params![rule.category_id, rule.pattern, rule.priority, rule.id],
+    ).map_err(|e| anyhow!("Cannot update rule {}: {}", rule.id, e))?;
+    if updated == 0 {
+        bail!("Cannot update rule {}: it no longer exists", rule.id);
+    }
+    Ok(())
+}
+
+pub(crate) fn delete_rule(tx: &SqlTransaction, id: i64) -> Result<()> {
+    tx.execute("DELETE FROM category_rules WHERE id = ?", params![id])?;
+    Ok(())
+}
+
+pub(crate) fn load_rule(tx: &SqlTransaction, id: i64) -> Result<Option<StoredRule>> {
+    let rule = tx.query_row(
+        "SELECT cr.id, cr.category_id, c.name, cr.pattern, cr.priority
+         FROM category_rules cr
+         JOIN categories c ON c.id = cr.category_id
+         WHERE cr.id = ?",
+        params![id],
+        |row| Ok(StoredRule {
+            id: row.get(0)?,
+            category_id: row.get(1)?,
+            category: row.get(2)?,
+            pattern: row.get(3)?,
+            priority: row.get(4)?,
+        }),
+    ).optional()?;
+    Ok(rule)
+}
+
+fn insert_category(tx: &SqlTransaction, id: i64, name: &str) -> Result<()> {
+    tx.execute("INSERT INTO categories (id, name) VALUES (?, ?)", params![id, name])
+        .map_err(|e| anyhow!("Cannot restore category '{}': {}", name, e))?;
+    Ok(())
+}
+
+// Whatever referred to the category was recorded and removed before it
+fn delete_category_row(tx: &SqlTransaction, id: i64) -> Result<()> {
+    tx.execute("DELETE FROM categories WHERE id = ?", params![id])?;
+    Ok(())
+}
+
+fn rename_category_row(tx: &SqlTransaction, id: i64, name: &str) -> Result<()> {
+    let updated = tx.execute("UPDATE categories SET name = ? WHERE id = ?", params![name, id])
+        .map_err(|e| anyhow!("Cannot rename category {} to '{}': {}", id, name, e))?;
+    if updated == 0 {
+        bail!("Cannot rename category {} to '{}': it no longer exists", id, name);
+    }
+    Ok(())
+}
+
+pub(crate) fn load_budget(tx: &SqlTransaction, category_id: i64) -> Result<Option<String>> {
+    let amount = tx
+        .query_row("SELECT amount FROM budgets WHERE category_id = ?", params![category_id], |row| row.get(0))
+        .optional()?;
+    Ok(amount)
+}
+
+pub(crate) fn write_budget(tx: &SqlTransaction, category_id: i64, amount: Option<&str>) -> Result<()> {
+    match amount {
+        Some(amount) => tx.execute(
+            "INSERT INTO budgets (category_id, amount) VALUES (?, ?)
+             ON CONFLICT(category_id) DO UPDATE SET amount = excluded.amount",
+            params![category_id, amount],
+        ),
+        None => tx.execute("DELETE FROM budgets WHERE category_id = ?", params![category_id]),
+    }
+    .map_err(|e| anyhow!("Cannot restore the budget of category {}: {}", category_id, e))?;
+    Ok(())
+}
+
+pub(crate) fn load_ledger_account(tx: &SqlTransaction, name: &str) -> Result<Option<String>> {
+    let account = tx
+        .query_row("SELECT account FROM ledger_accounts WHERE name = ?", params![name], |row| row.get(0))
+        .optional()?;
+    Ok(account)
+}
+
+pub(crate) fn write_ledger_account(tx: &SqlTransaction, name: &str, account: Option<&str>) -> Result<()> {
+    match account {
+        Some(account) => tx.execute(
+            "INSERT INTO ledger_accounts (name, account) VALUES (?, ?)
+             ON CONFLICT(name) DO UPDATE SET account = excluded.account",
+            params![name, account],
+        )?,
+        None => tx.execute("DELETE FROM ledger_accounts WHERE name = ?", params![name])?,
+    };
+    Ok(())
+}
+
+pub(crate) fn load_alias(tx: &SqlTransaction, pattern: &str) -> Result<Option<String>> {
+    let canonical = tx
+        .query_row("SELECT canonical FROM merchant_aliases WHERE pattern = ?", params![pattern], |row| row.get(0))
+        .optional()?;
+    Ok(canonical)
+}
+
+pub(crate) fn write_alias(tx: &SqlTransaction, pattern: &str, canonical: Option<&str>) -> Result<()> {
+    match canonical {
+        Some(canonical) => tx.execute(
+            "INSERT INTO merchant_aliases (pattern, canonical) VALUES (?, ?)
+             ON CONFLICT(pattern) DO UPDATE SET canonical = excluded.canonical",
+            params![pattern, canonical],
+        )?,
+        None => tx.execute("DELETE FROM merchant_aliases WHERE pattern = ?", params![pattern])?,
+    };
+    Ok(())
+}
+
+pub(crate) fn load_filter(tx: &SqlTransaction, name: &str) -> Result<Option<String>> {
+    let query = tx
+        .query_row("SELECT query FROM saved_filters WHERE name = ?", params![name], |row| row.get(0))
+        .optional()?;
+    Ok(query)
+}
+
+pub(crate) fn write_filter(tx: &SqlTransaction, name: &str, query: Option<&str>) -> Result<()> {
+    match query {
+        Some(query) => tx.execute(
+            "INSERT INTO saved_filters (name, query) VALUES (?, ?)
+             ON CONFLICT(name) DO UPDATE SET query = excluded.query",
+            params![name, query],
+        )?,
+        None => tx.execute("DELETE FROM saved_filters WHERE name = ?", params![name])?,
+    };
+    Ok(())
+}
+
+pub(crate) fn load_flag(tx: &SqlTransaction, transaction_id: i64, kind: &str) -> Result<Option<StoredFlag>> {
+    let flag = tx.query_row(
+        "SELECT kind, reason, status, flagged_at, reviewed_at
+         FROM transaction_flags WHERE transaction_id = ? AND kind = ?",
+        params![transaction_id, kind],
+        |row| Ok(StoredFlag {
+            kind: row.get(0)?,
+            reason: row.get(1)?,
+            status: row.get(2)?,
+            flagged_at: row.get(3)?,
+            reviewed_at: row.get(4)?,
+        }),
+    ).optional()?;
+    Ok(flag)
+}
+
+// A flag that detection has dropped since is left alone
+fn write_flag_review(tx: &SqlTransaction, transaction_id: i64, flag: &StoredFlag) -> Result<()> {
+    tx.execute(
+        "UPDATE transaction_flags SET status = ?, reviewed_at = ? WHERE transaction_id = ? AND kind = ?",
+        params![flag.status, flag.reviewed_at, transaction_id, flag.kind],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn load_splits(tx: &SqlTransaction, transaction_id: i64) -> Result<Vec<StoredSplit>> {
+    let mut stmt = tx.prepare(
+        "SELECT s.category_id, c.name, s.amount
+         FROM transaction_splits s
+         JOIN categories c ON c.id = s.category_id
+         WHERE s.transaction_id = ? ORDER BY s.id"
+    )?;
+    let splits = stmt
+        .query_map(params![transaction_id], |row| Ok(StoredSplit {
+            category_id: row.get(0)?,
+            category: row.get(1)?,
+            amount: row.get(2)?,
+        }))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(splits)
+}
+
+// Replaces every part, so the transaction ends up split exactly as given
+pub(crate) fn write_splits(tx: &SqlTransaction, transaction_id: i64, splits: &[StoredSplit]) -> Result<()> {
+    tx.execute("DELETE FROM transaction_splits WHERE transaction_id = ?", params![transaction_id])?;
+    for split in splits {
+        tx.execute(
+            "INSERT INTO transaction_splits (transaction_id, category_id, amount) VALUES (?, ?, ?)",
+            params![transaction_id, split.category_id, split.amount],
+        ).map_err(|e| anyhow!("Cannot split #{} into {}: {}", transaction_id, split.category, e))?;
+    }
+    Ok(())
+}
+
+// Oldest first, as (transaction id, assignment)
+pub(crate) fn load_category_assignments(tx: &SqlTransaction, category_id: i64) -> Result<Vec<(i64, StoredAssignment)>> {
+    let mut stmt = tx.prepare(
+        "SELECT transaction_id, id, category_id, assigned_at, source, confidence
+         FROM transaction_categories WHERE category_id = ? ORDER BY id"
+    )?;
+    let assignments = stmt
+        .query_map(params![category_id], |row| Ok((row.get(0)?, StoredAssignment {
+            id: row.get(1)?,
+            category_id: row.get(2)?,
+            assigned_at: row.get(3)?,
+            source: SourceKind::parse(&row.get::<_, String>(4)?).unwrap_or_default(),
+            confidence: row.get(5)?,
+        })))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(assignments)
+}
+
+pub(crate) fn load_assignment(tx: &SqlTransaction, transaction_id: i64, category_id: i64) -> Result<Option<StoredAssignment>> {
+    let assignment = tx.query_row(
+        "SELECT id, category_id, assigned_at, source, confidence
+         FROM transaction_categories WHERE transaction_id = ? AND category_id = ?",
+        params![transaction_id, category_id],
+        |row| Ok(StoredAssignment {
+            id: row.get(0)?,
+            category_id: row.get(1)?,
+            assigned_at: row.get(2)?,
+            source: SourceKind::parse(&row.get::<_, String>(3)?).unwrap_or_default(),
+            confidence: row.get(4)?,
+        }),
+    ).optional()?;
+    Ok(assignment)
+}
+
+pub(crate) fn load_stored(tx: &SqlTransaction, id: i64) -> Result<Option<StoredTransaction>> {
+    let row = tx.query_row(
+        "SELECT date, amount, merchant, description, account, balance, imported_at
+         FROM transactions WHERE id = ?",
+        params![id],
+        |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, Option<String>>(6)?,
+        )),
+    ).optional()?;
+    let Some((date, amount, merchant, description, account, balance, imported_at)) = row else {
+        return Ok(None);
+    };
+
+    let mut stmt = tx.prepare("SELECT tag FROM transaction_tags WHERE transaction_id = ? ORDER BY id")?;
+    let tags = stmt.query_map(params![id], |row| row.get(0))?.collect::<rusqlite::Result<Vec<String>>>()?;
+
+    let mut stmt = tx.prepare(
+        "SELECT id, category_id, assigned_at, source, confidence
+         FROM transaction_categories WHERE transaction_id = ? ORDER BY id"
+    )?;
+    let assignments = stmt
+        .query_map(params![id], |row| Ok(StoredAssignment {
+            id: row.get(0)?,
+            category_id: row.get(1)?,
+            assigned_at: row.get(2)?,
+            source: SourceKind::parse(&row.get::<_, String>(3)?).unwrap_or_default(),
+            confidence: row.get(4)?,
+        }))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = tx.prepare(
+        "SELECT kind, reason, status, flagged_at, reviewed_at
+         FROM transaction_flags WHERE transaction_id = ? ORDER BY id"
+    )?;
+    let flags = stmt
+        .query_map(params![id], |row| Ok(StoredFlag {
+            kind: row.get(0)?,
+            reason: row.get(1)?,
+            status: row.get(2)?,
+            flagged_at: row.get(3)?,
+            reviewed_at: row.get(4)?,
+        }))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let excluded = tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM excluded_transactions WHERE transaction_id = ?)",
+        params![id],
+        |row| row.get(0),
+    )?;
+    let splits = load_splits(tx, id)?;
+
+    Ok(Some(StoredTransaction {
+        id,
+        date,
+        amount,
+        merchant,
+        description,
+        account,
+        balance,
+        imported_at,
+        tags,
+        assignments,
+        flags,
+        excluded,
+        splits,
+    }))
+}
+
+pub(crate) fn delete_stored(tx: &SqlTransaction, id: i64) -> Result<()> {
+    tx.execute("DELETE FROM transaction_tags WHERE transaction_id = ?", params![id])?;
+    tx.execute("DELETE FROM transaction_categories WHERE transaction_id = ?", params![id])?;
+    tx.execute("DELETE FROM transaction_flags WHERE transaction_id = ?", params![id])?;
+    tx.execute("DELETE FROM excluded_transactions WHERE transaction_id = ?", params![id])?;
+    tx.execute("DELETE FROM transaction_splits WHERE transaction_id = ?", params![id])?;
+    tx.execute("DELETE FROM transactions WHERE id = ?", params![id])?;
+    Ok(())
+}
+
+// The row that holds the transaction's natural key, if any
+fn stored_key(tx: &SqlTransaction, stored: &StoredTransaction) -> Result<Option<i64>> {
+    let id = tx.query_row(
+        "SELECT id FROM transactions
+         WHERE date = ? AND amount = ? AND merchant = ? AND description = ? AND account = ? AND balance = ?",
+        params![stored.date, stored.amount, stored.merchant, stored.description, stored.account, stored.balance],
+        |row| row.get(0),
+    ).optional()?;
+    Ok(id)
+}
+
+// A transaction imported again after the delete is kept as it is, and so is
+// an unrelated one that was given the freed id; the restore is skipped
+fn restore_stored(tx: &SqlTransaction, stored: &StoredTransaction) -> Result<Option<String>> {
+    if let Some(id) = stored_key(tx, stored)? {
+        return Ok(Some(format!("#{} was imported again as #{} and kept", stored.id, id)));
+    }
+    let taken: bool = tx.query_row("SELECT EXISTS(SELECT 1 FROM transactions WHERE id = ?)", params![stored.id], |row| row.get(0))?;
+    if taken {
+        return Ok(Some(format!("#{} was not restored: its id belongs to another transaction now", stored.id)));
+    }
+
+    tx.execute(
+        "INSERT INTO transactions (id, date, amount, merchant, description, account, balance, imported_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            stored.id,
+            stored.date,
+            stored.amount,
+            stored.merchant,
+            stored.description,
+            stored.account,
+            stored.balance,
+            stored.imported_at,
+        ],
+    ).map_err(|e| anyhow!("Cannot restore transaction {}: {}", stored.id, e))?;
+
+    for tag in &stored.tags {
+        tx.execute(
+            "INSERT INTO transaction_tags (transaction_id, tag) VALUES (?, ?)",
+            params![stored.id, tag],
+        )?;
+    }
+    for assignment in &stored.assignments {
+        insert_assignment(tx, stored.id, assignment)?;
+    }
+    for flag in &stored.flags {
+        tx.execute(
+            "INSERT OR IGNORE INTO transaction_flags (transaction_id, kind, reason, status, flagged_at, reviewed_at)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![stored.id, flag.kind, flag.reason, flag.status, flag.flagged_at, flag.reviewed_at],
+        )?;
+    }
+    if stored.excluded {
+        set_excluded(tx, stored.id, true)?;
+    }
+    write_splits(tx, stored.id, &stored.splits)?;
+    Ok(None)
+}
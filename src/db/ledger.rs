@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+pub struct LedgerDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> LedgerDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    // `name` is a category or a bank account number
+    pub fn set_account(&mut self, name: &str, account: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO ledger_accounts (name, account) VALUES (?, ?)
+             ON CONFLICT(name) DO UPDATE SET account = excluded.account",
+            params![name, account],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_account(&mut self, name: &str) -> Result<bool> {
+        let removed = self.conn.execute("DELETE FROM ledger_accounts WHERE name = ?", params![name])?;
+        Ok(removed > 0)
+    }
+
+    pub fn get_accounts(&mut self) -> Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare("SELECT name, account FROM ledger_accounts")?;
+        let accounts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<HashMap<String, String>>>()?;
+        Ok(accounts)
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use crate::models::merchant::MerchantAlias;
+use super::journal::{load_alias, record, write_alias, Change};
+
+pub struct MerchantDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> MerchantDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn save_alias(&mut self, alias: &MerchantAlias) -> Result<()> {
+        self.write(&alias.pattern, Some(&alias.canonical), &format!("Showing '{}' as {}", alias.pattern, alias.canonical))?;
+        Ok(())
+    }
+
+    pub fn remove_alias(&mut self, pattern: &str) -> Result<bool> {
+        self.write(pattern, None, &format!("Removed the alias for '{}'", pattern))
+    }
+
+    // Journaled like other edits; returns whether anything changed
+    fn write(&mut self, pattern: &str, canonical: Option<&str>, description: &str) -> Result<bool> {
+        let tx = self.conn.transaction()?;
+        let before = load_alias(&tx, pattern)?;
+        if before.as_deref() == canonical {
+            return Ok(false);
+        }
+        write_alias(&tx, pattern, canonical)?;
+        let change = Change::AliasChanged { pattern: pattern.to_string(), before, after: canonical.map(str::to_string) };
+        record(&tx, description, &[change])?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn get_all_aliases(&mut self) -> Result<Vec<MerchantAlias>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT pattern, canonical FROM merchant_aliases ORDER BY canonical, pattern"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(MerchantAlias {
+                pattern: row.get(0)?,
+                canonical: row.get(1)?,
+            })
+        })?;
+
+        let mut aliases = Vec::new();
+        for row in rows {
+            aliases.push(row?);
+        }
+        Ok(aliases)
+    }
+
+    // Not an edit, so nothing to undo
+    pub fn initialize_default_aliases(&mut self) -> Result<()> {
+        for alias in MerchantAlias::default_aliases() {
+            self.conn.execute(
+                "INSERT INTO merchant_aliases (pattern, canonical) VALUES (?, ?)
+                 ON CONFLICT(pattern) DO UPDATE SET canonical = excluded.canonical",
+                params![alias.pattern, alias.canonical],
+            )?;
+        }
+        Ok(())
+    }
+}
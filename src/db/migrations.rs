@@ -0,0 +1,325 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, Transaction as SqlTransaction};
+
+#[derive(Debug)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&SqlTransaction) -> rusqlite::Result<()>,
+}
+
+// Append only. A migration that has shipped is never edited; fix it with a
+// new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        apply: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "Record where category assignments came from",
+        apply: category_sources,
+    },
+    Migration {
+        version: 3,
+        description: "Cascade category deletes and make assignments unique",
+        apply: category_constraints,
+    },
+    Migration {
+        version: 4,
+        description: "Drop flags keyed by CSV row numbers and tie flags to stored transactions",
+        apply: flag_transactions,
+    },
+    Migration {
+        version: 5,
+        description: "Split transactions across categories",
+        apply: transaction_splits,
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or_default()
+}
+
+// 0 for databases created before versioning, even if they have tables
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    let versioned: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !versioned {
+        return Ok(0);
+    }
+    let version: Option<u32> = conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+    Ok(version.unwrap_or_default())
+}
+
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>> {
+    let current = current_version(conn)?;
+    if current > latest_version() {
+        return Err(anyhow!(
+            "Database schema version {} is newer than this build supports ({}); upgrade finance-analyzer",
+            current,
+            latest_version()
+        ));
+    }
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+// Each migration commits on its own, so a failure leaves the database at
+// the last version that applied cleanly
+pub fn apply_pending(conn: &mut Connection) -> Result<Vec<&'static Migration>> {
+    let migrations = pending(conn)?;
+    for migration in &migrations {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+        (migration.apply)(&tx)
+            .map_err(|e| anyhow!("Migration {} ({}) failed: {}", migration.version, migration.description, e))?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?, ?)",
+            params![migration.version, migration.description],
+        )?;
+        tx.commit()?;
+    }
+    Ok(migrations)
+}
+
+// Tables created before a column existed don't get it from CREATE TABLE IF NOT EXISTS
+fn add_missing_column(tx: &SqlTransaction, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let exists: bool = tx.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+// Everything created before versioning. Databases from any earlier build
+// have some subset of these tables, so each one is created only if missing.
+fn initial_schema(tx: &SqlTransaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS transactions (
+            id INTEGER PRIMARY KEY,
+            date TEXT NOT NULL,
+            amount TEXT NOT NULL,
+            merchant TEXT NOT NULL,
+            description TEXT NOT NULL,
+            account TEXT NOT NULL,
+            balance TEXT NOT NULL DEFAULT '',
+            imported_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(date, amount, merchant, description, account, balance)
+        );
+
+        CREATE TABLE IF NOT EXISTS transaction_tags (
+            id INTEGER PRIMARY KEY,
+            transaction_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            UNIQUE(transaction_id, tag),
+            FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS excluded_transactions (
+            transaction_id INTEGER PRIMARY KEY,
+            excluded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS categories (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS category_rules (
+            id INTEGER PRIMARY KEY,
+            category_id INTEGER NOT NULL,
+            pattern TEXT NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 1,
+            FOREIGN KEY(category_id) REFERENCES categories(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS transaction_categories (
+            id INTEGER PRIMARY KEY,
+            transaction_id INTEGER NOT NULL,
+            category_id INTEGER NOT NULL,
+            assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(category_id) REFERENCES categories(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS transaction_flags (
+            id INTEGER PRIMARY KEY,
+            transaction_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            flagged_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            reviewed_at DATETIME,
+            UNIQUE(transaction_id, kind)
+        );
+
+        CREATE TABLE IF NOT EXISTS merchant_aliases (
+            id INTEGER PRIMARY KEY,
+            pattern TEXT NOT NULL UNIQUE,
+            canonical TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS budgets (
+            id INTEGER PRIMARY KEY,
+            category_id INTEGER NOT NULL UNIQUE,
+            amount TEXT NOT NULL,
+            FOREIGN KEY(category_id) REFERENCES categories(id)
+        );
+
+        CREATE TABLE IF NOT EXISTS ledger_accounts (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            account TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS saved_filters (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            query TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS journal (
+            id INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            changes TEXT NOT NULL,
+            undone INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );"
+    )
+}
+
+// Builds from just before versioning may already have these columns
+fn category_sources(tx: &SqlTransaction) -> rusqlite::Result<()> {
+    add_missing_column(tx, "transaction_categories", "source", "TEXT NOT NULL DEFAULT 'manual'")?;
+    add_missing_column(tx, "transaction_categories", "confidence", "REAL")
+}
+
+// SQLite can't add constraints to an existing table, so the three tables
+// that hang off categories are rebuilt. Rows pointing at categories that
+// no longer exist were already invisible, since every read joins on
+// categories; they are dropped rather than copied, as are assignments to
+// transactions that aren't stored, which the first release keyed by CSV
+// row. Of repeated
+// assignments only the latest is kept, which is the one that was shown.
+fn category_constraints(tx: &SqlTransaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DELETE FROM category_rules WHERE category_id NOT IN (SELECT id FROM categories);
+        DELETE FROM transaction_categories WHERE category_id NOT IN (SELECT id FROM categories);
+        DELETE FROM transaction_categories WHERE transaction_id NOT IN (SELECT id FROM transactions);
+        DELETE FROM budgets WHERE category_id NOT IN (SELECT id FROM categories);
+        DELETE FROM transaction_categories WHERE id NOT IN (
+            SELECT MAX(id) FROM transaction_categories GROUP BY transaction_id, category_id
+        );
+
+        CREATE TABLE category_rules_new (
+            id INTEGER PRIMARY KEY,
+            category_id INTEGER NOT NULL,
+            pattern TEXT NOT NULL,
+            priority INTEGER NOT NULL DEFAULT 1,
+            FOREIGN KEY(category_id) REFERENCES categories(id) ON DELETE CASCADE
+        );
+        INSERT INTO category_rules_new (id, category_id, pattern, priority)
+            SELECT id, category_id, pattern, priority FROM category_rules;
+        DROP TABLE category_rules;
+        ALTER TABLE category_rules_new RENAME TO category_rules;
+
+        CREATE TABLE transaction_categories_new (
+            id INTEGER PRIMARY KEY,
+            transaction_id INTEGER NOT NULL,
+            category_id INTEGER NOT NULL,
+            assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            source TEXT NOT NULL DEFAULT 'manual',
+            confidence REAL,
+            UNIQUE(transaction_id, category_id),
+            FOREIGN KEY(transaction_id) REFERENCES transactions(id) ON DELETE CASCADE,
+            FOREIGN KEY(category_id) REFERENCES categories(id) ON DELETE CASCADE
+        );
+        INSERT INTO transaction_categories_new (id, transaction_id, category_id, assigned_at, source, confidence)
+            SELECT id, transaction_id, category_id, assigned_at, source, confidence FROM transaction_categories;
+        DROP TABLE transaction_categories;
+        ALTER TABLE transaction_categories_new RENAME TO transaction_categories;
+
+        CREATE TABLE budgets_new (
+            id INTEGER PRIMARY KEY,
+            category_id INTEGER NOT NULL UNIQUE,
+            amount TEXT NOT NULL,
+            FOREIGN KEY(category_id) REFERENCES categories(id) ON DELETE CASCADE
+        );
+        INSERT INTO budgets_new (id, category_id, amount) SELECT id, category_id, amount FROM budgets;
+        DROP TABLE budgets;
+        ALTER TABLE budgets_new RENAME TO budgets;
+
+        -- Cascading deletes look rows up by category
+        CREATE INDEX idx_category_rules_category ON category_rules(category_id);
+        CREATE INDEX idx_transaction_categories_category ON transaction_categories(category_id);"
+    )
+}
+
+// Flags used to be keyed by the row number in the CSV file being viewed.
+// Those rows predate the transaction they now point at, or point at nothing.
+fn flag_transactions(tx: &SqlTransaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DELETE FROM transaction_flags WHERE NOT EXISTS (
+            SELECT 1 FROM transactions t
+            WHERE t.id = transaction_flags.transaction_id
+              AND (t.imported_at IS NULL OR transaction_flags.flagged_at IS NULL
+                   OR t.imported_at <= transaction_flags.flagged_at)
+        );
+
+        CREATE TABLE transaction_flags_new (
+            id INTEGER PRIMARY KEY,
+            transaction_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            flagged_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            reviewed_at DATETIME,
+            UNIQUE(transaction_id, kind),
+            FOREIGN KEY(transaction_id) REFERENCES transactions(id) ON DELETE CASCADE
+        );
+        INSERT INTO transaction_flags_new (id, transaction_id, kind, reason, status, flagged_at, reviewed_at)
+            SELECT id, transaction_id, kind, reason, status, flagged_at, reviewed_at FROM transaction_flags;
+        DROP TABLE transaction_flags;
+        ALTER TABLE transaction_flags_new RENAME TO transaction_flags;"
+    )
+}
+
+// A category that splits use can't be deleted until they are changed
+fn transaction_splits(tx: &SqlTransaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE transaction_splits (
+            id INTEGER PRIMARY KEY,
+            transaction_id INTEGER NOT NULL,
+            category_id INTEGER NOT NULL,
+            amount TEXT NOT NULL,
+            UNIQUE(transaction_id, category_id),
+            FOREIGN KEY(transaction_id) REFERENCES transactions(id) ON DELETE CASCADE,
+            FOREIGN KEY(category_id) REFERENCES categories(id)
+        );
+        CREATE INDEX idx_transaction_splits_category ON transaction_splits(category_id);"
+    )
+}
@@ -1,2 +1,15 @@
+pub mod batch;
+pub mod archive;
+pub mod budget;
 pub mod category;
-pub mod connection;
\ No newline at end of file
+pub mod connection;
+pub mod filter;
+pub mod flag;
+pub mod integrity;
+pub mod journal;
+pub mod ledger;
+pub mod merchant;
+pub mod migrations;
+pub mod settings;
+pub mod split;
+pub mod transaction;
\ No newline at end of file
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+
+// Settings are view preferences rather than data, so unlike the other
+// stores they are not journaled and undo leaves them alone
+pub struct SettingsDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> SettingsDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    pub fn get(&mut self, key: &str) -> Result<Option<String>> {
+        let value = self.conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?, ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use anyhow::{anyhow, bail, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use rust_decimal::Decimal;
+use crate::models::transaction::Split;
+use super::journal::{load_splits, record, write_splits, Change, StoredSplit};
+
+pub struct SplitDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> SplitDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    // Parts are given as positive amounts and take the transaction's sign.
+    // They must add up to the whole amount; no parts removes the split.
+    pub fn set_splits(&mut self, transaction_id: u64, splits: &[Split]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        let id = transaction_id as i64;
+        let amount: String = tx
+            .query_row("SELECT amount FROM transactions WHERE id = ?", params![id], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| anyhow!("No transaction with id {}", transaction_id))?;
+        let amount = Decimal::from_str(&amount)?;
+
+        if splits.len() == 1 {
+            bail!("A split needs at least two categories; assign a single category instead");
+        }
+        let mut seen = HashSet::new();
+        let mut after = Vec::with_capacity(splits.len());
+        for split in splits {
+            if !seen.insert(split.category.as_str()) {
+                bail!("{} appears more than once in the split", split.category);
+            }
+            let category_id: i64 = tx
+                .query_row("SELECT id FROM categories WHERE name = ?", params![split.category], |row| row.get(0))
+                .optional()?
+                .ok_or_else(|| anyhow!("Unknown category '{}'", split.category))?;
+            let part = if amount.is_sign_negative() { -split.amount.abs() } else { split.amount.abs() };
+            after.push(StoredSplit { category_id, category: split.category.clone(), amount: part.to_string() });
+        }
+        let total: Decimal = splits.iter().map(|split| split.amount.abs()).sum();
+        if !splits.is_empty() && total != amount.abs() {
+            bail!("The parts add up to {}, but transaction {} is {}", total, transaction_id, amount.abs());
+        }
+
+        let before = load_splits(&tx, id)?;
+        if before == after {
+            return Ok(());
+        }
+        write_splits(&tx, id, &after)?;
+        let description = if after.is_empty() {
+            format!("Removed the split from #{}", id)
+        } else {
+            format!("Split #{} across {} categories", id, after.len())
+        };
+        record(&tx, &description, &[Change::SplitsChanged { transaction_id: id, before, after }])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_all_splits(&mut self) -> Result<HashMap<u64, Vec<Split>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT s.transaction_id, c.name, s.amount
+             FROM transaction_splits s
+             JOIN categories c ON c.id = s.category_id
+             ORDER BY s.id"
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+
+        let mut splits: HashMap<u64, Vec<Split>> = HashMap::new();
+        for row in rows {
+            let (transaction_id, category, amount) = row?;
+            if let Ok(amount) = Decimal::from_str(&amount) {
+                splits.entry(transaction_id as u64).or_default().push(Split { category, amount });
+            }
+        }
+
+        Ok(splits)
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, Row, Transaction as SqlTransaction};
+use rust_decimal::Decimal;
+use crate::models::transaction::Transaction;
+use super::split::SplitDb;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub struct TransactionDb<'a> {
+    conn: &'a mut Connection,
+}
+
+impl<'a> TransactionDb<'a> {
+    pub fn new(conn: &'a mut Connection) -> Self {
+        Self { conn }
+    }
+
+    // Rows already stored are skipped, so re-importing a statement is safe.
+    // The resulting balance tells apart identical charges on the same day.
+    pub fn save_transactions(&mut self, transactions: &[Transaction]) -> Result<usize> {
+        let ids = self.insert_transactions(transactions)?;
+        Ok(ids.iter().flatten().count())
+    }
+
+    // Like save_transactions, but returns the new id of each row, or None
+    // where the row was already stored
+    pub fn insert_transactions(&mut self, transactions: &[Transaction]) -> Result<Vec<Option<u64>>> {
+        let tx = self.conn.transaction()?;
+        let ids = insert_rows(&tx, transactions)?;
+        tx.commit()?;
+        Ok(ids)
+    }
+
+    pub fn get_all_transactions(&mut self) -> Result<Vec<Transaction>> {
+        let mut tags = self.get_all_tags()?;
+        let excluded = self.get_excluded_ids()?;
+        let mut splits = SplitDb::new(self.conn).get_all_splits()?;
+        let mut stmt = self.conn.prepare(
+            "SELECT id, date, amount, merchant, description, account, balance
+             FROM transactions
+             ORDER BY date DESC, id"
+        )?;
+
+        let rows = stmt.query_map([], read_row)?;
+
+        let mut transactions = Vec::new();
+        for row in rows {
+            let (id, date, amount, merchant, description, account, balance) = row?;
+            transactions.push(Transaction {
+                id: id as u64,
+                date: NaiveDateTime::parse_from_str(&date, DATE_FORMAT)
+                    .with_context(|| format!("Invalid date '{}' for transaction {}", date, id))?,
+                amount: Decimal::from_str(&amount)
+                    .with_context(|| format!("Invalid amount '{}' for transaction {}", amount, id))?,
+                merchant: merchant.clone(),
+                raw_merchant: merchant,
+                description,
+                category: None,
+                category_source: None,
+                account,
+                balance: Decimal::from_str(&balance).ok(),
+                tags: tags.remove(&(id as u64)).unwrap_or_default(),
+                flags: Vec::new(),
+                excluded: excluded.contains(&(id as u64)),
+                splits: splits.remove(&(id as u64)).unwrap_or_default(),
+            });
+        }
+
+        Ok(transactions)
+    }
+
+    pub fn get_all_tags(&mut self) -> Result<HashMap<u64, Vec<String>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT transaction_id, tag FROM transaction_tags ORDER BY id"
+        )?;
+
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut tags: HashMap<u64, Vec<String>> = HashMap::new();
+        for row in rows {
+            let (transaction_id, tag) = row?;
+            tags.entry(transaction_id as u64).or_default().push(tag);
+        }
+
+        Ok(tags)
+    }
+
+    pub fn get_excluded_ids(&mut self) -> Result<HashSet<u64>> {
+        let mut stmt = self.conn.prepare("SELECT transaction_id FROM excluded_transactions")?;
+        let rows = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+
+        let mut ids = HashSet::new();
+        for row in rows {
+            ids.insert(row? as u64);
+        }
+        Ok(ids)
+    }
+
+    pub fn exists(&mut self, id: u64) -> Result<bool> {
+        let exists = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM transactions WHERE id = ?)",
+            params![id as i64],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    pub fn count(&mut self) -> Result<usize> {
+        let count: i64 = self.conn.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+}
+
+type TransactionRow = (i64, String, String, String, String, String, String);
+
+// For callers that store more in the same database transaction
+pub(crate) fn insert_rows(tx: &SqlTransaction, transactions: &[Transaction]) -> Result<Vec<Option<u64>>> {
+    let mut ids = Vec::with_capacity(transactions.len());
+
+    let mut stmt = tx.prepare(
+        "INSERT OR IGNORE INTO transactions (date, amount, merchant, description, account, balance)
+         VALUES (?, ?, ?, ?, ?, ?)"
+    )?;
+    let mut tag_stmt = tx.prepare(
+        "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag) VALUES (?, ?)"
+    )?;
+    for transaction in transactions {
+        let rows = stmt.execute(params![
+            transaction.date.format(DATE_FORMAT).to_string(),
+            transaction.amount.to_string(),
+            transaction.raw_merchant,
+            transaction.description,
+            transaction.account,
+            transaction.balance.map(|b| b.to_string()).unwrap_or_default(),
+        ])?;
+        if rows == 0 {
+            ids.push(None);
+            continue;
+        }
+
+        let id = tx.last_insert_rowid();
+        for tag in &transaction.tags {
+            tag_stmt.execute(params![id, tag])?;
+        }
+        ids.push(Some(id as u64));
+    }
+    Ok(ids)
+}
+
+fn read_row(row: &Row) -> rusqlite::Result<TransactionRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+        row.get(6)?,
+    ))
+}
@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use crate::analysis::aggregate::{category_totals, sorted_totals};
+use crate::models::transaction::Transaction;
+
+const TRANSACTION_COLUMNS: [&str; 9] = ["id", "date", "amount", "merchant", "category", "splits", "tags", "account", "description"];
+const SUMMARY_COLUMNS: [&str; 3] = ["category", "transactions", "total"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    JsonLines,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &str) -> Option<ExportFormat> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            "jsonl" | "ndjson" => Some(ExportFormat::JsonLines),
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Markdown => "Markdown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryRow {
+    pub category: String,
+    pub transactions: usize,
+    pub total: Decimal,
+}
+
+#[derive(Serialize)]
+struct SplitRecord<'a> {
+    category: &'a str,
+    amount: Decimal,
+}
+
+#[derive(Serialize)]
+struct TransactionRecord<'a> {
+    id: u64,
+    date: String,
+    amount: Decimal,
+    merchant: &'a str,
+    raw_merchant: &'a str,
+    category: &'a str,
+    splits: Vec<SplitRecord<'a>>,
+    tags: &'a [String],
+    account: &'a str,
+    description: &'a str,
+}
+
+impl<'a> From<&'a Transaction> for TransactionRecord<'a> {
+    fn from(transaction: &'a Transaction) -> Self {
+        TransactionRecord {
+            id: transaction.id,
+            date: transaction.date.format("%Y-%m-%d").to_string(),
+            amount: transaction.amount,
+            merchant: &transaction.merchant,
+            raw_merchant: &transaction.raw_merchant,
+            category: transaction.category.as_deref().unwrap_or("Uncategorized"),
+            splits: transaction
+                .splits
+                .iter()
+                .map(|split| SplitRecord { category: &split.category, amount: split.amount })
+                .collect(),
+            tags: &transaction.tags,
+            account: &transaction.account,
+            description: &transaction.description,
+        }
+    }
+}
+
+impl TransactionRecord<'_> {
+    fn fields(&self) -> [String; 9] {
+        [
+            self.id.to_string(),
+            self.date.clone(),
+            self.amount.to_string(),
+            self.merchant.to_string(),
+            self.category.to_string(),
+            self.splits
+                .iter()
+                .map(|split| format!("{}={}", split.category, split.amount))
+                .collect::<Vec<_>>()
+                .join("; "),
+            self.tags.join(" "),
+            self.account.to_string(),
+            self.description.to_string(),
+        ]
+    }
+}
+
+// Category totals and counts for the given transactions, largest first. A
+// split transaction counts once under each of its categories.
+pub fn summarize(transactions: &[&Transaction]) -> Vec<SummaryRow> {
+    let totals = category_totals(transactions.iter().copied());
+    sorted_totals(&totals)
+        .into_iter()
+        .map(|(category, total)| SummaryRow {
+            category: category.to_string(),
+            transactions: transactions
+                .iter()
+                .filter(|t| t.allocations().iter().any(|(name, _)| *name == category))
+                .count(),
+            total,
+        })
+        .collect()
+}
+
+pub fn write_transactions<W: Write>(format: ExportFormat, transactions: &[&Transaction], mut writer: W) -> Result<()> {
+    let records = transactions.iter().map(|&t| TransactionRecord::from(t));
+
+    match format {
+        ExportFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(&mut writer);
+            csv_writer.write_record(TRANSACTION_COLUMNS)?;
+            for record in records {
+                csv_writer.write_record(record.fields())?;
+            }
+            csv_writer.flush()?;
+        }
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, &records.collect::<Vec<_>>())?;
+            writeln!(writer)?;
+        }
+        ExportFormat::JsonLines => {
+            for record in records {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        }
+        ExportFormat::Markdown => {
+            write_markdown_row(&mut writer, &TRANSACTION_COLUMNS)?;
+            write_markdown_rule(&mut writer, &[false, false, true, false, false, false, false, false, false])?;
+            for record in records {
+                write_markdown_row(&mut writer, &record.fields())?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_summary<W: Write>(format: ExportFormat, rows: &[SummaryRow], mut writer: W) -> Result<()> {
+    let fields = |row: &SummaryRow| [row.category.clone(), row.transactions.to_string(), format!("{:.2}", row.total)];
+
+    match format {
+        ExportFormat::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(&mut writer);
+            csv_writer.write_record(SUMMARY_COLUMNS)?;
+            for row in rows {
+                csv_writer.write_record(fields(row))?;
+            }
+            csv_writer.flush()?;
+        }
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, rows)?;
+            writeln!(writer)?;
+        }
+        ExportFormat::JsonLines => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, row)?;
+                writeln!(writer)?;
+            }
+        }
+        ExportFormat::Markdown => {
+            write_markdown_row(&mut writer, &SUMMARY_COLUMNS)?;
+            write_markdown_rule(&mut writer, &[false, true, true])?;
+            for row in rows {
+                write_markdown_row(&mut writer, &fields(row))?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// Writes to `path`, picking the format from its extension
+pub fn export_to_file(path: &str, transactions: &[&Transaction], summary: bool) -> Result<ExportFormat> {
+    let format = ExportFormat::from_path(path)
+        .ok_or_else(|| anyhow!("Unknown export format for '{}' (use .csv, .json, .jsonl or .md)", path))?;
+    let file = File::create(path)?;
+
+    if summary {
+        write_summary(format, &summarize(transactions), file)?;
+    } else {
+        write_transactions(format, transactions, file)?;
+    }
+    Ok(format)
+}
+
+fn write_markdown_row<W: Write, S: AsRef<str>>(writer: &mut W, cells: &[S]) -> Result<()> {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| cell.as_ref().replace('|', "\\|").replace('\n', " "))
+        .collect();
+    writeln!(writer, "| {} |", cells.join(" | "))?;
+    Ok(())
+}
+
+fn write_markdown_rule<W: Write>(writer: &mut W, right_aligned: &[bool]) -> Result<()> {
+    let rule: Vec<&str> = right_aligned
+        .iter()
+        .map(|&right| if right { "---:" } else { "---" })
+        .collect();
+    writeln!(writer, "| {} |", rule.join(" | "))?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::db::{category::{self, CategoryDb}, merchant::MerchantDb, transaction::{self, TransactionDb}};
+use crate::ledger::{beancount, AccountMap};
+use crate::models::category::{Category, RuleMatcher, SourceKind};
+use crate::models::merchant::{MerchantAlias, MerchantNormalizer};
+use crate::models::transaction::Transaction;
+use crate::utils::csv::CsvTransactions;
+
+// Rows are read and stored this many at a time, each batch in its own
+// database transaction
+pub const IMPORT_BATCH: usize = 1000;
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+    pub read: usize,
+    pub inserted: usize,
+    // Entries that could not be imported, and why
+    pub skipped_reasons: Vec<String>,
+    // Rows no rule matched, stored or not
+    pub uncategorized: usize,
+}
+
+impl ImportSummary {
+    pub fn duplicates(&self) -> usize {
+        self.read - self.inserted
+    }
+}
+
+// Works out how many imported rows no rule will categorize. Merchants and
+// rule matches are worked out again whenever the app loads, so only the
+// count is kept; nothing here is stored.
+#[derive(Debug, Default)]
+pub struct ImportPipeline {
+    normalizer: MerchantNormalizer,
+    categories: HashMap<String, Category>,
+}
+
+impl ImportPipeline {
+    pub fn new(aliases: Vec<MerchantAlias>, categories: Vec<Category>) -> Self {
+        ImportPipeline {
+            normalizer: MerchantNormalizer::new(aliases),
+            categories: categories.into_iter().map(|c| (c.name.clone(), c)).collect(),
+        }
+    }
+
+    pub fn load(conn: &mut Connection) -> Result<Self> {
+        let aliases = MerchantDb::new(conn).get_all_aliases()?;
+        let categories = CategoryDb::new(conn).get_all_categories()?;
+        Ok(Self::new(aliases, categories))
+    }
+
+    // Built once per import and passed to `uncategorized`
+    pub fn rules(&self) -> RuleMatcher<'_> {
+        RuleMatcher::new(&self.categories)
+    }
+
+    // Rows that arrive with a category of their own count as categorized
+    pub fn uncategorized<'t>(&self, rules: &RuleMatcher, rows: impl IntoIterator<Item = &'t Transaction>) -> usize {
+        rows.into_iter()
+            .filter(|t| t.category.is_none())
+            .filter(|t| {
+                let merchant = self.normalizer.normalize(&t.raw_merchant);
+                rules.find_for(&merchant, &t.raw_merchant, &t.description).is_none()
+            })
+            .count()
+    }
+
+    // Reads and stores rows a batch at a time, so memory doesn't grow with
+    // the input. Batches stored before an error are kept, and `summary`
+    // says how far the import got.
+    pub fn import<I>(&self, conn: &mut Connection, rows: I, summary: &mut ImportSummary) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<Transaction>>,
+    {
+        let rules = self.rules();
+        let mut rows = rows.into_iter();
+        loop {
+            let batch = rows.by_ref().take(IMPORT_BATCH).collect::<Result<Vec<_>>>()?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            summary.uncategorized += self.uncategorized(&rules, &batch);
+            summary.inserted += TransactionDb::new(conn).save_transactions(&batch)?;
+            summary.read += batch.len();
+        }
+    }
+}
+
+pub fn import_csv(conn: &mut Connection, path: &str) -> Result<ImportSummary> {
+    let rows = CsvTransactions::open(path)
+        .with_context(|| format!("Failed to read transactions from {}", path))?;
+    let pipeline = ImportPipeline::load(conn)?;
+
+    let mut summary = ImportSummary::default();
+    pipeline.import(conn, rows, &mut summary).with_context(|| format!(
+        "Failed to read transactions from {} ({} new transactions before the error were kept)",
+        path, summary.inserted
+    ))?;
+    Ok(summary)
+}
+
+// Beancount entries become transactions on their asset account. The other
+// posting's account is mapped back to a category where one matches.
+pub fn import_beancount(conn: &mut Connection, path: &str) -> Result<ImportSummary> {
+    let input = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path))?;
+    let entries = beancount::parse(&input)
+        .with_context(|| format!("Failed to parse {}", path))?;
+
+    let map = AccountMap::load(conn)?;
+
+    let conversion = beancount::to_transactions(&entries, &map);
+
+    let (mut transactions, categories): (Vec<_>, Vec<_>) = conversion.transactions.into_iter().unzip();
+    for (transaction, category) in transactions.iter_mut().zip(&categories) {
+        transaction.category = category.clone();
+    }
+    let pipeline = ImportPipeline::load(conn)?;
+    let uncategorized = pipeline.uncategorized(&pipeline.rules(), &transactions);
+
+    // Rows stored without their categories would never get them back, as
+    // importing again skips them as duplicates
+    let tx = conn.transaction()?;
+    let ids = transaction::insert_rows(&tx, &transactions)?;
+    for (id, category) in ids.iter().zip(&categories) {
+        if let (Some(id), Some(category)) = (id, category)
+            && let Some(category_id) = category::category_id(&tx, category)?
+        {
+            category::assign(&tx, *id as i64, category_id, SourceKind::Import, None)?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(ImportSummary {
+        read: transactions.len(),
+        inserted: ids.iter().flatten().count(),
+        skipped_reasons: conversion.skipped,
+        uncategorized,
+    })
+}
+
+// Picks the importer from the file extension; anything else is read as an ING CSV
+pub fn import_file(conn: &mut Connection, path: &str) -> Result<ImportSummary> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+
+    match extension.as_deref() {
+        Some("beancount" | "bean") => import_beancount(conn, path),
+        _ => import_csv(conn, path),
+    }
+}
@@ -0,0 +1,236 @@
+use std::str::FromStr;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use crate::models::{category::CategoryType, transaction::Transaction};
+use super::{AccountMap, COMMODITY};
+
+#[derive(Debug, Clone)]
+pub struct Posting {
+    pub account: String,
+    pub amount: Option<Decimal>,
+    pub commodity: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub line: usize,
+    pub date: NaiveDate,
+    pub payee: Option<String>,
+    pub narration: String,
+    pub tags: Vec<String>,
+    pub balance: Option<Decimal>,
+    pub postings: Vec<Posting>,
+}
+
+// Only transactions are read; open, balance, price and other directives are ignored
+pub fn parse(input: &str) -> Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut in_transaction = false;
+
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let content = strip_comment(line);
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            if !in_transaction {
+                continue;
+            }
+            // Metadata keys start lowercase, account names never do
+            let trimmed = content.trim();
+            if trimmed.starts_with(|c: char| c.is_ascii_lowercase()) {
+                if let Some(value) = trimmed.strip_prefix("balance:")
+                    && let Some(entry) = entries.last_mut()
+                {
+                    entry.balance = Decimal::from_str(value.trim().trim_matches('"')).ok();
+                }
+                continue;
+            }
+            let posting = parse_posting(trimmed)
+                .map_err(|e| anyhow!("Line {}: {}", line_number, e))?;
+            if let Some(entry) = entries.last_mut() {
+                entry.postings.push(posting);
+            }
+            continue;
+        }
+
+        in_transaction = false;
+        let mut tokens = content.split_whitespace();
+        let (Some(date_token), Some(directive)) = (tokens.next(), tokens.next()) else {
+            continue;
+        };
+        let Ok(date) = NaiveDate::parse_from_str(date_token, "%Y-%m-%d") else {
+            continue;
+        };
+        if !matches!(directive, "*" | "!" | "txn") {
+            continue;
+        }
+
+        let header = content.trim_start()[date_token.len()..].trim_start()[directive.len()..].trim();
+        let (strings, tags) = parse_header(header)
+            .map_err(|e| anyhow!("Line {}: {}", line_number, e))?;
+        let (payee, narration) = match strings.len() {
+            0 => (None, String::new()),
+            1 => (None, strings[0].clone()),
+            _ => (Some(strings[0].clone()), strings[1].clone()),
+        };
+
+        entries.push(Entry {
+            line: line_number,
+            date,
+            payee,
+            narration,
+            tags,
+            balance: None,
+            postings: Vec::new(),
+        });
+        in_transaction = true;
+    }
+
+    Ok(entries)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Quoted payee/narration strings followed by #tags and ^links
+fn parse_header(header: &str) -> Result<(Vec<String>, Vec<String>)> {
+    let mut strings = Vec::new();
+    let mut tags = Vec::new();
+    let mut chars = header.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(anyhow!("Unterminated string")),
+                    }
+                }
+                strings.push(value);
+            }
+            '#' | '^' => {
+                chars.next();
+                let name: String = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect();
+                if c == '#' && !name.is_empty() {
+                    tags.push(name);
+                }
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            other => return Err(anyhow!("Unexpected '{}' in transaction header", other)),
+        }
+    }
+
+    Ok((strings, tags))
+}
+
+fn parse_posting(line: &str) -> Result<Posting> {
+    let mut tokens = line.split_whitespace().peekable();
+    // Optional per-posting flag
+    tokens.next_if(|t| *t == "*" || *t == "!");
+
+    let account = tokens.next().ok_or_else(|| anyhow!("Missing account"))?.to_string();
+    let amount = match tokens.next() {
+        Some(amount) => Some(
+            Decimal::from_str(&amount.replace(',', "")).map_err(|_| anyhow!("Invalid amount '{}' for {}", amount, account))?
+        ),
+        None => None,
+    };
+    // Costs and prices after the commodity are not needed for import
+    let commodity = tokens.next().map(str::to_string);
+
+    Ok(Posting { account, amount, commodity })
+}
+
+pub struct Conversion {
+    pub transactions: Vec<(Transaction, Option<String>)>,
+    pub skipped: Vec<String>,
+}
+
+// One transaction per entry, seen from the bank account's side. The first
+// other posting decides the category.
+pub fn to_transactions(entries: &[Entry], map: &AccountMap) -> Conversion {
+    let mut conversion = Conversion {
+        transactions: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for entry in entries {
+        let bank_index = entry.postings
+            .iter()
+            .position(|p| map.bank_for(&p.account).is_some())
+            .or_else(|| entry.postings
+                .iter()
+                .position(|p| p.account.starts_with("Assets:") || p.account.starts_with("Liabilities:")));
+        let Some(bank_index) = bank_index else {
+            conversion.skipped.push(format!("line {}: no asset or liability posting", entry.line));
+            continue;
+        };
+
+        let bank = &entry.postings[bank_index];
+        let amount = match bank.amount {
+            Some(amount) => amount,
+            None => -entry.postings.iter().filter_map(|p| p.amount).sum::<Decimal>(),
+        };
+        if entry.postings.iter().any(|p| p.commodity.as_deref().is_some_and(|c| c != COMMODITY)) {
+            conversion.skipped.push(format!("line {}: only {} amounts are supported", entry.line, COMMODITY));
+            continue;
+        }
+
+        let category = entry.postings
+            .iter()
+            .enumerate()
+            .find(|(i, _)| *i != bank_index)
+            .and_then(|(_, p)| map.category_for(&p.account))
+            .filter(|c| c != CategoryType::Uncategorized.as_str());
+
+        let (merchant, description) = match &entry.payee {
+            Some(payee) if !payee.is_empty() => (payee.clone(), entry.narration.clone()),
+            _ => (entry.narration.clone(), String::new()),
+        };
+
+        conversion.transactions.push((
+            Transaction {
+                id: 0,
+                date: entry.date.and_hms_opt(0, 0, 0).unwrap_or_default(),
+                amount,
+                merchant: merchant.clone(),
+                raw_merchant: merchant,
+                description,
+                category: None,
+                category_source: None,
+                account: map.bank_for(&bank.account).unwrap_or_else(|| bank.account.clone()),
+                balance: entry.balance,
+                tags: entry.tags.clone(),
+                flags: Vec::new(),
+                excluded: false,
+                splits: Vec::new(),
+            },
+            category,
+        ));
+    }
+
+    conversion
+}
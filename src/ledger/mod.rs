@@ -0,0 +1,268 @@
+pub mod beancount;
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use rusqlite::Connection;
+use rust_decimal::Decimal;
+use crate::db::{category::CategoryDb, ledger::LedgerDb};
+use crate::models::{category::CategoryType, transaction::Transaction};
+
+// ING statements are always in euros
+pub const COMMODITY: &str = "EUR";
+
+const ROOT_ACCOUNTS: [&str; 5] = ["Assets", "Liabilities", "Equity", "Income", "Expenses"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JournalFormat {
+    Ledger,
+    Hledger,
+    Beancount,
+}
+
+impl JournalFormat {
+    pub fn from_path(path: &str) -> Option<JournalFormat> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ledger" | "dat" => Some(JournalFormat::Ledger),
+            "journal" | "hledger" | "j" => Some(JournalFormat::Hledger),
+            "beancount" | "bean" => Some(JournalFormat::Beancount),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            JournalFormat::Ledger => "Ledger",
+            JournalFormat::Hledger => "hledger",
+            JournalFormat::Beancount => "Beancount",
+        }
+    }
+}
+
+// Maps categories and bank account numbers to journal account names.
+// Anything without an explicit mapping gets a name derived from it.
+#[derive(Debug, Clone)]
+pub struct AccountMap {
+    mappings: HashMap<String, String>,
+    categories: Vec<String>,
+}
+
+impl AccountMap {
+    pub fn new(mappings: HashMap<String, String>, categories: Vec<String>) -> Self {
+        AccountMap { mappings, categories }
+    }
+
+    pub fn load(conn: &mut Connection) -> Result<Self> {
+        let mappings = LedgerDb::new(conn).get_accounts()?;
+        let categories = CategoryDb::new(conn)
+            .get_all_categories()?
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        Ok(AccountMap::new(mappings, categories))
+    }
+
+    pub fn category_account(&self, category: Option<&str>, amount: Decimal) -> String {
+        let category = category.unwrap_or(CategoryType::Uncategorized.as_str());
+        if let Some(account) = self.mappings.get(category) {
+            return account.clone();
+        }
+
+        if category == CategoryType::InternalTransfer.as_str() {
+            "Assets:Transfers".to_string()
+        } else if category == CategoryType::Uncategorized.as_str() && amount > Decimal::ZERO {
+            "Income:Uncategorized".to_string()
+        } else {
+            format!("Expenses:{}", account_component(category))
+        }
+    }
+
+    pub fn bank_account(&self, account: &str) -> String {
+        match self.mappings.get(account) {
+            Some(mapped) => mapped.clone(),
+            None => format!("Assets:Bank:{}", account_component(account)),
+        }
+    }
+
+    // Reverse lookups used when importing a journal
+    pub fn category_for(&self, account: &str) -> Option<String> {
+        self.categories
+            .iter()
+            .find(|category| {
+                self.category_account(Some(category), -Decimal::ONE) == account
+                    || self.category_account(Some(category), Decimal::ONE) == account
+            })
+            .cloned()
+    }
+
+    pub fn bank_for(&self, account: &str) -> Option<String> {
+        if let Some((name, _)) = self.mappings
+            .iter()
+            .find(|(name, mapped)| *mapped == account && !self.categories.contains(name))
+        {
+            return Some(name.clone());
+        }
+        account.strip_prefix("Assets:Bank:").map(str::to_string)
+    }
+
+    pub fn mappings(&self) -> &HashMap<String, String> {
+        &self.mappings
+    }
+}
+
+// Beancount is the strictest of the three, so names valid there work everywhere
+pub fn validate_account(account: &str) -> Result<()> {
+    let mut components = account.split(':');
+    let root = components.next().unwrap_or_default();
+    if !ROOT_ACCOUNTS.contains(&root) {
+        return Err(anyhow!("Account '{}' must start with one of {}", account, ROOT_ACCOUNTS.join(", ")));
+    }
+
+    let mut count = 0;
+    for component in components {
+        let valid_start = component.chars().next().is_some_and(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        let valid_rest = component.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid_start || !valid_rest {
+            return Err(anyhow!(
+                "Invalid component '{}' in account '{}' (use letters, digits and '-', starting with a capital or digit)",
+                component,
+                account
+            ));
+        }
+        count += 1;
+    }
+
+    if count == 0 {
+        return Err(anyhow!("Account '{}' needs at least one component after '{}'", account, root));
+    }
+    Ok(())
+}
+
+// "Internal Transfer" -> "InternalTransfer"
+fn account_component(name: &str) -> String {
+    let component: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase()).unwrap_or_default();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect();
+
+    if component.is_empty() { "Unknown".to_string() } else { component }
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_/.".contains(c) { c } else { '-' })
+        .collect()
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", single_line(text).replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// One posting per split part, or one for the whole amount
+fn counterparts(transaction: &Transaction, map: &AccountMap) -> Vec<(String, Decimal)> {
+    if transaction.splits.is_empty() {
+        return vec![(map.category_account(transaction.category.as_deref(), transaction.amount), transaction.amount)];
+    }
+    transaction.splits
+        .iter()
+        .map(|split| (map.category_account(Some(&split.category), split.amount), split.amount))
+        .collect()
+}
+
+pub fn write_journal<W: Write>(
+    format: JournalFormat,
+    transactions: &[&Transaction],
+    map: &AccountMap,
+    mut writer: W,
+) -> Result<()> {
+    let mut transactions = transactions.to_vec();
+    transactions.sort_by(|a, b| a.date.cmp(&b.date).then(a.id.cmp(&b.id)));
+
+    let accounts: BTreeSet<String> = transactions
+        .iter()
+        .flat_map(|t| {
+            std::iter::once(map.bank_account(&t.account))
+                .chain(counterparts(t, map).into_iter().map(|(account, _)| account))
+        })
+        .collect();
+
+    if let Some(first) = transactions.first() {
+        let opened = first.date.format("%Y-%m-%d");
+        match format {
+            JournalFormat::Beancount => {
+                writeln!(writer, "option \"operating_currency\" \"{}\"\n", COMMODITY)?;
+                for account in &accounts {
+                    writeln!(writer, "{} open {}", opened, account)?;
+                }
+            }
+            JournalFormat::Ledger | JournalFormat::Hledger => {
+                writeln!(writer, "commodity {}\n", COMMODITY)?;
+                for account in &accounts {
+                    writeln!(writer, "account {}", account)?;
+                }
+            }
+        }
+        writeln!(writer)?;
+    }
+
+    for transaction in transactions {
+        let bank = map.bank_account(&transaction.account);
+        let payee = single_line(&transaction.merchant);
+        let description = single_line(&transaction.description);
+
+        match format {
+            JournalFormat::Ledger => {
+                writeln!(writer, "{} * {}", transaction.date.format("%Y/%m/%d"), payee)?;
+                if !description.is_empty() {
+                    writeln!(writer, "    ; {}", description)?;
+                }
+                if !transaction.tags.is_empty() {
+                    let tags: Vec<String> = transaction.tags.iter().map(|t| tag_name(t)).collect();
+                    writeln!(writer, "    ; :{}:", tags.join(":"))?;
+                }
+            }
+            JournalFormat::Hledger => {
+                write!(writer, "{} * {}", transaction.date.format("%Y-%m-%d"), payee)?;
+                if !description.is_empty() {
+                    write!(writer, " | {}", description)?;
+                }
+                if !transaction.tags.is_empty() {
+                    let tags: Vec<String> = transaction.tags.iter().map(|t| format!("{}:", tag_name(t))).collect();
+                    write!(writer, "  ; {}", tags.join(", "))?;
+                }
+                writeln!(writer)?;
+            }
+            JournalFormat::Beancount => {
+                write!(writer, "{} * {} {}", transaction.date.format("%Y-%m-%d"), quoted(&payee), quoted(&description))?;
+                for tag in &transaction.tags {
+                    write!(writer, " #{}", tag_name(tag))?;
+                }
+                writeln!(writer)?;
+                // Keeps identical same-day charges apart when the file is imported again
+                if let Some(balance) = transaction.balance {
+                    writeln!(writer, "    balance: \"{}\"", balance)?;
+                }
+            }
+        }
+
+        for (account, amount) in counterparts(transaction, map) {
+            writeln!(writer, "    {:<48}  {:>12.2} {}", account, -amount, COMMODITY)?;
+        }
+        writeln!(writer, "    {:<48}  {:>12.2} {}", bank, transaction.amount, COMMODITY)?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
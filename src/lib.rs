@@ -2,9 +2,20 @@ pub mod models;
 pub mod ui;
 pub mod utils;
 pub mod db;
+pub mod analysis;
+pub mod import;
+pub mod export;
+pub mod archive;
+pub mod report;
+pub mod ledger;
+pub mod query;
+pub mod repository;
+pub mod cli;
+pub mod config;
+pub mod passphrase;
 
 // Re-export commonly used items
 pub use models::transaction::Transaction;
 pub use models::category::{Category, CategoryType};
 pub use ui::app::App;
-pub use db::{connection::DbConnection, category::CategoryDb};
\ No newline at end of file
+pub use db::{connection::DbConnection, category::CategoryDb, settings::SettingsDb};
\ No newline at end of file
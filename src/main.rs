@@ -1,5 +1,8 @@
 use std::io;
-use anyhow::Result;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use anyhow::{bail, Result};
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
     execute,
@@ -12,20 +15,38 @@ use ratatui::{
 };
 
 use finance_analyzer::{
+    cli::{commands, Cli, Command, EXIT_FAILURE, EXIT_SUCCESS},
+    config::{default_db_location, Config, Profile, UNLOCK_ATTEMPTS},
+    db::connection::DbConnection,
+    models::flag::FlagStatus,
     ui::{
-        app::{App, InputMode, View},
+        app::{App, InputMode, LockScreen, View},
         render::{
             render_transaction_list, render_popup, render_category_summary,
             render_help_panel, render_category_selection, render_input_prompt,
+            render_forecast, render_review_queue, render_merchant_report, render_category_detail,
+            render_sort_menu, render_column_menu, render_batch_menu, render_history, render_header,
+            render_lock_screen, render_task_progress,
         },
     },
 };
 
+// How often progress is redrawn while a background task runs
+const TASK_TICK: Duration = Duration::from_millis(100);
+
+enum Exit {
+    Quit,
+    Locked,
+}
+
+// Takes the app by value so locking drops it, and the decrypted data with it
 fn run_app(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     mut app: App,
-) -> Result<()> {
+) -> Result<Exit> {
+    let mut last_input = Instant::now();
     loop {
+        app.poll_task();
         terminal.draw(|f| {
             let size = f.size();
             
@@ -33,19 +54,30 @@ fn run_app(
                 .direction(Direction::Vertical)
                 .margin(1)
                 .constraints([
+                    Constraint::Length(1),
                     Constraint::Min(3),
                     Constraint::Length(3),
                 ].as_ref())
                 .split(size);
 
+            render_header(f, &app, chunks[0]);
+
             match app.current_view {
-                View::TransactionList => render_transaction_list(f, &app, chunks[0]),
-                View::CategorySummary => render_category_summary(f, &app, chunks[0]),
-                View::TransactionDetail => render_transaction_list(f, &app, chunks[0]),
-                View::CategoryDetail => render_category_summary(f, &app, chunks[0]),
+                View::TransactionList => render_transaction_list(f, &mut app, chunks[1]),
+                View::CategorySummary => render_category_summary(f, &app, chunks[1]),
+                View::TransactionDetail => render_transaction_list(f, &mut app, chunks[1]),
+                View::CategoryDetail => render_category_detail(f, &app, chunks[1]),
+                View::Forecast => render_forecast(f, &app, chunks[1]),
+                View::ReviewQueue => render_review_queue(f, &app, chunks[1]),
+                View::MerchantReport => render_merchant_report(f, &app, chunks[1]),
+                View::History => render_history(f, &app, chunks[1]),
             }
             
-            render_help_panel(f, chunks[1]);
+            if app.task.is_some() {
+                render_task_progress(f, &app, chunks[2]);
+            } else {
+                render_help_panel(f, &app, chunks[2]);
+            }
 
             if matches!(app.current_view, View::TransactionDetail) {
                 render_popup(f, &app, size);
@@ -58,73 +90,197 @@ fn run_app(
             if app.input_mode == InputMode::Categorizing {
                 render_category_selection(f, &app, size);
             }
+
+            if app.input_mode == InputMode::Sorting {
+                render_sort_menu(f, &app, size);
+            }
+
+            if app.input_mode == InputMode::EditingColumns {
+                render_column_menu(f, &app, size);
+            }
+
+            if app.input_mode == InputMode::BatchMenu {
+                render_batch_menu(f, &app, size);
+            }
         })?;
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                match app.input_mode {
-                    InputMode::Normal => {
-                        match key.code {
-                            KeyCode::Char('q') => return Ok(()),
-                            KeyCode::Char('c') => {
-                                if app.selected_transaction.is_some() {
-                                    app.input_mode = InputMode::Categorizing;
-                                    app.category_selection = Some(0);
-                                }
-                            }
-                            KeyCode::Tab => {
-                                app.current_view = match app.current_view {
-                                    View::TransactionList => View::CategorySummary,
-                                    View::CategorySummary => View::TransactionList,
-                                    View::TransactionDetail => View::TransactionList,
-                                    View::CategoryDetail => View::CategorySummary,
+        let mut timeout = app.task.as_ref().map(|_| TASK_TICK);
+        // A running task holds off locking until it's done
+        if let Some(lock_after) = app.lock_after()
+            && app.task.is_none()
+        {
+            let idle = last_input.elapsed();
+            if idle >= lock_after {
+                return Ok(Exit::Locked);
+            }
+            timeout = Some(lock_after - idle);
+        }
+        if let Some(timeout) = timeout
+            && !event::poll(timeout)?
+        {
+            continue;
+        }
+        let event = event::read()?;
+        last_input = Instant::now();
+
+        if let Event::Key(key) = event
+            && key.kind == KeyEventKind::Press
+        {
+            app.status_message = None;
+            match app.input_mode {
+                InputMode::Normal => {
+                    match key.code {
+                        KeyCode::Char('q') => return Ok(Exit::Quit),
+                        KeyCode::Esc if app.task.is_some() => app.cancel_task(),
+                        KeyCode::Char('c') => app.start_categorizing(false),
+                        KeyCode::Char('C') if matches!(app.current_view, View::CategoryDetail) => {
+                            app.start_categorizing(true);
+                        }
+                        KeyCode::Tab => {
+                            app.current_view = match app.current_view {
+                                View::TransactionList => View::CategorySummary,
+                                View::CategorySummary => View::MerchantReport,
+                                View::MerchantReport => View::Forecast,
+                                View::Forecast => View::TransactionList,
+                                View::ReviewQueue => View::TransactionList,
+                                View::TransactionDetail => View::TransactionList,
+                                View::CategoryDetail => View::CategorySummary,
+                                View::History => View::TransactionList,
+                            };
+                        }
+                        KeyCode::Char('d') => {
+                            if let View::TransactionList = app.current_view {
+                                app.current_view = if matches!(app.current_view, View::TransactionDetail) {
+                                    View::TransactionList
+                                } else {
+                                    View::TransactionDetail
                                 };
                             }
-                            KeyCode::Char('d') => {
-                                if let View::TransactionList = app.current_view {
-                                    app.current_view = if matches!(app.current_view, View::TransactionDetail) {
-                                        View::TransactionList
-                                    } else {
-                                        View::TransactionDetail
-                                    };
-                                }
-                            }
-                            KeyCode::Esc => {
-                                if let View::TransactionDetail = app.current_view {
+                        }
+                        KeyCode::Esc => {
+                            match app.current_view {
+                                View::TransactionDetail | View::ReviewQueue | View::History => {
                                     app.current_view = View::TransactionList;
                                 }
+                                View::CategoryDetail => app.current_view = View::CategorySummary,
+                                View::TransactionList => app.clear_marks(),
+                                _ => {}
                             }
-                            KeyCode::Up => app.previous(),
-                            KeyCode::Down => app.next(),
-                            KeyCode::Char('s') => app.toggle_sort_order(),
-                            KeyCode::Char('f') => {
-                                app.input_mode = InputMode::Filtering;
+                        }
+                        KeyCode::Up if matches!(app.current_view, View::ReviewQueue) => app.previous_flag(),
+                        KeyCode::Down if matches!(app.current_view, View::ReviewQueue) => app.next_flag(),
+                        KeyCode::Up if matches!(app.current_view, View::MerchantReport) => app.previous_merchant(),
+                        KeyCode::Down if matches!(app.current_view, View::MerchantReport) => app.next_merchant(),
+                        KeyCode::Enter if matches!(app.current_view, View::MerchantReport) => app.drill_down_merchant(),
+                        KeyCode::Char('p') if matches!(app.current_view, View::MerchantReport) => {
+                            app.cycle_merchant_period();
+                        }
+                        KeyCode::Up if matches!(app.current_view, View::CategorySummary) => app.previous_category(),
+                        KeyCode::Down if matches!(app.current_view, View::CategorySummary) => app.next_category(),
+                        KeyCode::Enter if matches!(app.current_view, View::CategorySummary) => app.open_category_detail(),
+                        KeyCode::Up if matches!(app.current_view, View::CategoryDetail) => app.previous_detail_transaction(),
+                        KeyCode::Down if matches!(app.current_view, View::CategoryDetail) => app.next_detail_transaction(),
+                        KeyCode::Up if matches!(app.current_view, View::History) => app.previous_history_entry(),
+                        KeyCode::Down if matches!(app.current_view, View::History) => app.next_history_entry(),
+                        KeyCode::Up => app.previous(),
+                        KeyCode::Down => app.next(),
+                        KeyCode::PageUp if matches!(app.current_view, View::TransactionList) => app.page_up(),
+                        KeyCode::PageDown if matches!(app.current_view, View::TransactionList) => app.page_down(),
+                        KeyCode::Home if matches!(app.current_view, View::TransactionList) => app.select_first(),
+                        KeyCode::End if matches!(app.current_view, View::TransactionList) => app.select_last(),
+                        KeyCode::Char('g') if matches!(app.current_view, View::TransactionList) => {
+                            app.input_mode = InputMode::JumpingToDate;
+                        }
+                        KeyCode::Char('s') if matches!(app.current_view, View::TransactionList) => {
+                            app.open_sort_menu();
+                        }
+                        KeyCode::Char(' ') if matches!(app.current_view, View::TransactionList) => app.toggle_mark(),
+                        KeyCode::Char('A') if matches!(app.current_view, View::TransactionList) => app.toggle_mark_all(),
+                        KeyCode::Char('V') if matches!(app.current_view, View::TransactionList) => app.mark_range(),
+                        KeyCode::Char('b') if matches!(app.current_view, View::TransactionList | View::TransactionDetail) => {
+                            app.open_batch_menu();
+                        }
+                        KeyCode::Char('u') => app.undo(),
+                        KeyCode::Char('U') => app.redo(),
+                        KeyCode::Char('H') => app.open_history(),
+                        KeyCode::Char('v') if matches!(app.current_view, View::TransactionList | View::CategoryDetail) => {
+                            app.open_column_menu();
+                        }
+                        KeyCode::Char('h') if matches!(app.current_view, View::Forecast) => {
+                            app.cycle_forecast_horizon();
+                        }
+                        KeyCode::Char('t') if matches!(app.current_view, View::Forecast) => {
+                            app.input_mode = InputMode::EditingThreshold;
+                        }
+                        KeyCode::Char('e') if matches!(app.current_view, View::Forecast) => {
+                            app.input_mode = InputMode::ExportingForecast;
+                        }
+                        KeyCode::Char('a') if matches!(app.current_view, View::TransactionDetail) => {
+                            app.input_mode = InputMode::Aliasing;
+                        }
+                        KeyCode::Char('r') => {
+                            app.rebuild_review_queue();
+                            app.current_view = View::ReviewQueue;
+                        }
+                        KeyCode::Char('y') if matches!(app.current_view, View::ReviewQueue) => {
+                            app.review_selected_flag(FlagStatus::Confirmed);
+                        }
+                        KeyCode::Char('x') if matches!(app.current_view, View::ReviewQueue) => {
+                            app.review_selected_flag(FlagStatus::Dismissed);
+                        }
+                        KeyCode::Char('f') => {
+                            app.input_text = app.filter.clone().unwrap_or_default();
+                            app.input_mode = InputMode::Filtering;
+                        }
+                        KeyCode::Char('F') if matches!(app.current_view, View::TransactionList) => {
+                            if app.filter.is_some() {
+                                app.input_mode = InputMode::SavingFilter;
+                            } else {
+                                app.status_message = Some("Apply a filter with f before saving it".to_string());
                             }
-                            _ => {}
                         }
+                        KeyCode::Char('E') if matches!(app.current_view, View::TransactionList | View::CategorySummary) => {
+                            app.input_mode = InputMode::Exporting;
+                        }
+                        KeyCode::Char('i') if app.task.is_none() => app.input_mode = InputMode::Importing,
+                        _ => {}
                     }
-                    InputMode::Categorizing => {
-                        match key.code {
-                            KeyCode::Enter => app.submit_input(),
-                            KeyCode::Esc => {
-                                app.input_mode = InputMode::Normal;
-                                app.category_selection = None;
-                            }
-                            KeyCode::Up | KeyCode::Down => app.handle_category_selection(key.code),
-                            _ => {}
+                }
+                InputMode::Categorizing => {
+                    match key.code {
+                        KeyCode::Enter => app.submit_input(),
+                        KeyCode::Esc => {
+                            app.input_mode = InputMode::Normal;
+                            app.category_selection = None;
+                            app.bulk_category = None;
                         }
+                        KeyCode::Up | KeyCode::Down => app.handle_category_selection(key.code),
+                        _ => {}
                     }
-                    InputMode::Filtering => {
-                        match key.code {
-                            KeyCode::Enter => app.submit_input(),
-                            KeyCode::Esc => {
-                                app.input_text.clear();
-                                app.input_mode = InputMode::Normal;
-                            }
-                            KeyCode::Backspace => app.handle_backspace(),
-                            KeyCode::Char(c) => app.handle_input(c),
-                            _ => {}
+                }
+                InputMode::Sorting => app.handle_sort_menu(key.code),
+                InputMode::EditingColumns => app.handle_column_menu(key.code),
+                InputMode::BatchMenu => app.handle_batch_menu(key.code),
+                InputMode::Filtering
+                | InputMode::EditingThreshold
+                | InputMode::Aliasing
+                | InputMode::Exporting
+                | InputMode::ExportingForecast
+                | InputMode::Importing
+                | InputMode::JumpingToDate
+                | InputMode::SavingFilter
+                | InputMode::AddingTags
+                | InputMode::RemovingTags => {
+                    match key.code {
+                        KeyCode::Enter => app.submit_input(),
+                        KeyCode::Esc => {
+                            app.input_text.clear();
+                            app.input_error = None;
+                            app.input_mode = InputMode::Normal;
                         }
+                        KeyCode::Backspace => app.handle_backspace(),
+                        KeyCode::Char(c) => app.handle_input(c),
+                        _ => {}
                     }
                 }
             }
@@ -132,11 +288,60 @@ fn run_app(
     }
 }
 
-fn main() -> Result<()> {
-    let args: Vec<String> = std::env::args().collect();
-    let csv_path = args.get(1).ok_or_else(|| {
-        anyhow::anyhow!("Please provide a CSV file path as an argument\nUsage: finance-analyzer <csv-file-path>")
-    })?;
+fn run_lock_screen(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    profile: &Profile,
+) -> Result<Option<DbConnection>> {
+    let mut lock = LockScreen::default();
+    loop {
+        terminal.draw(|f| render_lock_screen(f, &lock, profile))?;
+
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Enter => match profile.unlock(&lock.passphrase)? {
+                    Some(db_connection) => return Ok(Some(db_connection)),
+                    None => {
+                        lock.passphrase.clear();
+                        lock.failed_attempts += 1;
+                        if lock.failed_attempts >= UNLOCK_ATTEMPTS {
+                            bail!("Wrong passphrase for {}", profile.db_path.display());
+                        }
+                        lock.error = Some("Wrong passphrase, try again".to_string());
+                    }
+                },
+                KeyCode::Backspace => {
+                    lock.passphrase.pop();
+                }
+                KeyCode::Char(c) => lock.passphrase.push(c),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn run_session(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    profile: &Profile,
+    mut app: App,
+) -> Result<()> {
+    loop {
+        match run_app(terminal, app)? {
+            Exit::Quit => return Ok(()),
+            Exit::Locked => match run_lock_screen(terminal, profile)? {
+                Some(db_connection) => app = App::start(profile, db_connection, None)?,
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+fn run_tui(profile: &Profile, csv_path: Option<&str>) -> Result<()> {
+    // Open before touching the terminal so a passphrase prompt or a schema
+    // error prints normally; the import itself runs once the UI is up
+    let app = App::start(profile, profile.open()?, csv_path)?;
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -144,8 +349,7 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let app = App::new(csv_path)?;
-    let res = run_app(&mut terminal, app);
+    let res = run_session(&mut terminal, profile, app);
 
     disable_raw_mode()?;
     execute!(
@@ -155,9 +359,41 @@ fn main() -> Result<()> {
     )?;
     terminal.show_cursor()?;
 
-    if let Err(err) = res {
-        println!("{err:?}");
+    res
+}
+
+fn run(cli: Cli) -> Result<u8> {
+    if cli.command.is_some() && cli.csv.is_some() {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, "a CSV path can't be combined with a subcommand; use `import` or `tui <CSV>`")
+            .exit();
     }
 
-    Ok(())
+    let config = Config::load()?;
+    let profile = config.resolve(cli.db.as_deref(), cli.profile.as_deref())?;
+    if profile.is_legacy() {
+        eprintln!(
+            "Note: using {} from the current directory. Move it to {} to use it from anywhere.",
+            profile.db_path.display(),
+            default_db_location()?.display()
+        );
+    }
+
+    match cli.command {
+        Some(Command::Tui { csv }) => run_tui(&profile, csv.as_deref()).map(|_| EXIT_SUCCESS),
+        Some(command) => commands::run(command, &config, &profile),
+        None => run_tui(&profile, cli.csv.as_deref()).map(|_| EXIT_SUCCESS),
+    }
+}
+
+fn main() -> ExitCode {
+    let result = run(Cli::parse());
+
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
 }
@@ -1,19 +1,108 @@
 use std::collections::HashMap;
-use rust_decimal::Decimal;
+use super::transaction::Transaction;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Category {
     pub name: String,
     pub rules: Vec<Rule>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
+    // 0 until the rule is saved
+    pub id: i64,
     pub pattern: String,
     pub category: String,
     pub priority: u8,
 }
 
+// Import is only ever a Beancount posting's account; ING exports carry tags
+// but no category. Categories imported before sources were recorded stay
+// Manual, as nothing tells them apart from the user's own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    Rule,
+    #[default]
+    Manual,
+    Classifier,
+    Import,
+}
+
+impl SourceKind {
+    pub fn all() -> Vec<SourceKind> {
+        vec![SourceKind::Rule, SourceKind::Manual, SourceKind::Classifier, SourceKind::Import]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceKind::Rule => "rule",
+            SourceKind::Manual => "manual",
+            SourceKind::Classifier => "classifier",
+            SourceKind::Import => "import",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<SourceKind> {
+        SourceKind::all().into_iter().find(|k| k.as_str() == value)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SourceKind::Rule => "Rule",
+            SourceKind::Manual => "Manual",
+            SourceKind::Classifier => "Classifier",
+            SourceKind::Import => "Imported",
+        }
+    }
+}
+
+// Why a transaction has its category
+#[derive(Debug, Clone, PartialEq)]
+pub enum CategorySource {
+    Rule { id: i64, pattern: String, priority: u8 },
+    Manual { assigned_at: Option<String> },
+    Classifier { confidence: f64, assigned_at: Option<String> },
+    Import { assigned_at: Option<String> },
+}
+
+impl CategorySource {
+    // Rebuilds a stored assignment's source; rule matches are never stored
+    pub fn stored(kind: SourceKind, confidence: Option<f64>, assigned_at: Option<String>) -> CategorySource {
+        match kind {
+            SourceKind::Classifier => CategorySource::Classifier { confidence: confidence.unwrap_or_default(), assigned_at },
+            SourceKind::Import => CategorySource::Import { assigned_at },
+            SourceKind::Manual | SourceKind::Rule => CategorySource::Manual { assigned_at },
+        }
+    }
+
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            CategorySource::Rule { .. } => SourceKind::Rule,
+            CategorySource::Manual { .. } => SourceKind::Manual,
+            CategorySource::Classifier { .. } => SourceKind::Classifier,
+            CategorySource::Import { .. } => SourceKind::Import,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let at = |assigned_at: &Option<String>| assigned_at
+            .as_deref()
+            .map(|at| format!(" at {}", at))
+            .unwrap_or_default();
+        match self {
+            CategorySource::Rule { id, pattern, priority } => {
+                format!("rule {} '{}' (priority {})", id, pattern, priority)
+            }
+            CategorySource::Manual { assigned_at } => format!("set by hand{}", at(assigned_at)),
+            CategorySource::Classifier { confidence, assigned_at } => {
+                format!("classifier, {:.0}% confident{}", confidence * 100.0, at(assigned_at))
+            }
+            CategorySource::Import { assigned_at } => format!("imported from the bank file{}", at(assigned_at)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum CategoryType {
     Groceries,
@@ -75,6 +164,7 @@ impl Category {
             name: name.to_string(),
             rules: patterns.iter()
                 .map(|(pattern, priority)| Rule {
+                    id: 0,
                     pattern: pattern.to_string(),
                     category: name.to_string(),
                     priority: *priority,
@@ -83,23 +173,8 @@ impl Category {
         }
     }
 
-    pub fn categorize_transaction(categories: &HashMap<String, Category>, merchant: &str, description: &str) -> Option<String> {
-        let mut all_rules: Vec<&Rule> = categories.values()
-            .flat_map(|c| c.rules.iter())
-            .collect();
-        
-        all_rules.sort_by(|a, b| b.priority.cmp(&a.priority));
-
-        let merchant_lower = merchant.to_lowercase();
-        let description_lower = description.to_lowercase();
-
-        for rule in all_rules {
-            let pattern_lower = rule.pattern.to_lowercase();
-            if merchant_lower.contains(&pattern_lower) || description_lower.contains(&pattern_lower) {
-                return Some(rule.category.clone());
-            }
-        }
-        None
+    pub fn matching_rule<'a>(categories: &'a HashMap<String, Category>, merchant: &str, description: &str) -> Option<&'a Rule> {
+        RuleMatcher::new(categories).find(merchant, description)
     }
 
     pub fn default_categories() -> Vec<Category> {
@@ -142,4 +217,50 @@ impl Category {
             ]),
         ]
     }
-}
\ No newline at end of file
+}
+
+// Every rule in match order with its pattern already lowercased, so a large
+// batch doesn't redo that work for each transaction
+#[derive(Debug)]
+pub struct RuleMatcher<'a> {
+    rules: Vec<(String, &'a Rule)>,
+}
+
+impl<'a> RuleMatcher<'a> {
+    pub fn new(categories: &'a HashMap<String, Category>) -> Self {
+        let mut rules: Vec<&Rule> = categories.values()
+            .flat_map(|c| c.rules.iter())
+            .collect();
+
+        // Ties go to the oldest rule so the match doesn't depend on map order
+        rules.sort_by_key(|r| (std::cmp::Reverse(r.priority), r.id));
+
+        RuleMatcher {
+            rules: rules.into_iter().map(|r| (r.pattern.to_lowercase(), r)).collect(),
+        }
+    }
+
+    pub fn find(&self, merchant: &str, description: &str) -> Option<&'a Rule> {
+        let merchant_lower = merchant.to_lowercase();
+        let description_lower = description.to_lowercase();
+
+        self.rules
+            .iter()
+            .find(|(pattern, _)| merchant_lower.contains(pattern) || description_lower.contains(pattern))
+            .map(|(_, rule)| *rule)
+    }
+
+    // Rules match the canonical merchant first, then the bank's raw text
+    pub fn find_for(&self, merchant: &str, raw_merchant: &str, description: &str) -> Option<&'a Rule> {
+        self.find(merchant, description).or_else(|| self.find(raw_merchant, description))
+    }
+
+    pub fn categorize(&self, transaction: &Transaction) -> Option<(String, CategorySource)> {
+        let rule = self.find_for(&transaction.merchant, &transaction.raw_merchant, &transaction.description)?;
+        Some((rule.category.clone(), CategorySource::Rule {
+            id: rule.id,
+            pattern: rule.pattern.clone(),
+            priority: rule.priority,
+        }))
+    }
+}
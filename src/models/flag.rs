@@ -0,0 +1,80 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagKind {
+    AmountOutlier,
+    FirstTimeMerchant,
+    DuplicateCharge,
+    ForeignCurrency,
+}
+
+impl FlagKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlagKind::AmountOutlier => "amount_outlier",
+            FlagKind::FirstTimeMerchant => "first_time_merchant",
+            FlagKind::DuplicateCharge => "duplicate_charge",
+            FlagKind::ForeignCurrency => "foreign_currency",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FlagKind::AmountOutlier => "Unusual amount",
+            FlagKind::FirstTimeMerchant => "New merchant",
+            FlagKind::DuplicateCharge => "Possible duplicate",
+            FlagKind::ForeignCurrency => "Foreign currency",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<FlagKind> {
+        match value {
+            "amount_outlier" => Some(FlagKind::AmountOutlier),
+            "first_time_merchant" => Some(FlagKind::FirstTimeMerchant),
+            "duplicate_charge" => Some(FlagKind::DuplicateCharge),
+            "foreign_currency" => Some(FlagKind::ForeignCurrency),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagStatus {
+    Pending,
+    Confirmed,
+    Dismissed,
+}
+
+impl FlagStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlagStatus::Pending => "pending",
+            FlagStatus::Confirmed => "confirmed",
+            FlagStatus::Dismissed => "dismissed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<FlagStatus> {
+        match value {
+            "pending" => Some(FlagStatus::Pending),
+            "confirmed" => Some(FlagStatus::Confirmed),
+            "dismissed" => Some(FlagStatus::Dismissed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionFlag {
+    pub kind: FlagKind,
+    pub reason: String,
+    pub status: FlagStatus,
+}
+
+impl TransactionFlag {
+    pub fn new(kind: FlagKind, reason: String) -> Self {
+        TransactionFlag {
+            kind,
+            reason,
+            status: FlagStatus::Pending,
+        }
+    }
+}
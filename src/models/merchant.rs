@@ -0,0 +1,129 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerchantAlias {
+    pub pattern: String,
+    pub canonical: String,
+}
+
+impl MerchantAlias {
+    pub fn new(pattern: &str, canonical: &str) -> Self {
+        MerchantAlias {
+            pattern: pattern.to_string(),
+            canonical: canonical.to_string(),
+        }
+    }
+
+    pub fn default_aliases() -> Vec<MerchantAlias> {
+        vec![
+            MerchantAlias::new("Albert Heijn", "Albert Heijn"),
+            MerchantAlias::new("AH to go", "Albert Heijn"),
+            MerchantAlias::new("Uber Eats", "Uber Eats"),
+            MerchantAlias::new("Uber BV", "Uber"),
+            MerchantAlias::new("Espresso House", "Espresso House"),
+        ]
+    }
+}
+
+// Payment service providers prefix the merchant name, e.g. "CCV*BAKKERIJ"
+const PAYMENT_PREFIXES: [&str; 6] = ["ccv*", "sumup *", "sumup*", "zettle_*", "pay.nl*", "sp *"];
+
+const CITY_SUFFIXES: [&str; 14] = [
+    "amsterdam",
+    "rotterdam",
+    "utrecht",
+    "den haag",
+    "'s-gravenhage",
+    "eindhoven",
+    "haarlem",
+    "leiden",
+    "groningen",
+    "amstelveen",
+    "schiphol",
+    "diemen",
+    "nld",
+    "nl",
+];
+
+// Strips payment prefixes, store numbers, terminal ids and location suffixes
+// from a bank description
+pub fn clean_merchant(raw: &str) -> String {
+    let mut name = raw.trim();
+    for prefix in PAYMENT_PREFIXES {
+        if name.len() > prefix.len() && name.get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(prefix)) {
+            name = name[prefix.len()..].trim_start();
+        }
+    }
+
+    // Banks print the store number or terminal id after the name and before
+    // the location, so everything from the first one on goes. Digits that
+    // belong to a name ("7-Eleven", "Formule 1") stay.
+    let mut tokens: Vec<&str> = name.split_whitespace().collect();
+    if let Some(id) = tokens.iter().skip(1).position(|token| is_store_or_terminal_id(token)) {
+        tokens.truncate(id + 1);
+    }
+
+    loop {
+        if tokens.len() < 2 {
+            break;
+        }
+        // Banks print locations in upper case; "Gemeente Amsterdam" is a name
+        let last = tokens[tokens.len() - 1];
+        let last_is_upper = last.chars().all(|c| !c.is_lowercase());
+        let joined = tokens.join(" ").to_lowercase();
+        if last_is_upper
+            && let Some(city) = CITY_SUFFIXES.iter().find(|city| joined.ends_with(&format!(" {}", city)))
+        {
+            tokens.truncate(tokens.len() - city.split_whitespace().count());
+            continue;
+        }
+
+        // An upper-case trailing word after a mixed-case name is a location
+        let rest_has_lowercase = tokens[..tokens.len() - 1].iter().any(|t| t.chars().any(|c| c.is_lowercase()));
+        if rest_has_lowercase && last.len() > 2 && last_is_upper {
+            tokens.pop();
+            continue;
+        }
+        break;
+    }
+
+    let cleaned = tokens.join(" ");
+    let cleaned = cleaned.trim_matches(|c: char| c == ',' || c == '-' || c == '*' || c.is_whitespace());
+    if cleaned.is_empty() {
+        raw.trim().to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+// "1234", "#12", "T-00321", "****4321": a hash or at least three digits
+fn is_store_or_terminal_id(token: &str) -> bool {
+    let digits = token.chars().filter(|c| c.is_ascii_digit()).count();
+    digits >= 3 || (token.starts_with('#') && digits > 0)
+}
+
+#[derive(Debug, Default)]
+pub struct MerchantNormalizer {
+    // Each alias with its pattern lowercased once up front
+    aliases: Vec<(String, MerchantAlias)>,
+}
+
+impl MerchantNormalizer {
+    pub fn new(mut aliases: Vec<MerchantAlias>) -> Self {
+        // The most specific pattern wins
+        aliases.sort_by_key(|a| std::cmp::Reverse(a.pattern.len()));
+        MerchantNormalizer {
+            aliases: aliases.into_iter().map(|a| (a.pattern.to_lowercase(), a)).collect(),
+        }
+    }
+
+    pub fn normalize(&self, raw: &str) -> String {
+        let cleaned = clean_merchant(raw);
+        let raw_lower = raw.to_lowercase();
+        let cleaned_lower = cleaned.to_lowercase();
+
+        self.aliases
+            .iter()
+            .find(|(pattern, _)| raw_lower.contains(pattern) || cleaned_lower.contains(pattern))
+            .map(|(_, alias)| alias.canonical.clone())
+            .unwrap_or(cleaned)
+    }
+}
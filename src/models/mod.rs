@@ -1,2 +1,4 @@
 pub mod transaction;
-pub mod category;
\ No newline at end of file
+pub mod category;
+pub mod flag;
+pub mod merchant;
\ No newline at end of file
@@ -1,34 +1,64 @@
 use chrono::NaiveDateTime;
 use rust_decimal::Decimal;
-use ratatui::{
-    style::{Color, Style},
-    text::{Line, Span},
-    widgets::ListItem,
-};
+use super::category::CategorySource;
+use super::flag::{FlagStatus, TransactionFlag};
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
 pub struct Transaction {
     pub id: u64,
     pub date: NaiveDateTime,
     pub amount: Decimal,
     pub merchant: String,
+    pub raw_merchant: String,
     pub description: String,
     pub category: Option<String>,
+    #[serde(skip)]
+    pub category_source: Option<CategorySource>,
+    pub account: String,
+    pub balance: Option<Decimal>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(skip)]
+    pub flags: Vec<TransactionFlag>,
+    // Left out of totals and reports
+    #[serde(skip)]
+    pub excluded: bool,
+    #[serde(skip)]
+    pub splits: Vec<Split>,
+}
+
+// Part of a transaction counted under another category. The parts of a
+// split transaction add up to its amount.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub category: String,
+    pub amount: Decimal,
 }
 
 impl Transaction {
-    pub fn to_list_item(&self) -> ListItem {
-        let amount_style = if self.amount < Decimal::ZERO {
-            Style::default().fg(Color::Red)
-        } else {
-            Style::default().fg(Color::Green)
-        };
+    pub fn has_pending_flags(&self) -> bool {
+        self.flags.iter().any(|f| f.status == FlagStatus::Pending)
+    }
 
-        ListItem::new(Line::from(vec![
-            Span::raw(format!("{:<10} ", self.date.format("%Y-%m-%d"))),
-            Span::styled(format!("{:>10} ", self.amount), amount_style),
-            Span::raw(format!("{:<30} ", self.merchant)),
-            Span::raw(self.category.as_deref().unwrap_or("Uncategorized")),
-        ]))
+    pub fn has_confirmed_flags(&self) -> bool {
+        self.flags.iter().any(|f| f.status == FlagStatus::Confirmed)
     }
-}
\ No newline at end of file
+
+    // What each category gets from this transaction: its split parts, or
+    // the whole amount under its own category
+    pub fn allocations(&self) -> Vec<(&str, Decimal)> {
+        if self.splits.is_empty() {
+            return vec![(self.category.as_deref().unwrap_or("Uncategorized"), self.amount)];
+        }
+        self.splits.iter().map(|split| (split.category.as_str(), split.amount)).collect()
+    }
+
+    // The part of the amount counted under `category`, if any
+    pub fn allocated_to(&self, category: &str) -> Option<Decimal> {
+        self.allocations()
+            .into_iter()
+            .filter(|(name, _)| *name == category)
+            .map(|(_, amount)| amount)
+            .reduce(|a, b| a + b)
+    }
+}
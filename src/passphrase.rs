@@ -0,0 +1,36 @@
+use anyhow::{bail, Context, Result};
+use zeroize::Zeroizing;
+
+// For scripts and cron jobs, which have no terminal to prompt on
+pub const PASSPHRASE_ENV: &str = "FINANCE_ANALYZER_PASSPHRASE";
+pub const NEW_PASSPHRASE_ENV: &str = "FINANCE_ANALYZER_NEW_PASSPHRASE";
+
+pub fn from_env() -> Option<Zeroizing<String>> {
+    std::env::var(PASSPHRASE_ENV).ok().map(Zeroizing::new)
+}
+
+// Reads from the terminal without echoing
+pub fn prompt(message: &str) -> Result<Zeroizing<String>> {
+    rpassword::prompt_password(message)
+        .map(Zeroizing::new)
+        .with_context(|| format!("Failed to read the passphrase; set {} when there is no terminal", PASSPHRASE_ENV))
+}
+
+// Asks twice, since a typo here locks the data away for good
+pub fn prompt_new() -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(NEW_PASSPHRASE_ENV) {
+        return check_new(Zeroizing::new(passphrase));
+    }
+    let passphrase = check_new(prompt("New passphrase: ")?)?;
+    if *prompt("Repeat the new passphrase: ")? != *passphrase {
+        bail!("The passphrases don't match; nothing was changed");
+    }
+    Ok(passphrase)
+}
+
+fn check_new(passphrase: Zeroizing<String>) -> Result<Zeroizing<String>> {
+    if passphrase.is_empty() {
+        bail!("The passphrase can't be empty");
+    }
+    Ok(passphrase)
+}
@@ -0,0 +1,142 @@
+mod parser;
+
+use std::collections::HashMap;
+use std::fmt;
+use chrono::NaiveDate;
+use regex::Regex;
+use rust_decimal::Decimal;
+use crate::models::transaction::Transaction;
+
+pub const FIELDS: [&str; 7] = ["category", "merchant", "description", "account", "tag", "amount", "date"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        QueryError { column, message: message.into() }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextField {
+    Category,
+    Merchant,
+    Description,
+    Account,
+    Tag,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    // A bare word: substring of merchant, description or category
+    Text(String),
+    Contains(TextField, String),
+    Equals(TextField, String),
+    Matches(TextField, Regex),
+    Amount(Comparison, Decimal),
+    // A year, month or day given as [start, end)
+    Date(Comparison, NaiveDate, NaiveDate),
+    // A saved filter pulled in with @name
+    Group(Vec<Term>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Term {
+    pub negated: bool,
+    pub predicate: Predicate,
+}
+
+// All terms must match
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+impl Query {
+    pub fn parse(input: &str, saved: &HashMap<String, String>) -> Result<Query, QueryError> {
+        parser::parse(input, saved)
+    }
+
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        all_match(&self.terms, transaction)
+    }
+}
+
+fn all_match(terms: &[Term], transaction: &Transaction) -> bool {
+    terms.iter().all(|term| term.predicate.matches(transaction) != term.negated)
+}
+
+fn field_values(field: TextField, transaction: &Transaction) -> Vec<&str> {
+    match field {
+        TextField::Category => vec![transaction.category.as_deref().unwrap_or("Uncategorized")],
+        TextField::Merchant => vec![&transaction.merchant, &transaction.raw_merchant],
+        TextField::Description => vec![&transaction.description],
+        TextField::Account => vec![&transaction.account],
+        TextField::Tag => transaction.tags.iter().map(String::as_str).collect(),
+    }
+}
+
+impl Predicate {
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        match self {
+            Predicate::Text(text) => {
+                transaction.merchant.to_lowercase().contains(text)
+                    || transaction.raw_merchant.to_lowercase().contains(text)
+                    || transaction.description.to_lowercase().contains(text)
+                    || transaction.category.as_ref().is_some_and(|c| c.to_lowercase().contains(text))
+            }
+            // Tags are atoms, so tag:x means the whole tag
+            Predicate::Contains(TextField::Tag, value) | Predicate::Equals(TextField::Tag, value) => {
+                field_values(TextField::Tag, transaction).iter().any(|v| v.to_lowercase() == *value)
+            }
+            Predicate::Contains(field, value) => {
+                field_values(*field, transaction).iter().any(|v| v.to_lowercase().contains(value))
+            }
+            Predicate::Equals(field, value) => {
+                field_values(*field, transaction).iter().any(|v| v.to_lowercase() == *value)
+            }
+            Predicate::Matches(field, regex) => {
+                field_values(*field, transaction).iter().any(|v| regex.is_match(v))
+            }
+            Predicate::Amount(comparison, amount) => match comparison {
+                Comparison::Less => transaction.amount < *amount,
+                Comparison::LessOrEqual => transaction.amount <= *amount,
+                Comparison::Greater => transaction.amount > *amount,
+                Comparison::GreaterOrEqual => transaction.amount >= *amount,
+                Comparison::Equal => transaction.amount == *amount,
+            },
+            Predicate::Date(comparison, start, end) => {
+                let date = transaction.date.date();
+                match comparison {
+                    Comparison::Less => date < *start,
+                    Comparison::LessOrEqual => date < *end,
+                    Comparison::Greater => date >= *end,
+                    Comparison::GreaterOrEqual => date >= *start,
+                    Comparison::Equal => date >= *start && date < *end,
+                }
+            }
+            Predicate::Group(terms) => all_match(terms, transaction),
+        }
+    }
+}
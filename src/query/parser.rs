@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use chrono::{Datelike, NaiveDate};
+use regex::RegexBuilder;
+use rust_decimal::Decimal;
+use super::{Comparison, Predicate, QueryError, Term, TextField, FIELDS};
+
+pub fn parse(input: &str, saved: &HashMap<String, String>) -> Result<super::Query, QueryError> {
+    let terms = Parser::new(input, saved).parse_terms()?;
+    Ok(super::Query { terms })
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    saved: &'a HashMap<String, String>,
+    // Set while looking for cycles: @names are collected, not expanded
+    references: Option<Vec<String>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &str, saved: &'a HashMap<String, String>) -> Self {
+        Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+            saved,
+            references: None,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn column(&self) -> usize {
+        self.pos + 1
+    }
+
+    fn error<T>(&self, column: usize, message: impl Into<String>) -> Result<T, QueryError> {
+        Err(QueryError::new(column, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn read_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&predicate) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn parse_terms(&mut self) -> Result<Vec<Term>, QueryError> {
+        let mut terms = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                return Ok(terms);
+            }
+            terms.push(self.parse_term()?);
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Term, QueryError> {
+        let negated = self.peek() == Some('-')
+            && self.chars.get(self.pos + 1).is_some_and(|c| !c.is_whitespace());
+        if negated {
+            self.pos += 1;
+        }
+
+        let start = self.column();
+        let predicate = match self.peek() {
+            Some('@') => {
+                self.pos += 1;
+                self.parse_saved(start)?
+            }
+            Some('"') => Predicate::Text(self.parse_quoted()?.to_lowercase()),
+            _ => {
+                let word = self.read_while(|c| c.is_ascii_alphabetic());
+                match self.peek() {
+                    Some(':' | '=' | '~' | '<' | '>') if !word.is_empty() => self.parse_field(&word, start)?,
+                    _ => {
+                        let rest = self.read_while(|c| !c.is_whitespace());
+                        Predicate::Text(format!("{}{}", word, rest).to_lowercase())
+                    }
+                }
+            }
+        };
+
+        Ok(Term { negated, predicate })
+    }
+
+    fn parse_saved(&mut self, start: usize) -> Result<Predicate, QueryError> {
+        let name = self.read_while(|c| c.is_alphanumeric() || c == '_' || c == '-');
+        if name.is_empty() {
+            return self.error(start, "expected a saved filter name after '@'");
+        }
+        if let Some(references) = &mut self.references {
+            references.push(name);
+            return Ok(Predicate::Group(Vec::new()));
+        }
+        let Some(query) = self.saved.get(&name) else {
+            return self.error(start, format!("no saved filter named '{}'", name));
+        };
+        let mut chain = vec![name.clone()];
+        if find_cycle(self.saved, &mut chain) {
+            let chain: Vec<String> = chain.iter().map(|n| format!("@{}", n)).collect();
+            return self.error(start, format!("saved filters refer to each other: {}", chain.join(" -> ")));
+        }
+
+        Parser::new(query, self.saved)
+            .parse_terms()
+            .map(Predicate::Group)
+            .map_err(|e| QueryError::new(start, format!("in @{}: {}", name, e)))
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, QueryError> {
+        let start = self.column();
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.pos += 1;
+                    value.extend(self.peek());
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some(c) => value.push(c),
+                None => return self.error(start, "unterminated quote"),
+            }
+            self.pos += 1;
+        }
+    }
+
+    // A /regex/, a "quoted value" or everything up to the next space
+    fn parse_value(&mut self) -> Result<String, QueryError> {
+        match self.peek() {
+            Some('"') => self.parse_quoted(),
+            _ => Ok(self.read_while(|c| !c.is_whitespace())),
+        }
+    }
+
+    fn parse_regex(&mut self) -> Result<String, QueryError> {
+        let start = self.column();
+        self.pos += 1;
+        let mut pattern = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') if self.chars.get(self.pos + 1) == Some(&'/') => {
+                    pattern.push('/');
+                    self.pos += 1;
+                }
+                Some('/') => {
+                    self.pos += 1;
+                    return Ok(pattern);
+                }
+                Some(c) => pattern.push(c),
+                None => return self.error(start, "unterminated regex, expected a closing '/'"),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Comparison {
+        let comparison = match (self.peek(), self.chars.get(self.pos + 1)) {
+            (Some('<'), Some('=')) => Comparison::LessOrEqual,
+            (Some('>'), Some('=')) => Comparison::GreaterOrEqual,
+            (Some('<'), _) => Comparison::Less,
+            (Some('>'), _) => Comparison::Greater,
+            _ => Comparison::Equal,
+        };
+        self.pos += match comparison {
+            Comparison::LessOrEqual | Comparison::GreaterOrEqual => 2,
+            _ => 1,
+        };
+        comparison
+    }
+
+    fn parse_field(&mut self, name: &str, start: usize) -> Result<Predicate, QueryError> {
+        let field = match name.to_lowercase().as_str() {
+            "category" | "cat" => Some(TextField::Category),
+            "merchant" => Some(TextField::Merchant),
+            "description" | "desc" => Some(TextField::Description),
+            "account" => Some(TextField::Account),
+            "tag" => Some(TextField::Tag),
+            "amount" | "date" => None,
+            _ => {
+                return self.error(start, format!("unknown field '{}' (expected one of {})", name, FIELDS.join(", ")));
+            }
+        };
+
+        let op_column = self.column();
+        let op = self.peek();
+
+        if let Some(field) = field {
+            self.pos += 1;
+            let value_column = self.column();
+            return match op {
+                Some('~') => {
+                    let pattern = if self.peek() == Some('/') { self.parse_regex()? } else { self.parse_value()? };
+                    if pattern.is_empty() {
+                        return self.error(value_column, format!("expected a pattern after '{}~'", name));
+                    }
+                    RegexBuilder::new(&pattern)
+                        .case_insensitive(true)
+                        .build()
+                        .map(|regex| Predicate::Matches(field, regex))
+                        .or_else(|e| self.error(value_column, format!("invalid regex: {}", error_summary(&e.to_string()))))
+                }
+                Some(':' | '=') => {
+                    let value = self.parse_value()?;
+                    if value.is_empty() {
+                        return self.error(value_column, format!("expected a value after '{}{}'", name, op.unwrap_or(':')));
+                    }
+                    Ok(if op == Some(':') {
+                        Predicate::Contains(field, value.to_lowercase())
+                    } else {
+                        Predicate::Equals(field, value.to_lowercase())
+                    })
+                }
+                _ => self.error(op_column, format!("'{}' only supports ':', '=' and '~'", name)),
+            };
+        }
+
+        if op == Some('~') {
+            return self.error(op_column, format!("'{}' does not support '~', use <, <=, >, >= or =", name));
+        }
+        let comparison = if op == Some(':') {
+            self.pos += 1;
+            Comparison::Equal
+        } else {
+            self.parse_comparison()
+        };
+
+        let value_column = self.column();
+        let value = self.parse_value()?;
+        if value.is_empty() {
+            return self.error(value_column, format!("expected a value after '{}'", name));
+        }
+
+        if name.eq_ignore_ascii_case("amount") {
+            Decimal::from_str(&value.replace(',', "."))
+                .map(|amount| Predicate::Amount(comparison, amount))
+                .or_else(|_| self.error(value_column, format!("'{}' is not an amount", value)))
+        } else {
+            let (from, until) = parse_date_range(&value)
+                .ok_or_else(|| QueryError::new(value_column, format!("'{}' is not a date (use YYYY, YYYY-MM or YYYY-MM-DD)", value)))?;
+            Ok(Predicate::Date(comparison, from, until))
+        }
+    }
+}
+
+// Follows @references from the last name in `chain`; on a cycle, `chain`
+// ends with the name that repeats
+fn find_cycle(saved: &HashMap<String, String>, chain: &mut Vec<String>) -> bool {
+    let Some(query) = chain.last().and_then(|name| saved.get(name)) else {
+        return false;
+    };
+
+    for reference in references(query, saved) {
+        let repeats = chain.contains(&reference);
+        chain.push(reference);
+        if repeats || find_cycle(saved, chain) {
+            return true;
+        }
+        chain.pop();
+    }
+    false
+}
+
+// Tokenized like any query, so an '@' in quoted text or a field value is
+// not a reference. Errors are left for the expansion to report.
+fn references(query: &str, saved: &HashMap<String, String>) -> Vec<String> {
+    let mut parser = Parser::new(query, saved);
+    parser.references = Some(Vec::new());
+    let _ = parser.parse_terms();
+    parser.references.unwrap_or_default()
+}
+
+// The days covered by a year, month or single date, end exclusive
+fn parse_date_range(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    match parts.as_slice() {
+        [year] if year.len() == 4 => {
+            let year = year.parse().ok()?;
+            Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?))
+        }
+        [year, month] => {
+            let start = NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)?;
+            let end = if start.month() == 12 {
+                NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1)?
+            };
+            Some((start, end))
+        }
+        [_, _, _] => {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            Some((date, date.succ_opt()?))
+        }
+        _ => None,
+    }
+}
+
+// regex errors span several lines with a caret diagram; the last one says what is wrong
+fn error_summary(message: &str) -> &str {
+    let last = message.lines().last().unwrap_or(message).trim();
+    last.strip_prefix("error: ").unwrap_or(last)
+}
@@ -0,0 +1,187 @@
+use std::io::Write;
+use anyhow::Result;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use crate::analysis::monthly::MonthlyReport;
+
+const STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #222; max-width: 900px; margin: 2em auto; padding: 0 1em; }
+h1 { border-bottom: 2px solid #333; padding-bottom: 0.3em; }
+h2 { margin-top: 2em; color: #333; }
+table { border-collapse: collapse; width: 100%; margin: 0.5em 0; }
+th, td { padding: 4px 8px; border-bottom: 1px solid #ddd; text-align: left; }
+td.num, th.num { text-align: right; font-variant-numeric: tabular-nums; }
+.cards { display: flex; gap: 1em; }
+.card { flex: 1; border: 1px solid #ccc; border-radius: 6px; padding: 0.8em; }
+.card .value { font-size: 1.4em; font-weight: bold; }
+.positive { color: #1a7f37; }
+.negative { color: #c62828; }
+.muted { color: #777; }
+@media print { body { margin: 0; } h2 { page-break-after: avoid; } table { page-break-inside: auto; } }
+";
+
+const BAR_HEIGHT: u32 = 22;
+const LABEL_WIDTH: u32 = 180;
+const CHART_WIDTH: u32 = 860;
+
+pub fn write_html<W: Write>(report: &MonthlyReport, mut writer: W) -> Result<()> {
+    let title = escape(&report.title());
+
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>{}</title>\n<style>{}</style>\n</head>\n<body>", title, STYLE)?;
+    writeln!(writer, "<h1>{}</h1>", title)?;
+    writeln!(writer, "<p class=\"muted\">{} transactions</p>", report.transactions)?;
+
+    writeln!(writer, "<div class=\"cards\">")?;
+    write_card(&mut writer, "Income", report.income)?;
+    write_card(&mut writer, "Expenses", -report.expenses)?;
+    write_card(&mut writer, "Net", report.net())?;
+    writeln!(writer, "</div>")?;
+    write_bar_chart(&mut writer, &[("Income", report.income, "#1a7f37"), ("Expenses", report.expenses, "#c62828")])?;
+
+    writeln!(writer, "<h2>Spending by category</h2>")?;
+    let spending: Vec<(&str, Decimal, &str)> = report
+        .spending_by_category()
+        .into_iter()
+        .map(|(category, amount)| (category, amount, "#3b6ea5"))
+        .collect();
+    if spending.is_empty() {
+        writeln!(writer, "<p class=\"muted\">No spending this month.</p>")?;
+    } else {
+        write_bar_chart(&mut writer, &spending)?;
+    }
+    writeln!(writer, "<table>\n<tr><th>Category</th><th class=\"num\">Total</th></tr>")?;
+    for (category, total) in &report.categories {
+        writeln!(writer, "<tr><td>{}</td>{}</tr>", escape(category), amount_cell(*total))?;
+    }
+    writeln!(writer, "</table>")?;
+
+    writeln!(writer, "<h2>Top merchants</h2>")?;
+    writeln!(
+        writer,
+        "<table>\n<tr><th>#</th><th>Merchant</th><th class=\"num\">Spent</th><th class=\"num\">Visits</th><th class=\"num\">Average</th></tr>"
+    )?;
+    for (rank, stats) in report.top_merchants.iter().enumerate() {
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{}</td><td class=\"num\">{:.2}</td><td class=\"num\">{}</td><td class=\"num\">{:.2}</td></tr>",
+            rank + 1,
+            escape(&stats.merchant),
+            stats.total_spend,
+            stats.visits,
+            stats.average_ticket
+        )?;
+    }
+    writeln!(writer, "</table>")?;
+
+    writeln!(writer, "<h2>Budget variance</h2>")?;
+    if report.budgets.is_empty() {
+        writeln!(writer, "<p class=\"muted\">No budgets set. Add one with <code>budget set &lt;category&gt; &lt;amount&gt;</code>.</p>")?;
+    } else {
+        writeln!(
+            writer,
+            "<table>\n<tr><th>Category</th><th class=\"num\">Budget</th><th class=\"num\">Spent</th><th class=\"num\">Remaining</th></tr>"
+        )?;
+        for variance in &report.budgets {
+            writeln!(
+                writer,
+                "<tr><td>{}</td><td class=\"num\">{:.2}</td><td class=\"num\">{:.2}</td>{}</tr>",
+                escape(&variance.category),
+                variance.budget,
+                variance.spent,
+                amount_cell(variance.remaining())
+            )?;
+        }
+        writeln!(writer, "</table>")?;
+    }
+
+    writeln!(writer, "<h2>Notable transactions</h2>")?;
+    writeln!(
+        writer,
+        "<table>\n<tr><th>Date</th><th>Merchant</th><th>Category</th><th class=\"num\">Amount</th><th>Why</th></tr>"
+    )?;
+    for transaction in &report.notable {
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td>{}</td><td>{}</td>{}<td>{}</td></tr>",
+            transaction.date.format("%Y-%m-%d"),
+            escape(&transaction.merchant),
+            escape(&transaction.category),
+            amount_cell(transaction.amount),
+            escape(&transaction.reason)
+        )?;
+    }
+    writeln!(writer, "</table>")?;
+
+    writeln!(writer, "</body>\n</html>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_card<W: Write>(writer: &mut W, label: &str, amount: Decimal) -> Result<()> {
+    writeln!(
+        writer,
+        "<div class=\"card\"><div class=\"muted\">{}</div><div class=\"value {}\">{:.2}</div></div>",
+        label,
+        sign_class(amount),
+        amount
+    )?;
+    Ok(())
+}
+
+// Horizontal bars scaled to the largest value, drawn as inline SVG
+fn write_bar_chart<W: Write>(writer: &mut W, bars: &[(&str, Decimal, &str)]) -> Result<()> {
+    let max = bars.iter().map(|(_, value, _)| value.abs()).max().unwrap_or_default();
+    let bar_space = CHART_WIDTH - LABEL_WIDTH - 90;
+    let height = bars.len() as u32 * (BAR_HEIGHT + 6) + 6;
+
+    writeln!(
+        writer,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100%\" viewBox=\"0 0 {} {}\" role=\"img\">",
+        CHART_WIDTH, height
+    )?;
+    for (i, (label, value, color)) in bars.iter().enumerate() {
+        let y = 6 + i as u32 * (BAR_HEIGHT + 6);
+        let width = if max.is_zero() {
+            0.0
+        } else {
+            (value.abs() / max).to_f64().unwrap_or(0.0) * bar_space as f64
+        };
+        writeln!(
+            writer,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\" font-size=\"13\">{}</text>",
+            LABEL_WIDTH - 8,
+            y + BAR_HEIGHT - 6,
+            escape(label)
+        )?;
+        writeln!(
+            writer,
+            "<rect x=\"{}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\" />",
+            LABEL_WIDTH, y, width, BAR_HEIGHT, color
+        )?;
+        writeln!(
+            writer,
+            "<text x=\"{:.1}\" y=\"{}\" font-size=\"12\">{:.2}</text>",
+            LABEL_WIDTH as f64 + width + 6.0,
+            y + BAR_HEIGHT - 6,
+            value
+        )?;
+    }
+    writeln!(writer, "</svg>")?;
+    Ok(())
+}
+
+fn amount_cell(amount: Decimal) -> String {
+    format!("<td class=\"num {}\">{:.2}</td>", sign_class(amount), amount)
+}
+
+fn sign_class(amount: Decimal) -> &'static str {
+    if amount < Decimal::ZERO { "negative" } else { "positive" }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,38 @@
+pub mod html;
+pub mod pdf;
+
+use std::io::Write;
+use std::path::Path;
+use anyhow::Result;
+use crate::analysis::monthly::MonthlyReport;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Html,
+    Pdf,
+}
+
+impl ReportFormat {
+    pub fn from_path(path: &str) -> Option<ReportFormat> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "html" | "htm" => Some(ReportFormat::Html),
+            "pdf" => Some(ReportFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+pub fn write_report<W: Write>(report: &MonthlyReport, format: ReportFormat, writer: W) -> Result<()> {
+    match format {
+        ReportFormat::Html => html::write_html(report, writer),
+        ReportFormat::Pdf => pdf::write_pdf(report, writer),
+    }
+}
@@ -0,0 +1,235 @@
+use std::io::Write;
+use anyhow::Result;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use crate::analysis::monthly::MonthlyReport;
+
+// A4 in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+
+const HEADING_FONT: Name = Name(b"F1");
+const BODY_FONT: Name = Name(b"F2");
+const BODY_SIZE: f32 = 9.0;
+const ROW_HEIGHT: f32 = 13.0;
+// Courier advances every glyph by 0.6 em
+const CHAR_WIDTH: f32 = BODY_SIZE * 0.6;
+
+const INCOME_COLOR: (f32, f32, f32) = (0.1, 0.5, 0.22);
+const EXPENSE_COLOR: (f32, f32, f32) = (0.78, 0.16, 0.16);
+const CATEGORY_COLOR: (f32, f32, f32) = (0.23, 0.43, 0.65);
+
+// Cells are (text, x, right aligned); right-aligned cells end at x
+type Cell<'a> = (&'a str, f32, bool);
+
+struct PageWriter {
+    pages: Vec<Vec<u8>>,
+    content: Content,
+    y: f32,
+}
+
+impl PageWriter {
+    fn new() -> Self {
+        PageWriter {
+            pages: Vec::new(),
+            content: Content::new(),
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    fn ensure_space(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            let content = std::mem::replace(&mut self.content, Content::new());
+            self.pages.push(content.finish());
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    fn text(&mut self, font: Name, size: f32, x: f32, y: f32, text: &str) {
+        self.content.set_fill_rgb(0.0, 0.0, 0.0);
+        self.content.begin_text();
+        self.content.set_font(font, size);
+        self.content.next_line(x, y);
+        self.content.show(Str(&encode(text)));
+        self.content.end_text();
+    }
+
+    fn title(&mut self, text: &str) {
+        self.ensure_space(30.0);
+        self.y -= 18.0;
+        self.text(HEADING_FONT, 18.0, MARGIN, self.y, text);
+        self.y -= 12.0;
+    }
+
+    fn heading(&mut self, text: &str) {
+        // Keep a heading on the same page as its first rows
+        self.ensure_space(26.0 + 3.0 * ROW_HEIGHT);
+        self.y -= 20.0;
+        self.text(HEADING_FONT, 12.0, MARGIN, self.y, text);
+        self.y -= 6.0;
+    }
+
+    fn row(&mut self, cells: &[Cell]) {
+        self.ensure_space(ROW_HEIGHT);
+        self.y -= ROW_HEIGHT;
+        for &(text, x, right) in cells {
+            let x = if right { x - text.chars().count() as f32 * CHAR_WIDTH } else { x };
+            self.text(BODY_FONT, BODY_SIZE, x, self.y, text);
+        }
+    }
+
+    fn bar(&mut self, label: &str, value: Decimal, max: Decimal, color: (f32, f32, f32)) {
+        let label_width = 150.0;
+        let bar_space = PAGE_WIDTH - 2.0 * MARGIN - label_width - 70.0;
+        let width = if max.is_zero() {
+            0.0
+        } else {
+            (value.abs() / max).to_f32().unwrap_or(0.0) * bar_space
+        };
+
+        self.ensure_space(ROW_HEIGHT + 2.0);
+        self.y -= ROW_HEIGHT + 2.0;
+        self.text(BODY_FONT, BODY_SIZE, MARGIN, self.y, &truncate(label, 26));
+        self.content.set_fill_rgb(color.0, color.1, color.2);
+        self.content.rect(MARGIN + label_width, self.y - 2.0, width, ROW_HEIGHT - 2.0);
+        self.content.fill_nonzero();
+        self.text(BODY_FONT, BODY_SIZE, MARGIN + label_width + width + 4.0, self.y, &format!("{:.2}", value));
+    }
+
+    fn note(&mut self, text: &str) {
+        self.row(&[(text, MARGIN, false)]);
+    }
+
+    fn finish(mut self) -> Vec<Vec<u8>> {
+        self.pages.push(self.content.finish());
+        self.pages
+    }
+}
+
+pub fn write_pdf<W: Write>(report: &MonthlyReport, mut writer: W) -> Result<()> {
+    let right = PAGE_WIDTH - MARGIN;
+    let mut page = PageWriter::new();
+
+    page.title(&report.title());
+    page.note(&format!("{} transactions", report.transactions));
+
+    page.heading("Income and expenses");
+    let max = report.income.max(report.expenses);
+    page.bar("Income", report.income, max, INCOME_COLOR);
+    page.bar("Expenses", report.expenses, max, EXPENSE_COLOR);
+    page.row(&[("Net", MARGIN, false), (&format!("{:.2}", report.net()), right, true)]);
+
+    page.heading("Spending by category");
+    let spending = report.spending_by_category();
+    let max = spending.iter().map(|(_, amount)| *amount).max().unwrap_or_default();
+    for (category, amount) in &spending {
+        page.bar(category, *amount, max, CATEGORY_COLOR);
+    }
+    page.y -= 6.0;
+    page.row(&[("Category", MARGIN, false), ("Total", right, true)]);
+    for (category, total) in &report.categories {
+        page.row(&[(category, MARGIN, false), (&format!("{:.2}", total), right, true)]);
+    }
+
+    page.heading("Top merchants");
+    page.row(&[("Merchant", MARGIN, false), ("Spent", 380.0, true), ("Visits", 440.0, true), ("Average", right, true)]);
+    for stats in &report.top_merchants {
+        page.row(&[
+            (&truncate(&stats.merchant, 50), MARGIN, false),
+            (&format!("{:.2}", stats.total_spend), 380.0, true),
+            (&stats.visits.to_string(), 440.0, true),
+            (&format!("{:.2}", stats.average_ticket), right, true),
+        ]);
+    }
+
+    page.heading("Budget variance");
+    if report.budgets.is_empty() {
+        page.note("No budgets set. Add one with `budget set <category> <amount>`.");
+    } else {
+        page.row(&[("Category", MARGIN, false), ("Budget", 340.0, true), ("Spent", 420.0, true), ("Remaining", right, true)]);
+        for variance in &report.budgets {
+            page.row(&[
+                (&variance.category, MARGIN, false),
+                (&format!("{:.2}", variance.budget), 340.0, true),
+                (&format!("{:.2}", variance.spent), 420.0, true),
+                (&format!("{:.2}", variance.remaining()), right, true),
+            ]);
+        }
+    }
+
+    page.heading("Notable transactions");
+    for transaction in &report.notable {
+        page.row(&[
+            (&transaction.date.format("%Y-%m-%d").to_string(), MARGIN, false),
+            (&truncate(&transaction.merchant, 28), MARGIN + 65.0, false),
+            (&truncate(&transaction.category, 18), MARGIN + 225.0, false),
+            (&format!("{:.2}", transaction.amount), right, true),
+        ]);
+        page.row(&[(&truncate(&transaction.reason, 80), MARGIN + 65.0, false)]);
+    }
+
+    writer.write_all(&assemble(&report.title(), page.finish()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn assemble(title: &str, pages: Vec<Vec<u8>>) -> Vec<u8> {
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let heading_font_id = Ref::new(3);
+    let body_font_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    let page_ids: Vec<Ref> = (0..pages.len()).map(|i| Ref::new(6 + 2 * i as i32)).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(pages.len() as i32);
+    pdf.document_info(info_id).title(pdf_writer::TextStr(title));
+
+    for (page_id, content) in page_ids.iter().zip(pages) {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        let mut fonts = resources.fonts();
+        fonts.pair(HEADING_FONT, heading_font_id);
+        fonts.pair(BODY_FONT, body_font_id);
+        fonts.finish();
+        resources.finish();
+        page.finish();
+        pdf.stream(content_id, &content);
+    }
+
+    pdf.type1_font(heading_font_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(body_font_id)
+        .base_font(Name(b"Courier"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+
+    pdf.finish()
+}
+
+// The standard fonts only cover WinAnsi; anything else becomes '?'
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            '€' => 0x80,
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u32 as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else {
+        let mut truncated: String = text.chars().take(max_chars - 1).collect();
+        truncated.push('~');
+        truncated
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use crate::db::{
+    batch::{Batch, BatchAction},
+    journal::JournalEntry,
+};
+use crate::models::{
+    category::{Category, CategorySource, Rule, SourceKind},
+    flag::{FlagKind, FlagStatus, TransactionFlag},
+    merchant::MerchantAlias,
+    transaction::Transaction,
+};
+use super::{
+    CategoryRepository, FilterRepository, FlagRepository, JournalRepository, MerchantRepository, RuleRepository,
+    SettingsRepository, TransactionRepository,
+};
+
+// Keeps everything in a Vec behind a lock; for tests and for code that
+// shouldn't need a database file
+#[derive(Debug, Default)]
+pub struct MemoryRepository {
+    state: Mutex<State>,
+    journal: Mutex<Vec<Edit>>,
+    // Not journaled, like the settings table
+    settings: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct State {
+    transactions: Vec<Transaction>,
+    categories: Vec<Category>,
+    assignments: HashMap<u64, (String, CategorySource)>,
+    flags: HashMap<u64, Vec<TransactionFlag>>,
+    aliases: Vec<MerchantAlias>,
+    filters: HashMap<String, String>,
+    last_transaction_id: u64,
+    last_rule_id: i64,
+}
+
+// What an edit changed: per transaction where other writes may touch the
+// same collection in between, whole collections elsewhere. Undoing it puts
+// back only those parts, like the row-level changes SQLite journals.
+#[derive(Debug)]
+struct Edit {
+    description: String,
+    transactions: Vec<Swap<u64, Transaction>>,
+    assignments: Vec<Swap<u64, (String, CategorySource)>>,
+    flags: Vec<Swap<u64, Vec<TransactionFlag>>>,
+    categories: Option<(Vec<Category>, Vec<Category>)>,
+    aliases: Option<(Vec<MerchantAlias>, Vec<MerchantAlias>)>,
+    filters: Option<(HashMap<String, String>, HashMap<String, String>)>,
+    undone: bool,
+}
+
+type Swap<K, V> = (K, Option<V>, Option<V>);
+
+impl MemoryRepository {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn journal(&self) -> MutexGuard<'_, Vec<Edit>> {
+        self.journal.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Runs `f` as one edit: nothing changes if it fails, and it is journaled
+    // if it changed anything
+    fn edit<T>(&self, description: String, f: impl FnOnce(&mut State) -> Result<T>) -> Result<T> {
+        let mut state = self.state();
+        let before = state.clone();
+        let result = f(&mut state);
+        if result.is_err() {
+            *state = before;
+        } else if *state != before {
+            let edit = Edit::between(description, &before, &state);
+            let mut journal = self.journal();
+            journal.retain(|edit| !edit.undone);
+            journal.push(edit);
+        }
+        result
+    }
+
+    // Undoes the newest edit that is not undone, or redoes the oldest one
+    // that is
+    fn step(&self, undo: bool) -> Option<String> {
+        let mut state = self.state();
+        let mut journal = self.journal();
+        let edit = if undo {
+            journal.iter_mut().rev().find(|edit| !edit.undone)?
+        } else {
+            journal.iter_mut().find(|edit| edit.undone)?
+        };
+        edit.apply(&mut state, undo);
+        edit.undone = undo;
+        Some(edit.description.clone())
+    }
+}
+
+fn swaps<K: Eq + Hash + Copy, V: Clone + PartialEq>(before: &HashMap<K, V>, after: &HashMap<K, V>) -> Vec<Swap<K, V>> {
+    let mut swaps: Vec<_> = before.iter()
+        .filter(|(key, value)| after.get(key) != Some(value))
+        .map(|(key, value)| (*key, Some(value.clone()), after.get(key).cloned()))
+        .collect();
+    swaps.extend(after.iter()
+        .filter(|(key, _)| !before.contains_key(key))
+        .map(|(key, value)| (*key, None, Some(value.clone()))));
+    swaps
+}
+
+fn changed<T: Clone + PartialEq>(before: &T, after: &T) -> Option<(T, T)> {
+    (before != after).then(|| (before.clone(), after.clone()))
+}
+
+fn pick<T: Clone>((before, after): &(T, T), undo: bool) -> T {
+    if undo { before.clone() } else { after.clone() }
+}
+
+fn by_id(transactions: &[Transaction]) -> HashMap<u64, Transaction> {
+    transactions.iter().map(|t| (t.id, t.clone())).collect()
+}
+
+fn put<K: Eq + Hash + Copy, V: Clone>(map: &mut HashMap<K, V>, swaps: &[Swap<K, V>], undo: bool) {
+    for (key, before, after) in swaps {
+        match if undo { before } else { after } {
+            Some(value) => map.insert(*key, value.clone()),
+            None => map.remove(key),
+        };
+    }
+}
+
+impl Edit {
+    fn between(description: String, before: &State, after: &State) -> Self {
+        Edit {
+            description,
+            transactions: swaps(&by_id(&before.transactions), &by_id(&after.transactions)),
+            assignments: swaps(&before.assignments, &after.assignments),
+            flags: swaps(&before.flags, &after.flags),
+            categories: changed(&before.categories, &after.categories),
+            aliases: changed(&before.aliases, &after.aliases),
+            filters: changed(&before.filters, &after.filters),
+            undone: false,
+        }
+    }
+
+    fn apply(&self, state: &mut State, undo: bool) {
+        let mut transactions = by_id(&state.transactions);
+        put(&mut transactions, &self.transactions, undo);
+        state.transactions = transactions.into_values().collect();
+        put(&mut state.assignments, &self.assignments, undo);
+        put(&mut state.flags, &self.flags, undo);
+        if let Some(categories) = &self.categories {
+            state.categories = pick(categories, undo);
+        }
+        if let Some(aliases) = &self.aliases {
+            state.aliases = pick(aliases, undo);
+        }
+        if let Some(filters) = &self.filters {
+            state.filters = pick(filters, undo);
+        }
+    }
+}
+
+impl State {
+    fn category(&mut self, name: &str) -> Option<&mut Category> {
+        self.categories.iter_mut().find(|c| c.name == name)
+    }
+
+    fn has_category(&self, name: &str) -> bool {
+        self.categories.iter().any(|c| c.name == name)
+    }
+
+    fn next_rule_id(&mut self) -> i64 {
+        self.last_rule_id += 1;
+        self.last_rule_id
+    }
+}
+
+// The columns SQLite uses to recognise a row it already has
+fn same_row(a: &Transaction, b: &Transaction) -> bool {
+    a.date == b.date
+        && a.amount == b.amount
+        && a.raw_merchant == b.raw_merchant
+        && a.description == b.description
+        && a.account == b.account
+        && a.balance == b.balance
+}
+
+#[async_trait]
+impl TransactionRepository for MemoryRepository {
+    async fn get_all_transactions(&self) -> Result<Vec<Transaction>> {
+        let mut transactions = self.state().transactions.clone();
+        transactions.sort_by(|a, b| b.date.cmp(&a.date).then(a.id.cmp(&b.id)));
+        Ok(transactions)
+    }
+
+    async fn save_transactions(&self, transactions: Vec<Transaction>) -> Result<usize> {
+        let mut state = self.state();
+        let mut inserted = 0;
+        for mut transaction in transactions {
+            if state.transactions.iter().any(|t| same_row(t, &transaction)) {
+                continue;
+            }
+            state.last_transaction_id += 1;
+            transaction.id = state.last_transaction_id;
+            transaction.merchant = transaction.raw_merchant.clone();
+            transaction.category = None;
+            transaction.category_source = None;
+            transaction.flags.clear();
+            transaction.excluded = false;
+            state.transactions.push(transaction);
+            inserted += 1;
+        }
+        Ok(inserted)
+    }
+
+    async fn count(&self) -> Result<usize> {
+        Ok(self.state().transactions.len())
+    }
+}
+
+#[async_trait]
+impl CategoryRepository for MemoryRepository {
+    async fn get_all_categories(&self) -> Result<Vec<Category>> {
+        Ok(self.state().categories.clone())
+    }
+
+    async fn get_category_by_name(&self, name: &str) -> Result<Option<Category>> {
+        Ok(self.state().category(name).cloned())
+    }
+
+    async fn upsert_category(&self, category: Category) -> Result<()> {
+        self.edit(format!("Saved category {}", category.name), |state| {
+            if !state.has_category(&category.name) {
+                state.categories.push(Category { name: category.name.clone(), rules: Vec::new() });
+            }
+
+            let mut rules = Vec::with_capacity(category.rules.len());
+            for rule in category.rules {
+                let existing = state.category(&category.name)
+                    .and_then(|c| c.rules.iter().find(|r| r.pattern == rule.pattern))
+                    .map(|r| r.id);
+                let id = match existing {
+                    Some(id) => id,
+                    None => state.next_rule_id(),
+                };
+                rules.push(Rule { id, category: category.name.clone(), ..rule });
+            }
+            if let Some(stored) = state.category(&category.name) {
+                stored.rules = rules;
+            }
+            Ok(())
+        })
+    }
+
+    async fn rename_category(&self, name: &str, new_name: &str) -> Result<bool> {
+        self.edit(format!("Renamed category {} to {}", name, new_name), |state| {
+            if !state.has_category(name) {
+                return Ok(false);
+            }
+            if new_name == name {
+                return Ok(true);
+            }
+            if state.has_category(new_name) {
+                bail!("Category '{}' already exists; merge into it instead", new_name);
+            }
+
+            if let Some(category) = state.category(name) {
+                category.name = new_name.to_string();
+                for rule in &mut category.rules {
+                    rule.category = new_name.to_string();
+                }
+            }
+            for (category, _) in state.assignments.values_mut() {
+                if category == name {
+                    *category = new_name.to_string();
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    async fn delete_category(&self, name: &str, reassign_to: Option<&str>) -> Result<bool> {
+        let description = match reassign_to {
+            Some(target) => format!("Deleted category {} (moved to {})", name, target),
+            None => format!("Deleted category {}", name),
+        };
+        self.edit(description, |state| {
+            if !state.has_category(name) {
+                return Ok(false);
+            }
+            if let Some(target) = reassign_to {
+                if !state.has_category(target) {
+                    bail!("Unknown category '{}'", target);
+                }
+                if target == name {
+                    bail!("Cannot reassign '{}' to itself", name);
+                }
+            }
+
+            state.categories.retain(|c| c.name != name);
+            match reassign_to {
+                Some(target) => {
+                    for (category, _) in state.assignments.values_mut() {
+                        if category == name {
+                            *category = target.to_string();
+                        }
+                    }
+                }
+                None => state.assignments.retain(|_, (category, _)| category != name),
+            }
+            Ok(true)
+        })
+    }
+
+    async fn assign_category(&self, transaction_id: u64, category: &str, source: SourceKind, confidence: Option<f64>) -> Result<()> {
+        let mut state = self.state();
+        if !state.has_category(category) {
+            bail!("Unknown category '{}'", category);
+        }
+        if !state.transactions.iter().any(|t| t.id == transaction_id) {
+            bail!("No transaction with id {}", transaction_id);
+        }
+        let source = CategorySource::stored(source, confidence, None);
+        state.assignments.insert(transaction_id, (category.to_string(), source));
+        Ok(())
+    }
+
+    async fn get_assigned_categories(&self) -> Result<HashMap<u64, (String, CategorySource)>> {
+        Ok(self.state().assignments.clone())
+    }
+
+    // Seeding isn't an edit, so it stays out of the journal
+    async fn initialize_default_categories(&self) -> Result<()> {
+        let mut state = self.state();
+        for category in Category::default_categories() {
+            if !state.has_category(&category.name) {
+                let rules = category.rules.iter().map(|rule| Rule { id: state.next_rule_id(), ..rule.clone() }).collect();
+                state.categories.push(Category { rules, ..category });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RuleRepository for MemoryRepository {
+    async fn get_rules(&self) -> Result<Vec<Rule>> {
+        let mut rules: Vec<Rule> = self.state().categories.iter().flat_map(|c| c.rules.iter().cloned()).collect();
+        rules.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.category.cmp(&b.category)).then(a.id.cmp(&b.id)));
+        Ok(rules)
+    }
+
+    async fn add_rule(&self, rule: Rule) -> Result<i64> {
+        self.edit(format!("Added rule '{}' for {}", rule.pattern, rule.category), |state| {
+            if !state.has_category(&rule.category) {
+                bail!("Unknown category '{}'", rule.category);
+            }
+            let id = state.next_rule_id();
+            if let Some(category) = state.category(&rule.category) {
+                category.rules.push(Rule { id, ..rule });
+            }
+            Ok(id)
+        })
+    }
+
+    async fn update_rule(&self, rule: Rule) -> Result<bool> {
+        self.edit(format!("Updated rule '{}' for {}", rule.pattern, rule.category), |state| {
+            if !state.categories.iter().any(|c| c.rules.iter().any(|r| r.id == rule.id)) {
+                return Ok(false);
+            }
+            if !state.has_category(&rule.category) {
+                bail!("Unknown category '{}'", rule.category);
+            }
+            for category in &mut state.categories {
+                category.rules.retain(|r| r.id != rule.id);
+            }
+            if let Some(category) = state.category(&rule.category) {
+                category.rules.push(rule);
+            }
+            Ok(true)
+        })
+    }
+
+    async fn remove_rule(&self, id: i64) -> Result<bool> {
+        let description = self.state().categories.iter()
+            .flat_map(|c| c.rules.iter())
+            .find(|r| r.id == id)
+            .map(|r| format!("Removed rule '{}' for {}", r.pattern, r.category))
+            .unwrap_or_default();
+        self.edit(description, |state| {
+            let mut removed = false;
+            for category in &mut state.categories {
+                let before = category.rules.len();
+                category.rules.retain(|r| r.id != id);
+                removed |= category.rules.len() != before;
+            }
+            Ok(removed)
+        })
+    }
+}
+
+#[async_trait]
+impl JournalRepository for MemoryRepository {
+    async fn apply_batch(&self, transaction_ids: Vec<u64>, action: BatchAction) -> Result<Batch> {
+        let description = action.describe(transaction_ids.len());
+        self.edit(description.clone(), |state| {
+            if let BatchAction::SetCategory(category) = &action
+                && !state.has_category(category)
+            {
+                bail!("Unknown category '{}'", category);
+            }
+            for id in &transaction_ids {
+                let Some(index) = state.transactions.iter().position(|t| t.id == *id) else {
+                    continue;
+                };
+                let transaction = &mut state.transactions[index];
+                match &action {
+                    BatchAction::SetCategory(category) => {
+                        let source = CategorySource::stored(SourceKind::Manual, None, None);
+                        state.assignments.insert(*id, (category.clone(), source));
+                    }
+                    BatchAction::AddTags(tags) => {
+                        for tag in tags {
+                            if !transaction.tags.contains(tag) {
+                                transaction.tags.push(tag.clone());
+                            }
+                        }
+                    }
+                    BatchAction::RemoveTags(tags) => transaction.tags.retain(|tag| !tags.contains(tag)),
+                    BatchAction::SetExcluded(excluded) => transaction.excluded = *excluded,
+                    BatchAction::Delete => {
+                        state.transactions.remove(index);
+                        state.assignments.remove(id);
+                        state.flags.remove(id);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        // Only SQLite keeps row-level changes
+        Ok(Batch { description, changes: Vec::new() })
+    }
+
+    async fn undo(&self) -> Result<Option<String>> {
+        Ok(self.step(true))
+    }
+
+    async fn redo(&self) -> Result<Option<String>> {
+        Ok(self.step(false))
+    }
+
+    async fn get_entries(&self, limit: usize) -> Result<Vec<JournalEntry>> {
+        Ok(self.journal()
+            .iter()
+            .enumerate()
+            .rev()
+            .take(limit)
+            .map(|(i, edit)| JournalEntry {
+                id: i as i64 + 1,
+                description: edit.description.clone(),
+                changes: Vec::new(),
+                undone: edit.undone,
+                created_at: String::new(),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl FlagRepository for MemoryRepository {
+    async fn save_flags(&self, flags: Vec<(u64, TransactionFlag)>) -> Result<()> {
+        let mut state = self.state();
+        for (transaction_id, stored) in state.flags.iter_mut() {
+            stored.retain(|f| {
+                f.status != FlagStatus::Pending
+                    || flags.iter().any(|(id, flag)| id == transaction_id && flag.kind == f.kind)
+            });
+        }
+        state.flags.retain(|_, stored| !stored.is_empty());
+        for (transaction_id, flag) in flags {
+            let stored = state.flags.entry(transaction_id).or_default();
+            if !stored.iter().any(|f| f.kind == flag.kind) {
+                stored.push(flag);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_all_flags(&self) -> Result<HashMap<u64, Vec<TransactionFlag>>> {
+        Ok(self.state().flags.clone())
+    }
+
+    async fn set_flag_status(&self, transaction_id: u64, kind: FlagKind, status: FlagStatus) -> Result<()> {
+        let description = format!("Marked the {} flag on #{} {}", kind.as_str(), transaction_id, status.as_str());
+        self.edit(description, |state| {
+            let flag = state.flags.get_mut(&transaction_id).and_then(|flags| flags.iter_mut().find(|f| f.kind == kind));
+            if let Some(flag) = flag {
+                flag.status = status;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[async_trait]
+impl MerchantRepository for MemoryRepository {
+    async fn get_all_aliases(&self) -> Result<Vec<MerchantAlias>> {
+        let mut aliases = self.state().aliases.clone();
+        aliases.sort_by(|a, b| a.canonical.cmp(&b.canonical).then(a.pattern.cmp(&b.pattern)));
+        Ok(aliases)
+    }
+
+    async fn save_alias(&self, alias: MerchantAlias) -> Result<()> {
+        self.edit(format!("Showing '{}' as {}", alias.pattern, alias.canonical), |state| {
+            state.aliases.retain(|a| a.pattern != alias.pattern);
+            state.aliases.push(alias);
+            Ok(())
+        })
+    }
+
+    async fn remove_alias(&self, pattern: &str) -> Result<bool> {
+        self.edit(format!("Removed the alias for '{}'", pattern), |state| {
+            let before = state.aliases.len();
+            state.aliases.retain(|a| a.pattern != pattern);
+            Ok(state.aliases.len() != before)
+        })
+    }
+
+    // Seeding isn't an edit, so it stays out of the journal
+    async fn initialize_default_aliases(&self) -> Result<()> {
+        let mut state = self.state();
+        for alias in MerchantAlias::default_aliases() {
+            state.aliases.retain(|a| a.pattern != alias.pattern);
+            state.aliases.push(alias);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FilterRepository for MemoryRepository {
+    async fn get_all_filters(&self) -> Result<HashMap<String, String>> {
+        Ok(self.state().filters.clone())
+    }
+
+    async fn save_filter(&self, name: &str, query: &str) -> Result<()> {
+        self.edit(format!("Saved filter @{}", name), |state| {
+            state.filters.insert(name.to_string(), query.to_string());
+            Ok(())
+        })
+    }
+
+    async fn remove_filter(&self, name: &str) -> Result<bool> {
+        self.edit(format!("Removed filter @{}", name), |state| Ok(state.filters.remove(name).is_some()))
+    }
+}
+
+#[async_trait]
+impl SettingsRepository for MemoryRepository {
+    async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.settings.lock().unwrap_or_else(PoisonError::into_inner).get(key).cloned())
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.settings.lock().unwrap_or_else(PoisonError::into_inner).insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
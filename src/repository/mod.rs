@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+use crate::db::{
+    batch::{Batch, BatchAction},
+    journal::JournalEntry,
+};
+use crate::models::{
+    category::{Category, CategorySource, Rule, SourceKind},
+    flag::{FlagKind, FlagStatus, TransactionFlag},
+    merchant::MerchantAlias,
+    transaction::Transaction,
+};
+
+pub mod memory;
+pub mod sqlite;
+
+pub use memory::MemoryRepository;
+pub use sqlite::{lock, SharedConnection, SqliteRepository};
+
+#[async_trait]
+pub trait TransactionRepository: Debug + Send + Sync {
+    // Newest first
+    async fn get_all_transactions(&self) -> Result<Vec<Transaction>>;
+    // Rows already stored are skipped; returns how many were new
+    async fn save_transactions(&self, transactions: Vec<Transaction>) -> Result<usize>;
+    async fn count(&self) -> Result<usize>;
+}
+
+#[async_trait]
+pub trait CategoryRepository: Debug + Send + Sync {
+    async fn get_all_categories(&self) -> Result<Vec<Category>>;
+    async fn get_category_by_name(&self, name: &str) -> Result<Option<Category>>;
+    // Inserts the category, or brings the stored one's rules in line with it
+    async fn upsert_category(&self, category: Category) -> Result<()>;
+    async fn rename_category(&self, name: &str, new_name: &str) -> Result<bool>;
+    async fn delete_category(&self, name: &str, reassign_to: Option<&str>) -> Result<bool>;
+    async fn assign_category(&self, transaction_id: u64, category: &str, source: SourceKind, confidence: Option<f64>) -> Result<()>;
+    // The most recent assignment per transaction
+    async fn get_assigned_categories(&self) -> Result<HashMap<u64, (String, CategorySource)>>;
+
+    async fn initialize_default_categories(&self) -> Result<()> {
+        for category in Category::default_categories() {
+            self.upsert_category(category).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+pub trait RuleRepository: Debug + Send + Sync {
+    // Highest priority first
+    async fn get_rules(&self) -> Result<Vec<Rule>>;
+    async fn add_rule(&self, rule: Rule) -> Result<i64>;
+    async fn update_rule(&self, rule: Rule) -> Result<bool>;
+    async fn remove_rule(&self, id: i64) -> Result<bool>;
+}
+
+// Edits that can be undone: batch actions, and whatever the other stores
+// record as they change
+#[async_trait]
+pub trait JournalRepository: Debug + Send + Sync {
+    // Applies the action to every transaction as a single edit
+    async fn apply_batch(&self, transaction_ids: Vec<u64>, action: BatchAction) -> Result<Batch>;
+    // Both return the edit's description, or None if there is nothing to step over
+    async fn undo(&self) -> Result<Option<String>>;
+    async fn redo(&self) -> Result<Option<String>>;
+    // Newest first
+    async fn get_entries(&self, limit: usize) -> Result<Vec<JournalEntry>>;
+}
+
+#[async_trait]
+pub trait FlagRepository: Debug + Send + Sync {
+    // Everything detection found: flags already stored keep their review
+    // status, pending ones missing from `flags` are dropped
+    async fn save_flags(&self, flags: Vec<(u64, TransactionFlag)>) -> Result<()>;
+    async fn get_all_flags(&self) -> Result<HashMap<u64, Vec<TransactionFlag>>>;
+    async fn set_flag_status(&self, transaction_id: u64, kind: FlagKind, status: FlagStatus) -> Result<()>;
+}
+
+#[async_trait]
+pub trait MerchantRepository: Debug + Send + Sync {
+    async fn get_all_aliases(&self) -> Result<Vec<MerchantAlias>>;
+    async fn save_alias(&self, alias: MerchantAlias) -> Result<()>;
+    async fn remove_alias(&self, pattern: &str) -> Result<bool>;
+
+    async fn initialize_default_aliases(&self) -> Result<()> {
+        for alias in MerchantAlias::default_aliases() {
+            self.save_alias(alias).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+pub trait FilterRepository: Debug + Send + Sync {
+    async fn get_all_filters(&self) -> Result<HashMap<String, String>>;
+    async fn save_filter(&self, name: &str, query: &str) -> Result<()>;
+    async fn remove_filter(&self, name: &str) -> Result<bool>;
+}
+
+#[async_trait]
+pub trait SettingsRepository: Debug + Send + Sync {
+    async fn get_setting(&self, key: &str) -> Result<Option<String>>;
+    async fn set_setting(&self, key: &str, value: &str) -> Result<()>;
+}
+
+// The stores the UI reads and writes through, usually all backed by the
+// same object
+#[derive(Debug, Clone)]
+pub struct Repositories {
+    pub transactions: Arc<dyn TransactionRepository>,
+    pub categories: Arc<dyn CategoryRepository>,
+    pub rules: Arc<dyn RuleRepository>,
+    pub journal: Arc<dyn JournalRepository>,
+    pub flags: Arc<dyn FlagRepository>,
+    pub merchants: Arc<dyn MerchantRepository>,
+    pub filters: Arc<dyn FilterRepository>,
+    pub settings: Arc<dyn SettingsRepository>,
+    // Only an encrypted file is worth locking
+    pub encrypted: bool,
+}
+
+impl Repositories {
+    pub fn sqlite(db: SharedConnection) -> Self {
+        let encrypted = lock(&db).is_encrypted();
+        Self::all(Arc::new(SqliteRepository::new(db)), encrypted)
+    }
+
+    pub fn memory() -> Self {
+        Self::all(Arc::new(MemoryRepository::default()), false)
+    }
+
+    fn all<R>(repository: Arc<R>, encrypted: bool) -> Self
+    where
+        R: TransactionRepository
+            + CategoryRepository
+            + RuleRepository
+            + JournalRepository
+            + FlagRepository
+            + MerchantRepository
+            + FilterRepository
+            + SettingsRepository
+            + 'static,
+    {
+        Self {
+            transactions: repository.clone(),
+            categories: repository.clone(),
+            rules: repository.clone(),
+            journal: repository.clone(),
+            flags: repository.clone(),
+            merchants: repository.clone(),
+            filters: repository.clone(),
+            settings: repository,
+            encrypted,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rusqlite::Connection;
+use crate::db::{
+    batch::{Batch, BatchAction, BatchDb},
+    category::CategoryDb,
+    connection::DbConnection,
+    filter::FilterDb,
+    flag::FlagDb,
+    journal::{JournalDb, JournalEntry},
+    merchant::MerchantDb,
+    settings::SettingsDb,
+    transaction::TransactionDb,
+};
+use crate::models::{
+    category::{Category, CategorySource, Rule, SourceKind},
+    flag::{FlagKind, FlagStatus, TransactionFlag},
+    merchant::MerchantAlias,
+    transaction::Transaction,
+};
+use super::{
+    CategoryRepository, FilterRepository, FlagRepository, JournalRepository, MerchantRepository, RuleRepository,
+    SettingsRepository, TransactionRepository,
+};
+
+pub type SharedConnection = Arc<Mutex<DbConnection>>;
+
+// A panic mid-query can't leave anything half-written, since SQLite rolls
+// back an open transaction when it's dropped, so a poisoned lock is still
+// safe to use
+pub fn lock(db: &SharedConnection) -> MutexGuard<'_, DbConnection> {
+    db.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Runs the synchronous *Db types on tokio's blocking pool, one call at a
+// time on the shared connection
+#[derive(Debug, Clone)]
+pub struct SqliteRepository {
+    db: SharedConnection,
+}
+
+impl SqliteRepository {
+    pub fn new(db: SharedConnection) -> Self {
+        Self { db }
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || f(lock(&db).get_connection())).await?
+    }
+}
+
+#[async_trait]
+impl TransactionRepository for SqliteRepository {
+    async fn get_all_transactions(&self) -> Result<Vec<Transaction>> {
+        self.run(|conn| TransactionDb::new(conn).get_all_transactions()).await
+    }
+
+    async fn save_transactions(&self, transactions: Vec<Transaction>) -> Result<usize> {
+        self.run(move |conn| TransactionDb::new(conn).save_transactions(&transactions)).await
+    }
+
+    async fn count(&self) -> Result<usize> {
+        self.run(|conn| TransactionDb::new(conn).count()).await
+    }
+}
+
+#[async_trait]
+impl CategoryRepository for SqliteRepository {
+    async fn get_all_categories(&self) -> Result<Vec<Category>> {
+        self.run(|conn| CategoryDb::new(conn).get_all_categories()).await
+    }
+
+    async fn get_category_by_name(&self, name: &str) -> Result<Option<Category>> {
+        let name = name.to_string();
+        self.run(move |conn| CategoryDb::new(conn).get_category_by_name(&name)).await
+    }
+
+    async fn upsert_category(&self, category: Category) -> Result<()> {
+        self.run(move |conn| CategoryDb::new(conn).upsert_category(&category).map(|_| ())).await
+    }
+
+    async fn rename_category(&self, name: &str, new_name: &str) -> Result<bool> {
+        let (name, new_name) = (name.to_string(), new_name.to_string());
+        self.run(move |conn| CategoryDb::new(conn).rename_category(&name, &new_name)).await
+    }
+
+    async fn delete_category(&self, name: &str, reassign_to: Option<&str>) -> Result<bool> {
+        let (name, reassign_to) = (name.to_string(), reassign_to.map(str::to_string));
+        self.run(move |conn| {
+            let moved = CategoryDb::new(conn).delete_category(&name, reassign_to.as_deref())?;
+            Ok(moved.is_some())
+        }).await
+    }
+
+    async fn assign_category(&self, transaction_id: u64, category: &str, source: SourceKind, confidence: Option<f64>) -> Result<()> {
+        let category = category.to_string();
+        self.run(move |conn| {
+            let mut category_db = CategoryDb::new(conn);
+            let category_id = category_db.get_category_id(&category)?
+                .ok_or_else(|| anyhow!("Unknown category '{}'", category))?;
+            category_db.assign_category(transaction_id as i64, category_id, source, confidence)
+        }).await
+    }
+
+    async fn get_assigned_categories(&self) -> Result<HashMap<u64, (String, CategorySource)>> {
+        self.run(|conn| CategoryDb::new(conn).get_assigned_categories()).await
+    }
+
+    // Seeding isn't an edit, so it stays out of the journal
+    async fn initialize_default_categories(&self) -> Result<()> {
+        self.run(|conn| CategoryDb::new(conn).initialize_default_categories()).await
+    }
+}
+
+#[async_trait]
+impl RuleRepository for SqliteRepository {
+    async fn get_rules(&self) -> Result<Vec<Rule>> {
+        self.run(|conn| {
+            let rules = CategoryDb::new(conn).get_rules()?;
+            Ok(rules.into_iter().map(|(_, rule)| rule).collect())
+        }).await
+    }
+
+    async fn add_rule(&self, rule: Rule) -> Result<i64> {
+        self.run(move |conn| CategoryDb::new(conn).add_rule(&rule)).await
+    }
+
+    async fn update_rule(&self, rule: Rule) -> Result<bool> {
+        self.run(move |conn| CategoryDb::new(conn).update_rule(&rule)).await
+    }
+
+    async fn remove_rule(&self, id: i64) -> Result<bool> {
+        self.run(move |conn| CategoryDb::new(conn).remove_rule(id)).await
+    }
+}
+
+#[async_trait]
+impl JournalRepository for SqliteRepository {
+    async fn apply_batch(&self, transaction_ids: Vec<u64>, action: BatchAction) -> Result<Batch> {
+        self.run(move |conn| BatchDb::new(conn).apply(&transaction_ids, &action)).await
+    }
+
+    async fn undo(&self) -> Result<Option<String>> {
+        self.run(|conn| JournalDb::new(conn).undo()).await
+    }
+
+    async fn redo(&self) -> Result<Option<String>> {
+        self.run(|conn| JournalDb::new(conn).redo()).await
+    }
+
+    async fn get_entries(&self, limit: usize) -> Result<Vec<JournalEntry>> {
+        self.run(move |conn| JournalDb::new(conn).get_entries(limit)).await
+    }
+}
+
+#[async_trait]
+impl FlagRepository for SqliteRepository {
+    async fn save_flags(&self, flags: Vec<(u64, TransactionFlag)>) -> Result<()> {
+        self.run(move |conn| FlagDb::new(conn).save_flags(&flags)).await
+    }
+
+    async fn get_all_flags(&self) -> Result<HashMap<u64, Vec<TransactionFlag>>> {
+        self.run(|conn| FlagDb::new(conn).get_all_flags()).await
+    }
+
+    async fn set_flag_status(&self, transaction_id: u64, kind: FlagKind, status: FlagStatus) -> Result<()> {
+        self.run(move |conn| FlagDb::new(conn).set_status(transaction_id, kind, status)).await
+    }
+}
+
+#[async_trait]
+impl MerchantRepository for SqliteRepository {
+    async fn get_all_aliases(&self) -> Result<Vec<MerchantAlias>> {
+        self.run(|conn| MerchantDb::new(conn).get_all_aliases()).await
+    }
+
+    async fn save_alias(&self, alias: MerchantAlias) -> Result<()> {
+        self.run(move |conn| MerchantDb::new(conn).save_alias(&alias)).await
+    }
+
+    async fn remove_alias(&self, pattern: &str) -> Result<bool> {
+        let pattern = pattern.to_string();
+        self.run(move |conn| MerchantDb::new(conn).remove_alias(&pattern)).await
+    }
+
+    // Seeding isn't an edit, so it stays out of the journal
+    async fn initialize_default_aliases(&self) -> Result<()> {
+        self.run(|conn| MerchantDb::new(conn).initialize_default_aliases()).await
+    }
+}
+
+#[async_trait]
+impl FilterRepository for SqliteRepository {
+    async fn get_all_filters(&self) -> Result<HashMap<String, String>> {
+        self.run(|conn| FilterDb::new(conn).get_all_filters()).await
+    }
+
+    async fn save_filter(&self, name: &str, query: &str) -> Result<()> {
+        let (name, query) = (name.to_string(), query.to_string());
+        self.run(move |conn| FilterDb::new(conn).save_filter(&name, &query)).await
+    }
+
+    async fn remove_filter(&self, name: &str) -> Result<bool> {
+        let name = name.to_string();
+        self.run(move |conn| FilterDb::new(conn).remove_filter(&name)).await
+    }
+}
+
+#[async_trait]
+impl SettingsRepository for SqliteRepository {
+    async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let key = key.to_string();
+        self.run(move |conn| SettingsDb::new(conn).get(&key)).await
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        let (key, value) = (key.to_string(), value.to_string());
+        self.run(move |conn| SettingsDb::new(conn).set(&key, &value)).await
+    }
+}
@@ -1,12 +1,40 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::NaiveDate;
 use rust_decimal::Decimal;
-use ratatui::widgets::ListState;
+use zeroize::Zeroizing;
+use ratatui::widgets::{ListState, TableState};
 use crossterm::event::KeyCode;
 use crate::models::{
-    category::{Category, CategoryType},
+    category::{Category, CategorySource, CategoryType, RuleMatcher},
+    flag::FlagStatus,
+    merchant::{clean_merchant, MerchantAlias, MerchantNormalizer},
     transaction::Transaction,
 };
-use crate::db::{connection::DbConnection, category::CategoryDb};
+use crate::db::{batch::BatchAction, journal::JournalEntry, connection::DbConnection};
+use crate::repository::Repositories;
+use crate::config::Profile;
+use crate::export::export_to_file;
+use super::task::{Task, TaskEvent, TaskKind};
+use super::columns::{default_columns, format_columns, parse_columns, ColumnSetting, RowCache, COLUMNS_KEY};
+use crate::query::{Query, QueryError};
+use crate::utils::csv::parse_tags;
+use crate::analysis::{
+    aggregate::{category_totals, sorted_totals},
+    anomaly::{detect_anomalies, AnomalyConfig},
+    forecast::{forecast_balances, write_forecast_csv, AccountForecast, ForecastConfig},
+    merchants::{
+        category_concentration, merchant_totals, top_merchants, transactions_in_period, Concentration,
+        MerchantStats, ReportPeriod,
+    },
+    trend::monthly_totals,
+};
+
+const LOW_BALANCE_THRESHOLD_KEY: &str = "forecast.low_balance_threshold";
+const CONCENTRATION_TOP_N: usize = 5;
+const HISTORY_LIMIT: usize = 200;
 
 #[derive(Debug)]
 pub enum View {
@@ -14,15 +42,44 @@ pub enum View {
     CategorySummary,
     TransactionDetail,
     CategoryDetail,
+    Forecast,
+    ReviewQueue,
+    MerchantReport,
+    History,
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryDetail {
+    pub category: String,
+    pub transactions: Vec<usize>,
+    pub total: Decimal,
+    pub monthly_totals: Vec<(String, Decimal)>,
+    pub top_merchants: Vec<MerchantStats>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SortOrder {
     Ascending,
     Descending,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl SortOrder {
+    pub fn reversed(self) -> SortOrder {
+        match self {
+            SortOrder::Ascending => SortOrder::Descending,
+            SortOrder::Descending => SortOrder::Ascending,
+        }
+    }
+
+    pub fn arrow(self) -> &'static str {
+        match self {
+            SortOrder::Ascending => "↑",
+            SortOrder::Descending => "↓",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SortField {
     Date,
     Amount,
@@ -30,228 +87,763 @@ pub enum SortField {
     Category,
 }
 
+impl SortField {
+    pub fn all() -> [SortField; 4] {
+        [SortField::Date, SortField::Amount, SortField::Merchant, SortField::Category]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortField::Date => "Date",
+            SortField::Amount => "Amount",
+            SortField::Merchant => "Merchant",
+            SortField::Category => "Category",
+        }
+    }
+
+    // Newest and largest first, names alphabetically
+    fn default_order(self) -> SortOrder {
+        match self {
+            SortField::Date | SortField::Amount => SortOrder::Descending,
+            SortField::Merchant | SortField::Category => SortOrder::Ascending,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SortKey {
+    pub field: SortField,
+    pub order: SortOrder,
+}
+
+impl SortKey {
+    pub fn new(field: SortField) -> Self {
+        SortKey { field, order: field.default_order() }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BatchMenuItem {
+    SetCategory,
+    AddTags,
+    RemoveTags,
+    MarkTransfer,
+    Exclude,
+    Include,
+    Delete,
+}
+
+impl BatchMenuItem {
+    pub fn all() -> [BatchMenuItem; 7] {
+        [
+            BatchMenuItem::SetCategory,
+            BatchMenuItem::AddTags,
+            BatchMenuItem::RemoveTags,
+            BatchMenuItem::MarkTransfer,
+            BatchMenuItem::Exclude,
+            BatchMenuItem::Include,
+            BatchMenuItem::Delete,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BatchMenuItem::SetCategory => "Set category",
+            BatchMenuItem::AddTags => "Add tags",
+            BatchMenuItem::RemoveTags => "Remove tags",
+            BatchMenuItem::MarkTransfer => "Mark as transfer",
+            BatchMenuItem::Exclude => "Exclude from reports",
+            BatchMenuItem::Include => "Include in reports",
+            BatchMenuItem::Delete => "Delete",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum InputMode {
     Normal,
     Filtering,
     Categorizing,
+    EditingThreshold,
+    Aliasing,
+    Exporting,
+    ExportingForecast,
+    Importing,
+    JumpingToDate,
+    SavingFilter,
+    Sorting,
+    EditingColumns,
+    BatchMenu,
+    AddingTags,
+    RemovingTags,
+}
+
+// Shown in place of the app once an encrypted database locks after being
+// left idle; the app and its connection are dropped until it is unlocked.
+// After UNLOCK_ATTEMPTS wrong passphrases the session ends.
+#[derive(Debug, Default)]
+pub struct LockScreen {
+    pub passphrase: Zeroizing<String>,
+    pub error: Option<String>,
+    pub failed_attempts: usize,
 }
 
 #[derive(Debug)]
 pub struct App {
     pub transactions: Vec<Transaction>,
-    pub filtered_transactions: Vec<usize>,
+    // Indices into `transactions` in display order, after filtering and sorting
+    pub list_view: Vec<usize>,
     pub categories: HashMap<String, Category>,
     pub current_view: View,
     pub selected_transaction: Option<usize>,
     pub category_totals: HashMap<String, Decimal>,
-    pub list_state: ListState,
-    pub sort_field: SortField,
-    pub sort_order: SortOrder,
+    pub list_state: TableState,
+    // Rows of the list that fit on screen, as of the last frame
+    pub list_page: usize,
+    pub row_cache: RowCache,
+    pub sort_primary: SortKey,
+    pub sort_secondary: Option<SortKey>,
+    pub sort_selection: Option<usize>,
+    pub columns: Vec<ColumnSetting>,
+    pub column_selection: Option<usize>,
+    // Ids of transactions marked for a batch action
+    pub marked: HashSet<u64>,
+    mark_anchor: Option<usize>,
+    pub batch_selection: Option<usize>,
+    pub history: Vec<JournalEntry>,
+    pub history_state: ListState,
     pub input_mode: InputMode,
     pub input_text: String,
     pub filter: Option<String>,
+    query: Query,
     pub can_show_details: bool,
     pub category_selection: Option<usize>,
-    pub available_categories: Vec<CategoryType>,
-    db_connection: DbConnection,
+    // The stored categories by name, as the picker offers them
+    pub available_categories: Vec<String>,
+    pub forecast_config: ForecastConfig,
+    pub forecasts: Vec<AccountForecast>,
+    pub status_message: Option<String>,
+    pub review_queue: Vec<(usize, usize)>,
+    pub review_state: ListState,
+    pub merchant_period: ReportPeriod,
+    pub merchant_stats: Vec<MerchantStats>,
+    pub merchant_concentration: Vec<Concentration>,
+    pub merchant_state: ListState,
+    pub category_state: ListState,
+    pub category_detail: Option<CategoryDetail>,
+    pub detail_state: TableState,
+    pub bulk_category: Option<String>,
+    pub saved_filters: HashMap<String, String>,
+    pub input_error: Option<String>,
+    pub profile: Profile,
+    // Import or categorization running in the background, if any
+    pub task: Option<Task>,
+    // Quick reads and edits block on the runtime; imports and
+    // categorization run on it as background tasks
+    repositories: Repositories,
+    runtime: tokio::runtime::Runtime,
+    // Off for the read-only commands, which show flags without storing them
+    store_flags: bool,
 }
 
 impl App {
-    pub fn new(csv_path: &str) -> anyhow::Result<Self> {
-        let transactions = crate::utils::csv::read_transactions_from_csv(csv_path)?;
-        let mut list_state = ListState::default();
-        if !transactions.is_empty() {
-            list_state.select(Some(0));
-        }
+    // For commands that only read: anomalies are flagged in memory, but
+    // nothing is written to the database
+    pub fn new(profile: &Profile, db_connection: DbConnection) -> anyhow::Result<Self> {
+        let repositories = Repositories::sqlite(Arc::new(Mutex::new(db_connection)));
+        Self::categorized(profile, repositories, false)
+    }
+
+    pub fn with_connection(profile: &Profile, db_connection: DbConnection) -> anyhow::Result<Self> {
+        Self::with_repositories(profile, Repositories::sqlite(Arc::new(Mutex::new(db_connection))))
+    }
+
+    // Categorizes everything before returning, for callers that use the
+    // result straight away
+    pub fn with_repositories(profile: &Profile, repositories: Repositories) -> anyhow::Result<Self> {
+        Self::categorized(profile, repositories, true)
+    }
+
+    fn categorized(profile: &Profile, repositories: Repositories, store_flags: bool) -> anyhow::Result<Self> {
+        let mut app = Self::load(profile, repositories)?;
+        app.store_flags = store_flags;
+        app.categorize_all_transactions()?;
+        app.after_categorization()?;
+        Ok(app)
+    }
 
-        // Initialize database connection
-        let mut db_connection = DbConnection::new("finance.db")?;
-        let mut category_db = CategoryDb::new(db_connection.get_connection());
-        
-        // Initialize default categories if none exist
-        if category_db.get_all_categories()?.is_empty() {
-            category_db.initialize_default_categories()?;
+    // For the TUI: shows the stored transactions at once, then categorizes
+    // them, or first imports `csv_path`, in the background
+    pub fn start(profile: &Profile, db_connection: DbConnection, csv_path: Option<&str>) -> anyhow::Result<Self> {
+        let repositories = Repositories::sqlite(Arc::new(Mutex::new(db_connection)));
+        let mut app = Self::load(profile, repositories)?;
+        match csv_path {
+            Some(path) => app.start_import(path),
+            None => app.start_categorization(),
         }
+        Ok(app)
+    }
+
+    fn load(profile: &Profile, repositories: Repositories) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+
+        let transactions = runtime.block_on(repositories.transactions.get_all_transactions())?;
 
-        // Get categories from database
-        let categories: HashMap<String, Category> = category_db.get_all_categories()?
+        let categories = runtime.block_on(async {
+            // Initialize default categories if none exist
+            if repositories.categories.get_all_categories().await?.is_empty() {
+                repositories.categories.initialize_default_categories().await?;
+            }
+            repositories.categories.get_all_categories().await
+        })?;
+        let categories: HashMap<String, Category> = categories
             .into_iter()
             .map(|c| (c.name.clone(), c))
             .collect();
 
+        runtime.block_on(async {
+            if repositories.merchants.get_all_aliases().await?.is_empty() {
+                repositories.merchants.initialize_default_aliases().await?;
+            }
+            anyhow::Ok(())
+        })?;
+
+        let settings = &repositories.settings;
+        let mut forecast_config = ForecastConfig::default();
+        if let Some(threshold) = runtime.block_on(settings.get_setting(LOW_BALANCE_THRESHOLD_KEY))?
+            && let Ok(threshold) = threshold.parse::<Decimal>()
+        {
+            forecast_config.low_balance_threshold = threshold;
+        }
+        let columns = match runtime.block_on(settings.get_setting(COLUMNS_KEY))? {
+            Some(value) => parse_columns(&value),
+            None => default_columns(),
+        };
+
+        let saved_filters = runtime.block_on(repositories.filters.get_all_filters())?;
+        let available_categories = category_names(&categories);
+
         let mut app = App {
             transactions,
-            filtered_transactions: Vec::new(),
+            list_view: Vec::new(),
             categories,
             current_view: View::TransactionList,
             selected_transaction: None,
             category_totals: HashMap::new(),
-            list_state,
-            sort_field: SortField::Date,
-            sort_order: SortOrder::Descending,
+            list_state: TableState::default(),
+            list_page: 1,
+            row_cache: RowCache::default(),
+            sort_primary: SortKey::new(SortField::Date),
+            sort_secondary: None,
+            sort_selection: None,
+            columns,
+            column_selection: None,
+            marked: HashSet::new(),
+            mark_anchor: None,
+            batch_selection: None,
+            history: Vec::new(),
+            history_state: ListState::default(),
             input_mode: InputMode::Normal,
             input_text: String::new(),
             filter: None,
+            query: Query::default(),
             can_show_details: false,
             category_selection: None,
-            available_categories: CategoryType::all(),
-            db_connection,
+            available_categories,
+            forecast_config,
+            forecasts: Vec::new(),
+            status_message: None,
+            review_queue: Vec::new(),
+            review_state: ListState::default(),
+            merchant_period: ReportPeriod::Last90Days,
+            merchant_stats: Vec::new(),
+            merchant_concentration: Vec::new(),
+            merchant_state: ListState::default(),
+            category_state: ListState::default(),
+            category_detail: None,
+            detail_state: TableState::default(),
+            bulk_category: None,
+            saved_filters,
+            input_error: None,
+            profile: profile.clone(),
+            task: None,
+            repositories,
+            runtime,
+            store_flags: true,
         };
 
-        app.categorize_all_transactions();
-        app.update_category_totals();
+        app.normalize_merchants()?;
+        app.refresh_list_view();
+        app.select_list_row(0);
 
         Ok(app)
     }
 
+    // Plaintext databases have nothing to lock
+    pub fn lock_after(&self) -> Option<Duration> {
+        self.profile.lock_after.filter(|_| self.is_encrypted())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.repositories.encrypted
+    }
+
     pub fn next(&mut self) {
-        let i = match self.list_state.selected() {
-            Some(i) => {
-                if i >= self.transactions.len().saturating_sub(1) {
-                    0
-                } else {
-                    i + 1
-                }
+        let len = self.list_view.len();
+        if len > 0 {
+            let i = self.list_state.selected().map_or(0, |i| (i + 1) % len);
+            self.select_list_row(i);
+        }
+    }
+
+    pub fn previous(&mut self) {
+        let len = self.list_view.len();
+        if len > 0 {
+            let i = self.list_state.selected().map_or(0, |i| (i + len - 1) % len);
+            self.select_list_row(i);
+        }
+    }
+
+    // Unlike next and previous, paging stops at either end of the list
+    pub fn page_down(&mut self) {
+        let row = self.list_state.selected().map_or(0, |i| i + self.list_page);
+        self.select_list_row(row);
+    }
+
+    pub fn page_up(&mut self) {
+        let row = self.list_state.selected().map_or(0, |i| i.saturating_sub(self.list_page));
+        self.select_list_row(row);
+    }
+
+    pub fn select_first(&mut self) {
+        self.select_list_row(0);
+    }
+
+    pub fn select_last(&mut self) {
+        self.select_list_row(self.list_view.len().saturating_sub(1));
+    }
+
+    // Selects the listed transaction nearest to `date`, the first of them on
+    // a tie, so it works whichever way the list is sorted
+    pub fn jump_to_date(&mut self, date: NaiveDate) -> bool {
+        let nearest = self.list_view
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &idx)| (self.transactions[idx].date.date() - date).num_days().abs())
+            .map(|(row, _)| row);
+        match nearest {
+            Some(row) => {
+                self.select_list_row(row);
+                true
             }
-            None => 0,
+            None => false,
+        }
+    }
+
+    // The rows of the list to draw when `height` of them fit. The window
+    // moves only as far as it must to keep the selected row in view.
+    pub fn list_window(&mut self, height: usize) -> Range<usize> {
+        let height = height.max(1);
+        let len = self.list_view.len();
+        self.list_page = height;
+
+        let offset = self.list_state.offset().min(len.saturating_sub(height));
+        let offset = match self.list_state.selected() {
+            Some(row) if row < offset => row,
+            Some(row) if row >= offset + height => row + 1 - height,
+            _ => offset,
         };
-        self.list_state.select(Some(i));
-        self.selected_transaction = Some(i);
+        *self.list_state.offset_mut() = offset;
+        offset..(offset + height).min(len)
     }
 
-    pub fn previous(&mut self) {
-        let i = match self.list_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.transactions.len().saturating_sub(1)
+    // Keeps the highlighted row and the selected transaction in step
+    fn select_list_row(&mut self, row: usize) {
+        if self.list_view.is_empty() {
+            self.list_state.select(None);
+            self.selected_transaction = None;
+        } else {
+            let row = row.min(self.list_view.len() - 1);
+            self.list_state.select(Some(row));
+            self.selected_transaction = Some(self.list_view[row]);
+        }
+    }
+
+    // Rebuilds the list from the active query and sort keys. `transactions`
+    // itself is never reordered, so indices held elsewhere stay valid.
+    fn refresh_list_view(&mut self) {
+        let mut list_view: Vec<usize> = self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| self.query.matches(t))
+            .map(|(i, _)| i)
+            .collect();
+
+        let keys: Vec<SortKey> = std::iter::once(self.sort_primary).chain(self.sort_secondary).collect();
+        let transactions = &self.transactions;
+        list_view.sort_by(|&a, &b| {
+            keys.iter()
+                .map(|key| compare_transactions(&transactions[a], &transactions[b], *key))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.list_view = list_view;
+    }
+
+    // Rebuilds the list keeping the same transaction highlighted, or the
+    // same row if that transaction no longer matches the filter
+    fn refresh_list(&mut self) {
+        let selected = self.selected_transaction;
+        let row = self.list_state.selected().unwrap_or(0);
+        self.refresh_list_view();
+        let row = selected
+            .and_then(|idx| self.list_view.iter().position(|&i| i == idx))
+            .unwrap_or(row);
+        self.select_list_row(row);
+    }
+
+    // Enter in the sort menu: makes the field the primary key, or flips its
+    // order if it already is. The previous primary becomes the secondary key.
+    pub fn set_primary_sort(&mut self, field: SortField) {
+        if self.sort_primary.field == field {
+            self.sort_primary.order = self.sort_primary.order.reversed();
+        } else {
+            let previous = self.sort_primary;
+            self.sort_primary = match self.sort_secondary {
+                Some(key) if key.field == field => key,
+                _ => SortKey::new(field),
+            };
+            self.sort_secondary = Some(previous);
+        }
+        self.refresh_list();
+    }
+
+    pub fn set_secondary_sort(&mut self, field: SortField) {
+        if self.sort_primary.field == field {
+            return;
+        }
+        self.sort_secondary = match self.sort_secondary {
+            Some(key) if key.field == field => Some(SortKey { field, order: key.order.reversed() }),
+            _ => Some(SortKey::new(field)),
+        };
+        self.refresh_list();
+    }
+
+    pub fn clear_secondary_sort(&mut self) {
+        self.sort_secondary = None;
+        self.refresh_list();
+    }
+
+    pub fn sort_description(&self) -> String {
+        std::iter::once(self.sort_primary)
+            .chain(self.sort_secondary)
+            .map(|key| format!("{} {}", key.field.label(), key.order.arrow()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn open_sort_menu(&mut self) {
+        let current = SortField::all().iter().position(|&f| f == self.sort_primary.field);
+        self.sort_selection = current;
+        self.input_mode = InputMode::Sorting;
+    }
+
+    pub fn handle_sort_menu(&mut self, key: KeyCode) {
+        let fields = SortField::all();
+        let Some(current) = self.sort_selection else {
+            return;
+        };
+        match key {
+            KeyCode::Up => self.sort_selection = Some((current + fields.len() - 1) % fields.len()),
+            KeyCode::Down => self.sort_selection = Some((current + 1) % fields.len()),
+            KeyCode::Enter => self.set_primary_sort(fields[current]),
+            KeyCode::Char(' ') => self.set_secondary_sort(fields[current]),
+            KeyCode::Backspace => self.clear_secondary_sort(),
+            KeyCode::Esc => {
+                self.sort_selection = None;
+                self.input_mode = InputMode::Normal;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl App {
+    pub fn open_column_menu(&mut self) {
+        self.column_selection = Some(0);
+        self.input_mode = InputMode::EditingColumns;
+    }
+
+    // Space shows or hides, +/- resize, 0 restores the automatic width and
+    // [ / ] move the column left or right
+    pub fn handle_column_menu(&mut self, key: KeyCode) {
+        let Some(current) = self.column_selection else {
+            return;
+        };
+        let len = self.columns.len();
+        match key {
+            KeyCode::Up => self.column_selection = Some((current + len - 1) % len),
+            KeyCode::Down => self.column_selection = Some((current + 1) % len),
+            KeyCode::Char(' ') => {
+                let visible = self.columns.iter().filter(|c| c.visible).count();
+                let column = &mut self.columns[current];
+                if column.visible && visible == 1 {
+                    self.status_message = Some("At least one column must stay visible".to_string());
                 } else {
-                    i - 1
+                    column.visible = !column.visible;
                 }
             }
-            None => 0,
-        };
-        self.list_state.select(Some(i));
-        self.selected_transaction = Some(i);
-    }
-
-    pub fn sort_transactions(&mut self) {
-        let sort_field = self.sort_field.clone();
-        let sort_order = self.sort_order.clone();
-        
-        if !self.filtered_transactions.is_empty() {
-            let transactions = &self.transactions;
-            self.filtered_transactions.sort_by(|&a, &b| {
-                let ta = &transactions[a];
-                let tb = &transactions[b];
-                compare_transactions(ta, tb, &sort_field, &sort_order)
-            });
-        } else {
-            self.transactions.sort_by(|a, b| {
-                compare_transactions(a, b, &sort_field, &sort_order)
-            });
+            KeyCode::Char('+') | KeyCode::Char('=') => self.columns[current].resize(2),
+            KeyCode::Char('-') => self.columns[current].resize(-2),
+            KeyCode::Char('0') => self.columns[current].width = None,
+            KeyCode::Char('[') if current > 0 => {
+                self.columns.swap(current, current - 1);
+                self.column_selection = Some(current - 1);
+            }
+            KeyCode::Char(']') if current + 1 < len => {
+                self.columns.swap(current, current + 1);
+                self.column_selection = Some(current + 1);
+            }
+            KeyCode::Esc | KeyCode::Enter => {
+                self.column_selection = None;
+                self.input_mode = InputMode::Normal;
+                let value = format_columns(&self.columns);
+                if let Err(e) = self.runtime.block_on(self.repositories.settings.set_setting(COLUMNS_KEY, &value)) {
+                    self.status_message = Some(format!("Failed to save column layout: {}", e));
+                }
+            }
+            _ => {}
         }
     }
 }
 
-fn compare_transactions(a: &Transaction, b: &Transaction, field: &SortField, order: &SortOrder) -> std::cmp::Ordering {
-    let ordering = match field {
+fn category_names(categories: &HashMap<String, Category>) -> Vec<String> {
+    let mut names: Vec<String> = categories.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+fn compare_transactions(a: &Transaction, b: &Transaction, key: SortKey) -> std::cmp::Ordering {
+    let ordering = match key.field {
         SortField::Date => a.date.cmp(&b.date),
         SortField::Amount => a.amount.cmp(&b.amount),
-        SortField::Merchant => a.merchant.cmp(&b.merchant),
+        SortField::Merchant => a.merchant.to_lowercase().cmp(&b.merchant.to_lowercase()),
         SortField::Category => a.category.cmp(&b.category),
     };
-    
-    match order {
+
+    match key.order {
         SortOrder::Ascending => ordering,
         SortOrder::Descending => ordering.reverse(),
     }
 }
 
 impl App {
-    pub fn toggle_sort_order(&mut self) {
-        self.sort_order = match self.sort_order {
-            SortOrder::Ascending => SortOrder::Descending,
-            SortOrder::Descending => SortOrder::Ascending,
-        };
-        self.sort_transactions();
-    }
-
     pub fn handle_input(&mut self, c: char) {
         match self.input_mode {
-            InputMode::Filtering | InputMode::Categorizing => {
+            InputMode::Filtering
+            | InputMode::Categorizing
+            | InputMode::EditingThreshold
+            | InputMode::Aliasing
+            | InputMode::Exporting
+            | InputMode::ExportingForecast
+            | InputMode::Importing
+            | InputMode::JumpingToDate
+            | InputMode::SavingFilter
+            | InputMode::AddingTags
+            | InputMode::RemovingTags => {
                 self.input_text.push(c);
+                self.input_error = None;
             }
-            InputMode::Normal => {}
+            InputMode::Normal | InputMode::Sorting | InputMode::EditingColumns | InputMode::BatchMenu => {}
         }
     }
 
     pub fn handle_backspace(&mut self) {
         match self.input_mode {
-            InputMode::Filtering | InputMode::Categorizing => {
+            InputMode::Filtering
+            | InputMode::Categorizing
+            | InputMode::EditingThreshold
+            | InputMode::Aliasing
+            | InputMode::Exporting
+            | InputMode::ExportingForecast
+            | InputMode::Importing
+            | InputMode::JumpingToDate
+            | InputMode::SavingFilter
+            | InputMode::AddingTags
+            | InputMode::RemovingTags => {
                 self.input_text.pop();
+                self.input_error = None;
             }
-            InputMode::Normal => {}
+            InputMode::Normal | InputMode::Sorting | InputMode::EditingColumns | InputMode::BatchMenu => {}
         }
     }
 
     pub fn submit_input(&mut self) {
         match self.input_mode {
             InputMode::Filtering => {
-                if !self.input_text.is_empty() {
-                    self.apply_filter(self.input_text.clone());
-                } else {
+                if self.input_text.trim().is_empty() {
                     self.clear_filter();
+                } else if let Err(e) = self.apply_filter(self.input_text.clone()) {
+                    // Keep the prompt open so the query can be fixed
+                    self.input_error = Some(e.to_string());
+                    return;
                 }
             }
             InputMode::Categorizing => {
-                if let Some(idx) = self.selected_transaction {
-                    if let Some(transaction) = self.transactions.get_mut(idx) {
-                        if let Some(cat_idx) = self.category_selection {
-                            if let Some(category_type) = self.available_categories.get(cat_idx) {
-                                let category_name = category_type.as_str();
-                                let mut category_db = CategoryDb::new(self.db_connection.get_connection());
-                                if let Ok(Some(_)) = category_db.get_category_by_name(category_name) {
-                                    transaction.category = Some(category_name.to_string());
-                                    self.update_category_totals();
-                                }
-                            }
+                if let Some(cat_idx) = self.category_selection
+                    && let Some(category_name) = self.available_categories.get(cat_idx).cloned()
+                {
+                    let targets: Vec<u64> = match self.bulk_category.take() {
+                        Some(from) => self.transactions
+                            .iter()
+                            .filter(|t| t.category.as_deref().unwrap_or("Uncategorized") == from)
+                            .map(|t| t.id)
+                            .collect(),
+                        None => self.batch_targets(),
+                    };
+                    self.recategorize(targets, &category_name);
+                }
+                self.category_selection = None;
+            }
+            InputMode::EditingThreshold => {
+                match self.input_text.trim().replace(',', ".").parse::<Decimal>() {
+                    Ok(threshold) => {
+                        if let Err(e) = self.set_low_balance_threshold(threshold) {
+                            self.status_message = Some(format!("Failed to save threshold: {}", e));
                         }
                     }
+                    Err(_) => {
+                        self.status_message = Some(format!("Invalid threshold '{}'", self.input_text));
+                    }
                 }
-                self.category_selection = None;
             }
-            InputMode::Normal => {}
+            InputMode::Aliasing => {
+                let canonical = self.input_text.trim().to_string();
+                if !canonical.is_empty()
+                    && let Err(e) = self.add_merchant_alias(&canonical)
+                {
+                    self.status_message = Some(format!("Failed to save alias: {}", e));
+                }
+            }
+            InputMode::SavingFilter => {
+                let name = self.input_text.trim().to_string();
+                if !name.is_empty()
+                    && let Err(e) = self.save_filter(&name)
+                {
+                    self.status_message = Some(format!("Failed to save filter: {}", e));
+                }
+            }
+            InputMode::AddingTags | InputMode::RemovingTags => {
+                let tags = parse_tags(&self.input_text);
+                if !tags.is_empty() {
+                    let action = if self.input_mode == InputMode::AddingTags {
+                        BatchAction::AddTags(tags)
+                    } else {
+                        BatchAction::RemoveTags(tags)
+                    };
+                    self.run_batch(self.batch_targets(), action);
+                }
+            }
+            InputMode::Exporting => {
+                let path = self.input_text.trim().to_string();
+                if !path.is_empty() {
+                    self.export(&path);
+                }
+            }
+            InputMode::ExportingForecast => {
+                let path = self.input_text.trim().to_string();
+                if !path.is_empty() {
+                    self.export_forecast(&path);
+                }
+            }
+            InputMode::Importing => {
+                let path = self.input_text.trim().to_string();
+                if !path.is_empty() {
+                    self.start_import(&path);
+                }
+            }
+            InputMode::JumpingToDate => {
+                let text = self.input_text.trim();
+                // A month on its own means its first day
+                let date = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                    .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d"));
+                match date {
+                    Ok(date) => {
+                        if !self.jump_to_date(date) {
+                            self.status_message = Some("No transactions listed".to_string());
+                        }
+                    }
+                    Err(_) => {
+                        // Keep the prompt open so the date can be fixed
+                        self.input_error = Some(format!("'{}' is not a date like 2024-03-15 or 2024-03", text));
+                        return;
+                    }
+                }
+            }
+            InputMode::Normal | InputMode::Sorting | InputMode::EditingColumns | InputMode::BatchMenu => {}
         }
         self.input_text.clear();
+        self.input_error = None;
         self.input_mode = InputMode::Normal;
     }
 
-    pub fn apply_filter(&mut self, filter: String) {
-        self.filter = Some(filter.to_lowercase());
-        self.filtered_transactions = self.transactions
-            .iter()
-            .enumerate()
-            .filter(|(_, t)| {
-                let filter = self.filter.as_ref().unwrap();
-                t.merchant.to_lowercase().contains(filter) ||
-                t.description.to_lowercase().contains(filter) ||
-                t.category.as_ref().map(|c| c.to_lowercase().contains(filter)).unwrap_or(false)
-            })
-            .map(|(i, _)| i)
-            .collect();
-        
-        if !self.filtered_transactions.is_empty() {
-            self.list_state.select(Some(0));
+    // Filters with the query language, e.g. `category:Groceries amount<-50 -tag:reimbursed`
+    pub fn apply_filter(&mut self, filter: String) -> Result<(), QueryError> {
+        self.query = Query::parse(&filter, &self.saved_filters)?;
+        self.filter = Some(filter);
+        self.refresh_list_view();
+        self.select_list_row(0);
+        Ok(())
+    }
+
+    pub fn save_filter(&mut self, name: &str) -> anyhow::Result<()> {
+        if !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return Err(anyhow::anyhow!("Filter names may only contain letters, digits, '-' and '_'"));
         }
+        let Some(query) = self.filter.clone() else {
+            return Err(anyhow::anyhow!("No filter is active"));
+        };
+
+        let mut saved_filters = self.saved_filters.clone();
+        saved_filters.insert(name.to_string(), query.clone());
+        Query::parse(&format!("@{}", name), &saved_filters)?;
+
+        self.runtime.block_on(self.repositories.filters.save_filter(name, &query))?;
+        self.saved_filters = saved_filters;
+        self.status_message = Some(format!("Saved filter as @{}", name));
+        Ok(())
+    }
+
+    // Transactions as currently listed: filtered and in sort order
+    pub fn visible_transactions(&self) -> Vec<&Transaction> {
+        self.list_view.iter().map(|&i| &self.transactions[i]).collect()
+    }
+
+    // The category summary exports its totals, every other view the listed
+    // transactions. Both follow the active filter.
+    pub fn export(&mut self, path: &str) {
+        let summary = matches!(self.current_view, View::CategorySummary);
+        let transactions = self.visible_transactions();
+
+        self.status_message = Some(match export_to_file(path, &transactions, summary) {
+            Ok(format) if summary && self.filter.is_some() => format!(
+                "Category totals of {} filtered transactions exported to {} ({})",
+                transactions.len(), path, format.label()
+            ),
+            Ok(format) if summary => format!("Category totals exported to {} ({})", path, format.label()),
+            Ok(format) => format!("{} transactions exported to {} ({})", transactions.len(), path, format.label()),
+            Err(e) => format!("Export failed: {}", e),
+        });
     }
 
     pub fn clear_filter(&mut self) {
         self.filter = None;
-        self.filtered_transactions.clear();
-        if !self.transactions.is_empty() {
-            self.list_state.select(Some(0));
-        }
+        self.query = Query::default();
+        self.refresh_list_view();
+        self.select_list_row(0);
     }
 
     pub fn handle_category_selection(&mut self, key: KeyCode) {
@@ -259,7 +851,7 @@ impl App {
             KeyCode::Up => {
                 if let Some(current) = self.category_selection {
                     self.category_selection = Some(if current == 0 {
-                        self.available_categories.len() - 1
+                        self.available_categories.len().saturating_sub(1)
                     } else {
                         current - 1
                     });
@@ -267,7 +859,7 @@ impl App {
             }
             KeyCode::Down => {
                 if let Some(current) = self.category_selection {
-                    self.category_selection = Some(if current >= self.available_categories.len() - 1 {
+                    self.category_selection = Some(if current >= self.available_categories.len().saturating_sub(1) {
                         0
                     } else {
                         current + 1
@@ -278,31 +870,655 @@ impl App {
         }
     }
 
-    pub fn categorize_all_transactions(&mut self) {
-        let mut category_db = CategoryDb::new(self.db_connection.get_connection());
-        let categories = match category_db.get_all_categories() {
-            Ok(cats) => cats.into_iter().map(|c| (c.name.clone(), c)).collect(),
-            Err(_) => return,
-        };
+    pub fn categorize_all_transactions(&mut self) -> anyhow::Result<()> {
+        let repository = &self.repositories.categories;
+        let categories: HashMap<String, Category> = self.runtime.block_on(repository.get_all_categories())?
+            .into_iter()
+            .map(|c| (c.name.clone(), c))
+            .collect();
+        let assigned = self.runtime.block_on(repository.get_assigned_categories())?;
+
+        // Anything a background run would find is about to be replaced
+        if self.task.as_ref().is_some_and(|task| task.kind == TaskKind::Categorize) {
+            self.task = None;
+        }
+        let rules = RuleMatcher::new(&categories);
 
+        self.row_cache.clear();
         for transaction in &mut self.transactions {
-            let category = Category::categorize_transaction(
-                &categories,
-                &transaction.merchant,
-                &transaction.description
-            );
-            transaction.category = category;
+            let found = category_for(transaction, &rules, &assigned);
+            (transaction.category, transaction.category_source) = found.unzip();
         }
+        // Renames, merges and their undos all end up here
+        self.available_categories = category_names(&categories);
+        self.categories = categories;
+        Ok(())
     }
 
     pub fn update_category_totals(&mut self) {
-        let mut totals = HashMap::new();
-        
-        for transaction in &self.transactions {
-            let category = transaction.category.as_deref().unwrap_or("Uncategorized").to_string();
-            *totals.entry(category).or_insert(Decimal::ZERO) += transaction.amount;
+        self.category_totals = category_totals(&self.transactions);
+
+        let len = self.category_totals.len();
+        self.category_state.select(match self.category_state.selected() {
+            _ if len == 0 => None,
+            Some(i) => Some(i.min(len - 1)),
+            None => Some(0),
+        });
+    }
+
+    // Categories ordered by absolute total, as shown in the summary
+    pub fn sorted_categories(&self) -> Vec<(&str, Decimal)> {
+        sorted_totals(&self.category_totals)
+    }
+}
+
+// A stored assignment wins over any rule
+pub(crate) fn category_for(
+    transaction: &Transaction,
+    rules: &RuleMatcher,
+    assigned: &HashMap<u64, (String, CategorySource)>,
+) -> Option<(String, CategorySource)> {
+    match assigned.get(&transaction.id) {
+        Some(assignment) => Some(assignment.clone()),
+        None => rules.categorize(transaction),
+    }
+}
+
+impl App {
+    pub fn update_forecast(&mut self) {
+        self.forecasts = forecast_balances(&self.transactions, &self.forecast_config);
+    }
+
+    pub fn cycle_forecast_horizon(&mut self) {
+        self.forecast_config.next_horizon();
+        self.update_forecast();
+    }
+
+    pub fn set_low_balance_threshold(&mut self, threshold: Decimal) -> anyhow::Result<()> {
+        self.runtime.block_on(self.repositories.settings.set_setting(LOW_BALANCE_THRESHOLD_KEY, &threshold.to_string()))?;
+        self.forecast_config.low_balance_threshold = threshold;
+        self.status_message = Some(format!("Low balance threshold set to {:.2}", threshold));
+        Ok(())
+    }
+
+    pub fn export_forecast(&mut self, path: &str) {
+        let result = std::fs::File::create(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_forecast_csv(&self.forecasts, self.forecast_config.low_balance_threshold, file));
+
+        self.status_message = Some(match result {
+            Ok(()) => format!("Forecast exported to {}", path),
+            Err(e) => format!("Forecast export failed: {}", e),
+        });
+    }
+
+    pub fn detect_anomalies(&mut self) -> anyhow::Result<()> {
+        let detected = detect_anomalies(&self.transactions, &AnomalyConfig::default());
+        if !self.store_flags {
+            let index: HashMap<u64, usize> = self.transactions.iter().enumerate().map(|(i, t)| (t.id, i)).collect();
+            for (id, flag) in detected {
+                if let Some(&i) = index.get(&id)
+                    && !self.transactions[i].flags.iter().any(|f| f.kind == flag.kind)
+                {
+                    self.transactions[i].flags.push(flag);
+                }
+            }
+            self.row_cache.clear();
+            self.rebuild_review_queue();
+            return Ok(());
+        }
+        let flags = &self.repositories.flags;
+        let mut stored = self.runtime.block_on(async {
+            flags.save_flags(detected).await?;
+            flags.get_all_flags().await
+        })?;
+        self.row_cache.clear();
+        for transaction in &mut self.transactions {
+            transaction.flags = stored.remove(&transaction.id).unwrap_or_default();
+        }
+
+        self.rebuild_review_queue();
+        Ok(())
+    }
+
+    pub fn rebuild_review_queue(&mut self) {
+        self.review_queue = self.transactions
+            .iter()
+            .enumerate()
+            .flat_map(|(tx_idx, transaction)| {
+                transaction.flags
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, flag)| flag.status == FlagStatus::Pending)
+                    .map(move |(flag_idx, _)| (tx_idx, flag_idx))
+            })
+            .collect();
+
+        let selected = match self.review_state.selected() {
+            _ if self.review_queue.is_empty() => None,
+            Some(i) => Some(i.min(self.review_queue.len() - 1)),
+            None => Some(0),
+        };
+        self.review_state.select(selected);
+    }
+
+    pub fn next_flag(&mut self) {
+        if let Some(i) = self.review_state.selected() {
+            self.review_state.select(Some((i + 1) % self.review_queue.len()));
+        }
+    }
+
+    pub fn previous_flag(&mut self) {
+        if let Some(i) = self.review_state.selected() {
+            let len = self.review_queue.len();
+            self.review_state.select(Some((i + len - 1) % len));
+        }
+    }
+
+    pub fn review_selected_flag(&mut self, status: FlagStatus) {
+        let Some(&(tx_idx, flag_idx)) = self.review_state.selected().and_then(|i| self.review_queue.get(i)) else {
+            return;
+        };
+        let transaction = &mut self.transactions[tx_idx];
+        let flag = &mut transaction.flags[flag_idx];
+
+        match self.runtime.block_on(self.repositories.flags.set_flag_status(transaction.id, flag.kind, status)) {
+            Ok(()) => {
+                flag.status = status;
+                self.row_cache.invalidate(tx_idx);
+                self.status_message = Some(format!("{} flag {} for {}", flag.kind.label(), status.as_str(), transaction.merchant));
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Failed to update flag: {}", e));
+            }
+        }
+        self.rebuild_review_queue();
+    }
+
+    pub fn normalize_merchants(&mut self) -> anyhow::Result<()> {
+        let aliases = self.runtime.block_on(self.repositories.merchants.get_all_aliases())?;
+        let normalizer = MerchantNormalizer::new(aliases);
+
+        self.row_cache.clear();
+        for transaction in &mut self.transactions {
+            transaction.merchant = normalizer.normalize(&transaction.raw_merchant);
+        }
+        Ok(())
+    }
+
+    // Maps the selected transaction's cleaned merchant to `canonical`
+    pub fn add_merchant_alias(&mut self, canonical: &str) -> anyhow::Result<()> {
+        let Some(transaction) = self.selected_transaction.and_then(|idx| self.transactions.get(idx)) else {
+            return Ok(());
+        };
+        let alias = MerchantAlias::new(&clean_merchant(&transaction.raw_merchant), canonical);
+
+        self.runtime.block_on(self.repositories.merchants.save_alias(alias.clone()))?;
+
+        self.normalize_merchants()?;
+        self.refresh_list();
+        self.start_categorization();
+        self.status_message = Some(format!("'{}' is now shown as {}", alias.pattern, alias.canonical));
+        Ok(())
+    }
+
+    pub fn update_merchant_report(&mut self) {
+        let in_period = transactions_in_period(&self.transactions, self.merchant_period);
+        self.merchant_stats = top_merchants(in_period.iter().copied());
+        self.merchant_concentration = category_concentration(in_period, CONCENTRATION_TOP_N);
+
+        self.merchant_state.select(if self.merchant_stats.is_empty() { None } else { Some(0) });
+    }
+
+    pub fn cycle_merchant_period(&mut self) {
+        self.merchant_period = self.merchant_period.next();
+        self.update_merchant_report();
+    }
+
+    pub fn next_merchant(&mut self) {
+        if let Some(i) = self.merchant_state.selected() {
+            self.merchant_state.select(Some((i + 1) % self.merchant_stats.len()));
+        }
+    }
+
+    pub fn previous_merchant(&mut self) {
+        if let Some(i) = self.merchant_state.selected() {
+            let len = self.merchant_stats.len();
+            self.merchant_state.select(Some((i + len - 1) % len));
+        }
+    }
+
+    // Shows the selected merchant's transactions for the report period
+    pub fn drill_down_merchant(&mut self) {
+        let Some(stats) = self.merchant_state.selected().and_then(|i| self.merchant_stats.get(i)) else {
+            return;
+        };
+        let Some(as_of) = self.transactions.iter().map(|t| t.date.date()).max() else {
+            return;
+        };
+
+        let mut query = format!("merchant=\"{}\"", stats.merchant.replace('\\', "\\\\").replace('"', "\\\""));
+        if let Some(start) = self.merchant_period.start(as_of) {
+            query.push_str(&format!(" date>={}", start.format("%Y-%m-%d")));
+        }
+        if let Err(e) = self.apply_filter(query) {
+            self.status_message = Some(format!("Could not filter by merchant: {}", e));
+            return;
+        }
+        self.current_view = View::TransactionList;
+    }
+
+    pub fn next_category(&mut self) {
+        if let Some(i) = self.category_state.selected() {
+            self.category_state.select(Some((i + 1) % self.category_totals.len()));
+        }
+    }
+
+    pub fn previous_category(&mut self) {
+        if let Some(i) = self.category_state.selected() {
+            let len = self.category_totals.len();
+            self.category_state.select(Some((i + len - 1) % len));
+        }
+    }
+
+    pub fn open_category_detail(&mut self) {
+        let Some(category) = self.category_state
+            .selected()
+            .and_then(|i| self.sorted_categories().get(i).map(|(name, _)| name.to_string()))
+        else {
+            return;
+        };
+
+        self.load_category_detail(category);
+        self.current_view = View::CategoryDetail;
+    }
+
+    // Split transactions are listed in full, but only their part for this
+    // category counts towards the totals
+    fn load_category_detail(&mut self, category: String) {
+        let mut indices: Vec<usize> = self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.allocated_to(&category).is_some())
+            .map(|(i, _)| i)
+            .collect();
+        indices.sort_by(|&a, &b| self.transactions[b].date.cmp(&self.transactions[a].date));
+
+        let parts: Vec<Transaction> = indices
+            .iter()
+            .map(|&i| {
+                let transaction = &self.transactions[i];
+                Transaction { amount: transaction.allocated_to(&category).unwrap_or_default(), ..transaction.clone() }
+            })
+            .collect();
+        self.category_detail = Some(CategoryDetail {
+            total: parts.iter().map(|t| t.amount).sum(),
+            monthly_totals: monthly_totals(&parts),
+            top_merchants: merchant_totals(&parts),
+            category,
+            transactions: indices,
+        });
+        self.detail_state.select(Some(0));
+    }
+
+    pub fn next_detail_transaction(&mut self) {
+        let len = self.category_detail.as_ref().map_or(0, |d| d.transactions.len());
+        if let Some(i) = self.detail_state.selected()
+            && len > 0
+        {
+            self.detail_state.select(Some((i + 1) % len));
+        }
+    }
+
+    pub fn previous_detail_transaction(&mut self) {
+        let len = self.category_detail.as_ref().map_or(0, |d| d.transactions.len());
+        if let Some(i) = self.detail_state.selected()
+            && len > 0
+        {
+            self.detail_state.select(Some((i + len - 1) % len));
+        }
+    }
+
+    // In the category detail, `bulk` moves every transaction of the category
+    pub fn start_categorizing(&mut self, bulk: bool) {
+        if let Some(detail) = &self.category_detail
+            && matches!(self.current_view, View::CategoryDetail)
+        {
+            if bulk {
+                self.bulk_category = Some(detail.category.clone());
+            } else {
+                self.selected_transaction = self.detail_state
+                    .selected()
+                    .and_then(|i| detail.transactions.get(i).copied());
+            }
+        } else {
+            self.selected_transaction = self.list_state
+                .selected()
+                .and_then(|row| self.list_view.get(row).copied());
+        }
+
+        if self.bulk_category.is_some() || self.selected_transaction.is_some() {
+            self.input_mode = InputMode::Categorizing;
+            self.category_selection = Some(0);
+        }
+    }
+
+    fn recategorize(&mut self, targets: Vec<u64>, category_name: &str) {
+        self.run_batch(targets, BatchAction::SetCategory(category_name.to_string()));
+
+        if self.category_detail.as_ref().is_some_and(|d| d.transactions.is_empty())
+            && matches!(self.current_view, View::CategoryDetail)
+        {
+            self.current_view = View::CategorySummary;
+        }
+    }
+
+    // Marked transactions in the list, otherwise the selected one
+    fn batch_targets(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = if !self.marked.is_empty() && matches!(self.current_view, View::TransactionList) {
+            self.marked.iter().copied().collect()
+        } else {
+            self.selected_transaction
+                .and_then(|idx| self.transactions.get(idx))
+                .map(|t| t.id)
+                .into_iter()
+                .collect()
+        };
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn toggle_mark(&mut self) {
+        let Some(row) = self.list_state.selected() else {
+            return;
+        };
+        if let Some(transaction) = self.list_view.get(row).map(|&idx| &self.transactions[idx])
+            && !self.marked.remove(&transaction.id)
+        {
+            self.marked.insert(transaction.id);
+        }
+        self.mark_anchor = Some(row);
+        self.next();
+    }
+
+    // Marks every row between the last toggled one and the current one
+    pub fn mark_range(&mut self) {
+        let (Some(anchor), Some(row)) = (self.mark_anchor, self.list_state.selected()) else {
+            self.status_message = Some("Mark a row with Space first, then move and press V".to_string());
+            return;
+        };
+        let (start, end) = (anchor.min(row), anchor.max(row));
+        for &idx in self.list_view.iter().take(end + 1).skip(start) {
+            self.marked.insert(self.transactions[idx].id);
+        }
+    }
+
+    // Marks everything the current filter shows, or clears the marks if it
+    // already is
+    pub fn toggle_mark_all(&mut self) {
+        let listed: Vec<u64> = self.list_view.iter().map(|&idx| self.transactions[idx].id).collect();
+        if listed.iter().all(|id| self.marked.contains(id)) {
+            self.clear_marks();
+        } else {
+            self.marked.extend(listed);
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.mark_anchor = None;
+    }
+
+    pub fn open_batch_menu(&mut self) {
+        if self.batch_targets().is_empty() {
+            return;
+        }
+        self.batch_selection = Some(0);
+        self.input_mode = InputMode::BatchMenu;
+    }
+
+    pub fn handle_batch_menu(&mut self, key: KeyCode) {
+        let items = BatchMenuItem::all();
+        let Some(current) = self.batch_selection else {
+            return;
+        };
+        match key {
+            KeyCode::Up => self.batch_selection = Some((current + items.len() - 1) % items.len()),
+            KeyCode::Down => self.batch_selection = Some((current + 1) % items.len()),
+            KeyCode::Esc => {
+                self.batch_selection = None;
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Enter => {
+                self.batch_selection = None;
+                self.input_mode = InputMode::Normal;
+                let targets = self.batch_targets();
+                match items[current] {
+                    BatchMenuItem::SetCategory => {
+                        self.input_mode = InputMode::Categorizing;
+                        self.category_selection = Some(0);
+                    }
+                    BatchMenuItem::AddTags => self.input_mode = InputMode::AddingTags,
+                    BatchMenuItem::RemoveTags => self.input_mode = InputMode::RemovingTags,
+                    BatchMenuItem::MarkTransfer => {
+                        self.recategorize(targets, CategoryType::InternalTransfer.as_str());
+                    }
+                    BatchMenuItem::Exclude => self.run_batch(targets, BatchAction::SetExcluded(true)),
+                    BatchMenuItem::Include => self.run_batch(targets, BatchAction::SetExcluded(false)),
+                    BatchMenuItem::Delete => self.run_batch(targets, BatchAction::Delete),
+                }
+            }
+            _ => {}
         }
+    }
 
-        self.category_totals = totals;
+    // Applies the action in one database transaction and keeps it for undo
+    pub fn run_batch(&mut self, targets: Vec<u64>, action: BatchAction) {
+        if targets.is_empty() {
+            return;
+        }
+        let result = self.runtime.block_on(self.repositories.journal.apply_batch(targets, action));
+        match result {
+            Ok(batch) => {
+                self.status_message = Some(format!("{} (u to undo)", batch.description));
+                if let Err(e) = self.reload_transactions() {
+                    self.status_message = Some(format!("Failed to reload transactions: {}", e));
+                }
+            }
+            Err(e) => {
+                self.status_message = Some(format!("Nothing changed: {}", e));
+            }
+        }
+    }
+
+    pub fn undo(&mut self) {
+        let result = self.runtime.block_on(self.repositories.journal.undo());
+        self.after_journal_step(result, "Undone", "Nothing to undo");
+    }
+
+    pub fn redo(&mut self) {
+        let result = self.runtime.block_on(self.repositories.journal.redo());
+        self.after_journal_step(result, "Redone", "Nothing to redo");
+    }
+
+    fn after_journal_step(&mut self, result: anyhow::Result<Option<String>>, done: &str, nothing: &str) {
+        self.status_message = Some(match result {
+            Ok(Some(description)) => {
+                if let Ok(filters) = self.runtime.block_on(self.repositories.filters.get_all_filters()) {
+                    self.saved_filters = filters;
+                }
+                match self.reload_transactions() {
+                    Ok(()) => format!("{}: {}", done, description),
+                    Err(e) => format!("Failed to reload transactions: {}", e),
+                }
+            }
+            Ok(None) => nothing.to_string(),
+            Err(e) => format!("{} failed, nothing changed: {}", done, e),
+        });
+        if matches!(self.current_view, View::History) {
+            self.load_history();
+        }
+    }
+
+    pub fn open_history(&mut self) {
+        self.load_history();
+        self.current_view = View::History;
+    }
+
+    fn load_history(&mut self) {
+        let entries = self.runtime.block_on(self.repositories.journal.get_entries(HISTORY_LIMIT));
+        match entries {
+            Ok(entries) => self.history = entries,
+            Err(e) => self.status_message = Some(format!("Failed to read history: {}", e)),
+        }
+        let len = self.history.len();
+        self.history_state.select(match self.history_state.selected() {
+            _ if len == 0 => None,
+            Some(i) => Some(i.min(len - 1)),
+            None => Some(0),
+        });
+    }
+
+    pub fn next_history_entry(&mut self) {
+        if let Some(i) = self.history_state.selected() {
+            self.history_state.select(Some((i + 1) % self.history.len()));
+        }
+    }
+
+    pub fn previous_history_entry(&mut self) {
+        if let Some(i) = self.history_state.selected() {
+            let len = self.history.len();
+            self.history_state.select(Some((i + len - 1) % len));
+        }
+    }
+
+    // Reads everything back from the database after a batch or undo
+    fn reload_transactions(&mut self) -> anyhow::Result<()> {
+        self.read_transactions()?;
+        self.categorize_all_transactions()?;
+        self.after_categorization()
+    }
+
+    // Keeps the highlighted transaction where possible. The transactions come
+    // back uncategorized
+    fn read_transactions(&mut self) -> anyhow::Result<()> {
+        let selected_id = self.selected_transaction
+            .and_then(|idx| self.transactions.get(idx))
+            .map(|t| t.id);
+
+        self.transactions = self.runtime.block_on(self.repositories.transactions.get_all_transactions())?;
+        self.row_cache.clear();
+        self.normalize_merchants()?;
+
+        let ids: HashSet<u64> = self.transactions.iter().map(|t| t.id).collect();
+        self.marked.retain(|id| ids.contains(id));
+        self.selected_transaction = selected_id.and_then(|id| self.transactions.iter().position(|t| t.id == id));
+        self.refresh_list();
+        Ok(())
+    }
+
+    // Everything worked out from the transactions' categories
+    fn after_categorization(&mut self) -> anyhow::Result<()> {
+        self.update_category_totals();
+        self.update_forecast();
+        self.update_merchant_report();
+        self.detect_anomalies()?;
+        if let Some(detail) = self.category_detail.take() {
+            self.load_category_detail(detail.category);
+        }
+        self.refresh_list();
+        Ok(())
+    }
+
+    pub fn start_import(&mut self, path: &str) {
+        if self.importing() {
+            self.status_message = Some("An import is already running".to_string());
+            return;
+        }
+        // A running categorization is dropped; the import recategorizes
+        // everything once it finishes
+        self.task = Some(Task::import(&self.runtime, &self.repositories, path));
+    }
+
+    pub fn start_categorization(&mut self) {
+        // Replacing an import would cancel it without reloading the rows
+        // it stored. It recategorizes everything when it finishes anyway.
+        if self.importing() {
+            return;
+        }
+        self.task = Some(Task::categorize(&self.runtime, &self.repositories, self.transactions.clone()));
+    }
+
+    pub fn importing(&self) -> bool {
+        self.task.as_ref().is_some_and(|task| matches!(task.kind, TaskKind::Import(_)))
+    }
+
+    pub fn cancel_task(&mut self) {
+        if let Some(task) = &self.task {
+            task.cancel();
+        }
+    }
+
+    // Applies whatever the background task reported since the last frame
+    pub fn poll_task(&mut self) {
+        let Some(task) = self.task.as_mut() else {
+            return;
+        };
+        for event in task.events() {
+            match event {
+                TaskEvent::Progress { done, total } => {
+                    if let Some(task) = self.task.as_mut() {
+                        (task.done, task.total) = (done, total);
+                    }
+                }
+                event => return self.finish_task(event),
+            }
+        }
+    }
+
+    fn finish_task(&mut self, event: TaskEvent) {
+        let Some(task) = self.task.take() else {
+            return;
+        };
+        match event {
+            TaskEvent::Imported { summary, cancelled } => {
+                self.status_message = Some(if cancelled {
+                    format!(
+                        "Import cancelled after {} rows; {} new transactions were kept",
+                        summary.read, summary.inserted
+                    )
+                } else {
+                    format!("Imported {} new transactions ({} already stored)", summary.inserted, summary.duplicates())
+                });
+                match self.read_transactions() {
+                    Ok(()) => self.start_categorization(),
+                    Err(e) => self.status_message = Some(format!("Failed to reload transactions: {}", e)),
+                }
+            }
+            TaskEvent::Categorized(mut results) => {
+                self.row_cache.clear();
+                for transaction in &mut self.transactions {
+                    if let Some(found) = results.remove(&transaction.id) {
+                        (transaction.category, transaction.category_source) = found.unzip();
+                    }
+                }
+                if let Err(e) = self.after_categorization() {
+                    self.status_message = Some(format!("Failed to update flags: {}", e));
+                }
+            }
+            TaskEvent::Cancelled => {
+                self.status_message = Some(format!("{} cancelled", task.kind.label()));
+            }
+            TaskEvent::Failed(e) => {
+                self.status_message = Some(format!("{} failed: {:#}", task.kind.label(), e));
+                // Batches stored before the error are kept, so show them
+                if let TaskKind::Import(_) = task.kind
+                    && self.read_transactions().is_ok()
+                {
+                    self.start_categorization();
+                }
+            }
+            TaskEvent::Progress { .. } => {}
+        }
     }
 }
\ No newline at end of file
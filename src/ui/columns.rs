@@ -0,0 +1,282 @@
+use std::collections::{HashMap, HashSet};
+use ratatui::{
+    layout::{Alignment, Constraint},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Cell, Row, Table},
+};
+use rust_decimal::Decimal;
+use crate::models::transaction::Transaction;
+
+pub const COLUMNS_KEY: &str = "transaction_list.columns";
+
+const MIN_WIDTH: u16 = 3;
+const MAX_WIDTH: u16 = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Flag,
+    Date,
+    Amount,
+    Merchant,
+    Category,
+    Account,
+    Tags,
+    Description,
+    Balance,
+}
+
+impl Column {
+    pub fn all() -> Vec<Column> {
+        vec![
+            Column::Flag,
+            Column::Date,
+            Column::Amount,
+            Column::Merchant,
+            Column::Category,
+            Column::Account,
+            Column::Tags,
+            Column::Description,
+            Column::Balance,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Column::Flag => "Flag",
+            Column::Date => "Date",
+            Column::Amount => "Amount",
+            Column::Merchant => "Merchant",
+            Column::Category => "Category",
+            Column::Account => "Account",
+            Column::Tags => "Tags",
+            Column::Description => "Description",
+            Column::Balance => "Balance",
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            Column::Flag => "flag",
+            Column::Date => "date",
+            Column::Amount => "amount",
+            Column::Merchant => "merchant",
+            Column::Category => "category",
+            Column::Account => "account",
+            Column::Tags => "tags",
+            Column::Description => "description",
+            Column::Balance => "balance",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Column> {
+        Column::all().into_iter().find(|c| c.key() == key)
+    }
+
+    fn visible_by_default(&self) -> bool {
+        matches!(self, Column::Flag | Column::Date | Column::Amount | Column::Merchant | Column::Category)
+    }
+
+    // Text columns share whatever space the fixed ones leave
+    fn auto_constraint(&self) -> Constraint {
+        match self {
+            Column::Flag => Constraint::Length(1),
+            Column::Date => Constraint::Length(10),
+            Column::Amount | Column::Balance => Constraint::Length(10),
+            Column::Category => Constraint::Length(17),
+            Column::Account => Constraint::Length(18),
+            Column::Merchant => Constraint::Fill(3),
+            Column::Tags => Constraint::Fill(1),
+            Column::Description => Constraint::Fill(2),
+        }
+    }
+
+    // Starting point when a column is first resized by hand
+    fn base_width(&self) -> u16 {
+        match self.auto_constraint() {
+            Constraint::Length(width) => width,
+            _ => 24,
+        }
+    }
+
+    fn alignment(&self) -> Alignment {
+        match self {
+            Column::Amount | Column::Balance => Alignment::Right,
+            _ => Alignment::Left,
+        }
+    }
+
+    fn cell(&self, transaction: &Transaction) -> Cell<'static> {
+        let amount_style = |amount: Decimal| if amount < Decimal::ZERO {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default().fg(Color::Green)
+        };
+
+        let line = match self {
+            Column::Flag if transaction.has_pending_flags() => Line::styled("⚑", Style::default().fg(Color::Yellow)),
+            Column::Flag if transaction.has_confirmed_flags() => Line::styled("⚑", Style::default().fg(Color::Red)),
+            Column::Flag => Line::raw(""),
+            Column::Date => Line::raw(transaction.date.format("%Y-%m-%d").to_string()),
+            Column::Amount => Line::styled(format!("{:.2}", transaction.amount), amount_style(transaction.amount)),
+            Column::Merchant => Line::raw(transaction.merchant.clone()),
+            Column::Category => Line::raw(transaction.category.clone().unwrap_or_else(|| "Uncategorized".to_string())),
+            Column::Account => Line::raw(transaction.account.clone()),
+            Column::Tags => Line::styled(
+                transaction.tags.iter().map(|t| format!("#{}", t)).collect::<Vec<_>>().join(" "),
+                Style::default().fg(Color::Cyan),
+            ),
+            Column::Description => Line::raw(transaction.description.clone()),
+            Column::Balance => match transaction.balance {
+                Some(balance) => Line::raw(format!("{:.2}", balance)),
+                None => Line::raw(""),
+            },
+        };
+        Cell::from(line.alignment(self.alignment()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnSetting {
+    pub column: Column,
+    pub visible: bool,
+    // None lets the column adapt to the terminal width
+    pub width: Option<u16>,
+}
+
+impl ColumnSetting {
+    fn constraint(&self) -> Constraint {
+        match self.width {
+            Some(width) => Constraint::Length(width),
+            None => self.column.auto_constraint(),
+        }
+    }
+
+    pub fn resize(&mut self, delta: i16) {
+        let width = self.width.unwrap_or_else(|| self.column.base_width()) as i16 + delta;
+        self.width = Some(width.clamp(MIN_WIDTH as i16, MAX_WIDTH as i16) as u16);
+    }
+}
+
+pub fn default_columns() -> Vec<ColumnSetting> {
+    Column::all()
+        .into_iter()
+        .map(|column| ColumnSetting { column, visible: column.visible_by_default(), width: None })
+        .collect()
+}
+
+// Stored as the visible columns in order, with an optional fixed width,
+// e.g. "flag,date,amount,merchant:40,category". Hidden columns follow in
+// their default order.
+pub fn parse_columns(value: &str) -> Vec<ColumnSetting> {
+    let mut columns: Vec<ColumnSetting> = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (key, width) = match entry.split_once(':') {
+            Some((key, width)) => (key, width.parse::<u16>().ok().map(|w| w.clamp(MIN_WIDTH, MAX_WIDTH))),
+            None => (entry, None),
+        };
+        if let Some(column) = Column::from_key(key)
+            && !columns.iter().any(|c| c.column == column)
+        {
+            columns.push(ColumnSetting { column, visible: true, width });
+        }
+    }
+
+    if columns.is_empty() {
+        return default_columns();
+    }
+    for column in Column::all() {
+        if !columns.iter().any(|c| c.column == column) {
+            columns.push(ColumnSetting { column, visible: false, width: None });
+        }
+    }
+    columns
+}
+
+pub fn format_columns(columns: &[ColumnSetting]) -> String {
+    columns
+        .iter()
+        .filter(|c| c.visible)
+        .map(|c| match c.width {
+            Some(width) => format!("{}:{}", c.column.key(), width),
+            None => c.column.key().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn row_style(excluded: bool, marked: bool) -> Style {
+    match (marked, excluded) {
+        (true, _) => Style::default().bg(Color::Blue),
+        (false, true) => Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC),
+        (false, false) => Style::default(),
+    }
+}
+
+pub fn transaction_row(transaction: &Transaction, columns: &[ColumnSetting], marked: &HashSet<u64>) -> Row<'static> {
+    Row::new(columns.iter().filter(|c| c.visible).map(|c| c.column.cell(transaction)))
+        .style(row_style(transaction.excluded, marked.contains(&transaction.id)))
+}
+
+// Every column's cell for one transaction, so changing the layout doesn't
+// need anything reformatted
+#[derive(Debug)]
+struct FormattedRow {
+    id: u64,
+    excluded: bool,
+    cells: Vec<Cell<'static>>,
+}
+
+// Formatted rows by index into the app's transactions. Rows are built the
+// first time they're on screen and kept until the transactions change;
+// marking only changes the style, so it doesn't count. The header's total
+// is kept here too, since it goes stale at the same points.
+#[derive(Debug, Default)]
+pub struct RowCache {
+    rows: HashMap<usize, FormattedRow>,
+    total: Option<Decimal>,
+}
+
+impl RowCache {
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.total = None;
+    }
+
+    pub fn total(&mut self, transactions: &[Transaction]) -> Decimal {
+        *self.total.get_or_insert_with(|| transactions.iter().map(|t| t.amount).sum())
+    }
+
+    pub fn invalidate(&mut self, index: usize) {
+        self.rows.remove(&index);
+    }
+
+    pub fn row(&mut self, index: usize, transaction: &Transaction, columns: &[ColumnSetting], marked: &HashSet<u64>) -> Row<'static> {
+        let formatted = self.rows.entry(index).or_insert_with(|| FormattedRow {
+            id: transaction.id,
+            excluded: transaction.excluded,
+            cells: Column::all().iter().map(|column| column.cell(transaction)).collect(),
+        });
+
+        // Column::all() lists the variants in declaration order
+        Row::new(columns.iter().filter(|c| c.visible).map(|c| formatted.cells[c.column as usize].clone()))
+            .style(row_style(formatted.excluded, marked.contains(&formatted.id)))
+    }
+}
+
+pub fn transaction_table<'a>(rows: Vec<Row<'a>>, columns: &[ColumnSetting]) -> Table<'a> {
+    let visible: Vec<&ColumnSetting> = columns.iter().filter(|c| c.visible).collect();
+
+    let header = Row::new(visible.iter().map(|c| match c.column {
+        Column::Flag => Cell::from(""),
+        column => Cell::from(Line::from(column.label()).alignment(column.alignment())),
+    }))
+    .style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow));
+
+    Table::new(rows, visible.iter().map(|c| c.constraint()))
+        .header(header)
+        .highlight_style(Style::default()
+            .add_modifier(Modifier::REVERSED)
+            .add_modifier(Modifier::BOLD))
+        .highlight_symbol("➤ ")
+}
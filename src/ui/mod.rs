@@ -1,2 +1,4 @@
 pub mod app;
-pub mod render;
\ No newline at end of file
+pub mod columns;
+pub mod render;
+pub mod task;
\ No newline at end of file
@@ -2,49 +2,49 @@ use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols,
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    widgets::{block::{Position, Title}, Axis, BarChart, Block, Borders, Chart, Clear, Dataset, Gauge, GraphType, List, ListItem, Paragraph, TableState},
 };
-use rust_decimal::Decimal;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 
-use super::app::{App, InputMode};
+use super::app::{App, BatchMenuItem, InputMode, LockScreen, SortField};
+use crate::config::Profile;
+use super::columns::{transaction_row, transaction_table};
+use super::task::TaskKind;
 
-pub fn render_transaction_list(f: &mut Frame, app: &App, area: Rect) {
-    let transactions = if app.filtered_transactions.is_empty() {
-        &app.transactions
-    } else {
-        &app.transactions
-    };
-
-    let items: Vec<ListItem> = if app.filtered_transactions.is_empty() {
-        transactions.iter().map(|t| t.to_list_item()).collect()
-    } else {
-        app.filtered_transactions.iter()
-            .map(|&idx| transactions[idx].to_list_item())
-            .collect()
-    };
-
-    let total_amount: Decimal = transactions.iter()
-        .map(|t| t.amount)
-        .sum();
+// Only the rows on screen are built, from the row cache
+pub fn render_transaction_list(f: &mut Frame, app: &mut App, area: Rect) {
+    let total_amount = app.row_cache.total(&app.transactions);
 
-    let header = format!(
-        "Transactions ({} total, {} shown) Total: {:.2}", 
+    let mut header = format!(
+        "Transactions ({} total, {} shown) Total: {:.2} Sort: {}",
         app.transactions.len(),
-        if app.filtered_transactions.is_empty() { app.transactions.len() } else { app.filtered_transactions.len() },
-        total_amount
+        app.list_view.len(),
+        total_amount,
+        app.sort_description()
     );
+    if let Some(filter) = &app.filter {
+        header.push_str(&format!(" Filter: {}", filter));
+    }
+    if !app.marked.is_empty() {
+        header.push_str(&format!(" • {} marked", app.marked.len()));
+    }
 
-    let list = List::new(items)
+    // Less the borders and the header row
+    let window = app.list_window(area.height.saturating_sub(3) as usize);
+    let rows = app.list_view[window.clone()]
+        .iter()
+        .map(|&idx| app.row_cache.row(idx, &app.transactions[idx], &app.columns, &app.marked))
+        .collect();
+
+    let table = transaction_table(rows, &app.columns)
         .block(Block::default()
             .title(header)
-            .borders(Borders::ALL))
-        .highlight_style(Style::default()
-            .add_modifier(Modifier::REVERSED)
-            .add_modifier(Modifier::BOLD))
-        .highlight_symbol("➤ ");
+            .borders(Borders::ALL));
 
-    f.render_stateful_widget(list, area, &mut app.list_state.clone());
+    let mut state = TableState::default().with_selected(app.list_state.selected().map(|row| row - window.start));
+    f.render_stateful_widget(table, area, &mut state);
 }
 
 pub fn render_popup(f: &mut Frame, app: &App, area: Rect) {
@@ -56,18 +56,38 @@ pub fn render_popup(f: &mut Frame, app: &App, area: Rect) {
                 Style::default().fg(Color::Green)
             };
 
-            vec![
+            let mut lines = vec![
                 Line::from(vec![Span::raw("Date:       "), Span::styled(transaction.date.format("%Y-%m-%d").to_string(), Style::default().add_modifier(Modifier::BOLD))]),
                 Line::from(vec![Span::raw("Amount:     "), Span::styled(format!("{:.2}", transaction.amount), amount_style.add_modifier(Modifier::BOLD))]),
                 Line::from(vec![Span::raw("Merchant:   "), Span::styled(&transaction.merchant, Style::default().add_modifier(Modifier::BOLD))]),
+                Line::from(vec![Span::raw("Raw:        "), Span::raw(&transaction.raw_merchant)]),
                 Line::from(""),
                 Line::from("Description:"),
                 Line::from(transaction.description.clone()),
                 Line::from(""),
                 Line::from(vec![Span::raw("Category:   "), Span::styled(transaction.category.as_deref().unwrap_or("Uncategorized"), Style::default().add_modifier(Modifier::BOLD))]),
+                Line::from(vec![
+                    Span::raw("Source:     "),
+                    match &transaction.category_source {
+                        Some(source) => Span::raw(source.describe()),
+                        None => Span::styled("no rule matched", Style::default().fg(Color::DarkGray)),
+                    },
+                ]),
+            ];
+            if !transaction.flags.is_empty() {
+                lines.push(Line::from(""));
+            }
+            for flag in &transaction.flags {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("⚑ {} ({}): ", flag.kind.label(), flag.status.as_str()), Style::default().fg(Color::Yellow)),
+                    Span::raw(flag.reason.as_str()),
+                ]));
+            }
+            lines.extend([
                 Line::from(""),
-                Line::from(vec![Span::styled("Esc", Style::default().fg(Color::Yellow)), Span::raw(" close • "), Span::styled("c", Style::default().fg(Color::Yellow)), Span::raw(" change category")]),
-            ]
+                Line::from(vec![Span::styled("Esc", Style::default().fg(Color::Yellow)), Span::raw(" close • "), Span::styled("c", Style::default().fg(Color::Yellow)), Span::raw(" change category • "), Span::styled("a", Style::default().fg(Color::Yellow)), Span::raw(" merchant alias")]),
+            ]);
+            lines
         } else {
             vec![Line::from("No transaction selected")]
         }
@@ -90,46 +110,436 @@ pub fn render_popup(f: &mut Frame, app: &App, area: Rect) {
 }
 
 pub fn render_category_summary(f: &mut Frame, app: &App, area: Rect) {
-    let mut items: Vec<(ListItem, Decimal)> = app.category_totals
-        .iter()
+    let items: Vec<ListItem> = app.sorted_categories()
+        .into_iter()
         .map(|(category, total)| {
-            let amount_style = if *total < Decimal::ZERO {
+            let amount_style = if total < Decimal::ZERO {
                 Style::default().fg(Color::Red)
             } else {
                 Style::default().fg(Color::Green)
             };
 
-            (ListItem::new(Line::from(vec![Span::raw(format!("{:<30} ", category)), Span::styled(format!("{:>10.2}", total), amount_style),])), *total)
+            ListItem::new(Line::from(vec![Span::raw(format!("{:<30} ", category)), Span::styled(format!("{:>10.2}", total), amount_style),]))
         })
         .collect();
 
-    items.sort_by(|a, b| b.1.abs().cmp(&a.1.abs()));
-
     let total_amount: Decimal = app.category_totals.values().sum();
 
-    let list = List::new(items.into_iter().map(|(item, _)| item).collect::<Vec<_>>())
+    let list = List::new(items)
         .block(Block::default()
-            .title(format!("Category Summary (Total: {:.2})", total_amount))
+            .title(format!("Category Summary (Total: {:.2}) • Enter details", total_amount))
             .borders(Borders::ALL))
         .highlight_style(Style::default()
             .add_modifier(Modifier::REVERSED));
 
-    f.render_widget(list, area);
+    f.render_stateful_widget(list, area, &mut app.category_state.clone());
 }
 
-pub fn render_help_panel(f: &mut Frame, area: Rect) {
-    let text = vec![Line::from(vec![Span::styled("↑/↓", Style::default().fg(Color::Yellow)), Span::raw(" Move • "), Span::styled("d", Style::default().fg(Color::Yellow)), Span::raw(" Details • "), Span::styled("Esc", Style::default().fg(Color::Yellow)), Span::raw(" Back • "), Span::styled("Tab", Style::default().fg(Color::Yellow)), Span::raw(" View • "), Span::styled("f", Style::default().fg(Color::Yellow)), Span::raw(" Filter • "), Span::styled("c", Style::default().fg(Color::Yellow)), Span::raw(" Category • "), Span::styled("s", Style::default().fg(Color::Yellow)), Span::raw(" Sort • "), Span::styled("q", Style::default().fg(Color::Yellow)), Span::raw(" Quit"),])];
+pub fn render_category_detail(f: &mut Frame, app: &App, area: Rect) {
+    let Some(detail) = &app.category_detail else {
+        return render_category_summary(f, app, area);
+    };
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(area);
+    let side = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(columns[1]);
+
+    let total = detail.total;
+
+    let rows = detail.transactions
+        .iter()
+        .map(|&idx| transaction_row(&app.transactions[idx], &app.columns, &app.marked))
+        .collect();
+    let table = transaction_table(rows, &app.columns)
+        .block(Block::default()
+            .title(format!(
+                "{} ({} transactions, {:.2}) • c recategorize • C move all • Esc back",
+                detail.category,
+                detail.transactions.len(),
+                total
+            ))
+            .borders(Borders::ALL));
+
+    f.render_stateful_widget(table, columns[0], &mut app.detail_state.clone());
+
+    // Show the most recent months that fit, each bar takes seven columns
+    let visible_months = (side[0].width.saturating_sub(2) / 7).max(1) as usize;
+    let labels: Vec<String> = detail.monthly_totals
+        .iter()
+        .map(|(month, _)| month.get(2..).unwrap_or(month).to_string())
+        .collect();
+    let bars: Vec<(&str, u64)> = detail.monthly_totals
+        .iter()
+        .zip(labels.iter())
+        .map(|((_, total), label)| (label.as_str(), total.abs().round().to_u64().unwrap_or(0)))
+        .collect();
+    let bars = &bars[bars.len().saturating_sub(visible_months)..];
+
+    let chart = BarChart::default()
+        .block(Block::default().title("Monthly trend").borders(Borders::ALL))
+        .data(bars)
+        .bar_width(5)
+        .bar_gap(2)
+        .bar_style(Style::default().fg(Color::Cyan))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Cyan));
+
+    f.render_widget(chart, side[0]);
+
+    let total_color = if total < Decimal::ZERO { Color::Red } else { Color::Green };
+    let merchants: Vec<Line> = detail.top_merchants
+        .iter()
+        .take(side[1].height.saturating_sub(2) as usize)
+        .map(|stats| Line::from(vec![
+            Span::raw(format!("{:<24} ", stats.merchant)),
+            Span::styled(format!("{:>10.2} ", stats.total_spend), Style::default().fg(total_color)),
+            Span::raw(format!("{:>4}x", stats.visits)),
+        ]))
+        .collect();
+
+    let top = Paragraph::new(merchants)
+        .block(Block::default().title("Top merchants").borders(Borders::ALL));
+
+    f.render_widget(top, side[1]);
+}
+
+pub fn render_review_queue(f: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app.review_queue
+        .iter()
+        .map(|&(tx_idx, flag_idx)| {
+            let transaction = &app.transactions[tx_idx];
+            let flag = &transaction.flags[flag_idx];
+            let amount_style = if transaction.amount < Decimal::ZERO {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default().fg(Color::Green)
+            };
+
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:<10} ", transaction.date.format("%Y-%m-%d"))),
+                Span::styled(format!("{:>10} ", transaction.amount), amount_style),
+                Span::raw(format!("{:<30} ", transaction.merchant)),
+                Span::styled(format!("{:<20} ", flag.kind.label()), Style::default().fg(Color::Yellow)),
+                Span::raw(flag.reason.as_str()),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .title(format!("Review Queue ({} pending) • y confirm • x dismiss • Esc back", app.review_queue.len()))
+            .borders(Borders::ALL))
+        .highlight_style(Style::default()
+            .add_modifier(Modifier::REVERSED)
+            .add_modifier(Modifier::BOLD))
+        .highlight_symbol("➤ ");
+
+    f.render_stateful_widget(list, area, &mut app.review_state.clone());
+}
+
+pub fn render_history(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(area);
+
+    let items: Vec<ListItem> = app.history
+        .iter()
+        .map(|entry| {
+            let style = if entry.undone {
+                Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:>5} ", entry.id), Style::default().fg(Color::DarkGray)),
+                Span::raw(format!("{}  ", entry.created_at)),
+                Span::styled(entry.description.as_str(), style),
+                Span::styled(if entry.undone { "  (undone)" } else { "" }, Style::default().fg(Color::Yellow)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .title(format!("History ({} edits) • u undo • U redo • Esc back", app.history.len()))
+            .borders(Borders::ALL))
+        .highlight_style(Style::default()
+            .add_modifier(Modifier::REVERSED)
+            .add_modifier(Modifier::BOLD))
+        .highlight_symbol("➤ ");
+
+    f.render_stateful_widget(list, chunks[0], &mut app.history_state.clone());
+
+    let changes: Vec<Line> = app.history_state
+        .selected()
+        .and_then(|i| app.history.get(i))
+        .map(|entry| {
+            entry.changes
+                .iter()
+                .take(chunks[1].height.saturating_sub(2) as usize)
+                .map(|change| {
+                    let merchant = change
+                        .transaction_id()
+                        .and_then(|id| app.transactions.iter().find(|t| t.id as i64 == id))
+                        .map(|t| format!("{:<24} ", t.merchant))
+                        .unwrap_or_default();
+                    Line::from(vec![Span::styled(merchant, Style::default().fg(Color::Cyan)), Span::raw(change.describe())])
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let details = Paragraph::new(changes)
+        .block(Block::default().title("Changes (before -> after)").borders(Borders::ALL));
+
+    f.render_widget(details, chunks[1]);
+}
+
+pub fn render_merchant_report(f: &mut Frame, app: &App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+        .split(area);
+
+    let items: Vec<ListItem> = app.merchant_stats
+        .iter()
+        .enumerate()
+        .map(|(rank, stats)| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:>3}. ", rank + 1)),
+                Span::raw(format!("{:<30} ", stats.merchant)),
+                Span::styled(format!("{:>10.2} ", stats.total_spend), Style::default().fg(Color::Red)),
+                Span::raw(format!("{:>5} visits ", stats.visits)),
+                Span::raw(format!("avg {:>8.2}", stats.average_ticket)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .title(format!("Top Merchants ({}) • p period • Enter transactions", app.merchant_period.label()))
+            .borders(Borders::ALL))
+        .highlight_style(Style::default()
+            .add_modifier(Modifier::REVERSED)
+            .add_modifier(Modifier::BOLD))
+        .highlight_symbol("➤ ");
+
+    f.render_stateful_widget(list, chunks[0], &mut app.merchant_state.clone());
+
+    let lines: Vec<Line> = app.merchant_concentration
+        .iter()
+        .map(|c| Line::from(c.describe()))
+        .collect();
+
+    let concentration = Paragraph::new(lines)
+        .block(Block::default().title("Concentration").borders(Borders::ALL));
+
+    f.render_widget(concentration, chunks[1]);
+}
+
+const SERIES_COLORS: [Color; 4] = [Color::Cyan, Color::Magenta, Color::Blue, Color::LightGreen];
+
+pub fn render_forecast(f: &mut Frame, app: &App, area: Rect) {
+    let threshold = app.forecast_config.low_balance_threshold;
+    let horizon = app.forecast_config.horizon_days;
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(8),
+            Constraint::Length(app.forecasts.len() as u16 + 2),
+        ])
+        .split(area);
+
+    let series: Vec<Vec<(f64, f64)>> = app.forecasts
+        .iter()
+        .map(|forecast| {
+            std::iter::once(forecast.starting_balance)
+                .chain(forecast.points.iter().map(|p| p.balance))
+                .enumerate()
+                .map(|(day, balance)| (day as f64, decimal_to_f64(balance)))
+                .collect()
+        })
+        .collect();
+    let threshold_line = vec![(0.0, decimal_to_f64(threshold)), (horizon as f64, decimal_to_f64(threshold))];
+
+    let (mut y_min, mut y_max) = series
+        .iter()
+        .flatten()
+        .chain(threshold_line.iter())
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &(_, y)| (lo.min(y), hi.max(y)));
+    let padding = ((y_max - y_min) * 0.1).max(10.0);
+    y_min -= padding;
+    y_max += padding;
+
+    let mut datasets: Vec<Dataset> = app.forecasts
+        .iter()
+        .zip(series.iter())
+        .enumerate()
+        .map(|(i, (forecast, points))| {
+            Dataset::default()
+                .name(forecast.account.clone())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(SERIES_COLORS[i % SERIES_COLORS.len()]))
+                .data(points)
+        })
+        .collect();
+    datasets.push(
+        Dataset::default()
+            .name(format!("threshold {:.2}", threshold))
+            .marker(symbols::Marker::Dot)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Red))
+            .data(&threshold_line),
+    );
+
+    let x_labels = match app.forecasts.first().and_then(|f| f.points.last()) {
+        Some(last) => vec![Span::raw("now"), Span::raw(last.date.format("%Y-%m-%d").to_string())],
+        None => vec![Span::raw("now"), Span::raw("")],
+    };
+
+    let chart = Chart::new(datasets)
+        .block(Block::default()
+            .title(format!("Balance Forecast (next {} days) • h horizon • t threshold • e export", horizon))
+            .borders(Borders::ALL))
+        .x_axis(Axis::default()
+            .bounds([0.0, horizon as f64])
+            .labels(x_labels)
+            .style(Style::default().fg(Color::DarkGray)))
+        .y_axis(Axis::default()
+            .bounds([y_min, y_max])
+            .labels(vec![
+                Span::raw(format!("{:.0}", y_min)),
+                Span::raw(format!("{:.0}", y_max)),
+            ])
+            .style(Style::default().fg(Color::DarkGray)));
+
+    f.render_widget(chart, chunks[0]);
+
+    let lines: Vec<Line> = app.forecasts
+        .iter()
+        .map(|forecast| {
+            let mut spans = vec![
+                Span::raw(format!("{:<20} ", forecast.account)),
+                Span::raw(format!("now {:>10.2}  ", forecast.starting_balance)),
+                Span::raw(format!("in {} days {:>10.2}  ", horizon, forecast.ending_balance())),
+                Span::raw(format!("{} recurring  ", forecast.recurring.len())),
+            ];
+            if let Some(warning) = forecast.low_balance_warning(threshold) {
+                spans.push(Span::styled(
+                    format!("⚠ below {:.2} on {} ({:.2})", threshold, warning.date.format("%Y-%m-%d"), warning.balance),
+                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                ));
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    let summary = Paragraph::new(lines)
+        .block(Block::default().title("Accounts").borders(Borders::ALL));
+
+    f.render_widget(summary, chunks[1]);
+}
+
+fn decimal_to_f64(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+pub fn render_header(f: &mut Frame, app: &App, area: Rect) {
+    let header = Line::from(vec![
+        Span::styled("Profile: ", Style::default().fg(Color::DarkGray)),
+        Span::styled(app.profile.name.as_str(), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+        Span::styled(format!("  {}", app.profile.db_path.display()), Style::default().fg(Color::DarkGray)),
+        Span::styled(if app.is_encrypted() { "  encrypted" } else { "" }, Style::default().fg(Color::Green)),
+    ]);
+
+    f.render_widget(Paragraph::new(header), area);
+}
+
+pub fn render_lock_screen(f: &mut Frame, lock: &LockScreen, profile: &Profile) {
+    let area = centered_rect(60, 30, f.size());
+    f.render_widget(Clear, f.size());
+
+    let text = vec![
+        Line::from(vec![
+            Span::styled("Profile: ", Style::default().fg(Color::DarkGray)),
+            Span::styled(profile.name.as_str(), Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+        ]),
+        Line::from(Span::styled(profile.db_path.display().to_string(), Style::default().fg(Color::DarkGray))),
+        Line::from(""),
+        Line::from(vec![
+            Span::raw("Passphrase: "),
+            Span::raw("*".repeat(lock.passphrase.chars().count())),
+        ]),
+    ];
+
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Locked (Enter to unlock, Esc to quit) ")
+        .border_style(Style::default().fg(Color::Yellow));
+    if let Some(error) = &lock.error {
+        block = block
+            .title(Title::from(Span::styled(format!(" {} ", error), Style::default().fg(Color::Red))).position(Position::Bottom))
+            .border_style(Style::default().fg(Color::Red));
+    }
+
+    f.render_widget(Paragraph::new(text).block(block), area);
+}
+
+pub fn render_help_panel(f: &mut Frame, app: &App, area: Rect) {
+    let text = vec![Line::from(vec![Span::styled("↑/↓", Style::default().fg(Color::Yellow)), Span::raw(" Move • "), Span::styled("PgUp/PgDn", Style::default().fg(Color::Yellow)), Span::raw(" Page • "), Span::styled("g", Style::default().fg(Color::Yellow)), Span::raw(" Go to date • "), Span::styled("d", Style::default().fg(Color::Yellow)), Span::raw(" Details • "), Span::styled("Esc", Style::default().fg(Color::Yellow)), Span::raw(" Back • "), Span::styled("Tab", Style::default().fg(Color::Yellow)), Span::raw(" View • "), Span::styled("f", Style::default().fg(Color::Yellow)), Span::raw(" Filter • "), Span::styled("F", Style::default().fg(Color::Yellow)), Span::raw(" Save filter • "), Span::styled("c", Style::default().fg(Color::Yellow)), Span::raw(" Category • "), Span::styled("s", Style::default().fg(Color::Yellow)), Span::raw(" Sort • "), Span::styled("v", Style::default().fg(Color::Yellow)), Span::raw(" Columns • "), Span::styled("Space", Style::default().fg(Color::Yellow)), Span::raw(" Mark • "), Span::styled("b", Style::default().fg(Color::Yellow)), Span::raw(" Batch • "), Span::styled("u", Style::default().fg(Color::Yellow)), Span::raw(" Undo • "), Span::styled("U", Style::default().fg(Color::Yellow)), Span::raw(" Redo • "), Span::styled("H", Style::default().fg(Color::Yellow)), Span::raw(" History • "), Span::styled("r", Style::default().fg(Color::Yellow)), Span::raw(" Review • "), Span::styled("E", Style::default().fg(Color::Yellow)), Span::raw(" Export • "), Span::styled("i", Style::default().fg(Color::Yellow)), Span::raw(" Import • "), Span::styled("q", Style::default().fg(Color::Yellow)), Span::raw(" Quit"),])];
+
+    let title = match &app.status_message {
+        Some(message) => Line::from(Span::styled(format!(" {} ", message), Style::default().fg(Color::Yellow))),
+        None => Line::from(" Help "),
+    };
 
     let help = Paragraph::new(text)
         .block(Block::default()
             .borders(Borders::ALL)
             .border_style(Style::default().fg(Color::DarkGray))
-            .title(" Help "))
+            .title(title))
         .alignment(ratatui::layout::Alignment::Center);
 
     f.render_widget(help, area);
 }
 
+// Takes the help panel's place while an import or categorization runs
+pub fn render_task_progress(f: &mut Frame, app: &App, area: Rect) {
+    let Some(task) = &app.task else {
+        return;
+    };
+
+    let title = if task.is_cancelling() {
+        format!(" {}: cancelling... ", task.kind.label())
+    } else {
+        format!(" {} (Esc to cancel) ", task.kind.label())
+    };
+    let label = match task.kind {
+        _ if task.total == 0 => "starting...".to_string(),
+        // Imports are measured in bytes of the file, so only the share is shown
+        TaskKind::Import(_) => format!("{:.0}%", task.ratio() * 100.0),
+        TaskKind::Categorize => format!("{} / {}", task.done, task.total),
+    };
+
+    let gauge = Gauge::default()
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::DarkGray))
+            .title(title))
+        .gauge_style(Style::default().fg(Color::Yellow))
+        .ratio(task.ratio())
+        .label(label);
+
+    f.render_widget(gauge, area);
+}
+
 pub fn render_category_selection(f: &mut Frame, app: &App, area: Rect) {
     let items: Vec<ListItem> = app.available_categories
         .iter()
@@ -156,15 +566,119 @@ pub fn render_category_selection(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(list, popup_area);
 }
 
+pub fn render_sort_menu(f: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = SortField::all()
+        .iter()
+        .enumerate()
+        .map(|(i, &field)| {
+            let role = if app.sort_primary.field == field {
+                format!("1st {}", app.sort_primary.order.arrow())
+            } else if let Some(key) = app.sort_secondary.filter(|key| key.field == field) {
+                format!("2nd {}", key.order.arrow())
+            } else {
+                String::new()
+            };
+            let style = if Some(i) == app.sort_selection {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<10}", field.label()), style),
+                Span::styled(role, Style::default().fg(Color::Cyan)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .title("Sort by (Enter primary, Space secondary, Backspace clear, Esc close)")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow)));
+
+    let popup_area = centered_rect(50, 30, area);
+    f.render_widget(Clear, popup_area);
+    f.render_widget(list, popup_area);
+}
+
+pub fn render_column_menu(f: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app.columns
+        .iter()
+        .enumerate()
+        .map(|(i, setting)| {
+            let style = if Some(i) == app.column_selection {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let width = match setting.width {
+                Some(width) => format!("{} cols", width),
+                None => "auto".to_string(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("[{}] {:<12}", if setting.visible { "x" } else { " " }, setting.column.label()), style),
+                Span::styled(width, Style::default().fg(Color::Cyan)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default()
+            .title("Columns (Space show/hide, +/- width, 0 auto, [ ] move, Esc done)")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow)));
+
+    let popup_area = centered_rect(50, 50, area);
+    f.render_widget(Clear, popup_area);
+    f.render_widget(list, popup_area);
+}
+
+pub fn render_batch_menu(f: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = BatchMenuItem::all()
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let style = if Some(i) == app.batch_selection {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::REVERSED)
+            } else if *item == BatchMenuItem::Delete {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(Span::styled(item.label(), style)))
+        })
+        .collect();
+
+    let count = if app.marked.is_empty() { 1 } else { app.marked.len() };
+    let list = List::new(items)
+        .block(Block::default()
+            .title(format!("Apply to {} transaction{} (Enter to apply, Esc to cancel)", count, if count == 1 { "" } else { "s" }))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow)));
+
+    let popup_area = centered_rect(50, 40, area);
+    f.render_widget(Clear, popup_area);
+    f.render_widget(list, popup_area);
+}
+
 pub fn render_input_prompt(f: &mut Frame, app: &App, area: Rect) {
     if app.input_mode == InputMode::Normal {
         return;
     }
 
     let (title, placeholder) = match app.input_mode {
-        InputMode::Filtering => ("Filter (Enter to apply, Esc to cancel)", "Enter text to filter transactions..."),
+        InputMode::Filtering => ("Filter (Enter to apply, Esc to cancel)", "e.g. category:Groceries amount<-50 date>=2026-01 merchant~/^AH/ -tag:x @saved"),
         InputMode::Categorizing => ("Categorize (Enter to apply, Esc to cancel)", "Enter category name..."),
-        InputMode::Normal => return,
+        InputMode::EditingThreshold => ("Low balance threshold (Enter to apply, Esc to cancel)", "Enter amount, e.g. 250.00..."),
+        InputMode::Aliasing => ("Merchant alias (Enter to apply, Esc to cancel)", "Enter the canonical merchant name..."),
+        InputMode::Exporting => ("Export to file (Enter to write, Esc to cancel)", "transactions.csv, .json, .jsonl or .md..."),
+        InputMode::ExportingForecast => ("Export forecast to CSV (Enter to write, Esc to cancel)", "forecast.csv..."),
+        InputMode::Importing => ("Import a CSV export (Enter to start, Esc to cancel)", "Path to the bank's CSV file..."),
+        InputMode::JumpingToDate => ("Go to date (Enter to jump, Esc to cancel)", "YYYY-MM-DD or YYYY-MM..."),
+        InputMode::SavingFilter => ("Save filter as (Enter to save, Esc to cancel)", "Name to recall it with @name..."),
+        InputMode::AddingTags => ("Add tags (Enter to apply, Esc to cancel)", "e.g. holiday reimbursed..."),
+        InputMode::RemovingTags => ("Remove tags (Enter to apply, Esc to cancel)", "e.g. holiday..."),
+        InputMode::Normal | InputMode::Sorting | InputMode::EditingColumns | InputMode::BatchMenu => return,
     };
 
     let input = Paragraph::new(if app.input_text.is_empty() {
@@ -172,10 +686,17 @@ pub fn render_input_prompt(f: &mut Frame, app: &App, area: Rect) {
     } else {
         Line::from(app.input_text.as_str())
     })
-    .block(Block::default()
-        .borders(Borders::ALL)
-        .title(title)
-        .border_style(Style::default().fg(Color::Yellow)));
+    .block(match &app.input_error {
+        Some(error) => Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .title(Title::from(Span::styled(format!(" {} ", error), Style::default().fg(Color::Red))).position(Position::Bottom))
+            .border_style(Style::default().fg(Color::Red)),
+        None => Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(Style::default().fg(Color::Yellow)),
+    });
 
     let popup_area = centered_rect(60, 10, area);
     f.render_widget(Clear, popup_area);
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use tokio::runtime::Runtime;
+use crate::import::{ImportPipeline, ImportSummary, IMPORT_BATCH};
+use crate::models::{
+    category::{Category, CategorySource, RuleMatcher},
+    transaction::Transaction,
+};
+use crate::repository::Repositories;
+use crate::utils::csv::CsvTransactions;
+use super::app::category_for;
+
+const CATEGORIZE_CHUNK: usize = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskKind {
+    Import(String),
+    Categorize,
+}
+
+impl TaskKind {
+    pub fn label(&self) -> String {
+        match self {
+            TaskKind::Import(path) => format!("Importing {}", path),
+            TaskKind::Categorize => "Categorizing".to_string(),
+        }
+    }
+}
+
+pub type Categorized = HashMap<u64, Option<(String, CategorySource)>>;
+
+#[derive(Debug)]
+pub enum TaskEvent {
+    Progress { done: usize, total: usize },
+    Imported { summary: ImportSummary, cancelled: bool },
+    Categorized(Categorized),
+    Cancelled,
+    Failed(anyhow::Error),
+}
+
+// A job running on the app's runtime. The UI polls it between frames;
+// dropping it asks the job to stop at its next step
+#[derive(Debug)]
+pub struct Task {
+    pub kind: TaskKind,
+    pub done: usize,
+    pub total: usize,
+    cancel: Arc<AtomicBool>,
+    events: Receiver<TaskEvent>,
+}
+
+#[derive(Clone)]
+struct Progress {
+    sender: Sender<TaskEvent>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Progress {
+    fn report(&self, done: usize, total: usize) {
+        let _ = self.sender.send(TaskEvent::Progress { done, total });
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+impl Task {
+    // The file is parsed on a blocking thread that stays at most a couple
+    // of batches ahead of the inserts; progress is in bytes read
+    pub fn import(runtime: &Runtime, repositories: &Repositories, path: &str) -> Self {
+        let repository = repositories.transactions.clone();
+        let merchants = repositories.merchants.clone();
+        let categories = repositories.categories.clone();
+        let path = path.to_string();
+        Self::spawn(runtime, TaskKind::Import(path.clone()), move |progress| async move {
+            let pipeline = ImportPipeline::new(merchants.get_all_aliases().await?, categories.get_all_categories().await?);
+            let mut rows = CsvTransactions::open(&path)
+                .with_context(|| format!("Failed to read transactions from {}", path))?;
+            let size = std::fs::metadata(&path)?.len() as usize;
+
+            let (sender, mut batches) = tokio::sync::mpsc::channel(2);
+            let reader = tokio::task::spawn_blocking(move || loop {
+                let batch = rows.by_ref().take(IMPORT_BATCH).collect::<Result<Vec<_>>>();
+                if batch.as_ref().is_ok_and(Vec::is_empty) {
+                    return;
+                }
+                let failed = batch.is_err();
+                let position = rows.position() as usize;
+                // A closed channel means the import was cancelled or failed
+                if sender.blocking_send(batch.map(|batch| (batch, position))).is_err() || failed {
+                    return;
+                }
+            });
+
+            let rules = pipeline.rules();
+            let mut summary = ImportSummary::default();
+            progress.report(0, size);
+            while let Some(batch) = batches.recv().await {
+                if progress.cancelled() {
+                    return Ok(TaskEvent::Imported { summary, cancelled: true });
+                }
+                let (batch, position) = batch.with_context(|| format!("Failed to read transactions from {}", path))?;
+                summary.uncategorized += pipeline.uncategorized(&rules, &batch);
+                summary.read += batch.len();
+                summary.inserted += repository.save_transactions(batch).await?;
+                progress.report(position, size);
+            }
+            reader.await?;
+            Ok(TaskEvent::Imported { summary, cancelled: false })
+        })
+    }
+
+    // Works on a snapshot; results are keyed by transaction id
+    pub fn categorize(runtime: &Runtime, repositories: &Repositories, transactions: Vec<Transaction>) -> Self {
+        let repository = repositories.categories.clone();
+        Self::spawn(runtime, TaskKind::Categorize, move |progress| async move {
+            let categories: HashMap<String, Category> = repository.get_all_categories().await?
+                .into_iter()
+                .map(|c| (c.name.clone(), c))
+                .collect();
+            let assigned = repository.get_assigned_categories().await?;
+            let rules = RuleMatcher::new(&categories);
+
+            let total = transactions.len();
+            let mut results = HashMap::with_capacity(total);
+            progress.report(0, total);
+            for chunk in transactions.chunks(CATEGORIZE_CHUNK) {
+                if progress.cancelled() {
+                    return Ok(TaskEvent::Cancelled);
+                }
+                for transaction in chunk {
+                    results.insert(transaction.id, category_for(transaction, &rules, &assigned));
+                }
+                progress.report(results.len(), total);
+                tokio::task::yield_now().await;
+            }
+            Ok(TaskEvent::Categorized(results))
+        })
+    }
+
+    fn spawn<F, Fut>(runtime: &Runtime, kind: TaskKind, job: F) -> Self
+    where
+        F: FnOnce(Progress) -> Fut,
+        Fut: Future<Output = Result<TaskEvent>> + Send + 'static,
+    {
+        let (sender, events) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let job = job(Progress { sender: sender.clone(), cancel: cancel.clone() });
+        runtime.spawn(async move {
+            let event = job.await.unwrap_or_else(TaskEvent::Failed);
+            let _ = sender.send(event);
+        });
+        Task { kind, done: 0, total: 0, cancel, events }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelling(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            (self.done as f64 / self.total as f64).min(1.0)
+        }
+    }
+
+    // Everything the job sent since the last call
+    pub fn events(&self) -> Vec<TaskEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -1,7 +1,8 @@
 use std::fs::File;
+use std::io::Read;
 use std::str::FromStr;
-use anyhow::Result;
-use csv::ReaderBuilder;
+use anyhow::{Context, Result};
+use csv::{Reader, ReaderBuilder, StringRecord};
 use chrono::NaiveDateTime;
 use rust_decimal::Decimal;
 use crate::models::transaction::Transaction;
@@ -15,45 +16,96 @@ fn parse_amount(amount: &str, debit_credit: &str) -> Result<Decimal> {
     Ok(decimal)
 }
 
+fn parse_balance(balance: &str) -> Option<Decimal> {
+    Decimal::from_str(&balance.trim().replace(',', ".")).ok()
+}
+
+// ING's Tag column holds free-form labels like "#holiday #france"
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    tags.split(|c: char| c.is_whitespace() || c == ',')
+        .map(|tag| tag.trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
 fn parse_date(date: &str) -> Result<NaiveDateTime> {
     let date = chrono::NaiveDateTime::parse_from_str(&format!("{}000000", date), "%Y%m%d%H%M%S")?;
     Ok(date)
 }
 
 pub fn read_transactions_from_csv(path: &str) -> Result<Vec<Transaction>> {
-    let file = File::open(path)?;
-    let mut rdr = ReaderBuilder::new()
-        .delimiter(b';')
-        .has_headers(true)
-        .from_reader(file);
-
-    let mut id_counter = 1u64;
-    let mut transactions = Vec::new();
-    
-    for result in rdr.records() {
-        let record = result?;
-        if record.len() < 7 {
-            continue;
-        }
+    CsvTransactions::open(path)?.collect()
+}
+
+// Reads an ING export one row at a time into the same record buffer, so
+// memory stays flat however long the statement is
+pub struct CsvTransactions<R> {
+    reader: Reader<R>,
+    record: StringRecord,
+    next_id: u64,
+}
+
+impl CsvTransactions<File> {
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
 
-        let date = parse_date(&record[0].trim_matches('"'))?;
-        let merchant = record[1].trim_matches('"').to_string();
-        let description = record[8].trim_matches('"').to_string();
-        let amount = parse_amount(
-            &record[6].trim_matches('"'),
-            &record[5].trim_matches('"')
-        )?;
-
-        transactions.push(Transaction {
-            id: id_counter,
-            date,
-            amount,
-            merchant,
-            description,
+impl<R: Read> CsvTransactions<R> {
+    pub fn new(reader: R) -> Self {
+        let reader = ReaderBuilder::new()
+            .delimiter(b';')
+            .has_headers(true)
+            .from_reader(reader);
+        CsvTransactions { reader, record: StringRecord::new(), next_id: 1 }
+    }
+
+    // Bytes consumed so far, for progress against the file size
+    pub fn position(&self) -> u64 {
+        self.reader.position().byte()
+    }
+
+    fn parse_record(&self) -> Result<Transaction> {
+        let record = &self.record;
+        let field = |i: usize| record.get(i).unwrap_or_default().trim_matches('"');
+
+        let merchant = field(1).to_string();
+        Ok(Transaction {
+            id: self.next_id,
+            date: parse_date(field(0))?,
+            amount: parse_amount(field(6), field(5))?,
+            merchant: merchant.clone(),
+            raw_merchant: merchant,
+            description: field(8).to_string(),
             category: None,
-        });
-        id_counter += 1;
+            category_source: None,
+            account: field(2).to_string(),
+            balance: record.get(9).and_then(|b| parse_balance(b.trim_matches('"'))),
+            tags: record.get(10).map(|t| parse_tags(t.trim_matches('"'))).unwrap_or_default(),
+            flags: Vec::new(),
+            excluded: false,
+            splits: Vec::new(),
+        })
     }
+}
+
+impl<R: Read> Iterator for CsvTransactions<R> {
+    type Item = Result<Transaction>;
 
-    Ok(transactions)
-}
\ No newline at end of file
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.read_record(&mut self.record) {
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e.into())),
+                Ok(true) if self.record.len() < 7 => continue,
+                Ok(true) => {
+                    let line = self.record.position().map(|p| p.line()).unwrap_or_default();
+                    let transaction = self.parse_record().with_context(|| format!("Invalid row on line {}", line));
+                    self.next_id += 1;
+                    return Some(transaction);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use finance_analyzer::archive::{read_archive, write_archive, ArchiveData};
+use finance_analyzer::db::{
+    archive::{ArchiveDb, Prefer, RestoreReport},
+    batch::{BatchAction, BatchDb},
+    budget::BudgetDb,
+    category::CategoryDb,
+    connection::DbConnection,
+    filter::FilterDb,
+    flag::FlagDb,
+    ledger::LedgerDb,
+    merchant::MerchantDb,
+    settings::SettingsDb,
+    split::SplitDb,
+    transaction::TransactionDb,
+};
+use finance_analyzer::models::category::Category;
+use finance_analyzer::models::flag::{FlagKind, TransactionFlag};
+use finance_analyzer::models::merchant::MerchantAlias;
+use finance_analyzer::models::transaction::Split;
+use rusqlite::Connection;
+use rust_decimal::Decimal;
+use common::{count, date, scratch_dir, transaction};
+
+// Something in every table an archive carries
+fn book() -> DbConnection {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let conn = db.get_connection();
+    TransactionDb::new(conn).save_transactions(&[
+        transaction(date(2024, 1, 2), "-12.50", "Albert Heijn"),
+        transaction(date(2024, 1, 3), "-8.00", "Jumbo"),
+        transaction(date(2024, 1, 4), "-3.20", "NS Groep"),
+    ]).unwrap();
+    CategoryDb::new(conn).save_category(&Category::new("Groceries", &[("ALBERT HEIJN", 1)])).unwrap();
+    CategoryDb::new(conn).save_category(&Category::new("Transport", &[("NS GROEP", 2)])).unwrap();
+    BudgetDb::new(conn).set_budget("Groceries", Decimal::from_str("300").unwrap()).unwrap();
+    BatchDb::new(conn).apply(&[2], &BatchAction::SetCategory("Groceries".to_string())).unwrap();
+    BatchDb::new(conn).apply(&[1], &BatchAction::AddTags(vec!["shared".to_string()])).unwrap();
+    BatchDb::new(conn).apply(&[3], &BatchAction::SetExcluded(true)).unwrap();
+    let flag = TransactionFlag::new(FlagKind::AmountOutlier, "3x the usual".to_string());
+    FlagDb::new(conn).save_flags(&[(1, flag)]).unwrap();
+    MerchantDb::new(conn).save_alias(&MerchantAlias::new("jumbo", "Jumbo Supermarkten")).unwrap();
+    FilterDb::new(conn).save_filter("food", "category:Groceries").unwrap();
+    LedgerDb::new(conn).set_account("Groceries", "Expenses:Food").unwrap();
+    SettingsDb::new(conn).set("forecast.low_balance_threshold", "500").unwrap();
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "10"), split("Transport", "2.50")]).unwrap();
+    db
+}
+
+fn split(category: &str, amount: &str) -> Split {
+    Split { category: category.to_string(), amount: Decimal::from_str(amount).unwrap() }
+}
+
+fn export(conn: &mut Connection) -> ArchiveData {
+    ArchiveDb::new(conn).export().unwrap()
+}
+
+fn json(data: &ArchiveData) -> serde_json::Value {
+    serde_json::to_value(data).unwrap()
+}
+
+fn write(dir: &Path, data: &ArchiveData) -> PathBuf {
+    let path = dir.join("backup.json");
+    write_archive(data, "test", 4, std::fs::File::create(&path).unwrap()).unwrap();
+    path
+}
+
+fn restore(conn: &mut Connection, data: &ArchiveData, prefer: Prefer) -> RestoreReport {
+    ArchiveDb::new(conn).restore(data, prefer, false).unwrap()
+}
+
+fn conflicts(report: &RestoreReport) -> Vec<&str> {
+    report.conflicts.iter().map(|c| c.kind).collect()
+}
+
+#[test]
+fn a_backup_restores_into_an_empty_database_unchanged() {
+    let dir = scratch_dir("archive-round-trip");
+    let mut db = book();
+    let path = write(&dir, &export(db.get_connection()));
+
+    let archive = read_archive(&path).unwrap();
+    assert_eq!(archive.manifest.counts["transactions"], 3);
+    assert_eq!(archive.manifest.counts["splits"], 1);
+    let mut empty = DbConnection::new(":memory:").unwrap();
+    let report = restore(empty.get_connection(), &archive.data, Prefer::Archive);
+
+    assert!(report.conflicts.is_empty());
+    assert_eq!(report.added["transactions"], 3);
+    assert_eq!(json(&export(empty.get_connection())), json(&export(db.get_connection())));
+}
+
+#[test]
+fn an_edited_archive_is_refused() {
+    let dir = scratch_dir("archive-tampered");
+    let path = write(&dir, &export(book().get_connection()));
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, text.replace("-12.50", "-1.50")).unwrap();
+
+    let error = read_archive(&path).unwrap_err().to_string();
+    assert!(error.contains("Checksum mismatch"), "{}", error);
+}
+
+#[test]
+fn an_archive_from_a_newer_build_is_refused() {
+    let dir = scratch_dir("archive-newer");
+    let path = write(&dir, &export(book().get_connection()));
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("\"format_version\":1"));
+    std::fs::write(&path, text.replace("\"format_version\":1", "\"format_version\":2")).unwrap();
+
+    let error = read_archive(&path).unwrap_err().to_string();
+    assert!(error.contains("newer than this build supports"), "{}", error);
+}
+
+// The archive disagrees with the book on a budget, a rule priority, a
+// category assignment, a split and a setting
+fn diverged() -> (DbConnection, ArchiveData) {
+    let mut archived = book();
+    let conn = archived.get_connection();
+    conn.execute("UPDATE category_rules SET priority = 5 WHERE pattern = 'ALBERT HEIJN'", []).unwrap();
+    BudgetDb::new(conn).set_budget("Groceries", Decimal::from_str("350").unwrap()).unwrap();
+    BatchDb::new(conn).apply(&[2], &BatchAction::SetCategory("Transport".to_string())).unwrap();
+    SettingsDb::new(conn).set("forecast.low_balance_threshold", "250").unwrap();
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "12"), split("Transport", "0.50")]).unwrap();
+    (book(), export(conn))
+}
+
+fn merged_values(conn: &mut Connection) -> (String, u8, String, String) {
+    let budget = BudgetDb::new(conn).get_budgets().unwrap()["Groceries"].to_string();
+    let priority = conn.query_row("SELECT priority FROM category_rules WHERE pattern = 'ALBERT HEIJN'", [], |row| row.get(0)).unwrap();
+    let category = CategoryDb::new(conn).get_assigned_categories().unwrap()[&2].0.clone();
+    let threshold = SettingsDb::new(conn).get("forecast.low_balance_threshold").unwrap().unwrap();
+    (budget, priority, category, threshold)
+}
+
+#[test]
+fn merging_keeps_local_values_when_preferring_local() {
+    let (mut db, archived) = diverged();
+    let conn = db.get_connection();
+
+    let report = restore(conn, &archived, Prefer::Local);
+
+    assert_eq!(conflicts(&report), ["rule priority", "budget", "split", "category", "setting"]);
+    assert_eq!(merged_values(conn), ("300".to_string(), 1, "Groceries".to_string(), "500".to_string()));
+    assert_eq!(report.journal_cleared, 0);
+    assert!(count(conn, "journal") > 0);
+}
+
+#[test]
+fn merging_takes_the_archive_values_when_preferring_the_archive() {
+    let (mut db, archived) = diverged();
+    let conn = db.get_connection();
+
+    let report = restore(conn, &archived, Prefer::Archive);
+
+    assert_eq!(conflicts(&report), ["rule priority", "budget", "split", "category", "setting"]);
+    assert_eq!(merged_values(conn), ("350".to_string(), 5, "Transport".to_string(), "250".to_string()));
+    // The journal's earlier entries may no longer apply
+    assert!(report.journal_cleared > 0);
+    assert_eq!(count(conn, "journal"), 0);
+}
+
+#[test]
+fn a_dry_run_reports_without_changing_anything() {
+    let (mut db, archived) = diverged();
+    let conn = db.get_connection();
+    let before = json(&export(conn));
+    let journal = count(conn, "journal");
+
+    let report = ArchiveDb::new(conn).restore(&archived, Prefer::Archive, true).unwrap();
+
+    assert_eq!(report.conflicts.len(), 5);
+    assert_eq!(report.journal_cleared as i64, journal);
+    assert_eq!(json(&export(conn)), before);
+    assert_eq!(count(conn, "journal"), journal);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use finance_analyzer::db::{category::CategoryDb, connection::DbConnection, transaction::TransactionDb};
+use finance_analyzer::import::import_beancount;
+use finance_analyzer::ledger::{beancount, write_journal, AccountMap, JournalFormat};
+use finance_analyzer::models::{category::{Category, CategorySource}, transaction::Transaction};
+use rust_decimal::Decimal;
+use common::{count, date, scratch_dir, transaction};
+
+fn map() -> AccountMap {
+    AccountMap::new(HashMap::new(), vec!["Groceries".to_string(), "Salary".to_string()])
+}
+
+fn journal(transactions: &[Transaction]) -> String {
+    let mut out = Vec::new();
+    write_journal(JournalFormat::Beancount, &transactions.iter().collect::<Vec<_>>(), &map(), &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn convert(input: &str) -> beancount::Conversion {
+    beancount::to_transactions(&beancount::parse(input).unwrap(), &map())
+}
+
+#[test]
+fn written_journals_read_back_unchanged() {
+    let mut groceries = transaction(date(2024, 1, 2), "-42.10", "Albert Heijn");
+    groceries.description = "Weekly \"big\" shop".to_string();
+    groceries.category = Some("Groceries".to_string());
+    groceries.balance = Some(Decimal::from_str("957.90").unwrap());
+    groceries.tags = vec!["food".to_string(), "home".to_string()];
+    let mut salary = transaction(date(2024, 1, 25), "2500.00", "Acme BV");
+    salary.category = Some("Salary".to_string());
+    let cash = transaction(date(2024, 1, 26), "-20.00", "ATM");
+    let originals = vec![groceries, salary, cash];
+
+    let conversion = convert(&journal(&originals));
+
+    assert!(conversion.skipped.is_empty());
+    let read: Vec<Transaction> = conversion.transactions
+        .into_iter()
+        .map(|(mut t, category)| {
+            t.category = category;
+            t
+        })
+        .collect();
+    assert_eq!(read, originals);
+}
+
+#[test]
+fn malformed_entries_are_rejected_or_skipped() {
+    let error = beancount::parse("2024-01-02 * \"Shop\" \"\"\n    Assets:Bank:NL01  -4,5x EUR\n").unwrap_err();
+    assert!(error.to_string().contains("Line 2: Invalid amount '-4,5x'"), "{}", error);
+
+    let error = beancount::parse("2024-01-02 * \"Shop\n").unwrap_err();
+    assert!(error.to_string().contains("Line 1: Unterminated string"), "{}", error);
+
+    // A header without a valid date is read as some other directive and ignored
+    assert!(beancount::parse("2024-13-02 * \"Shop\" \"\"\n    Assets:Bank:NL01  -4.50 EUR\n").unwrap().is_empty());
+
+    let conversion = convert(concat!(
+        "2024-01-02 * \"Shop\" \"\"\n",
+        "    Expenses:Groceries  4.50 EUR\n",
+        "    Equity:Opening  -4.50 EUR\n",
+        "\n",
+        "2024-01-03 * \"Shop\" \"\"\n",
+        "    Expenses:Groceries  4.50 USD\n",
+        "    Assets:Bank:NL01  -4.50 USD\n",
+    ));
+    assert!(conversion.transactions.is_empty());
+    assert_eq!(conversion.skipped, vec![
+        "line 1: no asset or liability posting".to_string(),
+        "line 5: only EUR amounts are supported".to_string(),
+    ]);
+}
+
+#[test]
+fn a_missing_bank_amount_balances_the_other_postings() {
+    let conversion = convert("2024-01-02 * \"Shop\" \"\"\n    Expenses:Groceries  4.50 EUR\n    Assets:Bank:NL01\n");
+
+    let (read, category) = &conversion.transactions[0];
+    assert_eq!(read.amount, Decimal::from_str("-4.50").unwrap());
+    assert_eq!(read.account, "NL01");
+    assert_eq!(category.as_deref(), Some("Groceries"));
+}
+
+#[test]
+fn imports_store_rows_and_categories_together() {
+    let path = scratch_dir("beancount-import").join("import.beancount");
+    fs::write(&path, concat!(
+        "2024-01-02 * \"Albert Heijn\" \"\"\n",
+        "    Expenses:Groceries  4.50 EUR\n",
+        "    Assets:Bank:NL01  -4.50 EUR\n",
+        "\n",
+        "2024-01-03 * \"Shop\" \"\"\n",
+        "    Expenses:Groceries  4.50 USD\n",
+        "    Assets:Bank:NL01  -4.50 USD\n",
+    )).unwrap();
+    let path = path.to_str().unwrap();
+    let mut db = DbConnection::new(":memory:").unwrap();
+    CategoryDb::new(db.get_connection()).save_category(&Category::new("Groceries", &[])).unwrap();
+
+    // An assignment that fails takes its transaction with it
+    db.get_connection().execute_batch(
+        "CREATE TRIGGER refuse BEFORE INSERT ON transaction_categories BEGIN SELECT RAISE(ABORT, 'refused'); END;"
+    ).unwrap();
+    assert!(import_beancount(db.get_connection(), path).is_err());
+    assert_eq!(count(db.get_connection(), "transactions"), 0);
+
+    db.get_connection().execute_batch("DROP TRIGGER refuse").unwrap();
+    let summary = import_beancount(db.get_connection(), path).unwrap();
+    assert_eq!(summary.inserted, 1);
+    assert_eq!(summary.skipped_reasons, vec!["line 5: only EUR amounts are supported".to_string()]);
+    let stored = TransactionDb::new(db.get_connection()).get_all_transactions().unwrap();
+    let assigned = CategoryDb::new(db.get_connection()).get_assigned_categories().unwrap();
+    let (category, source) = &assigned[&stored[0].id];
+    assert_eq!(category, "Groceries");
+    assert!(matches!(source, CategorySource::Import { .. }));
+}
@@ -0,0 +1,350 @@
+mod common;
+
+use std::str::FromStr;
+use finance_analyzer::db::{
+    batch::{BatchAction, BatchDb},
+    budget::BudgetDb,
+    category::{CategoryDb, MovedRows},
+    connection::DbConnection,
+    integrity::IntegrityDb,
+    journal::JournalDb,
+    ledger::LedgerDb,
+};
+use finance_analyzer::models::category::{Category, Rule, SourceKind};
+use rusqlite::Connection;
+use rust_decimal::Decimal;
+use common::{count, rule};
+
+// Groceries and Food overlap on JUMBO; transactions 1-3 exist, none assigned
+fn setup() -> DbConnection {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let mut category_db = CategoryDb::new(db.get_connection());
+    category_db.save_category(&Category::new("Groceries", &[("ALBERT HEIJN", 2), ("JUMBO", 1)])).unwrap();
+    category_db.save_category(&Category::new("Food", &[("JUMBO", 3), ("LIDL", 1)])).unwrap();
+    category_db.save_category(&Category::new("Transport", &[("NS GROEP", 1)])).unwrap();
+    db.get_connection()
+        .execute_batch(
+            "INSERT INTO transactions (id, date, amount, merchant, description, account) VALUES
+                (1, '2024-01-02', '-12.50', 'Albert Heijn', '', 'NL01'),
+                (2, '2024-01-03', '-8.00', 'Jumbo', '', 'NL01'),
+                (3, '2024-01-04', '-3.20', 'NS Groep', '', 'NL01');",
+        )
+        .unwrap();
+    db
+}
+
+fn assign(conn: &mut Connection, transaction_id: i64, name: &str) {
+    let mut category_db = CategoryDb::new(conn);
+    let category_id = category_db.get_category_id(name).unwrap().unwrap();
+    category_db.assign_category(transaction_id, category_id, SourceKind::Manual, None).unwrap();
+}
+
+fn assigned(conn: &mut Connection, transaction_id: u64) -> Option<String> {
+    CategoryDb::new(conn).get_assigned_categories().unwrap().remove(&transaction_id).map(|(name, _)| name)
+}
+
+fn names(conn: &mut Connection) -> Vec<String> {
+    let mut names: Vec<_> = CategoryDb::new(conn).get_all_categories().unwrap().into_iter().map(|c| c.name).collect();
+    names.sort();
+    names
+}
+
+fn patterns(conn: &mut Connection, name: &str) -> Vec<(String, u8)> {
+    let mut patterns: Vec<_> = CategoryDb::new(conn)
+        .get_category_by_name(name)
+        .unwrap()
+        .map(|c| c.rules.into_iter().map(|r| (r.pattern, r.priority)).collect())
+        .unwrap_or_default();
+    patterns.sort();
+    patterns
+}
+
+#[test]
+fn rename_carries_rules_assignments_budget_and_ledger_account() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    assign(conn, 1, "Groceries");
+    BudgetDb::new(conn).set_budget("Groceries", Decimal::from_str("300").unwrap()).unwrap();
+    LedgerDb::new(conn).set_account("Groceries", "Expenses:Groceries").unwrap();
+
+    assert!(CategoryDb::new(conn).rename_category("Groceries", "Supermarket").unwrap());
+
+    assert_eq!(names(conn), ["Food", "Supermarket", "Transport"]);
+    assert_eq!(patterns(conn, "Supermarket"), [("ALBERT HEIJN".to_string(), 2), ("JUMBO".to_string(), 1)]);
+    assert_eq!(assigned(conn, 1).as_deref(), Some("Supermarket"));
+    assert!(BudgetDb::new(conn).get_budgets().unwrap().contains_key("Supermarket"));
+    let accounts = LedgerDb::new(conn).get_accounts().unwrap();
+    assert_eq!(accounts.get("Supermarket").map(String::as_str), Some("Expenses:Groceries"));
+    assert!(!accounts.contains_key("Groceries"));
+}
+
+#[test]
+fn rename_to_an_existing_name_changes_nothing() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    LedgerDb::new(conn).set_account("Groceries", "Expenses:Groceries").unwrap();
+
+    let err = CategoryDb::new(conn).rename_category("Groceries", "Food").unwrap_err();
+
+    assert!(err.to_string().contains("already exists"), "{}", err);
+    assert_eq!(names(conn), ["Food", "Groceries", "Transport"]);
+    assert!(LedgerDb::new(conn).get_accounts().unwrap().contains_key("Groceries"));
+    assert!(!CategoryDb::new(conn).rename_category("Missing", "Other").unwrap());
+}
+
+#[test]
+fn delete_removes_rules_budget_and_assignments() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    assign(conn, 3, "Transport");
+    BudgetDb::new(conn).set_budget("Transport", Decimal::from_str("80").unwrap()).unwrap();
+    LedgerDb::new(conn).set_account("Transport", "Expenses:Transport").unwrap();
+
+    let moved = CategoryDb::new(conn).delete_category("Transport", None).unwrap();
+
+    assert_eq!(moved, Some(MovedRows::default()));
+    assert_eq!(names(conn), ["Food", "Groceries"]);
+    assert_eq!(count(conn, "category_rules"), 4);
+    assert_eq!(count(conn, "budgets"), 0);
+    assert_eq!(assigned(conn, 3), None);
+    assert!(LedgerDb::new(conn).get_accounts().unwrap().is_empty());
+    assert_eq!(CategoryDb::new(conn).delete_category("Transport", None).unwrap(), None);
+}
+
+// Edits journaled before the delete point at the category's rules and
+// assignments by id, so they must still undo once the delete is undone
+#[test]
+fn edits_before_a_delete_can_still_be_undone() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    let ns = CategoryDb::new(conn).get_rules().unwrap().into_iter().find(|(_, r)| r.pattern == "NS GROEP").unwrap().0;
+    let gvb = CategoryDb::new(conn).add_rule(&rule("Transport", "GVB", 1)).unwrap();
+    CategoryDb::new(conn).update_rule(&Rule { id: gvb, ..rule("Transport", "GVB", 2) }).unwrap();
+    CategoryDb::new(conn).remove_rule(ns).unwrap();
+    for category in ["Transport", "Food", "Transport"] {
+        BatchDb::new(conn).apply(&[3], &BatchAction::SetCategory(category.to_string())).unwrap();
+    }
+    BudgetDb::new(conn).set_budget("Transport", Decimal::from_str("80").unwrap()).unwrap();
+    LedgerDb::new(conn).set_account("Transport", "Expenses:Transport").unwrap();
+
+    CategoryDb::new(conn).delete_category("Transport", None).unwrap().unwrap();
+    assert_eq!(JournalDb::new(conn).undo().unwrap().as_deref(), Some("Deleted category Transport"));
+
+    assert_eq!(names(conn), ["Food", "Groceries", "Transport"]);
+    assert_eq!(patterns(conn, "Transport"), [("GVB".to_string(), 2)]);
+    assert_eq!(assigned(conn, 3).as_deref(), Some("Transport"));
+    assert!(BudgetDb::new(conn).get_budgets().unwrap().contains_key("Transport"));
+    assert!(LedgerDb::new(conn).get_accounts().unwrap().contains_key("Transport"));
+
+    while JournalDb::new(conn).undo().unwrap().is_some() {}
+    assert_eq!(patterns(conn, "Transport"), [("NS GROEP".to_string(), 1)]);
+    assert_eq!(assigned(conn, 3), None);
+
+    while JournalDb::new(conn).redo().unwrap().is_some() {}
+    assert_eq!(names(conn), ["Food", "Groceries"]);
+    assert_eq!(assigned(conn, 3).as_deref(), Some("Food"));
+    assert_eq!(count(conn, "category_rules"), 4);
+    assert_eq!(count(conn, "budgets"), 0);
+}
+
+#[test]
+fn delete_with_reassignment_keeps_the_newer_assignment() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    assign(conn, 1, "Groceries");
+    assign(conn, 2, "Food");
+    assign(conn, 2, "Groceries");
+    assign(conn, 3, "Food");
+    assign(conn, 3, "Transport");
+
+    let moved = CategoryDb::new(conn).delete_category("Groceries", Some("Food")).unwrap().unwrap();
+
+    assert_eq!(moved.assignments, 2);
+    assert_eq!(assigned(conn, 1).as_deref(), Some("Food"));
+    assert_eq!(assigned(conn, 2).as_deref(), Some("Food"));
+    assert_eq!(assigned(conn, 3).as_deref(), Some("Transport"));
+    assert_eq!(count(conn, "transaction_categories"), 4);
+
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    assert_eq!(names(conn), ["Food", "Groceries", "Transport"]);
+    assert_eq!(assigned(conn, 1).as_deref(), Some("Groceries"));
+    assert_eq!(assigned(conn, 2).as_deref(), Some("Groceries"));
+    assert_eq!(count(conn, "transaction_categories"), 5);
+}
+
+#[test]
+fn delete_with_reassignment_to_an_unknown_category_changes_nothing() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    assign(conn, 1, "Groceries");
+
+    assert!(CategoryDb::new(conn).delete_category("Groceries", Some("Missing")).is_err());
+    assert!(CategoryDb::new(conn).delete_category("Groceries", Some("Groceries")).is_err());
+
+    assert_eq!(names(conn), ["Food", "Groceries", "Transport"]);
+    assert_eq!(assigned(conn, 1).as_deref(), Some("Groceries"));
+}
+
+#[test]
+fn merge_moves_everything_the_target_lacks() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    assign(conn, 1, "Groceries");
+    assign(conn, 2, "Groceries");
+    assign(conn, 2, "Food");
+    BudgetDb::new(conn).set_budget("Groceries", Decimal::from_str("300").unwrap()).unwrap();
+    LedgerDb::new(conn).set_account("Groceries", "Expenses:Groceries").unwrap();
+
+    let moved = CategoryDb::new(conn).merge_categories("Groceries", "Food").unwrap();
+
+    assert_eq!(moved, MovedRows { rules: 1, assignments: 2, budget: true, ledger_account: true });
+    assert_eq!(names(conn), ["Food", "Transport"]);
+    // Food's own JUMBO rule wins over the one Groceries had
+    assert_eq!(
+        patterns(conn, "Food"),
+        [("ALBERT HEIJN".to_string(), 2), ("JUMBO".to_string(), 3), ("LIDL".to_string(), 1)]
+    );
+    assert_eq!(assigned(conn, 1).as_deref(), Some("Food"));
+    assert_eq!(assigned(conn, 2).as_deref(), Some("Food"));
+    assert_eq!(count(conn, "transaction_categories"), 2);
+    assert_eq!(BudgetDb::new(conn).get_budgets().unwrap().get("Food"), Some(&Decimal::from_str("300").unwrap()));
+    assert_eq!(LedgerDb::new(conn).get_accounts().unwrap().get("Food").map(String::as_str), Some("Expenses:Groceries"));
+
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    assert_eq!(names(conn), ["Food", "Groceries", "Transport"]);
+    assert_eq!(patterns(conn, "Groceries"), [("ALBERT HEIJN".to_string(), 2), ("JUMBO".to_string(), 1)]);
+    assert_eq!(patterns(conn, "Food"), [("JUMBO".to_string(), 3), ("LIDL".to_string(), 1)]);
+    assert_eq!(assigned(conn, 1).as_deref(), Some("Groceries"));
+    assert_eq!(assigned(conn, 2).as_deref(), Some("Food"));
+    assert_eq!(count(conn, "transaction_categories"), 3);
+    assert!(!BudgetDb::new(conn).get_budgets().unwrap().contains_key("Food"));
+    assert_eq!(LedgerDb::new(conn).get_accounts().unwrap().get("Groceries").map(String::as_str), Some("Expenses:Groceries"));
+
+    JournalDb::new(conn).redo().unwrap().unwrap();
+    assert_eq!(names(conn), ["Food", "Transport"]);
+    assert_eq!(count(conn, "transaction_categories"), 2);
+    assert_eq!(BudgetDb::new(conn).get_budgets().unwrap().get("Food"), Some(&Decimal::from_str("300").unwrap()));
+}
+
+#[test]
+fn merge_keeps_the_targets_budget_and_ledger_account() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    BudgetDb::new(conn).set_budget("Groceries", Decimal::from_str("300").unwrap()).unwrap();
+    BudgetDb::new(conn).set_budget("Food", Decimal::from_str("450").unwrap()).unwrap();
+    LedgerDb::new(conn).set_account("Groceries", "Expenses:Groceries").unwrap();
+    LedgerDb::new(conn).set_account("Food", "Expenses:Food").unwrap();
+
+    let moved = CategoryDb::new(conn).merge_categories("Groceries", "Food").unwrap();
+
+    assert!(!moved.budget && !moved.ledger_account);
+    let budgets = BudgetDb::new(conn).get_budgets().unwrap();
+    assert_eq!(budgets.len(), 1);
+    assert_eq!(budgets.get("Food"), Some(&Decimal::from_str("450").unwrap()));
+    let accounts = LedgerDb::new(conn).get_accounts().unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts.get("Food").map(String::as_str), Some("Expenses:Food"));
+}
+
+#[test]
+fn merge_with_an_unknown_category_changes_nothing() {
+    let mut db = setup();
+    let conn = db.get_connection();
+
+    assert!(CategoryDb::new(conn).merge_categories("Groceries", "Missing").is_err());
+    assert!(CategoryDb::new(conn).merge_categories("Missing", "Food").is_err());
+    assert!(CategoryDb::new(conn).merge_categories("Food", "Food").is_err());
+
+    assert_eq!(names(conn), ["Food", "Groceries", "Transport"]);
+    assert_eq!(count(conn, "category_rules"), 5);
+}
+
+#[test]
+fn upsert_inserts_then_brings_rules_in_line() {
+    let mut db = setup();
+    let conn = db.get_connection();
+
+    let id = CategoryDb::new(conn).upsert_category(&Category::new("Housing", &[("RENT", 1)])).unwrap();
+    assert_eq!(patterns(conn, "Housing"), [("RENT".to_string(), 1)]);
+
+    let jumbo = CategoryDb::new(conn).get_category_by_name("Groceries").unwrap().unwrap()
+        .rules.into_iter().find(|r| r.pattern == "JUMBO").unwrap().id;
+    let again = CategoryDb::new(conn)
+        .upsert_category(&Category::new("Groceries", &[("JUMBO", 5), ("PLUS", 1)]))
+        .unwrap();
+
+    assert_ne!(id, again);
+    assert_eq!(Some(again), CategoryDb::new(conn).get_category_id("Groceries").unwrap());
+    assert_eq!(patterns(conn, "Groceries"), [("JUMBO".to_string(), 5), ("PLUS".to_string(), 1)]);
+    let kept = CategoryDb::new(conn).get_category_by_name("Groceries").unwrap().unwrap()
+        .rules.into_iter().find(|r| r.pattern == "JUMBO").unwrap().id;
+    assert_eq!(kept, jumbo);
+}
+
+#[test]
+fn update_category_renames_and_replaces_rules_atomically() {
+    let mut db = setup();
+    let conn = db.get_connection();
+
+    assert!(CategoryDb::new(conn).update_category("Transport", &Category::new("Travel", &[("KLM", 2)])).unwrap());
+    assert_eq!(names(conn), ["Food", "Groceries", "Travel"]);
+    assert_eq!(patterns(conn, "Travel"), [("KLM".to_string(), 2)]);
+
+    // The rename fails, so the rule changes are rolled back with it
+    assert!(CategoryDb::new(conn).update_category("Travel", &Category::new("Food", &[])).is_err());
+    assert_eq!(patterns(conn, "Travel"), [("KLM".to_string(), 2)]);
+    assert!(!CategoryDb::new(conn).update_category("Missing", &Category::new("Missing", &[])).unwrap());
+}
+
+#[test]
+fn rules_can_be_added_updated_and_removed_and_updates_undone() {
+    let mut db = setup();
+    let conn = db.get_connection();
+
+    let id = CategoryDb::new(conn).add_rule(&rule("Transport", "GVB", 1)).unwrap();
+    let updated = Rule { id, pattern: "GVB AMSTERDAM".to_string(), category: "Food".to_string(), priority: 4 };
+    assert!(CategoryDb::new(conn).update_rule(&updated).unwrap());
+    assert!(patterns(conn, "Food").contains(&("GVB AMSTERDAM".to_string(), 4)));
+    assert!(!patterns(conn, "Transport").iter().any(|(p, _)| p.starts_with("GVB")));
+
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    assert!(patterns(conn, "Transport").contains(&("GVB".to_string(), 1)));
+    JournalDb::new(conn).redo().unwrap().unwrap();
+    assert!(patterns(conn, "Food").contains(&("GVB AMSTERDAM".to_string(), 4)));
+
+    assert!(CategoryDb::new(conn).remove_rule(id).unwrap());
+    assert!(!CategoryDb::new(conn).update_rule(&updated).unwrap());
+    assert!(!CategoryDb::new(conn).remove_rule(id).unwrap());
+}
+
+#[test]
+fn update_rule_to_an_unknown_category_changes_nothing() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    let id = CategoryDb::new(conn).add_rule(&rule("Transport", "GVB", 1)).unwrap();
+    let entries = count(conn, "journal");
+
+    assert!(CategoryDb::new(conn).update_rule(&Rule { id, ..rule("Missing", "GVB", 1) }).is_err());
+
+    assert!(patterns(conn, "Transport").contains(&("GVB".to_string(), 1)));
+    assert_eq!(count(conn, "journal"), entries);
+}
+
+#[test]
+fn assignments_to_missing_transactions_are_found_and_repaired() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    conn.execute_batch(
+        "PRAGMA foreign_keys = OFF;
+         INSERT INTO transaction_categories (transaction_id, category_id) VALUES (1, 1), (42, 1);
+         PRAGMA foreign_keys = ON;",
+    ).unwrap();
+
+    let problems = IntegrityDb::new(conn).check().unwrap();
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].table, "transaction_categories");
+    assert!(problems[0].description.contains("transactions"), "{}", problems[0].description);
+    assert_eq!(IntegrityDb::new(conn).repair(&problems).unwrap(), 1);
+    assert_eq!(count(conn, "transaction_categories"), 1);
+    assert!(IntegrityDb::new(conn).check().unwrap().is_empty());
+}
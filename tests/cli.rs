@@ -0,0 +1,36 @@
+mod common;
+
+use clap::Parser;
+use finance_analyzer::cli::{commands, Cli};
+use finance_analyzer::config::{opens, Config, Profile};
+use finance_analyzer::db::{connection::DbConnection, transaction::TransactionDb};
+use common::{date, scratch_dir, transaction};
+
+// The open count is process-wide, so this file holds a single test
+#[test]
+fn commands_open_the_profile_once() {
+    let dir = scratch_dir("cli-opens");
+    let path = dir.join("finance.db");
+    let mut db = DbConnection::new(&path).unwrap();
+    TransactionDb::new(db.get_connection())
+        .save_transactions(&[transaction(date(2024, 3, 1), "-4.50", "Bakery")])
+        .unwrap();
+    drop(db);
+    let profile = Profile { name: "test".to_string(), db_path: path, lock_after: None };
+    let output = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+    let commands: Vec<Vec<String>> = vec![
+        vec!["report".into()],
+        vec!["report".into(), "monthly".into(), "--output".into(), output("report.html")],
+        vec!["categorize".into()],
+        vec!["export".into(), "--output".into(), output("export.csv")],
+        vec!["ledger".into(), "export".into(), "--output".into(), output("journal.beancount")],
+        vec!["ledger".into(), "accounts".into()],
+    ];
+    for args in commands {
+        let cli = Cli::try_parse_from(std::iter::once("finance-analyzer".to_string()).chain(args.clone())).unwrap();
+        let before = opens();
+        commands::run(cli.command.unwrap(), &Config::default(), &profile).unwrap();
+        assert_eq!(opens() - before, 1, "{}", args.join(" "));
+    }
+}
@@ -0,0 +1,72 @@
+// Fixtures shared by the integration tests and benches; each one uses a
+// different subset
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use chrono::NaiveDate;
+use finance_analyzer::models::category::Rule;
+use finance_analyzer::models::transaction::Transaction;
+use rusqlite::Connection;
+use rust_decimal::Decimal;
+
+pub const ING_HEADER: &str = "\"Date\";\"Name / Description\";\"Account\";\"Counterparty\";\"Code\";\"Debit/credit\";\"Amount (EUR)\";\"Transaction type\";\"Notifications\";\"Resulting balance\";\"Tag\"\n";
+
+pub fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+// An unsaved transaction on account NL01, as a reader would produce it
+pub fn transaction(date: NaiveDate, amount: &str, merchant: &str) -> Transaction {
+    Transaction {
+        id: 0,
+        date: date.and_hms_opt(0, 0, 0).unwrap(),
+        amount: Decimal::from_str(amount).unwrap(),
+        merchant: merchant.to_string(),
+        raw_merchant: merchant.to_string(),
+        description: String::new(),
+        category: None,
+        category_source: None,
+        account: "NL01".to_string(),
+        balance: None,
+        tags: Vec::new(),
+        flags: Vec::new(),
+        excluded: false,
+        splits: Vec::new(),
+    }
+}
+
+pub fn rule(category: &str, pattern: &str, priority: u8) -> Rule {
+    Rule { id: 0, pattern: pattern.to_string(), category: category.to_string(), priority }
+}
+
+// An ING export of `rows` debits cycling through `merchants` over ten
+// years and three accounts. Every row differs in its balance, so none of
+// them count as duplicates.
+pub fn statement(merchants: &[&str], rows: usize) -> String {
+    let mut csv = ING_HEADER.to_string();
+    for i in 0..rows {
+        csv.push_str(&format!(
+            "\"{}{:02}{:02}\";\"{}\";\"NL0{}INGB0001234567\";\"\";\"BA\";\"Debit\";\"{},{:02}\";\"Payment terminal\";\"\";\"{},00\";\"\"\n",
+            2015 + i % 10, 1 + i % 12, 1 + i % 28,
+            merchants[i % merchants.len()],
+            i % 3,
+            i % 200, i % 100,
+            i
+        ));
+    }
+    csv
+}
+
+// A fresh directory per test, so tests can run in parallel
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("finance-analyzer-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn count(conn: &Connection, table: &str) -> i64 {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+}
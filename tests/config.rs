@@ -0,0 +1,118 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::Duration;
+use finance_analyzer::config::{expand, Config};
+use common::scratch_dir;
+
+// dirs reads $HOME and $XDG_DATA_HOME, and ./finance.db is looked up in the
+// working directory. They are set once, before any test reads them.
+fn home() -> PathBuf {
+    static SETUP: Once = Once::new();
+    let home = std::env::temp_dir().join(format!("finance-analyzer-config-{}", std::process::id()));
+    SETUP.call_once(|| {
+        let dir = scratch_dir("config");
+        assert_eq!(dir, home);
+        unsafe {
+            std::env::set_var("HOME", &dir);
+            std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+        }
+        std::env::set_current_dir(&dir).unwrap();
+    });
+    home
+}
+
+fn data_dir() -> PathBuf {
+    home().join("data").join("finance-analyzer")
+}
+
+fn config(name: &str, text: &str) -> Config {
+    let path = scratch_dir(name).join("config.toml");
+    std::fs::write(&path, text).unwrap();
+    Config::load_from(&path).unwrap()
+}
+
+#[test]
+fn profile_paths_are_relative_to_the_data_directory_or_home() {
+    let home = home();
+
+    assert_eq!(expand(Path::new("~/books/household.db")).unwrap(), home.join("books/household.db"));
+    assert_eq!(expand(Path::new("business.db")).unwrap(), data_dir().join("business.db"));
+    assert_eq!(expand(Path::new("/srv/books.db")).unwrap(), PathBuf::from("/srv/books.db"));
+}
+
+#[test]
+fn profiles_are_picked_from_the_flag_or_the_config() {
+    home();
+    let config = config(
+        "profiles",
+        r#"
+        default_profile = "household"
+        lock_after_minutes = 10
+
+        [profiles.household]
+        db = "~/books/household.db"
+
+        [profiles.business]
+        lock_after_minutes = 0
+        "#,
+    );
+
+    let household = config.resolve(None, None).unwrap();
+    assert_eq!(household.name, "household");
+    assert_eq!(household.db_path, home().join("books/household.db"));
+    assert_eq!(household.lock_after, Some(Duration::from_secs(600)));
+
+    let business = config.resolve(None, Some("business")).unwrap();
+    assert_eq!(business.db_path, data_dir().join("business.db"));
+    assert_eq!(business.lock_after, None);
+
+    let err = config.resolve(None, Some("busines")).unwrap_err().to_string();
+    assert!(err.contains("Unknown profile 'busines'"), "{}", err);
+}
+
+#[test]
+fn db_flag_wins_and_names_the_profile_after_the_file() {
+    home();
+    let config = config("db-flag", "[profiles.archive]\nlock_after_minutes = 1\n");
+
+    let profile = config.resolve(Some(Path::new("/tmp/archive.db")), None).unwrap();
+    assert_eq!(profile.name, "archive");
+    assert_eq!(profile.db_path, PathBuf::from("/tmp/archive.db"));
+    assert_eq!(profile.lock_after, Some(Duration::from_secs(60)));
+
+    let profile = config.resolve(Some(Path::new("/tmp/archive.db")), Some("old")).unwrap();
+    assert_eq!((profile.name.as_str(), profile.lock_after), ("old", Some(Duration::from_secs(900))));
+}
+
+// The only test that touches ./finance.db
+#[test]
+fn legacy_database_is_used_until_the_data_directory_has_one() {
+    let legacy = home().join("finance.db");
+    let config = Config::default();
+
+    let profile = config.resolve(None, None).unwrap();
+    assert_eq!(profile.db_path, data_dir().join("finance.db"));
+    assert!(!profile.is_legacy());
+
+    std::fs::write(&legacy, "").unwrap();
+    let profile = config.resolve(None, None).unwrap();
+    assert!(profile.is_legacy());
+
+    std::fs::create_dir_all(data_dir()).unwrap();
+    std::fs::write(data_dir().join("finance.db"), "").unwrap();
+    let err = config.resolve(None, None).unwrap_err().to_string();
+    assert!(err.contains("Move or delete one of them"), "{}", err);
+
+    let profile = config.resolve(Some(&legacy), None).unwrap();
+    assert_eq!(profile.db_path, legacy);
+}
+
+#[test]
+fn unknown_config_keys_are_rejected() {
+    let path = scratch_dir("config-typo").join("config.toml");
+    std::fs::write(&path, "default_profil = \"household\"\n").unwrap();
+
+    assert!(Config::load_from(&path).is_err());
+}
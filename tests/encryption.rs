@@ -0,0 +1,80 @@
+mod common;
+
+use std::path::Path;
+use finance_analyzer::db::connection::{is_encrypted, DbConnection};
+use finance_analyzer::db::transaction::TransactionDb;
+use common::{count, date, scratch_dir, transaction};
+
+fn merchants(db: &mut DbConnection) -> Vec<String> {
+    TransactionDb::new(db.get_connection())
+        .get_all_transactions()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.merchant)
+        .collect()
+}
+
+fn unlock(path: &Path, passphrase: &str) -> Option<DbConnection> {
+    DbConnection::unlock(path, passphrase).unwrap()
+}
+
+#[test]
+fn encrypting_and_decrypting_keeps_the_data() {
+    let path = scratch_dir("encryption-round-trip").join("finance.db");
+    let mut db = DbConnection::new(&path).unwrap();
+    TransactionDb::new(db.get_connection())
+        .save_transactions(&[transaction(date(2024, 1, 2), "-12.50", "Albert Heijn")])
+        .unwrap();
+    assert!(!is_encrypted(&path).unwrap());
+
+    db.rewrite(Some("correct horse")).unwrap();
+    assert!(is_encrypted(&path).unwrap());
+    assert!(!path.with_file_name("finance.db.rewrite").exists());
+    let error = DbConnection::open(&path).unwrap_err().to_string();
+    assert!(error.contains("is encrypted"), "{}", error);
+
+    let mut db = unlock(&path, "correct horse").unwrap();
+    assert!(db.is_encrypted());
+    assert_eq!(merchants(&mut db), ["Albert Heijn"]);
+    assert!(db.pending_migrations().unwrap().is_empty());
+
+    db.rewrite(None).unwrap();
+    assert!(!is_encrypted(&path).unwrap());
+    let mut db = DbConnection::open(&path).unwrap();
+    assert_eq!(merchants(&mut db), ["Albert Heijn"]);
+    assert_eq!(count(db.get_connection(), "journal"), 0);
+}
+
+#[test]
+fn a_wrong_passphrase_is_rejected_without_touching_the_file() {
+    let path = scratch_dir("encryption-wrong-passphrase").join("finance.db");
+    DbConnection::new(&path).unwrap().rewrite(Some("correct horse")).unwrap();
+    let before = std::fs::read(&path).unwrap();
+
+    assert!(unlock(&path, "battery staple").is_none());
+    assert!(unlock(&path, "").is_none());
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+    assert!(unlock(&path, "correct horse").is_some());
+}
+
+#[test]
+fn changing_the_passphrase_retires_the_old_one() {
+    let path = scratch_dir("encryption-change-passphrase").join("finance.db");
+    DbConnection::new(&path).unwrap().rewrite(Some("correct horse")).unwrap();
+
+    unlock(&path, "correct horse").unwrap().change_passphrase("battery staple").unwrap();
+
+    assert!(unlock(&path, "correct horse").is_none());
+    assert!(unlock(&path, "battery staple").is_some());
+}
+
+#[test]
+fn a_plain_database_has_no_passphrase_to_change() {
+    let path = scratch_dir("encryption-plain").join("finance.db");
+    let mut db = DbConnection::new(&path).unwrap();
+
+    let error = db.change_passphrase("correct horse").unwrap_err().to_string();
+    assert!(error.contains("not encrypted"), "{}", error);
+    let error = DbConnection::new(":memory:").unwrap().rewrite(Some("correct horse")).unwrap_err().to_string();
+    assert!(error.contains("in-memory"), "{}", error);
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::str::FromStr;
+use finance_analyzer::export::{summarize, write_summary, write_transactions, ExportFormat};
+use finance_analyzer::models::transaction::{Split, Transaction};
+use rust_decimal::Decimal;
+use common::{date, transaction};
+
+// Groceries split with Household, and an uncategorized train ticket
+fn book() -> Vec<Transaction> {
+    let mut groceries = transaction(date(2024, 1, 2), "-42.10", "Albert Heijn");
+    groceries.id = 1;
+    groceries.category = Some("Groceries".to_string());
+    groceries.splits = vec![
+        Split { category: "Groceries".to_string(), amount: Decimal::from_str("-30.00").unwrap() },
+        Split { category: "Household".to_string(), amount: Decimal::from_str("-12.10").unwrap() },
+    ];
+    let mut train = transaction(date(2024, 1, 3), "-3.20", "NS Groep");
+    train.id = 2;
+    vec![groceries, train]
+}
+
+fn transactions(format: ExportFormat) -> String {
+    let book = book();
+    let mut out = Vec::new();
+    write_transactions(format, &book.iter().collect::<Vec<_>>(), &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn the_extension_picks_the_format() {
+    let formats = [
+        ("out.csv", Some(ExportFormat::Csv)),
+        ("out.JSON", Some(ExportFormat::Json)),
+        ("out.jsonl", Some(ExportFormat::JsonLines)),
+        ("out.ndjson", Some(ExportFormat::JsonLines)),
+        ("out.md", Some(ExportFormat::Markdown)),
+        ("out.txt", None),
+    ];
+    for (path, format) in formats {
+        assert_eq!(ExportFormat::from_path(path), format, "{}", path);
+    }
+}
+
+#[test]
+fn csv_lists_the_split_parts() {
+    let csv = transactions(ExportFormat::Csv);
+    let lines: Vec<&str> = csv.lines().collect();
+
+    assert_eq!(lines[0], "id,date,amount,merchant,category,splits,tags,account,description");
+    assert_eq!(lines[1], "1,2024-01-02,-42.10,Albert Heijn,Groceries,Groceries=-30.00; Household=-12.10,,NL01,");
+    assert_eq!(lines[2], "2,2024-01-03,-3.20,NS Groep,Uncategorized,,,NL01,");
+}
+
+#[test]
+fn json_is_one_array_and_json_lines_one_object_per_line() {
+    let json: serde_json::Value = serde_json::from_str(&transactions(ExportFormat::Json)).unwrap();
+    let records = json.as_array().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["splits"][1]["category"], "Household");
+    assert_eq!(records[0]["splits"][1]["amount"], "-12.10");
+    assert_eq!(records[1]["splits"], serde_json::json!([]));
+
+    let lines = transactions(ExportFormat::JsonLines);
+    let records: Vec<serde_json::Value> = lines.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["splits"][0]["category"], "Groceries");
+}
+
+#[test]
+fn markdown_rows_have_a_cell_per_column() {
+    let markdown = transactions(ExportFormat::Markdown);
+
+    for line in markdown.lines() {
+        assert_eq!(line.matches(" | ").count(), 8, "{}", line);
+    }
+}
+
+#[test]
+fn the_summary_counts_split_parts_under_their_own_category() {
+    let book = book();
+    let rows = summarize(&book.iter().collect::<Vec<_>>());
+    let rows: Vec<(&str, usize, String)> = rows.iter().map(|r| (r.category.as_str(), r.transactions, r.total.to_string())).collect();
+
+    assert_eq!(rows, [
+        ("Groceries", 1, "-30.00".to_string()),
+        ("Household", 1, "-12.10".to_string()),
+        ("Uncategorized", 1, "-3.20".to_string()),
+    ]);
+
+    let mut out = Vec::new();
+    write_summary(ExportFormat::Json, &summarize(&book.iter().collect::<Vec<_>>()), &mut out).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 3);
+}
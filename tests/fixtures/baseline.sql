@@ -0,0 +1,30 @@
+-- Schema from the first release: categories and rules only, transactions
+-- were read from CSV on every start
+CREATE TABLE categories (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE category_rules (
+    id INTEGER PRIMARY KEY,
+    category_id INTEGER NOT NULL,
+    pattern TEXT NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 1,
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+CREATE TABLE transaction_categories (
+    id INTEGER PRIMARY KEY,
+    transaction_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+
+INSERT INTO categories (id, name) VALUES (1, 'Groceries'), (2, 'Utilities'), (3, 'Dining');
+INSERT INTO category_rules (category_id, pattern, priority) VALUES
+    (1, 'Albert Heijn', 1),
+    (1, 'Picnic', 1),
+    (2, 'ESSENT', 1),
+    (3, 'Bakkerij', 2);
+INSERT INTO transaction_categories (transaction_id, category_id, assigned_at) VALUES
+    (7, 3, '2024-02-01 10:00:00');
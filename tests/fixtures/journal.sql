@@ -0,0 +1,108 @@
+-- Schema just before category sources were recorded: everything except the
+-- source and confidence columns, and no schema_version table
+CREATE TABLE transactions (
+    id INTEGER PRIMARY KEY,
+    date TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    merchant TEXT NOT NULL,
+    description TEXT NOT NULL,
+    account TEXT NOT NULL,
+    balance TEXT NOT NULL DEFAULT '',
+    imported_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(date, amount, merchant, description, account, balance)
+);
+CREATE TABLE transaction_tags (
+    id INTEGER PRIMARY KEY,
+    transaction_id INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    UNIQUE(transaction_id, tag),
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+);
+CREATE TABLE excluded_transactions (
+    transaction_id INTEGER PRIMARY KEY,
+    excluded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+);
+CREATE TABLE categories (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE category_rules (
+    id INTEGER PRIMARY KEY,
+    category_id INTEGER NOT NULL,
+    pattern TEXT NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 1,
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+CREATE TABLE transaction_categories (
+    id INTEGER PRIMARY KEY,
+    transaction_id INTEGER NOT NULL,
+    category_id INTEGER NOT NULL,
+    assigned_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+CREATE TABLE transaction_flags (
+    id INTEGER PRIMARY KEY,
+    transaction_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    flagged_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at DATETIME,
+    UNIQUE(transaction_id, kind)
+);
+CREATE TABLE merchant_aliases (
+    id INTEGER PRIMARY KEY,
+    pattern TEXT NOT NULL UNIQUE,
+    canonical TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE budgets (
+    id INTEGER PRIMARY KEY,
+    category_id INTEGER NOT NULL UNIQUE,
+    amount TEXT NOT NULL,
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+CREATE TABLE ledger_accounts (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    account TEXT NOT NULL
+);
+CREATE TABLE saved_filters (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    query TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE journal (
+    id INTEGER PRIMARY KEY,
+    description TEXT NOT NULL,
+    changes TEXT NOT NULL,
+    undone INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO categories (id, name) VALUES (1, 'Groceries'), (2, 'Utilities'), (3, 'Dining');
+INSERT INTO category_rules (category_id, pattern, priority) VALUES
+    (1, 'Albert Heijn', 1),
+    (2, 'ESSENT', 1);
+INSERT INTO transactions (id, date, amount, merchant, description, account, balance) VALUES
+    (1, '2024-01-03 00:00:00', '-42.10', 'Albert Heijn 1234', 'Pasvolgnr: 001', 'NL11INGB0001234567', '1957.90'),
+    (2, '2024-01-05 00:00:00', '-89.00', 'ESSENT RETAIL ENERGIE', 'Termijnbedrag', 'NL11INGB0001234567', '1868.90'),
+    (3, '2024-01-06 00:00:00', '-12.50', 'Bakkerij Vink', 'Pasvolgnr: 001', 'NL11INGB0001234567', '1856.40');
+INSERT INTO transaction_tags (transaction_id, tag) VALUES (3, 'weekend');
+INSERT INTO excluded_transactions (transaction_id) VALUES (2);
+INSERT INTO transaction_categories (id, transaction_id, category_id, assigned_at) VALUES
+    (1, 3, 3, '2024-01-07 09:30:00');
+INSERT INTO budgets (category_id, amount) VALUES (1, '400');
+INSERT INTO saved_filters (name, query) VALUES ('big', 'amount<-50');
+INSERT INTO journal (description, changes) VALUES (
+    'Moved 1 transaction to Dining',
+    '[{"CategoryAssigned":{"transaction_id":3,"assignment":{"id":1,"category_id":3,"assigned_at":"2024-01-07 09:30:00"},"category":"Dining","previous":null}}]'
+);
@@ -0,0 +1,82 @@
+mod common;
+
+use std::str::FromStr;
+use chrono::{Duration, NaiveDate};
+use finance_analyzer::analysis::forecast::{forecast_balances, write_forecast_csv, AccountForecast, ForecastConfig};
+use finance_analyzer::models::transaction::Transaction;
+use rust_decimal::Decimal;
+use common::{date, transaction};
+
+fn decimal(value: &str) -> Decimal {
+    Decimal::from_str(value).unwrap()
+}
+
+fn on(id: u64, account: &str, date: NaiveDate, amount: &str, merchant: &str) -> Transaction {
+    let mut t = transaction(date, amount, merchant);
+    t.id = id;
+    t.account = account.to_string();
+    t
+}
+
+fn daily_spend(forecast: &AccountForecast) -> Decimal {
+    forecast.daily_variable_spending.values().sum::<Decimal>().round_dp(2)
+}
+
+#[test]
+fn a_new_account_averages_over_the_days_it_has() {
+    let as_of = date(2024, 6, 30);
+    let mut book: Vec<Transaction> = (0..10)
+        .map(|day| on(day + 1, "NEW", as_of - Duration::days(day as i64), "-10.00", "Albert Heijn"))
+        .collect();
+    // 90 days of history, plus one purchase from before the window
+    book.push(on(20, "OLD", as_of - Duration::days(200), "-500.00", "Ikea"));
+    book.push(on(21, "OLD", as_of - Duration::days(60), "-90.00", "Jumbo"));
+    book.push(on(22, "OLD", as_of - Duration::days(5), "-90.00", "Hema"));
+
+    let forecasts = forecast_balances(&book, &ForecastConfig::default());
+
+    assert_eq!(forecasts.iter().map(|f| f.account.as_str()).collect::<Vec<_>>(), ["NEW", "OLD"]);
+    assert_eq!(daily_spend(&forecasts[0]), decimal("-10"));
+    assert_eq!(daily_spend(&forecasts[1]), decimal("-2"));
+}
+
+#[test]
+fn recurring_items_are_scheduled_instead_of_averaged() {
+    let mut book: Vec<Transaction> = (0..3)
+        .map(|month| on(month + 1, "NL01", date(2024, 1 + month as u32, 1), "-950.00", "Woonbron"))
+        .collect();
+    let mut groceries = on(10, "NL01", date(2024, 3, 31), "-30.00", "Albert Heijn");
+    groceries.balance = Some(decimal("1500.00"));
+    book.push(groceries);
+    let config = ForecastConfig { low_balance_threshold: decimal("1000"), ..ForecastConfig::default() };
+
+    let forecast = &forecast_balances(&book, &config)[0];
+
+    assert_eq!(forecast.recurring.len(), 1);
+    assert_eq!(forecast.starting_balance, decimal("1500.00"));
+    // 30.00 over the 90-day window
+    assert_eq!(daily_spend(forecast), decimal("-0.33"));
+    assert_eq!(forecast.points[0].date, date(2024, 4, 1));
+    assert_eq!(forecast.points[0].balance, decimal("549.67"));
+    let warning = forecast.low_balance_warning(config.low_balance_threshold).unwrap();
+    assert_eq!(warning.date, date(2024, 4, 1));
+
+    let mut csv = Vec::new();
+    write_forecast_csv(std::slice::from_ref(forecast), config.low_balance_threshold, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "account,date,balance,below_threshold");
+    assert_eq!(lines[1], "NL01,2024-04-01,549.67,yes");
+    assert_eq!(lines.len(), 31);
+}
+
+#[test]
+fn the_horizon_cycles_through_30_60_and_90_days() {
+    let mut config = ForecastConfig::default();
+    let mut horizons = vec![config.horizon_days];
+    for _ in 0..3 {
+        config.next_horizon();
+        horizons.push(config.horizon_days);
+    }
+    assert_eq!(horizons, [30, 60, 90, 30]);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::io::Cursor;
+use finance_analyzer::db::{
+    category::CategoryDb,
+    connection::DbConnection,
+    merchant::MerchantDb,
+    transaction::TransactionDb,
+};
+use finance_analyzer::import::{ImportPipeline, ImportSummary, IMPORT_BATCH};
+use finance_analyzer::models::category::Category;
+use finance_analyzer::models::merchant::MerchantAlias;
+use finance_analyzer::utils::csv::CsvTransactions;
+use common::{statement, ING_HEADER};
+
+fn pipeline(db: &mut DbConnection) -> ImportPipeline {
+    let conn = db.get_connection();
+    CategoryDb::new(conn).save_category(&Category::new("Bakery", &[("ZETA", 1)])).unwrap();
+    MerchantDb::new(conn).save_alias(&MerchantAlias::new("ZETA BAKERY 12", "Zeta")).unwrap();
+    ImportPipeline::load(conn).unwrap()
+}
+
+#[test]
+fn rows_are_read_lazily_with_their_line_numbers() {
+    let csv = format!("{}\"20240301\";\"Zeta\";\"NL01\";\"\";\"BA\";\"Credit\";\"4,50\";\"\";\"lunch\";\"10,00\";\"#work\"\n\"2024-03-02\";\"Zeta\";\"NL01\";\"\";\"BA\";\"Debit\";\"1,00\";\"\";\"\";\"\";\"\"\n", ING_HEADER);
+    let mut rows = CsvTransactions::new(Cursor::new(csv.into_bytes()));
+
+    let first = rows.next().unwrap().unwrap();
+    assert_eq!(first.amount.to_string(), "4.50");
+    assert_eq!(first.description, "lunch");
+    assert_eq!(first.tags, ["work"]);
+    assert!(rows.position() < 250);
+
+    let error = rows.next().unwrap().unwrap_err();
+    assert!(format!("{:#}", error).contains("line 3"), "{:#}", error);
+    assert!(rows.next().is_none());
+}
+
+#[test]
+fn batches_are_deduplicated_and_unmatched_rows_counted() {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let pipeline = pipeline(&mut db);
+    let csv = statement(&["ZETA BAKERY 12", "Corner Shop"], IMPORT_BATCH * 2 + 10);
+
+    let mut summary = ImportSummary::default();
+    pipeline.import(db.get_connection(), CsvTransactions::new(csv.as_bytes()), &mut summary).unwrap();
+    assert_eq!((summary.read, summary.inserted, summary.uncategorized), (2010, 2010, 1005));
+
+    let mut again = ImportSummary::default();
+    pipeline.import(db.get_connection(), CsvTransactions::new(csv.as_bytes()), &mut again).unwrap();
+    assert_eq!((again.read, again.inserted, again.duplicates()), (2010, 0, 2010));
+
+    let batch: Vec<_> = CsvTransactions::new(csv.as_bytes()).take(2).map(Result::unwrap).collect();
+    let rules = pipeline.rules();
+    assert_eq!(pipeline.uncategorized(&rules, &batch), 1);
+    assert_eq!(pipeline.uncategorized(&rules, &batch[..1]), 0);
+}
+
+#[test]
+fn batches_stored_before_a_bad_row_are_kept() {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let pipeline = pipeline(&mut db);
+    let mut csv = statement(&["Corner Shop"], IMPORT_BATCH + 5);
+    csv.push_str("\"20240301\";\"Corner Shop\";\"NL01\";\"\";\"BA\";\"Debit\";\"lots\";\"\";\"\";\"\";\"\"\n");
+
+    let mut summary = ImportSummary::default();
+    let result = pipeline.import(db.get_connection(), CsvTransactions::new(csv.as_bytes()), &mut summary);
+    assert!(result.is_err());
+    assert_eq!(summary.inserted, IMPORT_BATCH);
+    assert_eq!(TransactionDb::new(db.get_connection()).count().unwrap(), IMPORT_BATCH);
+}
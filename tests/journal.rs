@@ -0,0 +1,158 @@
+mod common;
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use finance_analyzer::db::{
+    batch::{BatchAction, BatchDb},
+    budget::BudgetDb,
+    category::CategoryDb,
+    connection::DbConnection,
+    filter::FilterDb,
+    flag::FlagDb,
+    journal::JournalDb,
+    merchant::MerchantDb,
+    settings::SettingsDb,
+    transaction::TransactionDb,
+};
+use finance_analyzer::models::category::Category;
+use finance_analyzer::models::flag::{FlagKind, FlagStatus, TransactionFlag};
+use finance_analyzer::models::merchant::MerchantAlias;
+use finance_analyzer::models::transaction::Transaction;
+use finance_analyzer::repository::Repositories;
+use rusqlite::Connection;
+use rust_decimal::Decimal;
+use common::{count, date, transaction};
+
+fn statement() -> Vec<Transaction> {
+    vec![
+        transaction(date(2024, 1, 2), "-12.50", "Albert Heijn"),
+        transaction(date(2024, 1, 3), "-8.00", "Jumbo"),
+        transaction(date(2024, 1, 4), "-3.20", "NS Groep"),
+    ]
+}
+
+// Transactions 1-3 and a Groceries category; the journal starts empty
+fn setup() -> DbConnection {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let conn = db.get_connection();
+    TransactionDb::new(conn).save_transactions(&statement()).unwrap();
+    CategoryDb::new(conn).save_category(&Category::new("Groceries", &[("ALBERT HEIJN", 1)])).unwrap();
+    db
+}
+
+fn merchants(conn: &Connection) -> Vec<(i64, String)> {
+    let mut stmt = conn.prepare("SELECT id, merchant FROM transactions ORDER BY id").unwrap();
+    stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().collect::<rusqlite::Result<_>>().unwrap()
+}
+
+fn delete(conn: &mut Connection, id: u64) {
+    BatchDb::new(conn).apply(&[id], &BatchAction::Delete).unwrap();
+}
+
+#[test]
+fn undoing_a_delete_restores_tags_and_redo_deletes_again() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    BatchDb::new(conn).apply(&[2], &BatchAction::AddTags(vec!["shared".to_string()])).unwrap();
+    delete(conn, 2);
+
+    assert_eq!(JournalDb::new(conn).undo().unwrap().as_deref(), Some("Deleted 1 transaction"));
+    assert_eq!(merchants(conn).len(), 3);
+    assert_eq!(count(conn, "transaction_tags"), 1);
+
+    assert_eq!(JournalDb::new(conn).redo().unwrap().as_deref(), Some("Deleted 1 transaction"));
+    assert_eq!(merchants(conn).len(), 2);
+    assert_eq!(count(conn, "transaction_tags"), 0);
+}
+
+#[test]
+fn undoing_a_delete_keeps_a_transaction_imported_again() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    delete(conn, 1);
+    TransactionDb::new(conn).save_transactions(&statement()).unwrap();
+    assert_eq!(merchants(conn).len(), 3);
+
+    let undone = JournalDb::new(conn).undo().unwrap().unwrap();
+    assert_eq!(undone, "Deleted 1 transaction (#1 was imported again as #4 and kept)");
+    assert_eq!(merchants(conn).iter().map(|(id, _)| *id).collect::<Vec<_>>(), [2, 3, 4]);
+
+    let redone = JournalDb::new(conn).redo().unwrap().unwrap();
+    assert!(redone.contains("nothing to delete"), "{}", redone);
+    assert_eq!(merchants(conn).len(), 3);
+}
+
+// SQLite hands the highest freed id to the next insert
+#[test]
+fn undoing_a_delete_leaves_a_transaction_that_took_its_id_alone() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    delete(conn, 3);
+    TransactionDb::new(conn).save_transactions(&[transaction(date(2024, 2, 1), "-60.00", "Shell")]).unwrap();
+
+    let undone = JournalDb::new(conn).undo().unwrap().unwrap();
+    assert!(undone.contains("its id belongs to another transaction"), "{}", undone);
+    JournalDb::new(conn).redo().unwrap().unwrap();
+
+    assert_eq!(merchants(conn)[2], (3, "Shell".to_string()));
+}
+
+#[test]
+fn aliases_budgets_filters_and_flag_reviews_are_undone_newest_first() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    let flag = TransactionFlag::new(FlagKind::AmountOutlier, "3x the usual".to_string());
+    FlagDb::new(conn).save_flags(&[(1, flag)]).unwrap();
+
+    MerchantDb::new(conn).save_alias(&MerchantAlias::new("jumbo", "Jumbo Supermarkten")).unwrap();
+    BudgetDb::new(conn).set_budget("Groceries", Decimal::from_str("300").unwrap()).unwrap();
+    BudgetDb::new(conn).set_budget("Groceries", Decimal::from_str("350").unwrap()).unwrap();
+    FilterDb::new(conn).save_filter("food", "category:Groceries").unwrap();
+    FlagDb::new(conn).set_status(1, FlagKind::AmountOutlier, FlagStatus::Dismissed).unwrap();
+    assert_eq!(count(conn, "journal"), 5);
+
+    // Saving what is already stored is not an edit
+    FilterDb::new(conn).save_filter("food", "category:Groceries").unwrap();
+    assert!(!MerchantDb::new(conn).remove_alias("lidl").unwrap());
+    assert_eq!(count(conn, "journal"), 5);
+
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    let status: String = conn.query_row("SELECT status FROM transaction_flags", [], |row| row.get(0)).unwrap();
+    assert_eq!(status, "pending");
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    assert!(FilterDb::new(conn).get_all_filters().unwrap().is_empty());
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    assert_eq!(BudgetDb::new(conn).get_budgets().unwrap().get("Groceries"), Some(&Decimal::from_str("300").unwrap()));
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    assert!(BudgetDb::new(conn).get_budgets().unwrap().is_empty());
+    JournalDb::new(conn).undo().unwrap().unwrap();
+    assert!(MerchantDb::new(conn).get_all_aliases().unwrap().iter().all(|a| a.pattern != "jumbo"));
+    assert!(JournalDb::new(conn).undo().unwrap().is_none());
+
+    while JournalDb::new(conn).redo().unwrap().is_some() {}
+    assert!(MerchantDb::new(conn).get_all_aliases().unwrap().iter().any(|a| a.pattern == "jumbo"));
+    assert_eq!(BudgetDb::new(conn).get_budgets().unwrap().get("Groceries"), Some(&Decimal::from_str("350").unwrap()));
+    assert_eq!(FilterDb::new(conn).get_all_filters().unwrap().get("food").map(String::as_str), Some("category:Groceries"));
+    let status: String = conn.query_row("SELECT status FROM transaction_flags", [], |row| row.get(0)).unwrap();
+    assert_eq!(status, "dismissed");
+}
+
+#[test]
+fn settings_are_not_journaled() {
+    let mut db = setup();
+    let conn = db.get_connection();
+
+    SettingsDb::new(conn).set("ui.columns", "date,amount").unwrap();
+
+    assert_eq!(count(conn, "journal"), 0);
+}
+
+#[tokio::test]
+async fn seeding_default_categories_and_aliases_is_not_journaled() {
+    let db = Arc::new(Mutex::new(DbConnection::new(":memory:").unwrap()));
+    Repositories::sqlite(db.clone()).categories.initialize_default_categories().await.unwrap();
+    let mut db = db.lock().unwrap();
+    MerchantDb::new(db.get_connection()).initialize_default_aliases().unwrap();
+
+    assert_eq!(count(db.get_connection(), "journal"), 0);
+}
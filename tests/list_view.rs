@@ -0,0 +1,84 @@
+mod common;
+
+use std::path::PathBuf;
+use chrono::NaiveDate;
+use finance_analyzer::config::Profile;
+use finance_analyzer::db::{connection::DbConnection, transaction::TransactionDb};
+use finance_analyzer::models::transaction::Transaction;
+use finance_analyzer::App;
+use common::transaction;
+
+fn date(day: u32) -> NaiveDate {
+    common::date(2024, 1, 1) + chrono::Days::new(day as u64)
+}
+
+// One transaction a day for `days` days, listed newest first
+fn app(days: u32) -> App {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let transactions: Vec<Transaction> = (0..days)
+        .map(|day| transaction(date(day), &format!("-{}.00", day + 1), &format!("Shop {}", day)))
+        .collect();
+    TransactionDb::new(db.get_connection()).save_transactions(&transactions).unwrap();
+
+    let profile = Profile { name: "test".to_string(), db_path: PathBuf::from(":memory:"), lock_after: None };
+    App::with_connection(&profile, db).unwrap()
+}
+
+fn selected_date(app: &App) -> NaiveDate {
+    app.transactions[app.selected_transaction.unwrap()].date.date()
+}
+
+#[test]
+fn paging_stops_at_either_end() {
+    let mut app = app(50);
+    app.list_page = 20;
+
+    app.page_down();
+    app.page_down();
+    assert_eq!(app.list_state.selected(), Some(40));
+    app.page_down();
+    assert_eq!(app.list_state.selected(), Some(49));
+    assert_eq!(selected_date(&app), date(0));
+
+    app.page_up();
+    assert_eq!(app.list_state.selected(), Some(29));
+    app.select_first();
+    app.page_up();
+    assert_eq!(app.list_state.selected(), Some(0));
+    app.select_last();
+    assert_eq!(app.list_state.selected(), Some(49));
+}
+
+#[test]
+fn the_window_follows_the_selection() {
+    let mut app = app(50);
+    assert_eq!(app.list_window(10), 0..10);
+
+    app.select_last();
+    assert_eq!(app.list_window(10), 40..50);
+    app.page_up();
+    assert_eq!(app.list_window(10), 39..49);
+    // Moving within the window leaves it where it is
+    app.next();
+    assert_eq!(app.list_window(10), 39..49);
+
+    assert_eq!(app.list_window(100), 0..50);
+    assert_eq!(app.list_page, 100);
+}
+
+#[test]
+fn jumping_picks_the_nearest_listed_date() {
+    let mut app = app(50);
+    assert!(app.jump_to_date(date(10)));
+    assert_eq!(selected_date(&app), date(10));
+
+    assert!(app.jump_to_date(date(400)));
+    assert_eq!(selected_date(&app), date(49));
+
+    app.apply_filter("merchant:\"Shop 7\"".to_string()).unwrap();
+    assert!(app.jump_to_date(date(0)));
+    assert_eq!(selected_date(&app), date(7));
+
+    app.apply_filter("merchant:nobody".to_string()).unwrap();
+    assert!(!app.jump_to_date(date(0)));
+}
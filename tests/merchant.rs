@@ -0,0 +1,86 @@
+mod common;
+
+use finance_analyzer::analysis::merchants::{merchant_totals, top_merchants};
+use finance_analyzer::db::{connection::DbConnection, journal::JournalDb, merchant::MerchantDb};
+use finance_analyzer::models::merchant::{clean_merchant, MerchantAlias, MerchantNormalizer};
+use common::{date, transaction};
+
+#[test]
+fn store_numbers_terminal_ids_and_locations_are_stripped() {
+    let cases = [
+        ("Albert Heijn 1234 AMSTERDAM", "Albert Heijn"),
+        ("ALBERT HEIJN 1234 ZAANDAM", "ALBERT HEIJN"),
+        ("CCV*BAKKERIJ DE ZON 00321 UTRECHT", "BAKKERIJ DE ZON"),
+        ("SumUp *Koffiebar #12", "Koffiebar"),
+        ("Jumbo Supermarkt T-004512", "Jumbo Supermarkt"),
+        ("Bol.com Pas ****4321", "Bol.com Pas"),
+        ("Espresso House UTRECHT NLD", "Espresso House"),
+        ("Gemeente Amsterdam", "Gemeente Amsterdam"),
+    ];
+    for (raw, cleaned) in cases {
+        assert_eq!(clean_merchant(raw), cleaned, "{}", raw);
+    }
+}
+
+#[test]
+fn digits_that_are_part_of_the_name_are_kept() {
+    let cases = [
+        ("7-Eleven 0412 SCHIPHOL", "7-Eleven"),
+        ("Formule 1 Shop", "Formule 1 Shop"),
+        ("3M Nederland", "3M Nederland"),
+        ("Route 66 Diner", "Route 66 Diner"),
+        ("1234", "1234"),
+    ];
+    for (raw, cleaned) in cases {
+        assert_eq!(clean_merchant(raw), cleaned, "{}", raw);
+    }
+}
+
+#[test]
+fn the_longest_matching_alias_wins() {
+    let normalizer = MerchantNormalizer::new(vec![
+        MerchantAlias::new("uber", "Uber"),
+        MerchantAlias::new("uber eats", "Uber Eats"),
+    ]);
+
+    assert_eq!(normalizer.normalize("UBER EATS 1234 AMSTERDAM"), "Uber Eats");
+    assert_eq!(normalizer.normalize("Uber BV"), "Uber");
+    assert_eq!(normalizer.normalize("Jumbo 0042"), "Jumbo");
+}
+
+#[test]
+fn removing_an_alias_can_be_undone() {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let conn = db.get_connection();
+    MerchantDb::new(conn).save_alias(&MerchantAlias::new("jumbo", "Jumbo Supermarkten")).unwrap();
+
+    assert!(MerchantDb::new(conn).remove_alias("jumbo").unwrap());
+    assert!(!MerchantDb::new(conn).remove_alias("jumbo").unwrap());
+    assert!(MerchantDb::new(conn).get_all_aliases().unwrap().is_empty());
+
+    assert_eq!(JournalDb::new(conn).undo().unwrap().as_deref(), Some("Removed the alias for 'jumbo'"));
+    assert_eq!(MerchantDb::new(conn).get_all_aliases().unwrap(), [MerchantAlias::new("jumbo", "Jumbo Supermarkten")]);
+}
+
+#[test]
+fn merchant_totals_cover_income_and_transfers_that_top_merchants_skips() {
+    let mut salary = transaction(date(2024, 1, 25), "3200.00", "Werkgever BV");
+    salary.category = Some("Income".to_string());
+    let mut bonus = transaction(date(2024, 2, 25), "800.00", "Werkgever BV");
+    bonus.category = Some("Income".to_string());
+    let mut savings = transaction(date(2024, 1, 26), "-500.00", "Spaarrekening");
+    savings.category = Some("Internal Transfer".to_string());
+    let mut ignored = transaction(date(2024, 1, 27), "100.00", "Marktplaats");
+    ignored.excluded = true;
+    let book = [salary, bonus, savings, ignored];
+
+    assert!(top_merchants(&book).is_empty());
+    let stats: Vec<(String, String, usize)> = merchant_totals(&book)
+        .into_iter()
+        .map(|s| (s.merchant, s.total_spend.to_string(), s.visits))
+        .collect();
+    assert_eq!(stats, [
+        ("Werkgever BV".to_string(), "4000.00".to_string(), 2),
+        ("Spaarrekening".to_string(), "500.00".to_string(), 1),
+    ]);
+}
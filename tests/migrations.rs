@@ -0,0 +1,278 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use finance_analyzer::db::{
+    connection::DbConnection,
+    flag::FlagDb,
+    journal::JournalDb,
+    migrations::{latest_version, MIGRATIONS},
+};
+use finance_analyzer::models::flag::{FlagKind, TransactionFlag};
+use rusqlite::Connection;
+use common::{count, scratch_dir};
+
+fn fixture_db(name: &str, fixture: &str) -> PathBuf {
+    let path = scratch_dir(name).join("finance.db");
+    Connection::open(&path).unwrap().execute_batch(fixture).unwrap();
+    path
+}
+
+fn columns(conn: &Connection, table: &str) -> Vec<String> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?)").unwrap();
+    stmt.query_map([table], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+}
+
+fn backups(path: &Path) -> Vec<PathBuf> {
+    fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "bak"))
+        .collect()
+}
+
+fn applied_versions(conn: &Connection) -> Vec<u32> {
+    let mut stmt = conn.prepare("SELECT version FROM schema_version ORDER BY version").unwrap();
+    stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn new_database_starts_at_latest_version_without_backup() {
+    let path = scratch_dir("new").join("finance.db");
+    let mut db = DbConnection::new(&path).unwrap();
+
+    assert_eq!(db.schema_version().unwrap(), latest_version());
+    assert!(db.pending_migrations().unwrap().is_empty());
+    let conn = db.get_connection();
+    assert_eq!(applied_versions(conn), MIGRATIONS.iter().map(|m| m.version).collect::<Vec<_>>());
+    assert!(columns(conn, "transaction_categories").contains(&"source".to_string()));
+    assert!(backups(&path).is_empty());
+}
+
+#[test]
+fn baseline_database_gains_every_table() {
+    let path = fixture_db("baseline", include_str!("fixtures/baseline.sql"));
+    let mut db = DbConnection::new(&path).unwrap();
+    let conn = db.get_connection();
+
+    for table in ["transactions", "transaction_tags", "excluded_transactions", "budgets", "journal", "settings"] {
+        assert!(!columns(conn, table).is_empty(), "missing table {}", table);
+    }
+    assert_eq!(count(conn, "categories"), 3);
+    assert_eq!(count(conn, "category_rules"), 4);
+
+    let assignment_columns = columns(conn, "transaction_categories");
+    assert!(assignment_columns.contains(&"source".to_string()));
+    assert!(assignment_columns.contains(&"confidence".to_string()));
+    // Keyed by CSV row, so it belongs to no stored transaction
+    assert_eq!(count(conn, "transaction_categories"), 0);
+}
+
+#[test]
+fn cli_database_keeps_its_data() {
+    let path = fixture_db("cli", include_str!("fixtures/cli.sql"));
+    let mut db = DbConnection::new(&path).unwrap();
+    let conn = db.get_connection();
+
+    assert_eq!(count(conn, "transactions"), 4);
+    assert_eq!(count(conn, "transaction_flags"), 1);
+    assert_eq!(count(conn, "merchant_aliases"), 1);
+    let threshold: String = conn
+        .query_row("SELECT value FROM settings WHERE key = 'forecast.low_balance_threshold'", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(threshold, "500");
+}
+
+#[test]
+fn journal_written_before_sources_can_still_be_undone_and_redone() {
+    let path = fixture_db("journal", include_str!("fixtures/journal.sql"));
+    let mut db = DbConnection::new(&path).unwrap();
+    let conn = db.get_connection();
+    assert_eq!(count(conn, "transaction_tags"), 1);
+    assert_eq!(count(conn, "excluded_transactions"), 1);
+
+    let mut journal = JournalDb::new(conn);
+    assert_eq!(journal.undo().unwrap().as_deref(), Some("Moved 1 transaction to Dining"));
+    assert_eq!(count(conn, "transaction_categories"), 0);
+
+    let mut journal = JournalDb::new(conn);
+    assert!(journal.redo().unwrap().is_some());
+    let source: String = conn
+        .query_row("SELECT source FROM transaction_categories WHERE id = 1", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(source, "manual");
+}
+
+#[test]
+fn migration_backs_up_the_old_file_first() {
+    let path = fixture_db("backup", include_str!("fixtures/cli.sql"));
+    DbConnection::new(&path).unwrap();
+
+    let backups = backups(&path);
+    assert_eq!(backups.len(), 1);
+    let name = backups[0].file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.starts_with("finance.db.v0-"), "unexpected backup name {}", name);
+
+    let backup = Connection::open(&backups[0]).unwrap();
+    assert_eq!(count(&backup, "transactions"), 4);
+    assert!(!columns(&backup, "transaction_categories").contains(&"source".to_string()));
+    assert!(columns(&backup, "schema_version").is_empty());
+}
+
+#[test]
+fn dry_run_leaves_the_database_alone() {
+    let path = fixture_db("dry-run", include_str!("fixtures/journal.sql"));
+    let db = DbConnection::open(&path).unwrap();
+
+    assert_eq!(db.schema_version().unwrap(), 0);
+    let pending: Vec<u32> = db.pending_migrations().unwrap().iter().map(|m| m.version).collect();
+    assert_eq!(pending, MIGRATIONS.iter().map(|m| m.version).collect::<Vec<_>>());
+    assert!(db.backup_path().unwrap().is_some());
+    drop(db);
+
+    let conn = Connection::open(&path).unwrap();
+    assert!(columns(&conn, "schema_version").is_empty());
+    assert!(!columns(&conn, "transaction_categories").contains(&"source".to_string()));
+    assert!(backups(&path).is_empty());
+}
+
+#[test]
+fn migrating_twice_is_a_no_op() {
+    let path = fixture_db("twice", include_str!("fixtures/baseline.sql"));
+    DbConnection::new(&path).unwrap();
+
+    let mut db = DbConnection::open(&path).unwrap();
+    let report = db.migrate().unwrap();
+    assert!(report.applied.is_empty());
+    assert!(report.backup.is_none());
+    assert_eq!(report.to, latest_version());
+    assert_eq!(backups(&path).len(), 1);
+}
+
+#[test]
+fn database_from_a_newer_build_is_refused() {
+    let path = fixture_db("newer", include_str!("fixtures/baseline.sql"));
+    DbConnection::new(&path).unwrap();
+    Connection::open(&path)
+        .unwrap()
+        .execute("INSERT INTO schema_version (version, description) VALUES (?, 'From the future')", [latest_version() + 1])
+        .unwrap();
+
+    let error = DbConnection::new(&path).unwrap_err().to_string();
+    assert!(error.contains("newer than this build supports"), "{}", error);
+}
+
+#[test]
+fn repeated_assignments_collapse_to_the_latest() {
+    let path = fixture_db("unique", include_str!("fixtures/cli.sql"));
+    Connection::open(&path)
+        .unwrap()
+        .execute_batch(
+            // As written by a build that didn't enforce foreign keys
+            "PRAGMA foreign_keys = OFF;
+             INSERT INTO transaction_categories (transaction_id, category_id) VALUES (3, 1), (3, 3), (2, 2);
+             INSERT INTO transaction_categories (transaction_id, category_id) VALUES (4, 42), (9, 1);",
+        )
+        .unwrap();
+    let mut db = DbConnection::new(&path).unwrap();
+    let conn = db.get_connection();
+
+    let rows: Vec<(i64, i64, i64)> = conn
+        .prepare("SELECT id, transaction_id, category_id FROM transaction_categories ORDER BY id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    // The first (3, 3) and the assignments to a category or transaction
+    // that doesn't exist are gone
+    assert_eq!(rows, vec![(2, 3, 1), (3, 3, 3), (4, 2, 2)]);
+
+    let duplicate = conn.execute("INSERT INTO transaction_categories (transaction_id, category_id) VALUES (3, 1)", []);
+    assert!(duplicate.is_err());
+    let orphan = conn.execute("INSERT INTO transaction_categories (transaction_id, category_id) VALUES (9, 1)", []);
+    assert!(orphan.is_err());
+}
+
+#[test]
+fn deleting_a_category_removes_its_rules_budget_and_assignments() {
+    let path = fixture_db("cascade", include_str!("fixtures/cli.sql"));
+    let mut db = DbConnection::new(&path).unwrap();
+    let conn = db.get_connection();
+    conn.execute("INSERT INTO budgets (category_id, amount) VALUES (1, '300')", []).unwrap();
+    let rules_before = count(conn, "category_rules");
+    let own_rules: i64 = conn
+        .query_row("SELECT COUNT(*) FROM category_rules WHERE category_id = 1", [], |row| row.get(0))
+        .unwrap();
+
+    conn.execute("DELETE FROM categories WHERE id = 1", []).unwrap();
+
+    assert_eq!(count(conn, "category_rules"), rules_before - own_rules);
+    assert_eq!(count(conn, "budgets"), 0);
+    let assigned: i64 = conn
+        .query_row("SELECT COUNT(*) FROM transaction_categories WHERE category_id = 1", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(assigned, 0);
+    assert!(conn.execute("INSERT INTO category_rules (category_id, pattern) VALUES (1, 'gone')", []).is_err());
+}
+
+#[test]
+fn flags_from_before_transactions_were_stored_are_dropped() {
+    let path = fixture_db("flags", include_str!("fixtures/cli.sql"));
+    Connection::open(&path)
+        .unwrap()
+        .execute_batch(
+            // Flags on CSV row numbers, written before the rows were imported
+            "UPDATE transactions SET imported_at = '2024-02-01 10:00:00';
+             UPDATE transaction_flags SET flagged_at = '2024-02-01 10:05:00';
+             INSERT INTO transaction_flags (transaction_id, kind, reason, flagged_at) VALUES
+                 (1, 'duplicate', 'Row 1 of january.csv', '2024-01-20 08:00:00'),
+                 (9, 'large', 'Row 9 of january.csv', '2024-01-20 08:00:00');",
+        )
+        .unwrap();
+    let mut db = DbConnection::new(&path).unwrap();
+    let conn = db.get_connection();
+
+    let flagged: Vec<i64> = conn
+        .prepare("SELECT transaction_id FROM transaction_flags")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(flagged, vec![2]);
+
+    conn.execute("DELETE FROM transactions WHERE id = 2", []).unwrap();
+    assert_eq!(count(conn, "transaction_flags"), 0);
+
+    // Detection on a stale list doesn't flag a row that is gone
+    let flag = TransactionFlag::new(FlagKind::AmountOutlier, "3x the usual".to_string());
+    FlagDb::new(conn).save_flags(&[(2, flag)]).unwrap();
+    assert_eq!(count(conn, "transaction_flags"), 0);
+}
+
+#[test]
+fn assignments_from_before_sources_were_recorded_stay_manual() {
+    let path = fixture_db("import-sources", include_str!("fixtures/cli.sql"));
+    Connection::open(&path)
+        .unwrap()
+        .execute_batch(
+            // #1 was assigned seconds after it was imported, which a script
+            // editing by hand does as well as an import
+            "UPDATE transactions SET imported_at = '2024-01-06 12:00:00';
+             INSERT INTO transaction_categories (transaction_id, category_id, assigned_at) VALUES
+                 (1, 1, '2024-01-06 12:00:02');",
+        )
+        .unwrap();
+    let mut db = DbConnection::new(&path).unwrap();
+
+    let sources: Vec<(i64, String)> = db
+        .get_connection()
+        .prepare("SELECT transaction_id, source FROM transaction_categories ORDER BY transaction_id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(sources, [(1, "manual".to_string()), (3, "manual".to_string())]);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::collections::HashMap;
+use finance_analyzer::models::transaction::Transaction;
+use finance_analyzer::query::{Query, QueryError};
+use common::{date, transaction};
+
+fn book() -> Vec<Transaction> {
+    let mut rent = transaction(date(2024, 1, 1), "-950.00", "Woonbron");
+    rent.category = Some("Housing".to_string());
+    rent.description = "Huur januari".to_string();
+    let mut groceries = transaction(date(2024, 1, 31), "-42.10", "Albert Heijn 1234");
+    groceries.category = Some("Groceries".to_string());
+    groceries.tags = vec!["shared".to_string()];
+    let mut salary = transaction(date(2024, 2, 1), "3200.00", "Werkgever BV");
+    salary.account = "NL02".to_string();
+    let coffee = transaction(date(2024, 12, 31), "-3.20", "Coffee Company");
+    vec![rent, groceries, salary, coffee]
+}
+
+fn matching_with(input: &str, saved: &HashMap<String, String>) -> Vec<String> {
+    let query = Query::parse(input, saved).unwrap_or_else(|e| panic!("{}: {}", input, e));
+    book().into_iter().filter(|t| query.matches(t)).map(|t| t.merchant).collect()
+}
+
+fn matching(input: &str) -> Vec<String> {
+    matching_with(input, &HashMap::new())
+}
+
+fn error(input: &str, saved: &HashMap<String, String>) -> QueryError {
+    Query::parse(input, saved).unwrap_err()
+}
+
+fn saved(filters: &[(&str, &str)]) -> HashMap<String, String> {
+    filters.iter().map(|(name, query)| (name.to_string(), query.to_string())).collect()
+}
+
+#[test]
+fn text_fields_match_substrings_whole_values_and_regexes() {
+    assert_eq!(matching("heijn"), ["Albert Heijn 1234"]);
+    assert_eq!(matching("\"huur jan\""), ["Woonbron"]);
+    assert_eq!(matching("cat:hous"), ["Woonbron"]);
+    assert_eq!(matching("category=hous"), Vec::<String>::new());
+    assert_eq!(matching("category=Uncategorized"), ["Werkgever BV", "Coffee Company"]);
+    assert_eq!(matching("merchant~/^(albert|coffee)/"), ["Albert Heijn 1234", "Coffee Company"]);
+    assert_eq!(matching("merchant~\\d{4}$"), ["Albert Heijn 1234"]);
+    assert_eq!(matching("tag:shared account=nl01"), ["Albert Heijn 1234"]);
+    assert_eq!(matching("tag:share"), Vec::<String>::new());
+}
+
+#[test]
+fn amounts_compare_with_every_operator() {
+    assert_eq!(matching("amount<-42.10"), ["Woonbron"]);
+    assert_eq!(matching("amount<=-42.10"), ["Woonbron", "Albert Heijn 1234"]);
+    assert_eq!(matching("amount>0"), ["Werkgever BV"]);
+    assert_eq!(matching("amount>=-3,20"), ["Werkgever BV", "Coffee Company"]);
+    assert_eq!(matching("amount=-3.2"), ["Coffee Company"]);
+    assert_eq!(matching("amount:3200"), ["Werkgever BV"]);
+}
+
+#[test]
+fn dates_cover_the_whole_year_month_or_day() {
+    assert_eq!(matching("date:2024-01"), ["Woonbron", "Albert Heijn 1234"]);
+    assert_eq!(matching("date=2024-01-31"), ["Albert Heijn 1234"]);
+    assert_eq!(matching("date>2024-01"), ["Werkgever BV", "Coffee Company"]);
+    assert_eq!(matching("date>=2024-02"), ["Werkgever BV", "Coffee Company"]);
+    assert_eq!(matching("date<2024-01-31"), ["Woonbron"]);
+    assert_eq!(matching("date<=2024-01"), ["Woonbron", "Albert Heijn 1234"]);
+    assert_eq!(matching("date:2024-12"), ["Coffee Company"]);
+    assert_eq!(matching("date<2025 date>2023"), ["Woonbron", "Albert Heijn 1234", "Werkgever BV", "Coffee Company"]);
+}
+
+#[test]
+fn a_leading_minus_negates_a_term() {
+    assert_eq!(matching("-cat:groceries amount<0"), ["Woonbron", "Coffee Company"]);
+    assert_eq!(matching("-merchant~/^w/"), ["Albert Heijn 1234", "Coffee Company"]);
+    // A lone '-' is just text
+    assert_eq!(matching("- coffee"), Vec::<String>::new());
+}
+
+#[test]
+fn saved_filters_expand_in_place_and_can_be_negated() {
+    let saved = saved(&[("spending", "amount<0"), ("food", "@spending -cat:housing"), ("big", "amount<-100")]);
+
+    assert_eq!(matching_with("@food", &saved), ["Albert Heijn 1234", "Coffee Company"]);
+    assert_eq!(matching_with("@spending -@big", &saved), ["Albert Heijn 1234", "Coffee Company"]);
+    assert_eq!(matching_with("-@spending", &saved), ["Werkgever BV"]);
+}
+
+#[test]
+fn saved_filters_that_refer_to_each_other_are_refused() {
+    let saved = saved(&[("a", "amount<0 @b"), ("b", "@c"), ("c", "-@a")]);
+
+    let e = error("coffee @b", &saved);
+    assert_eq!(e.column, 8);
+    assert_eq!(e.message, "saved filters refer to each other: @b -> @c -> @a -> @b");
+}
+
+#[test]
+fn an_at_sign_in_quoted_text_or_a_value_is_not_a_reference() {
+    let saved = saved(&[("mail", "\"billing @invoices\" description:x@mail"), ("invoices", "@mail")]);
+
+    assert!(Query::parse("@mail", &saved).is_ok());
+    assert!(Query::parse("@invoices", &saved).is_ok());
+}
+
+#[test]
+fn errors_point_at_the_column_where_they_start() {
+    let none = HashMap::new();
+    let cases = [
+        ("coffee amont<5", 8, "unknown field 'amont'"),
+        ("amount<abc", 8, "'abc' is not an amount"),
+        ("date>2024-13", 6, "'2024-13' is not a date"),
+        ("date~2024", 5, "'date' does not support '~'"),
+        ("tag<x", 4, "'tag' only supports ':', '=' and '~'"),
+        ("merchant:", 10, "expected a value after 'merchant:'"),
+        ("merchant~/(ab/", 10, "invalid regex"),
+        ("merchant~/ab", 10, "unterminated regex"),
+        ("x \"open", 3, "unterminated quote"),
+        ("-@missing", 2, "no saved filter named 'missing'"),
+        ("@", 1, "expected a saved filter name after '@'"),
+    ];
+    for (input, column, message) in cases {
+        let e = error(input, &none);
+        assert_eq!(e.column, column, "{}: {}", input, e);
+        assert!(e.message.starts_with(message), "{}: {}", input, e);
+    }
+
+    let saved = saved(&[("broken", "amount<x")]);
+    let e = error("coffee @broken", &saved);
+    assert_eq!(e.column, 8);
+    assert_eq!(e.message, "in @broken: column 8: 'x' is not an amount");
+}
@@ -0,0 +1,279 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use finance_analyzer::config::Profile;
+use finance_analyzer::db::{batch::BatchAction, category::CategoryDb, connection::DbConnection, transaction::TransactionDb};
+use finance_analyzer::models::category::{Category, Rule, SourceKind};
+use finance_analyzer::models::flag::{FlagKind, FlagStatus, TransactionFlag};
+use finance_analyzer::models::merchant::MerchantAlias;
+use finance_analyzer::repository::Repositories;
+use finance_analyzer::App;
+use rust_decimal::Decimal;
+use common::{count, date, rule, scratch_dir, transaction};
+
+// Both implementations have to pass the same checks
+fn implementations() -> Vec<(&'static str, Repositories)> {
+    let db = DbConnection::new(":memory:").unwrap();
+    vec![
+        ("memory", Repositories::memory()),
+        ("sqlite", Repositories::sqlite(Arc::new(Mutex::new(db)))),
+    ]
+}
+
+#[tokio::test]
+async fn transactions_are_deduplicated_and_listed_newest_first() {
+    for (name, repos) in implementations() {
+        let saved = repos.transactions.save_transactions(vec![
+            transaction(date(2024, 3, 1), "-4.50", "Bakery"),
+            transaction(date(2024, 3, 3), "-20.00", "Jumbo"),
+            transaction(date(2024, 3, 1), "-4.50", "Bakery"),
+        ]).await.unwrap();
+        assert_eq!(saved, 2, "{}", name);
+
+        let again = repos.transactions.save_transactions(vec![transaction(date(2024, 3, 3), "-20.00", "Jumbo")]).await.unwrap();
+        assert_eq!(again, 0, "{}", name);
+        assert_eq!(repos.transactions.count().await.unwrap(), 2, "{}", name);
+
+        let all = repos.transactions.get_all_transactions().await.unwrap();
+        let merchants: Vec<_> = all.iter().map(|t| t.merchant.as_str()).collect();
+        assert_eq!(merchants, ["Jumbo", "Bakery"], "{}", name);
+        assert!(all.iter().all(|t| t.id > 0), "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn categories_can_be_upserted_renamed_and_deleted() {
+    for (name, repos) in implementations() {
+        let categories = &repos.categories;
+        categories.initialize_default_categories().await.unwrap();
+        assert!(categories.get_category_by_name("Groceries").await.unwrap().is_some(), "{}", name);
+
+        categories.upsert_category(Category::new("Coffee", &[("STARBUCKS", 1)])).await.unwrap();
+        let id = categories.get_category_by_name("Coffee").await.unwrap().unwrap().rules[0].id;
+        categories.upsert_category(Category::new("Coffee", &[("STARBUCKS", 4)])).await.unwrap();
+        let coffee = categories.get_category_by_name("Coffee").await.unwrap().unwrap();
+        assert_eq!((coffee.rules[0].id, coffee.rules[0].priority), (id, 4), "{}", name);
+
+        repos.transactions.save_transactions(vec![transaction(date(2024, 3, 1), "-4.50", "Starbucks")]).await.unwrap();
+        categories.assign_category(1, "Coffee", SourceKind::Manual, None).await.unwrap();
+        assert!(categories.assign_category(1, "Missing", SourceKind::Manual, None).await.is_err(), "{}", name);
+        assert!(categories.assign_category(2, "Coffee", SourceKind::Manual, None).await.is_err(), "{}", name);
+
+        assert!(categories.rename_category("Coffee", "Cafes").await.unwrap(), "{}", name);
+        assert!(categories.rename_category("Cafes", "Groceries").await.is_err(), "{}", name);
+        assert!(!categories.rename_category("Coffee", "Tea").await.unwrap(), "{}", name);
+        let assigned = categories.get_assigned_categories().await.unwrap();
+        assert_eq!(assigned.get(&1).map(|(c, _)| c.as_str()), Some("Cafes"), "{}", name);
+
+        assert!(categories.delete_category("Cafes", Some("Missing")).await.is_err(), "{}", name);
+        assert!(categories.delete_category("Cafes", Some("Entertainment")).await.unwrap(), "{}", name);
+        assert!(!categories.delete_category("Cafes", None).await.unwrap(), "{}", name);
+        let assigned = categories.get_assigned_categories().await.unwrap();
+        assert_eq!(assigned.get(&1).map(|(c, _)| c.as_str()), Some("Entertainment"), "{}", name);
+
+        assert!(categories.delete_category("Entertainment", None).await.unwrap(), "{}", name);
+        assert!(categories.get_assigned_categories().await.unwrap().is_empty(), "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn rules_can_be_added_updated_and_removed() {
+    for (name, repos) in implementations() {
+        repos.categories.upsert_category(Category::new("Coffee", &[])).await.unwrap();
+        repos.categories.upsert_category(Category::new("Dining", &[("RESTAURANT", 1)])).await.unwrap();
+
+        let id = repos.rules.add_rule(rule("Coffee", "STARBUCKS", 5)).await.unwrap();
+        assert!(repos.rules.add_rule(rule("Missing", "X", 1)).await.is_err(), "{}", name);
+        let rules = repos.rules.get_rules().await.unwrap();
+        assert_eq!(rules.iter().map(|r| r.pattern.as_str()).collect::<Vec<_>>(), ["STARBUCKS", "RESTAURANT"], "{}", name);
+
+        assert!(repos.rules.update_rule(Rule { id, ..rule("Dining", "STARBUCKS CAFE", 2) }).await.unwrap(), "{}", name);
+        assert!(repos.rules.update_rule(Rule { id, ..rule("Missing", "X", 1) }).await.is_err(), "{}", name);
+        let dining = repos.categories.get_category_by_name("Dining").await.unwrap().unwrap();
+        assert!(dining.rules.iter().any(|r| r.id == id && r.pattern == "STARBUCKS CAFE"), "{}", name);
+        let coffee = repos.categories.get_category_by_name("Coffee").await.unwrap().unwrap();
+        assert!(coffee.rules.is_empty(), "{}", name);
+
+        assert!(repos.rules.remove_rule(id).await.unwrap(), "{}", name);
+        assert!(!repos.rules.remove_rule(id).await.unwrap(), "{}", name);
+        assert!(!repos.rules.update_rule(Rule { id, ..rule("Dining", "X", 1) }).await.unwrap(), "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn batches_and_category_edits_are_undone_newest_first() {
+    for (name, repos) in implementations() {
+        repos.transactions.save_transactions(vec![
+            transaction(date(2024, 3, 1), "-4.50", "Bakery"),
+            transaction(date(2024, 3, 3), "-20.00", "Jumbo"),
+        ]).await.unwrap();
+        repos.categories.upsert_category(Category::new("Coffee", &[])).await.unwrap();
+        let ids: Vec<u64> = repos.transactions.get_all_transactions().await.unwrap().iter().map(|t| t.id).collect();
+
+        assert!(repos.journal.apply_batch(ids.clone(), BatchAction::SetCategory("Missing".to_string())).await.is_err(), "{}", name);
+        let batch = repos.journal.apply_batch(ids.clone(), BatchAction::AddTags(vec!["shared".to_string()])).await.unwrap();
+        assert_eq!(batch.description, "Tagged 2 transactions with #shared", "{}", name);
+        repos.journal.apply_batch(vec![ids[0]], BatchAction::Delete).await.unwrap();
+        assert!(repos.categories.rename_category("Coffee", "Cafes").await.unwrap(), "{}", name);
+
+        let entries = repos.journal.get_entries(10).await.unwrap();
+        assert_eq!(entries.len(), 4, "{}", name);
+        assert_eq!(entries[1].description, "Deleted 1 transaction", "{}", name);
+
+        assert!(repos.journal.undo().await.unwrap().is_some(), "{}", name);
+        assert!(repos.categories.get_category_by_name("Coffee").await.unwrap().is_some(), "{}", name);
+        assert_eq!(repos.journal.undo().await.unwrap().as_deref(), Some("Deleted 1 transaction"), "{}", name);
+        let all = repos.transactions.get_all_transactions().await.unwrap();
+        assert_eq!(all.len(), 2, "{}", name);
+        assert!(all.iter().all(|t| t.tags == ["shared"]), "{}", name);
+        assert!(repos.journal.undo().await.unwrap().is_some(), "{}", name);
+        assert!(repos.transactions.get_all_transactions().await.unwrap().iter().all(|t| t.tags.is_empty()), "{}", name);
+
+        assert!(repos.journal.redo().await.unwrap().is_some(), "{}", name);
+        assert!(repos.journal.redo().await.unwrap().is_some(), "{}", name);
+        assert_eq!(repos.transactions.count().await.unwrap(), 1, "{}", name);
+        assert!(repos.journal.get_entries(10).await.unwrap()[0].undone, "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn aliases_filters_flags_and_settings_are_stored() {
+    for (name, repos) in implementations() {
+        repos.transactions.save_transactions(vec![transaction(date(2024, 3, 1), "-4.50", "Bakery")]).await.unwrap();
+        let id = repos.transactions.get_all_transactions().await.unwrap()[0].id;
+
+        repos.merchants.save_alias(MerchantAlias::new("bakery", "Bakkerij")).await.unwrap();
+        assert_eq!(repos.merchants.get_all_aliases().await.unwrap().len(), 1, "{}", name);
+        assert!(repos.merchants.remove_alias("bakery").await.unwrap(), "{}", name);
+        assert!(!repos.merchants.remove_alias("bakery").await.unwrap(), "{}", name);
+
+        repos.filters.save_filter("food", "category:Groceries").await.unwrap();
+        let filters = repos.filters.get_all_filters().await.unwrap();
+        assert_eq!(filters.get("food").map(String::as_str), Some("category:Groceries"), "{}", name);
+        assert!(repos.filters.remove_filter("food").await.unwrap(), "{}", name);
+
+        let flag = TransactionFlag::new(FlagKind::AmountOutlier, "3x the usual".to_string());
+        repos.flags.save_flags(vec![(id, flag)]).await.unwrap();
+        repos.flags.set_flag_status(id, FlagKind::AmountOutlier, FlagStatus::Dismissed).await.unwrap();
+        let flags = repos.flags.get_all_flags().await.unwrap();
+        assert_eq!(flags[&id][0].status, FlagStatus::Dismissed, "{}", name);
+
+        // Alias, filter and flag edits can be undone; settings are not
+        assert_eq!(repos.journal.get_entries(10).await.unwrap().len(), 5, "{}", name);
+        repos.settings.set_setting("ui.columns", "date,amount").await.unwrap();
+        assert_eq!(repos.settings.get_setting("ui.columns").await.unwrap().as_deref(), Some("date,amount"), "{}", name);
+        assert_eq!(repos.settings.get_setting("missing").await.unwrap(), None, "{}", name);
+        assert_eq!(repos.journal.get_entries(10).await.unwrap().len(), 5, "{}", name);
+
+        repos.journal.undo().await.unwrap().unwrap();
+        assert_eq!(repos.flags.get_all_flags().await.unwrap()[&id][0].status, FlagStatus::Pending, "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn detection_drops_pending_flags_it_no_longer_finds() {
+    for (name, repos) in implementations() {
+        repos.transactions.save_transactions(vec![
+            transaction(date(2024, 3, 1), "-4.50", "Bakery"),
+            transaction(date(2024, 3, 2), "-90.00", "Bakery"),
+        ]).await.unwrap();
+        let ids: Vec<u64> = repos.transactions.get_all_transactions().await.unwrap().iter().map(|t| t.id).collect();
+        let flag = |kind| TransactionFlag::new(kind, "detected".to_string());
+        repos.flags.save_flags(vec![
+            (ids[0], flag(FlagKind::AmountOutlier)),
+            (ids[1], flag(FlagKind::AmountOutlier)),
+            (ids[1], flag(FlagKind::FirstTimeMerchant)),
+        ]).await.unwrap();
+        repos.flags.set_flag_status(ids[0], FlagKind::AmountOutlier, FlagStatus::Confirmed).await.unwrap();
+
+        repos.flags.save_flags(vec![(ids[1], flag(FlagKind::AmountOutlier))]).await.unwrap();
+
+        let flags = repos.flags.get_all_flags().await.unwrap();
+        let kinds = |id| flags[&id].iter().map(|f| (f.kind, f.status)).collect::<Vec<_>>();
+        assert_eq!(kinds(ids[0]), [(FlagKind::AmountOutlier, FlagStatus::Confirmed)], "{}", name);
+        assert_eq!(kinds(ids[1]), [(FlagKind::AmountOutlier, FlagStatus::Pending)], "{}", name);
+    }
+}
+
+#[test]
+fn the_app_runs_without_a_database() {
+    let repos = Repositories::memory();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(repos.transactions.save_transactions(vec![
+        transaction(date(2024, 3, 1), "-4.50", "BAKERY 0412"),
+        transaction(date(2024, 3, 3), "-20.00", "Jumbo"),
+    ])).unwrap();
+
+    let profile = Profile { name: "test".to_string(), db_path: PathBuf::from(":memory:"), lock_after: None };
+    let mut app = App::with_repositories(&profile, repos).unwrap();
+    assert!(!app.categories.is_empty());
+    assert_eq!(app.lock_after(), None);
+
+    let id = app.transactions[0].id;
+    app.run_batch(vec![id], BatchAction::SetCategory("Groceries".to_string()));
+    assert_eq!(app.status_message.as_deref(), Some("Moved 1 transaction to Groceries (u to undo)"));
+    assert_eq!(app.transactions[0].category.as_deref(), Some("Groceries"));
+
+    app.undo();
+    assert_eq!(app.status_message.as_deref(), Some("Undone: Moved 1 transaction to Groceries"));
+    assert_ne!(app.transactions[0].category.as_deref(), Some("Groceries"));
+}
+
+#[test]
+fn read_only_commands_flag_in_memory_without_storing_flags() {
+    let path = scratch_dir("read-only-flags").join("finance.db");
+    let mut charges = [
+        transaction(date(2024, 3, 1), "-4.50", "Bakery"),
+        transaction(date(2024, 3, 1), "-4.50", "Bakery"),
+    ];
+    charges[0].balance = Some(Decimal::from(100));
+    let mut db = DbConnection::new(&path).unwrap();
+    TransactionDb::new(db.get_connection()).save_transactions(&charges).unwrap();
+    drop(db);
+    let profile = Profile { name: "test".to_string(), db_path: path.clone(), lock_after: None };
+
+    let app = App::new(&profile, DbConnection::open(&path).unwrap()).unwrap();
+    assert!(app.transactions.iter().any(|t| t.flags.iter().any(|f| f.kind == FlagKind::DuplicateCharge)));
+    assert_eq!(count(DbConnection::open(&path).unwrap().get_connection(), "transaction_flags"), 0);
+
+    App::with_connection(&profile, DbConnection::new(&path).unwrap()).unwrap();
+    assert_eq!(count(DbConnection::open(&path).unwrap().get_connection(), "transaction_flags"), 1);
+}
+
+#[test]
+fn the_category_picker_offers_the_stored_categories() {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    TransactionDb::new(db.get_connection())
+        .save_transactions(&[transaction(date(2024, 3, 1), "-4.50", "Bakery")])
+        .unwrap();
+    let mut categories = CategoryDb::new(db.get_connection());
+    categories.initialize_default_categories().unwrap();
+    categories.rename_category("Groceries", "Food").unwrap();
+    let profile = Profile { name: "test".to_string(), db_path: PathBuf::from(":memory:"), lock_after: None };
+    let mut app = App::with_connection(&profile, db).unwrap();
+    assert!(app.available_categories.contains(&"Food".to_string()));
+    assert!(!app.available_categories.contains(&"Groceries".to_string()));
+
+    app.list_state.select(Some(0));
+    app.start_categorizing(false);
+    app.category_selection = app.available_categories.iter().position(|c| c == "Food");
+    app.submit_input();
+    assert_eq!(app.transactions[0].category.as_deref(), Some("Food"));
+
+    // Undoing the assignment and then the rename brings the old name back
+    app.undo();
+    app.undo();
+    assert!(app.available_categories.contains(&"Groceries".to_string()));
+    assert!(!app.available_categories.contains(&"Food".to_string()));
+}
+
+#[test]
+fn failing_to_read_assignments_is_an_error() {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    db.get_connection().execute_batch("DROP TABLE transaction_categories").unwrap();
+    let profile = Profile { name: "test".to_string(), db_path: PathBuf::from(":memory:"), lock_after: None };
+
+    let error = App::with_connection(&profile, db).err().unwrap();
+    assert!(error.to_string().contains("transaction_categories"), "{}", error);
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use finance_analyzer::analysis::aggregate::category_totals;
+use finance_analyzer::config::Profile;
+use finance_analyzer::db::{
+    batch::{BatchAction, BatchDb},
+    category::CategoryDb,
+    connection::DbConnection,
+    journal::JournalDb,
+    split::SplitDb,
+    transaction::TransactionDb,
+};
+use finance_analyzer::models::category::Category;
+use finance_analyzer::models::transaction::Split;
+use finance_analyzer::App;
+use rusqlite::Connection;
+use rust_decimal::Decimal;
+use common::{count, date, transaction};
+
+// A 42.10 debit at #1, a refund at #2 and two categories to split them over
+fn setup() -> DbConnection {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let conn = db.get_connection();
+    TransactionDb::new(conn).save_transactions(&[
+        transaction(date(2024, 1, 2), "-42.10", "Albert Heijn"),
+        transaction(date(2024, 1, 5), "12.00", "Albert Heijn"),
+    ]).unwrap();
+    CategoryDb::new(conn).save_category(&Category::new("Groceries", &[("ALBERT HEIJN", 1)])).unwrap();
+    CategoryDb::new(conn).save_category(&Category::new("Household", &[])).unwrap();
+    db
+}
+
+fn split(category: &str, amount: &str) -> Split {
+    Split { category: category.to_string(), amount: Decimal::from_str(amount).unwrap() }
+}
+
+fn stored(conn: &mut Connection, id: u64) -> Vec<(String, String)> {
+    SplitDb::new(conn)
+        .get_all_splits()
+        .unwrap()
+        .remove(&id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|split| (split.category, split.amount.to_string()))
+        .collect()
+}
+
+fn pairs(values: &[(&str, &str)]) -> Vec<(String, String)> {
+    values.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+}
+
+#[test]
+fn parts_take_the_sign_of_the_transaction() {
+    let mut db = setup();
+    let conn = db.get_connection();
+
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "30"), split("Household", "12.10")]).unwrap();
+    SplitDb::new(conn).set_splits(2, &[split("Groceries", "-10"), split("Household", "2")]).unwrap();
+
+    assert_eq!(stored(conn, 1), pairs(&[("Groceries", "-30"), ("Household", "-12.10")]));
+    assert_eq!(stored(conn, 2), pairs(&[("Groceries", "10"), ("Household", "2")]));
+}
+
+#[test]
+fn splits_are_undone_and_redone() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "30"), split("Household", "12.10")]).unwrap();
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "40"), split("Household", "2.10")]).unwrap();
+    SplitDb::new(conn).set_splits(1, &[]).unwrap();
+    assert_eq!(count(conn, "transaction_splits"), 0);
+
+    assert_eq!(JournalDb::new(conn).undo().unwrap().as_deref(), Some("Removed the split from #1"));
+    assert_eq!(stored(conn, 1), pairs(&[("Groceries", "-40"), ("Household", "-2.10")]));
+    assert_eq!(JournalDb::new(conn).undo().unwrap().as_deref(), Some("Split #1 across 2 categories"));
+    assert_eq!(stored(conn, 1), pairs(&[("Groceries", "-30"), ("Household", "-12.10")]));
+    JournalDb::new(conn).undo().unwrap();
+    assert!(stored(conn, 1).is_empty());
+
+    JournalDb::new(conn).redo().unwrap();
+    assert_eq!(stored(conn, 1), pairs(&[("Groceries", "-30"), ("Household", "-12.10")]));
+}
+
+#[test]
+fn splits_that_do_not_add_up_are_refused() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    let cases = [
+        (1, vec![split("Groceries", "30"), split("Household", "12")], "add up to 42"),
+        (1, vec![split("Groceries", "42.10")], "at least two categories"),
+        (1, vec![split("Groceries", "30"), split("Groceries", "12.10")], "more than once"),
+        (1, vec![split("Groceries", "30"), split("Garden", "12.10")], "Unknown category 'Garden'"),
+        (9, vec![split("Groceries", "30"), split("Household", "12.10")], "No transaction with id 9"),
+    ];
+
+    for (id, splits, message) in cases {
+        let e = SplitDb::new(conn).set_splits(id, &splits).unwrap_err().to_string();
+        assert!(e.contains(message), "{}", e);
+    }
+    assert_eq!(count(conn, "transaction_splits"), 0);
+    assert_eq!(count(conn, "journal"), 0);
+}
+
+#[test]
+fn a_category_in_a_split_cannot_be_deleted_or_merged_away() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "30"), split("Household", "12.10")]).unwrap();
+
+    let e = CategoryDb::new(conn).delete_category("Household", None).unwrap_err().to_string();
+    assert!(e.contains("Household is part of the split on #1"), "{}", e);
+    assert!(CategoryDb::new(conn).merge_categories("Household", "Groceries").is_err());
+    assert_eq!(stored(conn, 1).len(), 2);
+}
+
+#[test]
+fn deleting_a_transaction_drops_its_split_until_undone() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "30"), split("Household", "12.10")]).unwrap();
+
+    BatchDb::new(conn).apply(&[1], &BatchAction::Delete).unwrap();
+    assert_eq!(count(conn, "transaction_splits"), 0);
+
+    JournalDb::new(conn).undo().unwrap();
+    assert_eq!(stored(conn, 1), pairs(&[("Groceries", "-30"), ("Household", "-12.10")]));
+}
+
+#[test]
+fn totals_count_each_part_under_its_own_category() {
+    let mut db = setup();
+    let conn = db.get_connection();
+    SplitDb::new(conn).set_splits(1, &[split("Groceries", "30"), split("Household", "12.10")]).unwrap();
+
+    let mut transactions = TransactionDb::new(conn).get_all_transactions().unwrap();
+    let refund = transactions.iter_mut().find(|t| t.id == 2).unwrap();
+    refund.category = Some("Groceries".to_string());
+    let totals = category_totals(&transactions);
+
+    assert_eq!(totals["Groceries"], Decimal::from_str("-18").unwrap());
+    assert_eq!(totals["Household"], Decimal::from_str("-12.10").unwrap());
+    assert_eq!(totals.len(), 2);
+}
+
+#[test]
+fn the_category_detail_counts_only_the_part_in_that_category() {
+    let mut db = setup();
+    SplitDb::new(db.get_connection()).set_splits(1, &[split("Groceries", "30"), split("Household", "12.10")]).unwrap();
+    let profile = Profile { name: "test".to_string(), db_path: PathBuf::from(":memory:"), lock_after: None };
+    let mut app = App::with_connection(&profile, db).unwrap();
+
+    let household = app.sorted_categories().iter().position(|(name, _)| *name == "Household").unwrap();
+    app.category_state.select(Some(household));
+    app.open_category_detail();
+
+    let detail = app.category_detail.as_ref().unwrap();
+    assert_eq!(detail.transactions.len(), 1);
+    assert_eq!(detail.total, Decimal::from_str("-12.10").unwrap());
+    assert_eq!(detail.monthly_totals, [("2024-01".to_string(), Decimal::from_str("-12.10").unwrap())]);
+    assert_eq!(detail.top_merchants[0].total_spend, Decimal::from_str("12.10").unwrap());
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use finance_analyzer::config::Profile;
+use finance_analyzer::db::{connection::DbConnection, transaction::TransactionDb};
+use finance_analyzer::App;
+use common::{date, scratch_dir, statement, transaction};
+
+fn app() -> App {
+    let mut db = DbConnection::new(":memory:").unwrap();
+    let stored = [transaction(date(2024, 1, 5), "-3.20", "BAKERY 0412")];
+    TransactionDb::new(db.get_connection()).save_transactions(&stored).unwrap();
+
+    let profile = Profile { name: "test".to_string(), db_path: PathBuf::from(":memory:"), lock_after: None };
+    App::with_connection(&profile, db).unwrap()
+}
+
+fn wait_for_tasks(app: &mut App) {
+    let started = Instant::now();
+    while app.task.is_some() {
+        assert!(started.elapsed() < Duration::from_secs(30), "task did not finish");
+        app.poll_task();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn editing_aliases_does_not_replace_a_running_import() {
+    let dir = scratch_dir("alias-during-import");
+    let path = dir.join("statement.csv");
+    std::fs::write(&path, statement(&["Albert Heijn 1234", "Shell 55"], 5_000)).unwrap();
+
+    let mut app = app();
+    wait_for_tasks(&mut app);
+    app.selected_transaction = Some(0);
+
+    app.start_import(path.to_str().unwrap());
+    app.add_merchant_alias("Bakery").unwrap();
+    assert!(app.importing());
+
+    app.start_import(path.to_str().unwrap());
+    assert_eq!(app.status_message.as_deref(), Some("An import is already running"));
+
+    wait_for_tasks(&mut app);
+    assert_eq!(app.transactions.len(), 5_001);
+    assert!(app.transactions.iter().any(|t| t.merchant == "Bakery"));
+}